@@ -39,7 +39,7 @@ use futures::{prelude::*, ready};
 use multiaddr::Multiaddr;
 use std::{
     error::Error,
-    fmt,
+    fmt, io,
     pin::Pin,
     task::{Context, Poll},
     time::Duration,
@@ -51,7 +51,7 @@ use std::{
 /// The upgrade process is defined by the following stages:
 ///
 ///    [`authenticate`](Builder::authenticate)`{1}`
-/// -> [`apply`](Authenticated::apply)`{*}`
+/// -> [`apply`](Authenticated::apply) | [`check`](Authenticated::check)`{*}`
 /// -> [`multiplex`](Authenticated::multiplex)`{1}`
 ///
 /// It thus enforces the following invariants on every transport
@@ -217,6 +217,46 @@ where
         ))
     }
 
+    /// Checks whether a connection to the authenticated remote may proceed.
+    ///
+    /// The supplied function is applied to the [`PeerId`] of the remote and the
+    /// [`ConnectedPoint`] of the connection before any further upgrade is
+    /// negotiated on it. Connections it rejects are closed and fail with an
+    /// error of kind [`io::ErrorKind::PermissionDenied`].
+    ///
+    /// ## Transitions
+    ///
+    ///   * Transport output: `(PeerId, C) -> (PeerId, C)`.
+    #[allow(clippy::type_complexity)]
+    pub fn check<C, F>(
+        self,
+        check: F,
+    ) -> Authenticated<
+        AndThen<
+            T,
+            impl FnOnce((PeerId, C), ConnectedPoint) -> future::Ready<io::Result<(PeerId, C)>> + Clone,
+        >,
+    >
+    where
+        T: Transport<Output = (PeerId, C)>,
+        F: FnMut(&PeerId, &ConnectedPoint) -> bool + Clone,
+    {
+        Authenticated(Builder::new(
+            self.0.inner.and_then(move |(i, c), endpoint| {
+                let mut check = check;
+                if check(&i, &endpoint) {
+                    future::ready(Ok((i, c)))
+                } else {
+                    future::ready(Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        format!("Connection to peer {} denied.", i),
+                    )))
+                }
+            }),
+            self.0.version,
+        ))
+    }
+
     /// Upgrades the transport with a (sub)stream multiplexer.
     ///
     /// The supplied upgrade receives the I/O resource `C` and must
@@ -114,6 +114,9 @@ impl super::Recorder<libp2p_identify::IdentifyEvent> for super::Metrics {
                     .received_info_listen_addrs
                     .observe(info.listen_addrs.len() as f64);
             }
+            libp2p_identify::IdentifyEvent::ReceivedDelta { .. } => {
+                self.identify.received.inc();
+            }
             libp2p_identify::IdentifyEvent::Sent { .. } => {
                 self.identify.sent.inc();
             }
@@ -39,6 +39,7 @@ pub struct Metrics {
     dial_attempt: Counter,
     outgoing_connection_error: Family<OutgoingConnectionErrorLabels, Counter>,
     connected_to_banned_peer: Counter,
+    resource_limit_exceeded: Counter,
 }
 
 impl Metrics {
@@ -108,6 +109,13 @@ impl Metrics {
             Box::new(connected_to_banned_peer.clone()),
         );
 
+        let resource_limit_exceeded = Counter::default();
+        sub_registry.register(
+            "resource_limit_exceeded",
+            "Number of substreams rejected by the resource manager",
+            Box::new(resource_limit_exceeded.clone()),
+        );
+
         let connections_established = Family::default();
         sub_registry.register(
             "connections_established",
@@ -134,6 +142,7 @@ impl Metrics {
             dial_attempt,
             outgoing_connection_error,
             connected_to_banned_peer,
+            resource_limit_exceeded,
         }
     }
 }
@@ -225,6 +234,12 @@ impl<TBvEv, THandleErr> super::Recorder<libp2p_swarm::SwarmEvent<TBvEv, THandleE
                     libp2p_swarm::DialError::ConnectionIo(_) => {
                         record(OutgoingConnectionErrorError::ConnectionIo)
                     }
+                    libp2p_swarm::DialError::Denied(_) => {
+                        record(OutgoingConnectionErrorError::Denied)
+                    }
+                    libp2p_swarm::DialError::ResourceLimitExceeded(_) => {
+                        record(OutgoingConnectionErrorError::ResourceLimitExceeded)
+                    }
                 };
             }
             libp2p_swarm::SwarmEvent::BannedPeer { .. } => {
@@ -245,6 +260,9 @@ impl<TBvEv, THandleErr> super::Recorder<libp2p_swarm::SwarmEvent<TBvEv, THandleE
             libp2p_swarm::SwarmEvent::Dialing(_) => {
                 self.swarm.dial_attempt.inc();
             }
+            libp2p_swarm::SwarmEvent::ResourceLimitExceeded { .. } => {
+                self.swarm.resource_limit_exceeded.inc();
+            }
         }
     }
 }
@@ -297,6 +315,8 @@ enum OutgoingConnectionErrorError {
     InvalidPeerId,
     WrongPeerId,
     ConnectionIo,
+    Denied,
+    ResourceLimitExceeded,
     TransportMultiaddrNotSupported,
     TransportOther,
 }
@@ -314,6 +334,8 @@ enum PendingInboundConnectionError {
     Aborted,
     Io,
     ConnectionLimit,
+    Denied,
+    ResourceLimitExceeded,
 }
 
 impl<TTransErr> From<&libp2p_swarm::PendingInboundConnectionError<TTransErr>>
@@ -337,6 +359,12 @@ impl<TTransErr> From<&libp2p_swarm::PendingInboundConnectionError<TTransErr>>
                 PendingInboundConnectionError::Aborted
             }
             libp2p_swarm::PendingInboundConnectionError::IO(_) => PendingInboundConnectionError::Io,
+            libp2p_swarm::PendingInboundConnectionError::Denied(_) => {
+                PendingInboundConnectionError::Denied
+            }
+            libp2p_swarm::PendingInboundConnectionError::ResourceLimitExceeded(_) => {
+                PendingInboundConnectionError::ResourceLimitExceeded
+            }
         }
     }
 }
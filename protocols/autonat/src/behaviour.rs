@@ -509,6 +509,10 @@ impl NetworkBehaviour for Behaviour {
     fn inject_listener_closed(&mut self, id: ListenerId, reason: Result<(), &std::io::Error>) {
         self.inner.inject_listener_closed(id, reason)
     }
+
+    fn inject_supported_protocols_change(&mut self, protocols: &[Vec<u8>]) {
+        self.inner.inject_supported_protocols_change(protocols)
+    }
 }
 
 type Action = NetworkBehaviourAction<
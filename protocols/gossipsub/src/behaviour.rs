@@ -20,11 +20,13 @@
 
 use std::{
     cmp::{max, Ordering},
+    collections::hash_map::Entry,
     collections::HashSet,
     collections::VecDeque,
     collections::{BTreeSet, HashMap},
     fmt,
     net::IpAddr,
+    slice,
     sync::Arc,
     task::{Context, Poll},
     time::Duration,
@@ -38,32 +40,39 @@ use rand::{seq::SliceRandom, thread_rng};
 
 use libp2p_core::{
     connection::ConnectionId, identity::Keypair, multiaddr::Protocol::Ip4,
-    multiaddr::Protocol::Ip6, ConnectedPoint, Multiaddr, PeerId,
+    multiaddr::Protocol::Ip6, ConnectedPoint, Multiaddr, PeerId, PeerRecord,
 };
 use libp2p_swarm::{
     dial_opts::{self, DialOpts},
-    IntoConnectionHandler, NetworkBehaviour, NetworkBehaviourAction, NotifyHandler, PollParameters,
+    DialError, IntoConnectionHandler, NetworkBehaviour, NetworkBehaviourAction, NotifyHandler,
+    PollParameters,
 };
 use wasm_timer::Instant;
 
 use crate::backoff::BackoffStorage;
+use crate::choke::ChokeTracker;
 use crate::config::{GossipsubConfig, ValidationMode};
 use crate::error::{PublishError, SubscriptionError, ValidationError};
 use crate::gossip_promises::GossipPromises;
 use crate::handler::{GossipsubHandler, GossipsubHandlerIn, HandlerEvent};
-use crate::mcache::MessageCache;
+use crate::mcache::{MessageCache, MessageCacheBackend};
 use crate::metrics::{Churn, Config as MetricsConfig, Inclusion, Metrics, Penalty};
-use crate::peer_score::{PeerScore, PeerScoreParams, PeerScoreThresholds, RejectReason};
+use crate::peer_score::{
+    PeerScore, PeerScoreBreakdown, PeerScoreParams, PeerScoreThresholds, RejectReason,
+};
 use crate::protocol::SIGNING_PREFIX;
+use crate::rate_limit::RateLimiter;
 use crate::subscription_filter::{AllowAllSubscriptionFilter, TopicSubscriptionFilter};
 use crate::time_cache::{DuplicateCache, TimeCache};
 use crate::topic::{Hasher, Topic, TopicHash};
+use crate::tracer::{GossipsubTracer, RpcMeta};
 use crate::transform::{DataTransform, IdentityTransform};
 use crate::types::{
-    FastMessageId, GossipsubControlAction, GossipsubMessage, GossipsubSubscription,
+    FailedMessages, FastMessageId, GossipsubControlAction, GossipsubMessage, GossipsubSubscription,
     GossipsubSubscriptionAction, MessageAcceptance, MessageId, PeerInfo, RawGossipsubMessage,
 };
 use crate::types::{GossipsubRpc, PeerConnections, PeerKind};
+use crate::validation::{MessageValidator, PendingMessage, ValidationPipeline, ValidatorConfig};
 use crate::{rpc_proto, TopicScoreParams};
 use std::{cmp::Ordering::Equal, fmt::Debug};
 use wasm_timer::Interval;
@@ -71,6 +80,15 @@ use wasm_timer::Interval;
 #[cfg(test)]
 mod tests;
 
+/// The maximum number of message ids per peer we remember from IDONTWANT control messages.
+const IDONTWANT_CAP: usize = 10_000;
+
+/// The time after which message ids received in IDONTWANT control messages are forgotten.
+const IDONTWANT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The maximum number of signed peer records we keep of peers we are not connected to.
+const MAX_UNCONNECTED_PEER_RECORDS: usize = 1_000;
+
 /// Determines if published messages should be signed or not.
 ///
 /// Without signing, a number of privacy preserving modes can be selected.
@@ -144,6 +162,24 @@ pub enum GossipsubEvent {
     },
     /// A peer that does not support gossipsub has connected.
     GossipsubNotSupported { peer_id: PeerId },
+    /// Messages could not be sent to a peer since the last heartbeat, as its connection did not
+    /// keep up. Control messages are never dropped.
+    SlowPeer {
+        /// The peer the messages could not be sent to.
+        peer_id: PeerId,
+        /// The number of messages dropped, by reason.
+        failed_messages: FailedMessages,
+    },
+    /// The score of a connected peer changed by at least
+    /// [`GossipsubConfig::score_change_event_threshold`] since it was last reported.
+    PeerScoreChanged {
+        /// The peer whose score changed.
+        peer_id: PeerId,
+        /// The score of the peer when it was last reported, or 0 if it was not reported before.
+        previous_score: f64,
+        /// The current score of the peer and its components.
+        breakdown: PeerScoreBreakdown,
+    },
 }
 
 /// A data structure for storing configuration for publishing messages. See [`MessageAuthenticity`]
@@ -314,6 +350,36 @@ pub struct Gossipsub<
 
     /// Keep track of a set of internal metrics relating to gossipsub.
     metrics: Option<Metrics>,
+
+    /// The messages that could not be sent to each peer since the last heartbeat.
+    failed_messages: HashMap<PeerId, FailedMessages>,
+
+    /// The scores of connected peers as last reported through
+    /// [`GossipsubEvent::PeerScoreChanged`].
+    reported_scores: HashMap<PeerId, f64>,
+
+    /// Validates received messages of the topics with a registered [`MessageValidator`].
+    validation_pipeline: ValidationPipeline,
+
+    /// Tracks the rate of published messages per topic.
+    publish_rate_limiter: RateLimiter<TopicHash>,
+
+    /// Tracks the rate of received messages per peer and topic.
+    inbound_rate_limiter: RateLimiter<(PeerId, TopicHash)>,
+
+    /// Signed peer records of connected peers and peers being dialed, sent along with them in peer
+    /// exchange.
+    peer_records: HashMap<PeerId, PeerRecord>,
+
+    /// The time at which the signed peer records of peers we are not connected to are forgotten.
+    peer_record_expirations: HashMap<PeerId, Instant>,
+
+    /// Tracks the delivery latency of mesh peers to choke the slow ones, if enabled through
+    /// [`GossipsubConfig::choke_mesh_peers`].
+    choke_tracker: Option<ChokeTracker>,
+
+    /// Traces the propagation of messages, if set through [`Gossipsub::with_tracer`].
+    tracer: Option<Box<dyn GossipsubTracer>>,
 }
 
 impl<D, F> Gossipsub<D, F>
@@ -423,6 +489,17 @@ where
 
         Ok(Gossipsub {
             metrics: metrics.map(|(registry, cfg)| Metrics::new(registry, cfg)),
+            failed_messages: HashMap::new(),
+            reported_scores: HashMap::new(),
+            validation_pipeline: ValidationPipeline::default(),
+            publish_rate_limiter: RateLimiter::default(),
+            inbound_rate_limiter: RateLimiter::default(),
+            peer_records: HashMap::new(),
+            peer_record_expirations: HashMap::new(),
+            choke_tracker: config
+                .choke_mesh_peers()
+                .then(|| ChokeTracker::new(config.duplicate_cache_time())),
+            tracer: None,
             events: VecDeque::new(),
             control_pool: HashMap::new(),
             publish_config: privacy.into(),
@@ -440,7 +517,8 @@ where
                 config.heartbeat_interval(),
                 config.backoff_slack(),
             ),
-            mcache: MessageCache::new(config.history_gossip(), config.history_length()),
+            mcache: MessageCache::new(config.history_gossip(), config.history_length())
+                .with_byte_limits(config.mcache_max_bytes(), config.mcache_max_topic_bytes()),
             heartbeat: Interval::new_at(
                 Instant::now() + config.heartbeat_initial_delay(),
                 config.heartbeat_interval(),
@@ -503,6 +581,24 @@ where
             .map(|(score, ..)| score.score(peer_id))
     }
 
+    /// Returns the score of a peer together with the contribution of each scoring component, if
+    /// peer scoring is active.
+    pub fn peer_score_breakdown(&self, peer_id: &PeerId) -> Option<PeerScoreBreakdown> {
+        self.peer_score
+            .as_ref()
+            .map(|(score, ..)| score.score_breakdown(peer_id))
+    }
+
+    /// Returns the score breakdowns of all connected peers, if peer scoring is active.
+    pub fn peer_score_snapshot(&self) -> Option<HashMap<PeerId, PeerScoreBreakdown>> {
+        self.peer_score.as_ref().map(|(score, ..)| {
+            self.connected_peers
+                .keys()
+                .map(|peer_id| (*peer_id, score.score_breakdown(peer_id)))
+                .collect()
+        })
+    }
+
     /// Subscribe to a topic.
     ///
     /// Returns [`Ok(true)`] if the subscription worked. Returns [`Ok(false)`] if we were already
@@ -592,6 +688,15 @@ where
         topic: Topic<H>,
         data: impl Into<Vec<u8>>,
     ) -> Result<MessageId, PublishError> {
+        let topic_hash = topic.hash();
+        let rate_limit = self.config.publish_rate_limit(&topic_hash);
+        if let Some(limit) = rate_limit {
+            if !self.publish_rate_limiter.has_capacity(&topic_hash, limit) {
+                debug!("Publish rate limit of topic {} reached", topic);
+                return Err(PublishError::RateLimited);
+            }
+        }
+
         let data = data.into();
 
         // Transform the data before building a raw_message.
@@ -731,14 +836,23 @@ where
         let msg_bytes = event.encoded_len();
         for peer_id in recipient_peers.iter() {
             trace!("Sending message to peer: {:?}", peer_id);
-            self.send_message(*peer_id, event.clone())?;
+            self.send_message_with_ids(*peer_id, event.clone(), slice::from_ref(&msg_id))?;
 
             if let Some(m) = self.metrics.as_mut() {
                 m.msg_sent(&topic_hash, msg_bytes);
             }
         }
 
+        // Only successful publishes count towards the rate limit.
+        if let Some(limit) = rate_limit {
+            self.publish_rate_limiter
+                .try_acquire(topic_hash.clone(), limit);
+        }
+
         debug!("Published message: {:?}", &msg_id);
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.publish_message(&msg_id, &topic_hash);
+        }
 
         if let Some(metrics) = self.metrics.as_mut() {
             metrics.register_published_message(&topic_hash);
@@ -775,9 +889,7 @@ where
         let reject_reason = match acceptance {
             MessageAcceptance::Accept => {
                 let (raw_message, originating_peers) = match self.mcache.validate(msg_id) {
-                    Some((raw_message, originating_peers)) => {
-                        (raw_message.clone(), originating_peers)
-                    }
+                    Some((raw_message, originating_peers)) => (raw_message, originating_peers),
                     None => {
                         warn!(
                             "Message not in cache. Ignoring forwarding. Message Id: {}",
@@ -810,6 +922,14 @@ where
             if let Some(metrics) = self.metrics.as_mut() {
                 metrics.register_msg_validation(&raw_message.topic, &acceptance);
             }
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.reject_message(
+                    Some(msg_id),
+                    propagation_source,
+                    &raw_message.topic,
+                    reject_reason,
+                );
+            }
 
             // Tell peer_score about reject
             // Reject the original source, and any duplicates we've seen from other peers.
@@ -855,6 +975,40 @@ where
         }
     }
 
+    /// Adds the signed peer record of a peer, e.g. learned through another protocol, replacing an
+    /// older record of the peer. The record is sent along with the peer in peer exchange and kept
+    /// until the peer disconnects. The record of a peer we are not connected to is forgotten if we
+    /// do not connect to the peer within the prune backoff.
+    ///
+    /// Returns false if a record with the same or a newer sequence number is already known, or if
+    /// we are not connected to the peer and already keep the maximum number of records of peers we
+    /// are not connected to.
+    pub fn add_peer_record(&mut self, record: PeerRecord) -> bool {
+        let peer_id = record.peer_id();
+        let connected = self.connected_peers.contains_key(&peer_id);
+        match self.peer_records.entry(peer_id) {
+            Entry::Occupied(mut entry) => {
+                if entry.get().seq() >= record.seq() {
+                    return false;
+                }
+                entry.insert(record);
+            }
+            Entry::Vacant(entry) => {
+                if !connected && self.peer_record_expirations.len() >= MAX_UNCONNECTED_PEER_RECORDS
+                {
+                    debug!("Too many peer records, ignoring record of peer {}", peer_id);
+                    return false;
+                }
+                entry.insert(record);
+            }
+        }
+        if !connected {
+            self.peer_record_expirations
+                .insert(peer_id, Instant::now() + self.config.prune_backoff());
+        }
+        true
+    }
+
     /// Removes a peer from the blacklist if it has previously been blacklisted.
     pub fn remove_blacklisted_peer(&mut self, peer_id: &PeerId) {
         if self.blacklisted_peers.remove(peer_id) {
@@ -862,6 +1016,17 @@ where
         }
     }
 
+    /// Sets the backend holding the payloads of the messages in the message cache. Messages that
+    /// are already cached are moved to the new backend.
+    pub fn with_message_cache_backend(&mut self, backend: impl MessageCacheBackend) {
+        self.mcache.set_backend(Box::new(backend));
+    }
+
+    /// Sets the tracer notified of the propagation of messages, replacing any previous tracer.
+    pub fn with_tracer(&mut self, tracer: impl GossipsubTracer) {
+        self.tracer = Some(Box::new(tracer));
+    }
+
     /// Activates the peer scoring system with the given parameters. This will reset all scores
     /// if there was already another peer scoring system activated. Returns an error if the
     /// params are not valid or if they got already set.
@@ -910,6 +1075,41 @@ where
         }
     }
 
+    /// Registers a validator for the received messages of a topic, replacing a previous one.
+    ///
+    /// Received messages of the topic are validated by the validator instead of the application,
+    /// regardless of [`GossipsubConfig::validate_messages`]. Only accepted messages are reported
+    /// through [`GossipsubEvent::Message`], for which
+    /// [`Gossipsub::report_message_validation_result`] must not be called. Returns an error if the
+    /// configuration is not valid.
+    pub fn register_validator<H: Hasher>(
+        &mut self,
+        topic: &Topic<H>,
+        config: ValidatorConfig,
+        validator: impl MessageValidator,
+    ) -> Result<(), &'static str> {
+        config.validate()?;
+        self.validation_pipeline
+            .register(topic.hash(), Box::new(validator), config);
+        Ok(())
+    }
+
+    /// Removes the validator of a topic. Messages still waiting for their validation, including
+    /// those being validated, are ignored.
+    ///
+    /// Returns true if a validator was registered for the topic.
+    pub fn unregister_validator<H: Hasher>(&mut self, topic: &Topic<H>) -> bool {
+        match self.validation_pipeline.unregister(&topic.hash()) {
+            Some(pending) => {
+                for pending in pending {
+                    self.handle_validation_result(pending, MessageAcceptance::Ignore);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Sets the application specific score for a peer. Returns true if scoring is active and
     /// the peer is connected or if the score of the peer is not yet expired, false otherwise.
     pub fn set_application_score(&mut self, peer_id: &PeerId, new_score: f64) -> bool {
@@ -935,6 +1135,9 @@ where
         if let Some(m) = self.metrics.as_mut() {
             m.joined(topic_hash)
         }
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.join(topic_hash);
+        }
 
         // check if we have mesh_n peers in fanout[topic] and add them to the mesh if we do,
         // removing the fanout entry.
@@ -1016,6 +1219,9 @@ where
             if let Some((peer_score, ..)) = &mut self.peer_score {
                 peer_score.graft(&peer_id, topic_hash.clone());
             }
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.graft(&peer_id, topic_hash);
+            }
             Self::control_pool_add(
                 &mut self.control_pool,
                 peer_id,
@@ -1083,7 +1289,10 @@ where
                 |p| p != peer && !self.score_below_threshold(p, |_| 0.0).0,
             )
             .into_iter()
-            .map(|p| PeerInfo { peer_id: Some(p) })
+            .map(|p| PeerInfo {
+                peer_id: Some(p),
+                signed_peer_record: self.peer_records.get(&p).cloned(),
+            })
             .collect()
         } else {
             Vec::new()
@@ -1114,9 +1323,15 @@ where
             if let Some(m) = self.metrics.as_mut() {
                 m.left(topic_hash)
             }
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.leave(topic_hash);
+            }
             for peer in peers {
                 // Send a PRUNE control message
                 debug!("LEAVE: Sending PRUNE to peer: {:?}", peer);
+                if let Some(tracer) = self.tracer.as_mut() {
+                    tracer.prune(&peer, topic_hash);
+                }
                 let on_unsubscribe = true;
                 let control =
                     self.make_prune(topic_hash, &peer, self.config.do_px(), on_unsubscribe);
@@ -1189,31 +1404,54 @@ where
             return;
         }
 
+        // Mesh peers we choked announce the messages they forward to us via IHAVE. These
+        // announcements replace the forwarding, thus are exempt from the IHAVE flood protection.
+        let (announcements, mut ihave_msgs): (Vec<_>, Vec<_>) = match self.choke_tracker.as_mut() {
+            Some(choke_tracker) => {
+                let (announcements, ihave_msgs): (Vec<_>, Vec<_>) = ihave_msgs
+                    .into_iter()
+                    .partition(|(topic, _)| choke_tracker.is_choked(topic, peer_id));
+                for (topic, ids) in &announcements {
+                    for id in ids {
+                        let latency = choke_tracker.observe(id);
+                        choke_tracker.record(topic, peer_id, latency);
+                    }
+                }
+                (announcements, ihave_msgs)
+            }
+            None => (Vec::new(), ihave_msgs),
+        };
+
         // IHAVE flood protection
-        let peer_have = self.count_received_ihave.entry(*peer_id).or_insert(0);
-        *peer_have += 1;
-        if *peer_have > self.config.max_ihave_messages() {
-            debug!(
-                "IHAVE: peer {} has advertised too many times ({}) within this heartbeat \
-            interval; ignoring",
-                peer_id, *peer_have
-            );
-            return;
+        if !ihave_msgs.is_empty() {
+            let peer_have = self.count_received_ihave.entry(*peer_id).or_insert(0);
+            *peer_have += 1;
+            if *peer_have > self.config.max_ihave_messages() {
+                debug!(
+                    "IHAVE: peer {} has advertised too many times ({}) within this heartbeat \
+                interval; ignoring",
+                    peer_id, *peer_have
+                );
+                ihave_msgs.clear();
+            }
         }
 
         if let Some(iasked) = self.count_sent_iwant.get(peer_id) {
-            if *iasked >= self.config.max_ihave_length() {
+            if *iasked >= self.config.max_ihave_length() && !ihave_msgs.is_empty() {
                 debug!(
                     "IHAVE: peer {} has already advertised too many messages ({}); ignoring",
                     peer_id, *iasked
                 );
-                return;
+                ihave_msgs.clear();
             }
         }
 
         trace!("Handling IHAVE for peer: {:?}", peer_id);
 
         let mut iwant_ids = HashSet::new();
+        // The announced messages of choked mesh peers, which are requested regardless of the
+        // IHAVE limits.
+        let mut announced_ids = HashSet::new();
 
         let want_message = |id: &MessageId| {
             if self.duplicate_cache.contains(id) {
@@ -1251,7 +1489,18 @@ where
             }
         }
 
-        if !iwant_ids.is_empty() {
+        for (topic, ids) in announcements {
+            for id in ids.into_iter().filter(want_message) {
+                if !iwant_ids.contains(&id) && announced_ids.insert(id) {
+                    // Register the IWANT metric
+                    if let Some(metrics) = self.metrics.as_mut() {
+                        metrics.register_iwant(&topic);
+                    }
+                }
+            }
+        }
+
+        if !iwant_ids.is_empty() || !announced_ids.is_empty() {
             let iasked = self.count_sent_iwant.entry(*peer_id).or_insert(0);
             let mut iask = iwant_ids.len();
             if *iasked + iask > self.config.max_ihave_length() {
@@ -1273,6 +1522,7 @@ where
 
             iwant_ids_vec.truncate(iask as usize);
             *iasked += iask;
+            iwant_ids_vec.extend(announced_ids);
 
             for message_id in &iwant_ids_vec {
                 // Add all messages to the pending list
@@ -1330,7 +1580,7 @@ where
                         peer_id, &id
                     );
                 } else {
-                    cached_messages.insert(id.clone(), msg.clone());
+                    cached_messages.insert(id.clone(), msg);
                 }
             }
         }
@@ -1338,7 +1588,7 @@ where
         if !cached_messages.is_empty() {
             debug!("IWANT: Sending cached messages to peer: {:?}", peer_id);
             // Send the messages to the peer
-            let message_list: Vec<_> = cached_messages.into_iter().map(|entry| entry.1).collect();
+            let (message_ids, message_list): (Vec<_>, Vec<_>) = cached_messages.into_iter().unzip();
 
             let topics = message_list
                 .iter()
@@ -1354,7 +1604,10 @@ where
 
             let msg_bytes = message.encoded_len();
 
-            if self.send_message(*peer_id, message).is_err() {
+            if self
+                .send_message_with_ids(*peer_id, message, &message_ids)
+                .is_err()
+            {
                 error!("Failed to send cached messages. Messages too large");
             } else if let Some(m) = self.metrics.as_mut() {
                 // Sending of messages succeeded, register them on the internal metrics.
@@ -1487,6 +1740,9 @@ where
                         &self.connected_peers,
                     );
 
+                    if let Some(tracer) = self.tracer.as_mut() {
+                        tracer.graft(peer_id, &topic_hash);
+                    }
                     if let Some((peer_score, ..)) = &mut self.peer_score {
                         peer_score.graft(peer_id, topic_hash);
                     }
@@ -1558,6 +1814,9 @@ where
                 if let Some((peer_score, ..)) = &mut self.peer_score {
                     peer_score.prune(peer_id, topic_hash.clone());
                 }
+                if let Some(tracer) = self.tracer.as_mut() {
+                    tracer.prune(peer_id, topic_hash);
+                }
 
                 update_backoff = true;
 
@@ -1608,12 +1867,9 @@ where
                         continue;
                     }
 
-                    // NOTE: We cannot dial any peers from PX currently as we typically will not
-                    // know their multiaddr. Until SignedRecords are spec'd this
-                    // remains a stub. By default `config.prune_peers()` is set to zero and
-                    // this is skipped. If the user modifies this, this will only be able to
-                    // dial already known peers (from an external discovery mechanism for
-                    // example).
+                    // PX peers are dialed at the addresses of their signed peer records. Peers
+                    // without a record can only be dialed if their addresses are known through
+                    // another behaviour.
                     if self.config.prune_peers() > 0 {
                         self.px_connect(px);
                     }
@@ -1626,9 +1882,6 @@ where
     fn px_connect(&mut self, mut px: Vec<PeerInfo>) {
         let n = self.config.prune_peers();
         // Ignore peerInfo with no ID
-        //
-        //TODO: Once signed records are spec'd: Can we use peerInfo without any IDs if they have a
-        // signed peer record?
         px = px.into_iter().filter(|p| p.peer_id.is_some()).collect();
         if px.len() > n {
             // only use at most prune_peers many random peers
@@ -1638,12 +1891,16 @@ where
         }
 
         for p in px {
-            // TODO: Once signed records are spec'd: extract signed peer record if given and handle
-            // it, see https://github.com/libp2p/specs/pull/217
             if let Some(peer_id) = p.peer_id {
                 // mark as px peer
                 self.px_peers.insert(peer_id);
 
+                // remember the addresses of its signed peer record, which are then used to dial
+                // the peer through `addresses_of_peer`
+                if let Some(record) = p.signed_peer_record {
+                    self.add_peer_record(record);
+                }
+
                 // dial peer
                 let handler = self.new_handler();
                 self.events.push_back(NetworkBehaviourAction::Dial {
@@ -1676,6 +1933,14 @@ where
                 "Rejecting message from blacklisted peer: {}",
                 propagation_source
             );
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.reject_message(
+                    Some(msg_id),
+                    propagation_source,
+                    &raw_message.topic,
+                    RejectReason::BlackListedPeer,
+                );
+            }
             if let Some((peer_score, .., gossip_promises)) = &mut self.peer_score {
                 peer_score.reject_message(
                     propagation_source,
@@ -1695,6 +1960,14 @@ where
                     "Rejecting message from peer {} because of blacklisted source: {}",
                     propagation_source, source
                 );
+                if let Some(tracer) = self.tracer.as_mut() {
+                    tracer.reject_message(
+                        Some(msg_id),
+                        propagation_source,
+                        &raw_message.topic,
+                        RejectReason::BlackListedSource,
+                    );
+                }
                 self.handle_invalid_message(
                     propagation_source,
                     raw_message,
@@ -1725,6 +1998,14 @@ where
                 "Dropping message {} claiming to be from self but forwarded from {}",
                 msg_id, propagation_source
             );
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.reject_message(
+                    Some(msg_id),
+                    propagation_source,
+                    &raw_message.topic,
+                    RejectReason::SelfOrigin,
+                );
+            }
             self.handle_invalid_message(propagation_source, raw_message, RejectReason::SelfOrigin);
             return false;
         }
@@ -1734,12 +2015,13 @@ where
 
     /// Handles a newly received [`RawGossipsubMessage`].
     ///
-    /// Forwards the message to all peers in the mesh.
+    /// Forwards the message to all peers in the mesh. Returns the id of the message, `None` if it
+    /// was dropped before its id was computed.
     fn handle_received_message(
         &mut self,
         mut raw_message: RawGossipsubMessage,
         propagation_source: &PeerId,
-    ) {
+    ) -> Option<MessageId> {
         // Record the received metric
         if let Some(metrics) = self.metrics.as_mut() {
             metrics.msg_recvd_unfiltered(&raw_message.topic, raw_message.raw_protobuf_len());
@@ -1763,10 +2045,14 @@ where
                     // The peers in this cache are used to prevent us forwarding redundant messages onto
                     // these peers.
                     self.mcache.observe_duplicate(&msg_id, propagation_source);
+                    self.record_delivery(&msg_id, &raw_message.topic, propagation_source);
+                    if let Some(tracer) = self.tracer.as_mut() {
+                        tracer.duplicate_message(&msg_id, propagation_source, &raw_message.topic);
+                    }
                 }
 
                 // This message has been seen previously. Ignore it
-                return;
+                return Some(msg_id);
             }
         }
 
@@ -1775,13 +2061,18 @@ where
             Ok(message) => message,
             Err(e) => {
                 debug!("Invalid message. Transform error: {:?}", e);
+                let reject_reason = RejectReason::ValidationError(ValidationError::TransformFailed);
+                if let Some(tracer) = self.tracer.as_mut() {
+                    tracer.reject_message(
+                        None,
+                        propagation_source,
+                        &raw_message.topic,
+                        reject_reason,
+                    );
+                }
                 // Reject the message and return
-                self.handle_invalid_message(
-                    propagation_source,
-                    &raw_message,
-                    RejectReason::ValidationError(ValidationError::TransformFailed),
-                );
-                return;
+                self.handle_invalid_message(propagation_source, &raw_message, reject_reason);
+                return None;
             }
         };
 
@@ -1792,7 +2083,26 @@ where
         // Peers get penalized if this message is invalid. We don't add it to the duplicate cache
         // and instead continually penalize peers that repeatedly send this message.
         if !self.message_is_valid(&msg_id, &mut raw_message, propagation_source) {
-            return;
+            return Some(msg_id);
+        }
+
+        // Drop new messages exceeding the inbound rate limit of the topic and penalize the peer.
+        // Duplicates are not rate limited, as they are neither delivered nor forwarded.
+        if let Some(limit) = self.config.inbound_rate_limit(&message.topic) {
+            if !self.duplicate_cache.contains(&msg_id)
+                && !self
+                    .inbound_rate_limiter
+                    .try_acquire((*propagation_source, message.topic.clone()), limit)
+            {
+                debug!(
+                    "Peer {} exceeded the rate limit of topic {}, dropping message",
+                    propagation_source, message.topic
+                );
+                if let Some((peer_score, ..)) = &mut self.peer_score {
+                    peer_score.add_penalty(propagation_source, 1);
+                }
+                return Some(msg_id);
+            }
         }
 
         // Add the message to the duplicate caches
@@ -1809,7 +2119,11 @@ where
                 peer_score.duplicated_message(propagation_source, &msg_id, &message.topic);
             }
             self.mcache.observe_duplicate(&msg_id, propagation_source);
-            return;
+            self.record_delivery(&msg_id, &message.topic, propagation_source);
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.duplicate_message(&msg_id, propagation_source, &message.topic);
+            }
+            return Some(msg_id);
         }
         debug!(
             "Put message {:?} in duplicate_cache and resolve promises",
@@ -1821,6 +2135,8 @@ where
             metrics.msg_recvd(&message.topic);
         }
 
+        self.record_delivery(&msg_id, &message.topic, propagation_source);
+
         // Tells score that message arrived (but is maybe not fully validated yet).
         // Consider the message as delivered for gossip promises.
         if let Some((peer_score, .., gossip_promises)) = &mut self.peer_score {
@@ -1828,12 +2144,34 @@ where
             gossip_promises.message_delivered(&msg_id);
         }
 
+        // Messages of topics with a registered validator are held until they are validated.
+        let validate_in_pipeline = self.mesh.contains_key(&message.topic)
+            && self.validation_pipeline.has_validator(&message.topic);
+        if validate_in_pipeline {
+            raw_message.validated = false;
+        }
+
         // Add the message to our memcache
         self.mcache.put(&msg_id, raw_message.clone());
 
+        // Ask our mesh peers not to send us large messages we already received.
+        if raw_message.raw_protobuf_len() > self.config.idontwant_message_size_threshold() {
+            self.send_idontwant(&raw_message, &msg_id, propagation_source);
+        }
+
+        if validate_in_pipeline {
+            debug!("Queueing received message for validation");
+            self.validation_pipeline
+                .enqueue(msg_id.clone(), *propagation_source, message);
+            return Some(msg_id);
+        }
+
         // Dispatch the message to the user if we are subscribed to any of the topics
         if self.mesh.contains_key(&message.topic) {
             debug!("Sending received message to user");
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.deliver_message(&msg_id, propagation_source, &message.topic);
+            }
             self.events.push_back(NetworkBehaviourAction::GenerateEvent(
                 GossipsubEvent::Message {
                     propagation_source: *propagation_source,
@@ -1846,7 +2184,7 @@ where
                 "Received message on a topic we are not subscribed to: {:?}",
                 message.topic
             );
-            return;
+            return Some(msg_id);
         }
 
         // forward the message to mesh peers, if no validation is required
@@ -1864,6 +2202,71 @@ where
             }
             debug!("Completed message handling for message: {:?}", msg_id);
         }
+        Some(msg_id)
+    }
+
+    /// Records the delivery of a message, to choke the mesh peers with a high delivery latency.
+    /// Only the latency of episub peers is recorded, as the other peers don't support CHOKE.
+    fn record_delivery(
+        &mut self,
+        msg_id: &MessageId,
+        topic: &TopicHash,
+        propagation_source: &PeerId,
+    ) {
+        let choke_tracker = match self.choke_tracker.as_mut() {
+            Some(choke_tracker) => choke_tracker,
+            None => return,
+        };
+        let latency = choke_tracker.observe(msg_id);
+        let in_mesh = self
+            .mesh
+            .get(topic)
+            .map_or(false, |peers| peers.contains(propagation_source));
+        let episub = matches!(
+            self.connected_peers
+                .get(propagation_source)
+                .map(|v| &v.kind),
+            Some(PeerKind::Episub)
+        );
+        // Choked peers only deliver the messages we request, their latency is recorded for their
+        // announcements instead.
+        if in_mesh && episub && !choke_tracker.is_choked(topic, propagation_source) {
+            choke_tracker.record(topic, propagation_source, latency);
+        }
+    }
+
+    /// Applies the result of the validation of a message by a registered [`MessageValidator`].
+    fn handle_validation_result(&mut self, pending: PendingMessage, acceptance: MessageAcceptance) {
+        let PendingMessage {
+            message_id,
+            propagation_source,
+            message,
+            ..
+        } = pending;
+        let accepted = matches!(acceptance, MessageAcceptance::Accept);
+        if let Err(e) =
+            self.report_message_validation_result(&message_id, &propagation_source, acceptance)
+        {
+            error!(
+                "Failed to forward validated message {}: {:?}",
+                message_id, e
+            );
+        }
+
+        // Dispatch accepted messages to the user if we are still subscribed to the topic
+        if accepted && self.mesh.contains_key(&message.topic) {
+            debug!("Sending validated message to user");
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.deliver_message(&message_id, &propagation_source, &message.topic);
+            }
+            self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                GossipsubEvent::Message {
+                    propagation_source,
+                    message_id,
+                    message,
+                },
+            ));
+        }
     }
 
     // Handles invalid messages received.
@@ -1974,7 +2377,10 @@ where
                             self.connected_peers
                                 .get(propagation_source)
                                 .map(|v| &v.kind),
-                            Some(PeerKind::Gossipsubv1_1) | Some(PeerKind::Gossipsub)
+                            Some(PeerKind::Episub)
+                                | Some(PeerKind::Gossipsubv1_2)
+                                | Some(PeerKind::Gossipsubv1_1)
+                                | Some(PeerKind::Gossipsub)
                         )
                         && !Self::score_below_threshold_from_scores(
                             &self.peer_score,
@@ -2007,6 +2413,9 @@ where
                                 if let Some((peer_score, ..)) = &mut self.peer_score {
                                     peer_score.graft(propagation_source, topic_hash.clone());
                                 }
+                                if let Some(tracer) = self.tracer.as_mut() {
+                                    tracer.graft(propagation_source, topic_hash);
+                                }
                                 topics_to_graft.push(topic_hash.clone());
                             }
                         }
@@ -2126,6 +2535,36 @@ where
         self.count_sent_iwant.clear();
         self.count_received_ihave.clear();
 
+        // clean up rate limits that are back to their full capacity
+        self.publish_rate_limiter.prune();
+        self.inbound_rate_limiter.prune();
+
+        // forget the signed peer records of peers we did not connect to in time
+        let peer_records = &mut self.peer_records;
+        self.peer_record_expirations.retain(|peer_id, expiration| {
+            let expired = *expiration <= start;
+            if expired {
+                peer_records.remove(peer_id);
+            }
+            !expired
+        });
+
+        // report peers that did not keep up with the messages we sent them
+        for (peer_id, failed_messages) in self.failed_messages.drain() {
+            self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                GossipsubEvent::SlowPeer {
+                    peer_id,
+                    failed_messages,
+                },
+            ));
+        }
+
+        // clean up expired IDONTWANT message ids
+        for peer in self.connected_peers.values_mut() {
+            peer.dont_send
+                .retain(|_, received| received.elapsed() < IDONTWANT_TIMEOUT);
+        }
+
         // apply iwant penalties
         self.apply_iwant_penalties();
 
@@ -2144,6 +2583,23 @@ where
                     .entry(peer_id)
                     .or_insert_with(|| peer_score.metric_score(peer_id, self.metrics.as_mut()));
             }
+
+            // report peers whose score changed significantly since it was last reported
+            if let Some(threshold) = self.config.score_change_event_threshold() {
+                for (peer_id, score) in scores.iter() {
+                    let previous_score = self.reported_scores.get(*peer_id).copied().unwrap_or(0.0);
+                    if (score - previous_score).abs() >= threshold {
+                        self.reported_scores.insert(**peer_id, *score);
+                        self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                            GossipsubEvent::PeerScoreChanged {
+                                peer_id: **peer_id,
+                                previous_score,
+                                breakdown: peer_score.score_breakdown(peer_id),
+                            },
+                        ));
+                    }
+                }
+            }
         }
 
         // maintain the mesh for each topic
@@ -2491,6 +2947,40 @@ where
             })
         }
 
+        // choke or unchoke mesh peers based on their delivery latency
+        if let Some(choke_tracker) = self.choke_tracker.as_mut() {
+            let (to_choke, to_unchoke) = choke_tracker.heartbeat(&self.mesh, &self.config);
+            for (peer, topic_hash) in to_choke {
+                if let Some((peer_score, ..)) = &mut self.peer_score {
+                    peer_score.choke(&peer, topic_hash.clone());
+                }
+                Self::control_pool_add(
+                    &mut self.control_pool,
+                    peer,
+                    GossipsubControlAction::Choke { topic_hash },
+                );
+            }
+            for (peer, topic_hash) in to_unchoke {
+                if let Some((peer_score, ..)) = &mut self.peer_score {
+                    peer_score.unchoke(&peer, topic_hash.clone());
+                }
+                Self::control_pool_add(
+                    &mut self.control_pool,
+                    peer,
+                    GossipsubControlAction::Unchoke { topic_hash },
+                );
+            }
+        }
+
+        // chokes only apply while the choking peer is in the mesh
+        for (peer_id, peer) in self.connected_peers.iter_mut() {
+            let mesh = &self.mesh;
+            peer.choking.retain(|topic| {
+                mesh.get(topic)
+                    .map_or(false, |peers| peers.contains(peer_id))
+            });
+        }
+
         self.emit_gossip();
 
         // send graft/prunes
@@ -2504,6 +2994,10 @@ where
         // shift the memcache
         self.mcache.shift();
 
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.flush();
+        }
+
         debug!("Completed Heartbeat");
         if let Some(metrics) = self.metrics.as_mut() {
             let duration = u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX);
@@ -2594,6 +3088,9 @@ where
                 if let Some((peer_score, ..)) = &mut self.peer_score {
                     peer_score.graft(&peer, topic.clone());
                 }
+                if let Some(tracer) = self.tracer.as_mut() {
+                    tracer.graft(&peer, topic);
+                }
 
                 // inform the handler of the peer being added to the mesh
                 // If the peer did not previously exist in any mesh, inform the handler
@@ -2665,6 +3162,9 @@ where
                     on_unsubscribe,
                 );
                 remaining_prunes.push(prune);
+                if let Some(tracer) = self.tracer.as_mut() {
+                    tracer.prune(peer, topic_hash);
+                }
                 // inform the handler
                 peer_removed_from_mesh(
                     *peer,
@@ -2693,6 +3193,139 @@ where
         }
     }
 
+    /// Sends an IDONTWANT for the given message to our gossipsub v1.2 mesh peers, except to the
+    /// peers we know to already have the message.
+    fn send_idontwant(
+        &mut self,
+        message: &RawGossipsubMessage,
+        msg_id: &MessageId,
+        propagation_source: &PeerId,
+    ) {
+        let recipient_peers = match self.mesh.get(&message.topic) {
+            Some(mesh_peers) => mesh_peers
+                .iter()
+                .filter(|peer_id| {
+                    *peer_id != propagation_source
+                        && Some(*peer_id) != message.source.as_ref()
+                        && matches!(
+                            self.connected_peers.get(peer_id).map(|v| &v.kind),
+                            Some(PeerKind::Episub) | Some(PeerKind::Gossipsubv1_2)
+                        )
+                })
+                .cloned()
+                .collect::<Vec<_>>(),
+            None => return,
+        };
+
+        if recipient_peers.is_empty() {
+            return;
+        }
+
+        let event = GossipsubRpc {
+            subscriptions: Vec::new(),
+            messages: Vec::new(),
+            control_msgs: vec![GossipsubControlAction::IDontWant {
+                message_ids: vec![msg_id.clone()],
+            }],
+        }
+        .into_protobuf();
+
+        for peer in recipient_peers {
+            debug!(
+                "Sending IDONTWANT for message: {:?} to peer {:?}",
+                msg_id, peer
+            );
+            if self.send_message(peer, event.clone()).is_err() {
+                error!("Failed to send IDONTWANT. Message too large");
+            } else if let Some(m) = self.metrics.as_mut() {
+                m.register_idontwant_sent(1);
+            }
+        }
+    }
+
+    /// Handles an IDONTWANT control message. Remembers the message ids, so that the messages are
+    /// not forwarded to the peer.
+    fn handle_idontwant(&mut self, peer_id: &PeerId, message_ids: Vec<MessageId>) {
+        let peer = match self.connected_peers.get_mut(peer_id) {
+            Some(peer) => peer,
+            None => {
+                error!(
+                    "IDONTWANT: Received IDONTWANT from unknown peer {}",
+                    peer_id
+                );
+                return;
+            }
+        };
+
+        if let Some(metrics) = self.metrics.as_mut() {
+            metrics.register_idontwant_recvd(message_ids.len());
+        }
+
+        let now = Instant::now();
+        for message_id in message_ids {
+            if peer.dont_send.len() >= IDONTWANT_CAP {
+                debug!(
+                    "IDONTWANT: Peer {} exceeded the maximum of remembered message ids",
+                    peer_id
+                );
+                break;
+            }
+            peer.dont_send.insert(message_id, now);
+        }
+    }
+
+    /// Handles CHOKE control messages. The messages we forward to the peer on the topics are only
+    /// announced via IHAVE until it unchokes us.
+    fn handle_choke(&mut self, peer_id: &PeerId, topics: Vec<TopicHash>) {
+        let peer = match self.connected_peers.get_mut(peer_id) {
+            Some(peer) => peer,
+            None => {
+                error!("CHOKE: Received CHOKE from unknown peer {}", peer_id);
+                return;
+            }
+        };
+
+        if peer.kind != PeerKind::Episub {
+            debug!(
+                "CHOKE: Ignoring CHOKE from peer {} which didn't negotiate episub",
+                peer_id
+            );
+            return;
+        }
+
+        for topic in topics {
+            // A choke only applies while the peer is in our mesh.
+            if self
+                .mesh
+                .get(&topic)
+                .map_or(false, |peers| peers.contains(peer_id))
+            {
+                debug!("CHOKE: Peer {} choked us on topic {:?}", peer_id, topic);
+                peer.choking.insert(topic);
+            } else {
+                debug!(
+                    "CHOKE: Ignoring CHOKE from peer {} not in the mesh of topic {:?}",
+                    peer_id, topic
+                );
+            }
+        }
+    }
+
+    /// Handles UNCHOKE control messages. The messages on the topics are forwarded to the peer
+    /// again.
+    fn handle_unchoke(&mut self, peer_id: &PeerId, topics: Vec<TopicHash>) {
+        if let Some(peer) = self.connected_peers.get_mut(peer_id) {
+            if peer.kind != PeerKind::Episub {
+                return;
+            }
+            for topic in topics {
+                if peer.choking.remove(&topic) {
+                    debug!("UNCHOKE: Peer {} unchoked us on topic {:?}", peer_id, topic);
+                }
+            }
+        }
+    }
+
     /// Helper function which forwards a message to mesh\[topic\] peers.
     ///
     /// Returns true if at least one peer was messaged.
@@ -2712,6 +3345,8 @@ where
 
         debug!("Forwarding message: {:?}", msg_id);
         let mut recipient_peers = HashSet::new();
+        // Mesh peers that choked us, which are only sent an IHAVE for the message.
+        let mut choking_peers = HashSet::new();
 
         {
             // Populate the recipient peers mapping
@@ -2738,12 +3373,73 @@ where
                         && !originating_peers.contains(peer_id)
                         && Some(peer_id) != message.source.as_ref()
                     {
-                        recipient_peers.insert(*peer_id);
+                        let choking = self
+                            .connected_peers
+                            .get(peer_id)
+                            .map_or(false, |peer| peer.choking.contains(topic));
+                        if choking {
+                            choking_peers.insert(*peer_id);
+                        } else {
+                            recipient_peers.insert(*peer_id);
+                        }
                     }
                 }
             }
         }
 
+        // Don't forward the message to peers that sent an IDONTWANT for it.
+        let mut suppressed = 0;
+        choking_peers.retain(|peer_id| {
+            !self
+                .connected_peers
+                .get(peer_id)
+                .map_or(false, |peer| peer.dont_send.contains_key(msg_id))
+        });
+        recipient_peers.retain(|peer_id| {
+            let dont_send = self
+                .connected_peers
+                .get(peer_id)
+                .map_or(false, |peer| peer.dont_send.contains_key(msg_id));
+            if dont_send {
+                suppressed += 1;
+            }
+            !dont_send
+        });
+        if suppressed > 0 {
+            debug!(
+                "Not forwarding message {:?} to {} peers that sent IDONTWANT",
+                msg_id, suppressed
+            );
+            if let Some(m) = self.metrics.as_mut() {
+                let msg_bytes = message.raw_protobuf_len();
+                for _ in 0..suppressed {
+                    m.msg_suppressed(&message.topic, msg_bytes);
+                }
+            }
+        }
+
+        // announce the message to mesh peers that choked us
+        let announced = !choking_peers.is_empty();
+        if announced {
+            let event = GossipsubRpc {
+                subscriptions: Vec::new(),
+                messages: Vec::new(),
+                control_msgs: vec![GossipsubControlAction::IHave {
+                    topic_hash: message.topic.clone(),
+                    message_ids: vec![msg_id.clone()],
+                }],
+            }
+            .into_protobuf();
+
+            for peer in choking_peers {
+                debug!(
+                    "Sending IHAVE for message: {:?} to choking peer {:?}",
+                    msg_id, peer
+                );
+                self.send_message(peer, event.clone())?;
+            }
+        }
+
         // forward the message to peers
         if !recipient_peers.is_empty() {
             let event = GossipsubRpc {
@@ -2756,7 +3452,7 @@ where
             let msg_bytes = event.encoded_len();
             for peer in recipient_peers.iter() {
                 debug!("Sending message: {:?} to peer {:?}", msg_id, peer);
-                self.send_message(*peer, event.clone())?;
+                self.send_message_with_ids(*peer, event.clone(), slice::from_ref(msg_id))?;
                 if let Some(m) = self.metrics.as_mut() {
                     m.msg_sent(&message.topic, msg_bytes);
                 }
@@ -2764,7 +3460,7 @@ where
             debug!("Completed forwarding message");
             Ok(true)
         } else {
-            Ok(false)
+            Ok(announced)
         }
     }
 
@@ -2899,6 +3595,17 @@ where
         &mut self,
         peer_id: PeerId,
         message: rpc_proto::Rpc,
+    ) -> Result<(), PublishError> {
+        self.send_message_with_ids(peer_id, message, &[])
+    }
+
+    /// Sends a GossipsubRpc message to a peer like [`Gossipsub::send_message`], given the ids of
+    /// the messages it contains in order for tracing.
+    fn send_message_with_ids(
+        &mut self,
+        peer_id: PeerId,
+        message: rpc_proto::Rpc,
+        message_ids: &[MessageId],
     ) -> Result<(), PublishError> {
         // If the message is oversized, try and fragment it. If it cannot be fragmented, log an
         // error and drop the message (all individual messages should be small enough to fit in the
@@ -2906,7 +3613,11 @@ where
 
         let messages = self.fragment_message(message)?;
 
+        let mut message_ids = message_ids.iter().cloned();
         for message in messages {
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.send_rpc(&peer_id, &RpcMeta::from_proto(&message, &mut message_ids));
+            }
             self.events
                 .push_back(NetworkBehaviourAction::NotifyHandler {
                     peer_id,
@@ -3028,6 +3739,39 @@ where
                         .prune
                         .push(prune.clone());
                 }
+                for idontwant in &control.idontwant {
+                    let len = idontwant.encoded_len();
+                    create_or_add_rpc!(len);
+                    rpc_list
+                        .last_mut()
+                        .expect("Always an element")
+                        .control
+                        .get_or_insert_with(|| empty_control.clone())
+                        .idontwant
+                        .push(idontwant.clone());
+                }
+                for choke in &control.choke {
+                    let len = choke.encoded_len();
+                    create_or_add_rpc!(len);
+                    rpc_list
+                        .last_mut()
+                        .expect("Always an element")
+                        .control
+                        .get_or_insert_with(|| empty_control.clone())
+                        .choke
+                        .push(choke.clone());
+                }
+                for unchoke in &control.unchoke {
+                    let len = unchoke.encoded_len();
+                    create_or_add_rpc!(len);
+                    rpc_list
+                        .last_mut()
+                        .expect("Always an element")
+                        .control
+                        .get_or_insert_with(|| empty_control.clone())
+                        .unchoke
+                        .push(unchoke.clone());
+                }
             } else {
                 let len = control.encoded_len();
                 create_or_add_rpc!(len);
@@ -3062,9 +3806,33 @@ where
             self.config.validation_mode().clone(),
             self.config.idle_timeout(),
             self.config.support_floodsub(),
+            self.config.send_queue_len(),
+            self.config.send_queue_timeout(),
         )
     }
 
+    fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        self.peer_records
+            .get(peer_id)
+            .map(|record| record.addresses().to_vec())
+            .unwrap_or_default()
+    }
+
+    fn inject_dial_failure(
+        &mut self,
+        peer_id: Option<PeerId>,
+        _: Self::ConnectionHandler,
+        _: &DialError,
+    ) {
+        // Forget the signed peer records of peers we failed to connect to.
+        if let Some(peer_id) = peer_id {
+            if !self.connected_peers.contains_key(&peer_id) {
+                self.peer_records.remove(&peer_id);
+                self.peer_record_expirations.remove(&peer_id);
+            }
+        }
+    }
+
     fn inject_connection_established(
         &mut self,
         peer_id: &PeerId,
@@ -3105,9 +3873,13 @@ where
             .or_insert(PeerConnections {
                 kind: PeerKind::Floodsub,
                 connections: vec![],
+                dont_send: HashMap::new(),
+                choking: HashSet::new(),
             })
             .connections
             .push(*connection_id);
+        // Keep the signed peer record of the peer while it is connected.
+        self.peer_record_expirations.remove(peer_id);
 
         if other_established == 0 {
             // Ignore connections from blacklisted peers.
@@ -3228,6 +4000,12 @@ where
                                 m.peers_removed(topic, Churn::Dc, 1);
                                 m.set_mesh_peers(topic, mesh_peers.len());
                             }
+                            if let Some(choke_tracker) = self.choke_tracker.as_mut() {
+                                choke_tracker.remove_peer(topic, peer_id);
+                            }
+                            if let Some(tracer) = self.tracer.as_mut() {
+                                tracer.prune(peer_id, topic);
+                            }
                         };
                     }
 
@@ -3277,6 +4055,11 @@ where
             }
 
             self.connected_peers.remove(peer_id);
+            self.reported_scores.remove(peer_id);
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.remove_peer(peer_id);
+            }
+            self.peer_records.remove(peer_id);
 
             if let Some((peer_score, ..)) = &mut self.peer_score {
                 peer_score.remove_peer(peer_id);
@@ -3346,6 +4129,9 @@ where
                         kind, propagation_source
                     );
                     if let PeerKind::Floodsub = conn.kind {
+                        if let Some(tracer) = self.tracer.as_mut() {
+                            tracer.add_peer(&propagation_source, &kind);
+                        }
                         conn.kind = kind;
                     }
                 }
@@ -3355,6 +4141,7 @@ where
                 invalid_messages,
             } => {
                 // Handle the gossipsub RPC
+                let mut meta = self.tracer.as_ref().map(|_| RpcMeta::from_rpc(&rpc));
 
                 // Handle subscriptions
                 // Update connected peers topics
@@ -3367,10 +4154,23 @@ where
                     self.score_below_threshold(&propagation_source, |pst| pst.graylist_threshold)
                 {
                     debug!("RPC Dropped from greylisted peer {}", propagation_source);
+                    if let (Some(tracer), Some(meta)) = (self.tracer.as_mut(), meta) {
+                        tracer.recv_rpc(&propagation_source, &meta);
+                    }
                     return;
                 }
 
                 // Handle any invalid messages from this peer
+                if let Some(tracer) = self.tracer.as_mut() {
+                    for (raw_message, validation_error) in &invalid_messages {
+                        tracer.reject_message(
+                            None,
+                            &propagation_source,
+                            &raw_message.topic,
+                            RejectReason::ValidationError(*validation_error),
+                        );
+                    }
+                }
                 if self.peer_score.is_some() {
                     for (raw_message, validation_error) in invalid_messages {
                         self.handle_invalid_message(
@@ -3400,7 +4200,13 @@ where
                         warn!("Received more messages than permitted. Ignoring further messages. Processed: {}", count);
                         break;
                     }
-                    self.handle_received_message(raw_message, &propagation_source);
+                    let message_id = self.handle_received_message(raw_message, &propagation_source);
+                    if let Some(meta) = meta.as_mut() {
+                        meta.messages[count].message_id = message_id;
+                    }
+                }
+                if let (Some(tracer), Some(meta)) = (self.tracer.as_mut(), meta) {
+                    tracer.recv_rpc(&propagation_source, &meta);
                 }
 
                 // Handle control messages
@@ -3408,6 +4214,8 @@ where
                 let mut ihave_msgs = vec![];
                 let mut graft_msgs = vec![];
                 let mut prune_msgs = vec![];
+                let mut choke_msgs = vec![];
+                let mut unchoke_msgs = vec![];
                 for control_msg in rpc.control_msgs {
                     match control_msg {
                         GossipsubControlAction::IHave {
@@ -3425,6 +4233,13 @@ where
                             peers,
                             backoff,
                         } => prune_msgs.push((topic_hash, peers, backoff)),
+                        GossipsubControlAction::IDontWant { message_ids } => {
+                            self.handle_idontwant(&propagation_source, message_ids)
+                        }
+                        GossipsubControlAction::Choke { topic_hash } => choke_msgs.push(topic_hash),
+                        GossipsubControlAction::Unchoke { topic_hash } => {
+                            unchoke_msgs.push(topic_hash)
+                        }
                     }
                 }
                 if !ihave_msgs.is_empty() {
@@ -3436,6 +4251,29 @@ where
                 if !prune_msgs.is_empty() {
                     self.handle_prune(&propagation_source, prune_msgs);
                 }
+                if !choke_msgs.is_empty() {
+                    self.handle_choke(&propagation_source, choke_msgs);
+                }
+                if !unchoke_msgs.is_empty() {
+                    self.handle_unchoke(&propagation_source, unchoke_msgs);
+                }
+            }
+            HandlerEvent::MessageDropped { topics, reason } => {
+                debug!(
+                    "Dropped {} messages for peer {}: {:?}",
+                    topics.len(),
+                    propagation_source,
+                    reason
+                );
+                if let Some(metrics) = self.metrics.as_mut() {
+                    for topic in &topics {
+                        metrics.register_send_failure(topic, reason);
+                    }
+                }
+                self.failed_messages
+                    .entry(propagation_source)
+                    .or_default()
+                    .record(reason, topics.len());
             }
         }
     }
@@ -3445,6 +4283,12 @@ where
         cx: &mut Context<'_>,
         _: &mut impl PollParameters,
     ) -> Poll<NetworkBehaviourAction<Self::OutEvent, Self::ConnectionHandler>> {
+        while let Poll::Ready(results) = self.validation_pipeline.poll(cx) {
+            for (pending, acceptance) in results {
+                self.handle_validation_result(pending, acceptance);
+            }
+        }
+
         if let Some(event) = self.events.pop_front() {
             return Poll::Ready(event.map_in(|e: Arc<GossipsubHandlerIn>| {
                 // clone send event reference if others references are present
@@ -3567,6 +4411,8 @@ fn get_random_peers_dynamic(
                 f(p) && match connected_peers.get(p) {
                     Some(connections) if connections.kind == PeerKind::Gossipsub => true,
                     Some(connections) if connections.kind == PeerKind::Gossipsubv1_1 => true,
+                    Some(connections) if connections.kind == PeerKind::Gossipsubv1_2 => true,
+                    Some(connections) if connections.kind == PeerKind::Episub => true,
                     _ => false,
                 }
             })
@@ -29,7 +29,7 @@ mod tests {
     use rand::Rng;
 
     use crate::{
-        GossipsubConfig, GossipsubConfigBuilder, GossipsubMessage, IdentTopic as Topic,
+        GossipsubConfig, GossipsubConfigBuilder, GossipsubMessage, IdentTopic as Topic, RateLimit,
         TopicScoreParams,
     };
 
@@ -37,7 +37,7 @@ mod tests {
     use crate::error::ValidationError;
     use crate::subscription_filter::WhitelistSubscriptionFilter;
     use crate::transform::{DataTransform, IdentityTransform};
-    use crate::types::FastMessageId;
+    use crate::types::{FastMessageId, SendFailure};
     use libp2p_core::Endpoint;
     use std::collections::hash_map::DefaultHasher;
     use std::hash::{Hash, Hasher};
@@ -314,13 +314,13 @@ mod tests {
                     .peers
                     .into_iter()
                     .filter_map(|info| {
-                        info.peer_id
-                            .and_then(|id| PeerId::from_bytes(&id).ok())
-                            .map(|peer_id|
-                                    //TODO signedPeerRecord, see https://github.com/libp2p/specs/pull/217
-                                    PeerInfo {
-                                        peer_id: Some(peer_id),
-                                    })
+                        let peer_id = info.peer_id.and_then(|id| PeerId::from_bytes(&id).ok())?;
+                        Some(PeerInfo {
+                            peer_id: Some(peer_id),
+                            signed_peer_record: info.signed_peer_record.and_then(|bytes| {
+                                crate::protocol::decode_signed_peer_record(&peer_id, &bytes)
+                            }),
+                        })
                     })
                     .collect::<Vec<PeerInfo>>();
 
@@ -332,10 +332,41 @@ mod tests {
                 });
             }
 
+            let idontwant_msgs: Vec<GossipsubControlAction> = rpc_control
+                .idontwant
+                .into_iter()
+                .map(|idontwant| GossipsubControlAction::IDontWant {
+                    message_ids: idontwant
+                        .message_ids
+                        .into_iter()
+                        .map(MessageId::from)
+                        .collect::<Vec<_>>(),
+                })
+                .collect();
+
+            let choke_msgs: Vec<GossipsubControlAction> = rpc_control
+                .choke
+                .into_iter()
+                .map(|choke| GossipsubControlAction::Choke {
+                    topic_hash: TopicHash::from_raw(choke.topic_id.unwrap_or_default()),
+                })
+                .collect();
+
+            let unchoke_msgs: Vec<GossipsubControlAction> = rpc_control
+                .unchoke
+                .into_iter()
+                .map(|unchoke| GossipsubControlAction::Unchoke {
+                    topic_hash: TopicHash::from_raw(unchoke.topic_id.unwrap_or_default()),
+                })
+                .collect();
+
             control_msgs.extend(ihave_msgs);
             control_msgs.extend(iwant_msgs);
             control_msgs.extend(graft_msgs);
             control_msgs.extend(prune_msgs);
+            control_msgs.extend(idontwant_msgs);
+            control_msgs.extend(choke_msgs);
+            control_msgs.extend(unchoke_msgs);
         }
 
         GossipsubRpc {
@@ -945,6 +976,8 @@ mod tests {
                     PeerConnections {
                         kind: PeerKind::Gossipsubv1_1,
                         connections: vec![ConnectionId::new(1)],
+                        dont_send: HashMap::new(),
+                        choking: HashSet::new(),
                     },
                 )
             })
@@ -1827,6 +1860,7 @@ mod tests {
         for _ in 0..config.prune_peers() + 5 {
             px.push(PeerInfo {
                 peer_id: Some(PeerId::random()),
+                signed_peer_record: None,
             });
         }
 
@@ -2518,6 +2552,7 @@ mod tests {
         //handle prune from single peer with px peers
         let px = vec![PeerInfo {
             peer_id: Some(PeerId::random()),
+            signed_peer_record: None,
         }];
 
         gs.handle_prune(
@@ -3122,6 +3157,7 @@ mod tests {
         // Handle prune from peer peers[0] with px peers
         let px = vec![PeerInfo {
             peer_id: Some(PeerId::random()),
+            signed_peer_record: None,
         }];
         gs.handle_prune(
             &peers[0],
@@ -3147,6 +3183,7 @@ mod tests {
         //handle prune from peer peers[1] with px peers
         let px = vec![PeerInfo {
             peer_id: Some(PeerId::random()),
+            signed_peer_record: None,
         }];
         gs.handle_prune(
             &peers[1],
@@ -5366,4 +5403,766 @@ mod tests {
         // We unsubscribe from the topic.
         let _ = gs.unsubscribe(&Topic::new(topic));
     }
+
+    #[test]
+    /// Test that IDONTWANT is sent to gossipsub v1.2 mesh peers for large messages only.
+    fn test_send_idontwant_for_large_messages() {
+        let config = GossipsubConfigBuilder::default()
+            .idontwant_message_size_threshold(100)
+            .build()
+            .unwrap();
+        let (mut gs, _, topic_hashes) = inject_nodes1()
+            .peer_no(0)
+            .topics(vec![String::from("topic")])
+            .to_subscribe(true)
+            .gs_config(config)
+            .create_network();
+
+        let add_peer_of_kind = |gs: &mut Gossipsub, kind| {
+            add_peer_with_addr_and_kind(
+                gs,
+                &topic_hashes,
+                false,
+                false,
+                Multiaddr::empty(),
+                Some(kind),
+            )
+        };
+        let source = add_peer_of_kind(&mut gs, PeerKind::Gossipsubv1_2);
+        let v1_2_peer = add_peer_of_kind(&mut gs, PeerKind::Gossipsubv1_2);
+        let v1_1_peer = add_peer_of_kind(&mut gs, PeerKind::Gossipsubv1_1);
+        for peer in [&source, &v1_2_peer, &v1_1_peer] {
+            assert!(gs.mesh[&topic_hashes[0]].contains(peer));
+        }
+        flush_events(&mut gs);
+
+        let mut message = RawGossipsubMessage {
+            source: Some(PeerId::random()),
+            data: vec![0; 10],
+            sequence_number: Some(0),
+            topic: topic_hashes[0].clone(),
+            signature: None,
+            key: None,
+            validated: true,
+        };
+        gs.handle_received_message(message.clone(), &source);
+        assert_eq!(
+            count_control_msgs(&gs, |_, action| matches!(
+                action,
+                GossipsubControlAction::IDontWant { .. }
+            )),
+            0,
+            "No IDONTWANT should be sent for small messages"
+        );
+
+        message.sequence_number = Some(1);
+        message.data = vec![0; 200];
+        let msg_id = gs.config.message_id(&GossipsubMessage {
+            source: message.source,
+            data: message.data.clone(),
+            sequence_number: message.sequence_number,
+            topic: message.topic.clone(),
+        });
+        gs.handle_received_message(message, &source);
+
+        assert_eq!(
+            count_control_msgs(&gs, |peer_id, action| matches!(
+                action,
+                GossipsubControlAction::IDontWant { message_ids }
+                    if peer_id == &v1_2_peer && message_ids == &vec![msg_id.clone()]
+            )),
+            1,
+            "IDONTWANT should be sent to the gossipsub v1.2 mesh peer"
+        );
+        assert_eq!(
+            count_control_msgs(&gs, |_, action| matches!(
+                action,
+                GossipsubControlAction::IDontWant { .. }
+            )),
+            1,
+            "IDONTWANT should neither be sent to the source nor to gossipsub v1.1 peers"
+        );
+    }
+
+    #[test]
+    /// Test that messages are not forwarded to peers that sent an IDONTWANT for them.
+    fn test_do_not_forward_message_after_idontwant() {
+        let (mut gs, peers, topic_hashes) = inject_nodes1()
+            .peer_no(3)
+            .topics(vec![String::from("topic")])
+            .to_subscribe(true)
+            .create_network();
+        flush_events(&mut gs);
+
+        let message = RawGossipsubMessage {
+            source: Some(PeerId::random()),
+            data: vec![12],
+            sequence_number: Some(0),
+            topic: topic_hashes[0].clone(),
+            signature: None,
+            key: None,
+            validated: true,
+        };
+        let msg_id = gs.config.message_id(&GossipsubMessage {
+            source: message.source,
+            data: message.data.clone(),
+            sequence_number: message.sequence_number,
+            topic: message.topic.clone(),
+        });
+
+        gs.inject_event(
+            peers[1],
+            ConnectionId::new(0),
+            HandlerEvent::Message {
+                rpc: GossipsubRpc {
+                    messages: vec![],
+                    subscriptions: vec![],
+                    control_msgs: vec![GossipsubControlAction::IDontWant {
+                        message_ids: vec![msg_id.clone()],
+                    }],
+                },
+                invalid_messages: vec![],
+            },
+        );
+        assert!(gs.connected_peers[&peers[1]]
+            .dont_send
+            .contains_key(&msg_id));
+
+        gs.handle_received_message(message, &peers[0]);
+
+        let receivers = gs
+            .events
+            .iter()
+            .filter_map(|e| match e {
+                NetworkBehaviourAction::NotifyHandler { peer_id, event, .. } => match **event {
+                    GossipsubHandlerIn::Message(ref m) if !m.publish.is_empty() => Some(*peer_id),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(receivers, vec![peers[2]]);
+    }
+
+    #[test]
+    /// Test that messages the handler could not send are reported once per heartbeat.
+    fn test_slow_peer_reported_on_heartbeat() {
+        let (mut gs, peers, _) = inject_nodes1()
+            .peer_no(2)
+            .topics(vec![String::from("topic")])
+            .to_subscribe(true)
+            .create_network();
+        flush_events(&mut gs);
+
+        for reason in [
+            SendFailure::QueueFull,
+            SendFailure::QueueFull,
+            SendFailure::Timeout,
+        ] {
+            gs.inject_event(
+                peers[0],
+                ConnectionId::new(0),
+                HandlerEvent::MessageDropped {
+                    topics: vec![Topic::new("topic").hash()],
+                    reason,
+                },
+            );
+        }
+
+        gs.heartbeat();
+
+        let slow_peers = gs
+            .events
+            .iter()
+            .filter_map(|e| match e {
+                NetworkBehaviourAction::GenerateEvent(GossipsubEvent::SlowPeer {
+                    peer_id,
+                    failed_messages,
+                }) => Some((*peer_id, *failed_messages)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            slow_peers,
+            vec![(
+                peers[0],
+                FailedMessages {
+                    queue_full: 2,
+                    timeout: 1,
+                }
+            )]
+        );
+
+        // The failures are only reported once.
+        flush_events(&mut gs);
+        gs.heartbeat();
+        assert!(!gs.events.iter().any(|e| matches!(
+            e,
+            NetworkBehaviourAction::GenerateEvent(GossipsubEvent::SlowPeer { .. })
+        )));
+    }
+
+    #[test]
+    fn test_peer_score_changes_reported_on_heartbeat() {
+        let config = GossipsubConfigBuilder::default()
+            .score_change_event_threshold(Some(5.0))
+            .build()
+            .unwrap();
+        let peer_score_params = PeerScoreParams {
+            app_specific_weight: 1.0,
+            ..Default::default()
+        };
+
+        let (mut gs, peers, _) = inject_nodes1()
+            .peer_no(2)
+            .topics(vec!["test".into()])
+            .to_subscribe(true)
+            .gs_config(config)
+            .scoring(Some((peer_score_params, PeerScoreThresholds::default())))
+            .create_network();
+        flush_events(&mut gs);
+
+        let score_changes = |gs: &Gossipsub<IdentityTransform, AllowAllSubscriptionFilter>| {
+            gs.events
+                .iter()
+                .filter_map(|e| match e {
+                    NetworkBehaviourAction::GenerateEvent(GossipsubEvent::PeerScoreChanged {
+                        peer_id,
+                        previous_score,
+                        breakdown,
+                    }) => Some((*peer_id, *previous_score, breakdown.score)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Only changes past the threshold are reported.
+        gs.set_application_score(&peers[0], 10.0);
+        gs.set_application_score(&peers[1], 4.0);
+        gs.heartbeat();
+        assert_eq!(score_changes(&gs), vec![(peers[0], 0.0, 10.0)]);
+
+        // Changes are relative to the last reported score.
+        flush_events(&mut gs);
+        gs.set_application_score(&peers[0], 6.0);
+        gs.set_application_score(&peers[1], 5.0);
+        gs.heartbeat();
+        assert_eq!(score_changes(&gs), vec![(peers[1], 0.0, 5.0)]);
+
+        let snapshot = gs.peer_score_snapshot().unwrap();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[&peers[0]].application_score, 6.0);
+        assert_eq!(snapshot[&peers[1]].application_score, 5.0);
+    }
+
+    #[test]
+    /// Test that messages of topics with a registered validator are only delivered and forwarded
+    /// once accepted by the validator.
+    fn test_registered_validator_validates_messages() {
+        let (mut gs, peers, topic_hashes) = inject_nodes1()
+            .peer_no(3)
+            .topics(vec![String::from("topic")])
+            .to_subscribe(true)
+            .create_network();
+        flush_events(&mut gs);
+
+        gs.register_validator(
+            &Topic::new("topic"),
+            ValidatorConfig {
+                max_batch_size: 2,
+                ..ValidatorConfig::default()
+            },
+            |messages: Vec<GossipsubMessage>| {
+                let acceptances = messages
+                    .iter()
+                    .map(|m| {
+                        if m.data == [0] {
+                            MessageAcceptance::Accept
+                        } else {
+                            MessageAcceptance::Reject
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                futures::FutureExt::boxed(futures::future::ready(acceptances))
+            },
+        )
+        .unwrap();
+
+        let msg_ids = (0..2)
+            .map(|i| {
+                let message = RawGossipsubMessage {
+                    source: Some(PeerId::random()),
+                    data: vec![i],
+                    sequence_number: Some(i as u64),
+                    topic: topic_hashes[0].clone(),
+                    signature: None,
+                    key: None,
+                    validated: true,
+                };
+                gs.handle_received_message(message.clone(), &peers[0]);
+                gs.config.message_id(&GossipsubMessage {
+                    source: message.source,
+                    data: message.data,
+                    sequence_number: message.sequence_number,
+                    topic: message.topic,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Nothing is delivered or forwarded before the validation.
+        assert!(gs.events.is_empty());
+
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        while let Poll::Ready(results) = gs.validation_pipeline.poll(&mut cx) {
+            for (pending, acceptance) in results {
+                gs.handle_validation_result(pending, acceptance);
+            }
+        }
+
+        let delivered = gs
+            .events
+            .iter()
+            .filter_map(|e| match e {
+                NetworkBehaviourAction::GenerateEvent(GossipsubEvent::Message {
+                    message_id,
+                    ..
+                }) => Some(message_id.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(delivered, vec![msg_ids[0].clone()]);
+
+        let mut receivers = gs
+            .events
+            .iter()
+            .filter_map(|e| match e {
+                NetworkBehaviourAction::NotifyHandler { peer_id, event, .. } => match **event {
+                    GossipsubHandlerIn::Message(ref m) if !m.publish.is_empty() => Some(*peer_id),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        receivers.sort();
+        let mut expected = vec![peers[1], peers[2]];
+        expected.sort();
+        assert_eq!(receivers, expected);
+
+        // The rejected message got removed from the cache.
+        assert!(gs.mcache.get(&msg_ids[0]).is_some());
+        assert!(gs.mcache.get(&msg_ids[1]).is_none());
+    }
+
+    #[test]
+    /// Test that publishing beyond the publish rate limit of a topic fails, counting only
+    /// successful publishes.
+    fn test_publish_rate_limit() {
+        let topic = Topic::new("test");
+        let config = GossipsubConfigBuilder::default()
+            .publish_rate_limit(topic.hash(), RateLimit::new(2, Duration::from_secs(60)))
+            .build()
+            .unwrap();
+        let (mut gs, _, _) = inject_nodes1()
+            .peer_no(2)
+            .topics(vec!["test".into(), "other".into()])
+            .to_subscribe(true)
+            .gs_config(config)
+            .create_network();
+
+        assert!(matches!(
+            gs.publish(topic.clone(), vec![0; gs.config.max_transmit_size()]),
+            Err(PublishError::MessageTooLarge)
+        ));
+        assert!(gs.publish(topic.clone(), vec![1]).is_ok());
+        assert!(gs.publish(topic.clone(), vec![2]).is_ok());
+        assert!(matches!(
+            gs.publish(topic, vec![3]),
+            Err(PublishError::RateLimited)
+        ));
+        // Other topics are not limited.
+        assert!(gs.publish(Topic::new("other"), vec![3]).is_ok());
+    }
+
+    #[test]
+    /// Test that messages beyond the inbound rate limit are dropped and penalize the peer, while
+    /// duplicates are not counted.
+    fn test_inbound_rate_limit_penalizes_peer() {
+        let topic = Topic::new("test");
+        let config = GossipsubConfigBuilder::default()
+            .inbound_rate_limit(topic.hash(), RateLimit::new(2, Duration::from_secs(60)))
+            .build()
+            .unwrap();
+        let peer_score_params = PeerScoreParams {
+            behaviour_penalty_weight: -1.0,
+            ..Default::default()
+        };
+        let (mut gs, peers, topic_hashes) = inject_nodes1()
+            .peer_no(2)
+            .topics(vec!["test".into()])
+            .to_subscribe(true)
+            .gs_config(config)
+            .scoring(Some((peer_score_params, PeerScoreThresholds::default())))
+            .create_network();
+        flush_events(&mut gs);
+
+        let source = PeerId::random();
+        for seq in [0, 0, 0, 1, 2, 3, 4] {
+            let message = RawGossipsubMessage {
+                // The source is part of the message id, keep it for the duplicates.
+                source: Some(source),
+                data: vec![seq as u8],
+                sequence_number: Some(seq),
+                topic: topic_hashes[0].clone(),
+                signature: None,
+                key: None,
+                validated: true,
+            };
+            gs.handle_received_message(message, &peers[0]);
+        }
+
+        let delivered = gs
+            .events
+            .iter()
+            .filter(|e| {
+                matches!(
+                    e,
+                    NetworkBehaviourAction::GenerateEvent(GossipsubEvent::Message { .. })
+                )
+            })
+            .count();
+        assert_eq!(delivered, 2);
+        // The three dropped messages add a behaviour penalty of 3, which is squared.
+        assert_eq!(gs.peer_score(&peers[0]), Some(-9.0));
+        assert_eq!(gs.peer_score(&peers[1]), Some(0.0));
+    }
+
+    #[test]
+    /// Test that signed peer records received in PX are used to dial the peers and are sent along
+    /// with them in PX once connected.
+    fn test_signed_peer_records_in_px() {
+        let config = GossipsubConfigBuilder::default()
+            .do_px()
+            .prune_peers(16)
+            .build()
+            .unwrap();
+        let (mut gs, peers, topics) = inject_nodes1()
+            .peer_no(1)
+            .topics(vec!["test".into()])
+            .to_subscribe(true)
+            .gs_config(config.clone())
+            .create_network();
+
+        let keypair = Keypair::generate_ed25519();
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/1234".parse().unwrap();
+        let record = PeerRecord::new(&keypair, vec![address.clone()]).unwrap();
+        let px_peer = record.peer_id();
+
+        gs.handle_prune(
+            &peers[0],
+            vec![(
+                topics[0].clone(),
+                vec![PeerInfo {
+                    peer_id: Some(px_peer),
+                    signed_peer_record: Some(record.clone()),
+                }],
+                Some(config.prune_backoff().as_secs()),
+            )],
+        );
+
+        // The px peer is dialed at the addresses of its record.
+        assert!(gs.events.iter().any(|e| matches!(
+            e,
+            NetworkBehaviourAction::Dial { opts, .. } if opts.get_peer_id() == Some(px_peer)
+        )));
+        assert_eq!(gs.addresses_of_peer(&px_peer), vec![address]);
+
+        // Once connected, its record is sent along with it in PX.
+        gs.inject_connection_established(
+            &px_peer,
+            &ConnectionId::new(1),
+            &ConnectedPoint::Dialer {
+                address: Multiaddr::empty(),
+                role_override: Endpoint::Dialer,
+            },
+            None,
+            0,
+        );
+        gs.inject_event(
+            px_peer,
+            ConnectionId::new(1),
+            HandlerEvent::PeerKind(PeerKind::Gossipsubv1_1),
+        );
+        gs.handle_received_subscriptions(
+            &[GossipsubSubscription {
+                action: GossipsubSubscriptionAction::Subscribe,
+                topic_hash: topics[0].clone(),
+            }],
+            &px_peer,
+        );
+        flush_events(&mut gs);
+        gs.send_graft_prune(
+            HashMap::new(),
+            vec![(peers[0], vec![topics[0].clone()])]
+                .into_iter()
+                .collect(),
+            HashSet::new(),
+        );
+        assert_eq!(
+            count_control_msgs(&gs, |peer_id, m| peer_id == &peers[0]
+                && matches!(
+                    m,
+                    GossipsubControlAction::Prune { peers, .. }
+                        if peers == &vec![PeerInfo {
+                            peer_id: Some(px_peer),
+                            signed_peer_record: Some(record.clone()),
+                        }]
+                )),
+            1
+        );
+
+        // Records are forgotten when the peer disconnects.
+        disconnect_peer(&mut gs, &px_peer);
+        assert!(gs.addresses_of_peer(&px_peer).is_empty());
+    }
+
+    #[test]
+    /// Test that the signed peer records of peers we are not connected to are capped and expire.
+    fn test_unconnected_peer_records_are_limited() {
+        let (mut gs, _, _) = inject_nodes1()
+            .peer_no(1)
+            .topics(vec!["test".into()])
+            .to_subscribe(true)
+            .create_network();
+
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/1234".parse().unwrap();
+        let records = (0..=MAX_UNCONNECTED_PEER_RECORDS)
+            .map(|_| PeerRecord::new(&Keypair::generate_ed25519(), vec![address.clone()]).unwrap())
+            .collect::<Vec<_>>();
+        for record in &records[..MAX_UNCONNECTED_PEER_RECORDS] {
+            assert!(gs.add_peer_record(record.clone()));
+        }
+        assert!(!gs.add_peer_record(records[MAX_UNCONNECTED_PEER_RECORDS].clone()));
+
+        // The record of a peer that connects is kept, the others expire.
+        let connected = records[0].peer_id();
+        gs.inject_connection_established(
+            &connected,
+            &ConnectionId::new(1),
+            &ConnectedPoint::Dialer {
+                address: Multiaddr::empty(),
+                role_override: Endpoint::Dialer,
+            },
+            None,
+            0,
+        );
+        for expiration in gs.peer_record_expirations.values_mut() {
+            *expiration = Instant::now();
+        }
+        gs.heartbeat();
+        assert_eq!(gs.addresses_of_peer(&connected), vec![address]);
+        assert_eq!(gs.peer_records.len(), 1);
+        assert!(gs.peer_record_expirations.is_empty());
+    }
+
+    #[test]
+    /// Test that slow mesh peers are choked via CHOKE, that their announcements are requested
+    /// regardless of the IHAVE limits and that they are unchoked via UNCHOKE once they announce
+    /// messages fast again.
+    fn test_choke_slow_mesh_peers() {
+        let config = GossipsubConfigBuilder::default()
+            .choke_mesh_peers(true)
+            .choke_latency_threshold(Duration::from_millis(20))
+            .unchoke_latency_threshold(Duration::from_millis(10))
+            .choke_min_deliveries(1)
+            .mesh_unchoked_min(2)
+            .max_ihave_messages(1)
+            .build()
+            .unwrap();
+        let (mut gs, peers, topic_hashes) = inject_nodes1()
+            .peer_no(4)
+            .topics(vec![String::from("topic")])
+            .to_subscribe(true)
+            .gs_config(config)
+            .create_network();
+        for peer in &peers {
+            gs.connected_peers.get_mut(peer).unwrap().kind = PeerKind::Episub;
+        }
+        flush_events(&mut gs);
+
+        let message = RawGossipsubMessage {
+            source: Some(PeerId::random()),
+            data: vec![12],
+            sequence_number: Some(0),
+            topic: topic_hashes[0].clone(),
+            signature: None,
+            key: None,
+            validated: true,
+        };
+
+        // The last peer delivers a message well after the first one.
+        gs.handle_received_message(message.clone(), &peers[0]);
+        sleep(Duration::from_millis(30));
+        gs.handle_received_message(message, &peers[3]);
+        gs.heartbeat();
+        assert_eq!(
+            count_control_msgs(&gs, |peer_id, action| matches!(
+                action,
+                GossipsubControlAction::Choke { topic_hash }
+                    if peer_id == &peers[3] && topic_hash == &topic_hashes[0]
+            )),
+            1,
+            "The slow peer should be choked"
+        );
+        assert_eq!(
+            count_control_msgs(&gs, |_, action| matches!(
+                action,
+                GossipsubControlAction::Choke { .. }
+            )),
+            1,
+            "Only the slow peer should be choked"
+        );
+        flush_events(&mut gs);
+
+        // The choked peer announces more messages than a peer may advertise per heartbeat.
+        let msg_ids: Vec<_> = (0..3u8).map(|i| MessageId::new(&[i])).collect();
+        for msg_id in &msg_ids {
+            gs.handle_ihave(
+                &peers[3],
+                vec![(topic_hashes[0].clone(), vec![msg_id.clone()])],
+            );
+        }
+        assert_eq!(
+            count_control_msgs(&gs, |peer_id, action| matches!(
+                action,
+                GossipsubControlAction::IWant { message_ids }
+                    if peer_id == &peers[3] && message_ids.len() == 1
+            )),
+            msg_ids.len(),
+            "All announced messages should be requested"
+        );
+
+        // The choked peer is unchoked after announcing messages first.
+        gs.heartbeat();
+        assert_eq!(
+            count_control_msgs(&gs, |peer_id, action| matches!(
+                action,
+                GossipsubControlAction::Unchoke { topic_hash }
+                    if peer_id == &peers[3] && topic_hash == &topic_hashes[0]
+            )),
+            1,
+            "The choked peer should be unchoked"
+        );
+    }
+
+    #[test]
+    /// Test that messages are only announced via IHAVE to mesh peers that choked us, until they
+    /// unchoke us.
+    fn test_honour_choke() {
+        let (mut gs, peers, topic_hashes) = inject_nodes1()
+            .peer_no(3)
+            .topics(vec![String::from("topic")])
+            .to_subscribe(true)
+            .create_network();
+        for peer in &peers {
+            gs.connected_peers.get_mut(peer).unwrap().kind = PeerKind::Episub;
+        }
+        flush_events(&mut gs);
+
+        let source = PeerId::random();
+        let message = |sequence_number| RawGossipsubMessage {
+            source: Some(source),
+            data: vec![12],
+            sequence_number: Some(sequence_number),
+            topic: topic_hashes[0].clone(),
+            signature: None,
+            key: None,
+            validated: true,
+        };
+        // Returns the peers that were sent the message and the peers that were sent an IHAVE.
+        let receivers = |gs: &mut Gossipsub<IdentityTransform, AllowAllSubscriptionFilter>| {
+            let mut messages = Vec::new();
+            let mut ihaves = Vec::new();
+            for event in gs.events.drain(..) {
+                if let NetworkBehaviourAction::NotifyHandler { peer_id, event, .. } = event {
+                    if let GossipsubHandlerIn::Message(ref m) = *event {
+                        if !m.publish.is_empty() {
+                            messages.push(peer_id);
+                        }
+                        if m.control.as_ref().map_or(false, |c| !c.ihave.is_empty()) {
+                            ihaves.push(peer_id);
+                        }
+                    }
+                }
+            }
+            messages.sort();
+            (messages, ihaves)
+        };
+
+        gs.handle_choke(&peers[2], vec![topic_hashes[0].clone()]);
+        gs.handle_received_message(message(0), &peers[0]);
+        assert_eq!(receivers(&mut gs), (vec![peers[1]], vec![peers[2]]));
+
+        gs.handle_unchoke(&peers[2], vec![topic_hashes[0].clone()]);
+        gs.handle_received_message(message(1), &peers[0]);
+        let mut expected = vec![peers[1], peers[2]];
+        expected.sort();
+        assert_eq!(receivers(&mut gs), (expected, vec![]));
+    }
+
+    #[test]
+    fn test_choke_only_episub_peers() {
+        let config = GossipsubConfigBuilder::default()
+            .choke_mesh_peers(true)
+            .choke_latency_threshold(Duration::from_millis(20))
+            .unchoke_latency_threshold(Duration::from_millis(10))
+            .choke_min_deliveries(1)
+            .mesh_unchoked_min(1)
+            .build()
+            .unwrap();
+        // The peers negotiated gossipsub v1.1, which doesn't support CHOKE.
+        let (mut gs, peers, topic_hashes) = inject_nodes1()
+            .peer_no(3)
+            .topics(vec![String::from("topic")])
+            .to_subscribe(true)
+            .gs_config(config)
+            .create_network();
+        flush_events(&mut gs);
+
+        let message = |sequence_number| RawGossipsubMessage {
+            source: Some(PeerId::random()),
+            data: vec![12],
+            sequence_number: Some(sequence_number),
+            topic: topic_hashes[0].clone(),
+            signature: None,
+            key: None,
+            validated: true,
+        };
+
+        // The last peer delivers a message well after the first one, but isn't choked.
+        gs.handle_received_message(message(0), &peers[0]);
+        sleep(Duration::from_millis(30));
+        gs.handle_received_message(message(0), &peers[2]);
+        gs.heartbeat();
+        assert_eq!(
+            count_control_msgs(&gs, |_, action| matches!(
+                action,
+                GossipsubControlAction::Choke { .. }
+            )),
+            0,
+            "Peers that didn't negotiate episub should never be choked"
+        );
+        flush_events(&mut gs);
+
+        // A CHOKE of a peer that didn't negotiate episub is ignored.
+        gs.handle_choke(&peers[2], vec![topic_hashes[0].clone()]);
+        assert!(gs.connected_peers[&peers[2]].choking.is_empty());
+        gs.handle_received_message(message(1), &peers[0]);
+        assert!(gs.events.iter().any(|event| matches!(
+            event,
+            NetworkBehaviourAction::NotifyHandler { peer_id, event, .. }
+                if peer_id == &peers[2]
+                    && matches!(&**event, GossipsubHandlerIn::Message(m) if !m.publish.is_empty())
+        )));
+    }
 }
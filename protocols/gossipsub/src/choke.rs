@@ -0,0 +1,302 @@
+// Copyright 2022 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Episub-style choking of slow mesh peers. A choked mesh peer is asked via CHOKE to only
+//! announce the messages it forwards to us via IHAVE instead of pushing them, until it is asked
+//! to push them again via UNCHOKE.
+
+use crate::config::GossipsubConfig;
+use crate::time_cache::TimeCache;
+use crate::topic::TopicHash;
+use crate::types::MessageId;
+use libp2p_core::PeerId;
+use log::debug;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::time::Duration;
+use wasm_timer::Instant;
+
+/// Mesh peers along with a topic of their mesh.
+type MeshPeers = Vec<(PeerId, TopicHash)>;
+
+/// The latencies of the deliveries of a mesh peer since they were last evaluated.
+#[derive(Default)]
+struct DeliveryLatencies {
+    deliveries: u32,
+    total: Duration,
+}
+
+impl DeliveryLatencies {
+    fn average(&self) -> Option<Duration> {
+        if self.deliveries == 0 {
+            None
+        } else {
+            Some(self.total / self.deliveries)
+        }
+    }
+}
+
+/// Tracks the delivery latency of mesh peers and decides at the heartbeat which of them to choke
+/// or unchoke.
+pub(crate) struct ChokeTracker {
+    /// The time at which each recent message was first delivered or announced.
+    first_seen: TimeCache<MessageId, Instant>,
+    /// The delivery latencies of the mesh peers of each topic.
+    latencies: HashMap<TopicHash, HashMap<PeerId, DeliveryLatencies>>,
+    /// The mesh peers of each topic we choked.
+    choked: HashMap<TopicHash, HashSet<PeerId>>,
+}
+
+impl ChokeTracker {
+    /// Creates a tracker remembering the first delivery of messages for `ttl`.
+    pub(crate) fn new(ttl: Duration) -> Self {
+        ChokeTracker {
+            first_seen: TimeCache::new(ttl),
+            latencies: HashMap::new(),
+            choked: HashMap::new(),
+        }
+    }
+
+    /// Observes the delivery or announcement of a message, returning the time elapsed since it
+    /// was first observed.
+    pub(crate) fn observe(&mut self, msg_id: &MessageId) -> Duration {
+        let now = Instant::now();
+        let first_seen = self.first_seen.entry(msg_id.clone()).or_insert_with(|| now);
+        now.saturating_duration_since(*first_seen)
+    }
+
+    /// Records the latency of a delivery by a mesh peer. The latency of choked peers is recorded
+    /// for their announcements instead.
+    pub(crate) fn record(&mut self, topic: &TopicHash, peer: &PeerId, latency: Duration) {
+        let latencies = self
+            .latencies
+            .entry(topic.clone())
+            .or_default()
+            .entry(*peer)
+            .or_default();
+        latencies.deliveries += 1;
+        latencies.total += latency;
+    }
+
+    /// Returns true if we choked the mesh peer on the topic.
+    pub(crate) fn is_choked(&self, topic: &TopicHash, peer: &PeerId) -> bool {
+        self.choked
+            .get(topic)
+            .map_or(false, |choked| choked.contains(peer))
+    }
+
+    /// Forgets about a peer that left the mesh of a topic.
+    pub(crate) fn remove_peer(&mut self, topic: &TopicHash, peer: &PeerId) {
+        if let Some(latencies) = self.latencies.get_mut(topic) {
+            latencies.remove(peer);
+        }
+        if let Some(choked) = self.choked.get_mut(topic) {
+            choked.remove(peer);
+        }
+    }
+
+    /// Chokes the mesh peers whose average delivery latency exceeds the choke threshold and
+    /// unchokes the ones whose average latency fell below the unchoke threshold. Peers are only
+    /// evaluated once they made enough deliveries, which are then reset.
+    ///
+    /// Returns the newly choked and the newly unchoked mesh peers with their topic.
+    pub(crate) fn heartbeat(
+        &mut self,
+        mesh: &HashMap<TopicHash, BTreeSet<PeerId>>,
+        config: &GossipsubConfig,
+    ) -> (MeshPeers, MeshPeers) {
+        // Forget about the peers that left the mesh.
+        self.latencies
+            .retain(|topic, latencies| match mesh.get(topic) {
+                Some(mesh_peers) => {
+                    latencies.retain(|peer, _| mesh_peers.contains(peer));
+                    true
+                }
+                None => false,
+            });
+        self.choked.retain(|topic, choked| match mesh.get(topic) {
+            Some(mesh_peers) => {
+                choked.retain(|peer| mesh_peers.contains(peer));
+                true
+            }
+            None => false,
+        });
+
+        let mut to_choke = Vec::new();
+        let mut to_unchoke = Vec::new();
+        for (topic, mesh_peers) in mesh {
+            let latencies = self.latencies.entry(topic.clone()).or_default();
+            let choked = self.choked.entry(topic.clone()).or_default();
+
+            // The average latencies of the peers with enough deliveries to be evaluated.
+            let evaluated: HashMap<PeerId, Duration> = latencies
+                .iter()
+                .filter(|(_, l)| l.deliveries as usize >= config.choke_min_deliveries())
+                .filter_map(|(peer, l)| l.average().map(|average| (*peer, average)))
+                .collect();
+
+            choked.retain(|peer| match evaluated.get(peer) {
+                Some(average) if *average < config.unchoke_latency_threshold() => {
+                    debug!(
+                        "Unchoking peer {} on topic {}, average latency {:?}",
+                        peer, topic, average
+                    );
+                    to_unchoke.push((*peer, topic.clone()));
+                    false
+                }
+                _ => true,
+            });
+
+            let mut slow_peers: Vec<(PeerId, Duration)> = evaluated
+                .iter()
+                .filter(|(peer, average)| {
+                    !choked.contains(peer) && **average > config.choke_latency_threshold()
+                })
+                .map(|(peer, average)| (*peer, *average))
+                .collect();
+            // Choke the slowest peers first.
+            slow_peers.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+            let mut unchoked = mesh_peers.len().saturating_sub(choked.len());
+            for (peer, average) in slow_peers {
+                if unchoked <= config.mesh_unchoked_min() {
+                    break;
+                }
+                debug!(
+                    "Choking peer {} on topic {}, average latency {:?}",
+                    peer, topic, average
+                );
+                choked.insert(peer);
+                to_choke.push((peer, topic.clone()));
+                unchoked -= 1;
+            }
+
+            // Unchoke the fastest choked peers if the mesh shrank below the minimum of unchoked
+            // peers.
+            if unchoked < config.mesh_unchoked_min() && !choked.is_empty() {
+                let mut fastest: Vec<(PeerId, Option<Duration>)> = choked
+                    .iter()
+                    .map(|peer| (*peer, latencies.get(peer).and_then(|l| l.average())))
+                    .collect();
+                fastest.sort_by_key(|(_, average)| average.unwrap_or(Duration::MAX));
+                for (peer, _) in fastest
+                    .into_iter()
+                    .take(config.mesh_unchoked_min() - unchoked)
+                {
+                    debug!(
+                        "Unchoking peer {} on topic {} to fill the mesh",
+                        peer, topic
+                    );
+                    choked.remove(&peer);
+                    to_unchoke.push((peer, topic.clone()));
+                }
+            }
+
+            for peer in evaluated.keys() {
+                latencies.remove(peer);
+            }
+        }
+
+        (to_choke, to_unchoke)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GossipsubConfigBuilder;
+
+    fn config() -> GossipsubConfig {
+        GossipsubConfigBuilder::default()
+            .choke_mesh_peers(true)
+            .choke_latency_threshold(Duration::from_millis(100))
+            .unchoke_latency_threshold(Duration::from_millis(50))
+            .choke_min_deliveries(2)
+            .mesh_unchoked_min(2)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn chokes_and_unchokes_peers() {
+        let config = config();
+        let topic = TopicHash::from_raw("topic");
+        let peers: Vec<PeerId> = (0..4).map(|_| PeerId::random()).collect();
+        let mesh = HashMap::from([(topic.clone(), peers.iter().cloned().collect())]);
+        let mut tracker = ChokeTracker::new(Duration::from_secs(60));
+
+        // The first peer is slow, the second one has not made enough deliveries yet.
+        for _ in 0..2 {
+            tracker.record(&topic, &peers[0], Duration::from_millis(200));
+            tracker.record(&topic, &peers[2], Duration::ZERO);
+            tracker.record(&topic, &peers[3], Duration::from_millis(60));
+        }
+        tracker.record(&topic, &peers[1], Duration::from_millis(200));
+        let (choked, unchoked) = tracker.heartbeat(&mesh, &config);
+        assert_eq!(choked, vec![(peers[0], topic.clone())]);
+        assert!(unchoked.is_empty());
+        assert!(tracker.is_choked(&topic, &peers[0]));
+        assert!(!tracker.is_choked(&topic, &peers[1]));
+        assert!(!tracker.is_choked(&topic, &peers[2]));
+        assert!(!tracker.is_choked(&topic, &peers[3]));
+
+        // Latencies between the thresholds neither choke nor unchoke peers.
+        tracker.record(&topic, &peers[0], Duration::from_millis(60));
+        tracker.record(&topic, &peers[0], Duration::from_millis(60));
+        assert_eq!(tracker.heartbeat(&mesh, &config), (vec![], vec![]));
+        assert!(tracker.is_choked(&topic, &peers[0]));
+
+        tracker.record(&topic, &peers[0], Duration::ZERO);
+        tracker.record(&topic, &peers[0], Duration::ZERO);
+        let (choked, unchoked) = tracker.heartbeat(&mesh, &config);
+        assert!(choked.is_empty());
+        assert_eq!(unchoked, vec![(peers[0], topic.clone())]);
+        assert!(!tracker.is_choked(&topic, &peers[0]));
+    }
+
+    #[test]
+    fn keeps_minimum_of_unchoked_peers() {
+        let config = config();
+        let topic = TopicHash::from_raw("topic");
+        let peers: Vec<PeerId> = (0..3).map(|_| PeerId::random()).collect();
+        let mut mesh = HashMap::from([(topic.clone(), peers.iter().cloned().collect())]);
+        let mut tracker = ChokeTracker::new(Duration::from_secs(60));
+
+        for peer in &peers {
+            tracker.record(&topic, peer, Duration::from_millis(200));
+            tracker.record(&topic, peer, Duration::from_millis(200));
+        }
+        tracker.heartbeat(&mesh, &config);
+        let choked: Vec<_> = peers
+            .iter()
+            .filter(|peer| tracker.is_choked(&topic, peer))
+            .collect();
+        assert_eq!(choked.len(), 1);
+
+        // Removing an unchoked peer from the mesh unchokes the choked one.
+        let unchoked = peers
+            .iter()
+            .find(|peer| !tracker.is_choked(&topic, peer))
+            .unwrap();
+        mesh.get_mut(&topic).unwrap().remove(unchoked);
+        let (_, unchoked) = tracker.heartbeat(&mesh, &config);
+        assert_eq!(unchoked, vec![(*choked[0], topic.clone())]);
+        assert!(!tracker.is_choked(&topic, choked[0]));
+    }
+}
@@ -19,12 +19,15 @@
 // DEALINGS IN THE SOFTWARE.
 
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 
 use libp2p_core::PeerId;
 
+use crate::rate_limit::RateLimit;
 use crate::types::{FastMessageId, GossipsubMessage, MessageId, RawGossipsubMessage};
+use crate::TopicHash;
 
 /// The types of message validation that can be employed by gossipsub.
 #[derive(Debug, Clone)]
@@ -91,16 +94,29 @@ pub struct GossipsubConfig {
     iwant_followup_time: Duration,
     support_floodsub: bool,
     published_message_ids_cache_time: Duration,
+    idontwant_message_size_threshold: usize,
+    send_queue_len: usize,
+    send_queue_timeout: Duration,
+    mcache_max_bytes: Option<usize>,
+    mcache_max_topic_bytes: Option<usize>,
+    score_change_event_threshold: Option<f64>,
+    choke_mesh_peers: bool,
+    choke_latency_threshold: Duration,
+    unchoke_latency_threshold: Duration,
+    choke_min_deliveries: usize,
+    mesh_unchoked_min: usize,
+    publish_rate_limits: HashMap<TopicHash, RateLimit>,
+    inbound_rate_limits: HashMap<TopicHash, RateLimit>,
 }
 
 impl GossipsubConfig {
     // All the getters
 
     /// The protocol id prefix to negotiate this protocol. The protocol id is of the form
-    /// `/<prefix>/<supported-versions>`. As gossipsub supports version 1.0 and 1.1, there are two
-    /// protocol id's supported.
+    /// `/<prefix>/<supported-versions>`. As gossipsub supports version 1.0, 1.1 and 1.2, there are
+    /// three protocol id's supported.
     ///
-    /// The default prefix is `meshsub`, giving the supported protocol ids: `/meshsub/1.1.0` and `/meshsub/1.0.0`, negotiated in that order.
+    /// The default prefix is `meshsub`, giving the supported protocol ids: `/meshsub/1.2.0+episub`, `/meshsub/1.2.0`, `/meshsub/1.1.0` and `/meshsub/1.0.0`, negotiated in that order.
     pub fn protocol_id_prefix(&self) -> &Cow<'static, str> {
         &self.protocol_id_prefix
     }
@@ -261,8 +277,7 @@ impl GossipsubConfig {
     /// When we prune a peer that's eligible for PX (has a good score, etc), we will try to
     /// send them signed peer records for up to `prune_peers` other peers that we
     /// know of. It is recommended that this value is larger than `mesh_n_high` so that the pruned
-    /// peer can reliably form a full mesh. The default is typically 16 however until signed
-    /// records are spec'd this is disabled and set to 0.
+    /// peer can reliably form a full mesh. The default is 0, i.e. peer exchange is disabled.
     pub fn prune_peers(&self) -> usize {
         self.prune_peers
     }
@@ -374,6 +389,97 @@ impl GossipsubConfig {
     pub fn published_message_ids_cache_time(&self) -> Duration {
         self.published_message_ids_cache_time
     }
+
+    /// The minimum encoded size in bytes of a received message for which we send IDONTWANT
+    /// control messages to our gossipsub v1.2 mesh peers, asking them not to forward the message
+    /// to us. The default is 1000 bytes.
+    pub fn idontwant_message_size_threshold(&self) -> usize {
+        self.idontwant_message_size_threshold
+    }
+
+    /// The maximum number of published and forwarded messages queued for sending on a single
+    /// connection. Further messages are dropped until the peer catches up. Control messages are
+    /// bounded by the same limit separately and are always sent first. The default is 5000.
+    pub fn send_queue_len(&self) -> usize {
+        self.send_queue_len
+    }
+
+    /// The maximum time a published or forwarded message waits in the send queue of a connection
+    /// before it is dropped. The default is 5 seconds.
+    pub fn send_queue_timeout(&self) -> Duration {
+        self.send_queue_timeout
+    }
+
+    /// The maximum number of payload bytes held in the message cache. When exceeded, the oldest
+    /// messages are evicted from the cache and no longer gossiped or served to IWANT requests.
+    /// Messages awaiting validation are always cached and not evicted. The default is `None`
+    /// (unbounded).
+    pub fn mcache_max_bytes(&self) -> Option<usize> {
+        self.mcache_max_bytes
+    }
+
+    /// The maximum number of payload bytes held in the message cache per topic. When exceeded,
+    /// the oldest messages of the topic are evicted from the cache. The default is `None`
+    /// (unbounded).
+    pub fn mcache_max_topic_bytes(&self) -> Option<usize> {
+        self.mcache_max_topic_bytes
+    }
+
+    /// The minimum change of the score of a connected peer, since it was last reported, for which
+    /// a [`crate::GossipsubEvent::PeerScoreChanged`] is emitted at the heartbeat. Requires peer
+    /// scoring to be activated. The default is `None` (no events are emitted).
+    pub fn score_change_event_threshold(&self) -> Option<f64> {
+        self.score_change_event_threshold
+    }
+
+    /// Whether to choke slow mesh peers. A choked mesh peer remains in the mesh, but is asked via
+    /// CHOKE to only announce the messages it forwards to us via IHAVE, which we request via IWANT
+    /// if needed. Mesh peers are choked and unchoked at the heartbeat, based on the latency of
+    /// their deliveries and announcements relative to the first delivery of each message. Only
+    /// peers that negotiated the episub protocol are choked. Regardless of this setting, chokes of
+    /// episub peers are honoured. The default is false.
+    pub fn choke_mesh_peers(&self) -> bool {
+        self.choke_mesh_peers
+    }
+
+    /// The average latency of the deliveries of a mesh peer, relative to the first delivery of
+    /// each message, above which the peer is choked. The default is 250 milliseconds.
+    pub fn choke_latency_threshold(&self) -> Duration {
+        self.choke_latency_threshold
+    }
+
+    /// The average latency of the deliveries of a choked mesh peer, relative to the first delivery
+    /// of each message, below which the peer is unchoked. Must not exceed
+    /// `choke_latency_threshold`. The default is 100 milliseconds.
+    pub fn unchoke_latency_threshold(&self) -> Duration {
+        self.unchoke_latency_threshold
+    }
+
+    /// The minimum number of deliveries of a mesh peer on a topic from which its average latency
+    /// is evaluated at the heartbeat, to choke or unchoke it. The default is 10.
+    pub fn choke_min_deliveries(&self) -> usize {
+        self.choke_min_deliveries
+    }
+
+    /// The minimum number of unchoked peers kept in the mesh of each topic. Must not exceed
+    /// `mesh_n_low`. The default is 2.
+    pub fn mesh_unchoked_min(&self) -> usize {
+        self.mesh_unchoked_min
+    }
+
+    /// The rate limit of messages published to a topic. Publishing beyond the limit fails with
+    /// [`crate::error::PublishError::RateLimited`]. Only successful publishes count towards the
+    /// limit. By default, publishing is not rate limited.
+    pub fn publish_rate_limit(&self, topic: &TopicHash) -> Option<RateLimit> {
+        self.publish_rate_limits.get(topic).copied()
+    }
+
+    /// The rate limit of messages received from a single peer on a topic. Messages beyond the limit
+    /// are dropped and each of them adds to the behaviour penalty (P₇) of the peer. Duplicates
+    /// are not counted. By default, received messages are not rate limited.
+    pub fn inbound_rate_limit(&self, topic: &TopicHash) -> Option<RateLimit> {
+        self.inbound_rate_limits.get(topic).copied()
+    }
 }
 
 impl Default for GossipsubConfig {
@@ -429,7 +535,7 @@ impl Default for GossipsubConfigBuilder {
                 fast_message_id_fn: None,
                 allow_self_origin: false,
                 do_px: false,
-                prune_peers: 0, // NOTE: Increasing this currently has little effect until Signed records are implemented.
+                prune_peers: 0,
                 prune_backoff: Duration::from_secs(60),
                 unsubscribe_backoff: Duration::from_secs(10),
                 backoff_slack: 1,
@@ -445,6 +551,19 @@ impl Default for GossipsubConfigBuilder {
                 iwant_followup_time: Duration::from_secs(3),
                 support_floodsub: false,
                 published_message_ids_cache_time: Duration::from_secs(10),
+                idontwant_message_size_threshold: 1000,
+                send_queue_len: 5000,
+                send_queue_timeout: Duration::from_secs(5),
+                mcache_max_bytes: None,
+                mcache_max_topic_bytes: None,
+                score_change_event_threshold: None,
+                choke_mesh_peers: false,
+                choke_latency_threshold: Duration::from_millis(250),
+                unchoke_latency_threshold: Duration::from_millis(100),
+                choke_min_deliveries: 10,
+                mesh_unchoked_min: 2,
+                publish_rate_limits: HashMap::new(),
+                inbound_rate_limits: HashMap::new(),
             },
         }
     }
@@ -630,7 +749,8 @@ impl GossipsubConfigBuilder {
     /// When we prune a peer that's eligible for PX (has a good score, etc), we will try to
     /// send them signed peer records for up to [`Self::prune_peers] other peers that we
     /// know of. It is recommended that this value is larger than [`Self::mesh_n_high`] so that the
-    /// pruned peer can reliably form a full mesh. The default is 16.
+    /// pruned peer can reliably form a full mesh. The default is 0, i.e. peer exchange is
+    /// disabled.
     pub fn prune_peers(&mut self, prune_peers: usize) -> &mut Self {
         self.config.prune_peers = prune_peers;
         self
@@ -770,6 +890,113 @@ impl GossipsubConfigBuilder {
         self
     }
 
+    /// The minimum encoded size in bytes of a received message for which we send IDONTWANT
+    /// control messages to our gossipsub v1.2 mesh peers, asking them not to forward the message
+    /// to us. The default is 1000 bytes.
+    pub fn idontwant_message_size_threshold(
+        &mut self,
+        idontwant_message_size_threshold: usize,
+    ) -> &mut Self {
+        self.config.idontwant_message_size_threshold = idontwant_message_size_threshold;
+        self
+    }
+
+    /// The maximum number of published and forwarded messages queued for sending on a single
+    /// connection. Further messages are dropped until the peer catches up. Control messages are
+    /// bounded by the same limit separately and are always sent first. The default is 5000.
+    pub fn send_queue_len(&mut self, send_queue_len: usize) -> &mut Self {
+        self.config.send_queue_len = send_queue_len;
+        self
+    }
+
+    /// The maximum time a published or forwarded message waits in the send queue of a connection
+    /// before it is dropped. The default is 5 seconds.
+    pub fn send_queue_timeout(&mut self, send_queue_timeout: Duration) -> &mut Self {
+        self.config.send_queue_timeout = send_queue_timeout;
+        self
+    }
+
+    /// The maximum number of payload bytes held in the message cache. When exceeded, the oldest
+    /// messages are evicted from the cache and no longer gossiped or served to IWANT requests.
+    /// Messages awaiting validation are always cached and not evicted. The default is `None`
+    /// (unbounded).
+    pub fn mcache_max_bytes(&mut self, mcache_max_bytes: Option<usize>) -> &mut Self {
+        self.config.mcache_max_bytes = mcache_max_bytes;
+        self
+    }
+
+    /// The maximum number of payload bytes held in the message cache per topic. When exceeded,
+    /// the oldest messages of the topic are evicted from the cache. The default is `None`
+    /// (unbounded).
+    pub fn mcache_max_topic_bytes(&mut self, mcache_max_topic_bytes: Option<usize>) -> &mut Self {
+        self.config.mcache_max_topic_bytes = mcache_max_topic_bytes;
+        self
+    }
+
+    /// The minimum change of the score of a connected peer, since it was last reported, for which
+    /// a [`crate::GossipsubEvent::PeerScoreChanged`] is emitted at the heartbeat. Requires peer
+    /// scoring to be activated. The default is `None` (no events are emitted).
+    pub fn score_change_event_threshold(&mut self, threshold: Option<f64>) -> &mut Self {
+        self.config.score_change_event_threshold = threshold;
+        self
+    }
+
+    /// Whether to choke slow mesh peers. A choked mesh peer remains in the mesh, but is asked via
+    /// CHOKE to only announce the messages it forwards to us via IHAVE, which we request via IWANT
+    /// if needed. Mesh peers are choked and unchoked at the heartbeat, based on the latency of
+    /// their deliveries and announcements relative to the first delivery of each message. Only
+    /// peers that negotiated the episub protocol are choked. Regardless of this setting, chokes of
+    /// episub peers are honoured. The default is false.
+    pub fn choke_mesh_peers(&mut self, choke_mesh_peers: bool) -> &mut Self {
+        self.config.choke_mesh_peers = choke_mesh_peers;
+        self
+    }
+
+    /// The average latency of the deliveries of a mesh peer, relative to the first delivery of
+    /// each message, above which the peer is choked. The default is 250 milliseconds.
+    pub fn choke_latency_threshold(&mut self, choke_latency_threshold: Duration) -> &mut Self {
+        self.config.choke_latency_threshold = choke_latency_threshold;
+        self
+    }
+
+    /// The average latency of the deliveries of a choked mesh peer, relative to the first delivery
+    /// of each message, below which the peer is unchoked. Must not exceed
+    /// `choke_latency_threshold`. The default is 100 milliseconds.
+    pub fn unchoke_latency_threshold(&mut self, unchoke_latency_threshold: Duration) -> &mut Self {
+        self.config.unchoke_latency_threshold = unchoke_latency_threshold;
+        self
+    }
+
+    /// The minimum number of deliveries of a mesh peer on a topic from which its average latency
+    /// is evaluated at the heartbeat, to choke or unchoke it. The default is 10.
+    pub fn choke_min_deliveries(&mut self, choke_min_deliveries: usize) -> &mut Self {
+        self.config.choke_min_deliveries = choke_min_deliveries;
+        self
+    }
+
+    /// The minimum number of unchoked peers kept in the mesh of each topic. Must not exceed
+    /// `mesh_n_low`. The default is 2.
+    pub fn mesh_unchoked_min(&mut self, mesh_unchoked_min: usize) -> &mut Self {
+        self.config.mesh_unchoked_min = mesh_unchoked_min;
+        self
+    }
+
+    /// The rate limit of messages published to a topic. Publishing beyond the limit fails with
+    /// [`crate::error::PublishError::RateLimited`]. Only successful publishes count towards the
+    /// limit. By default, publishing is not rate limited.
+    pub fn publish_rate_limit(&mut self, topic: TopicHash, limit: RateLimit) -> &mut Self {
+        self.config.publish_rate_limits.insert(topic, limit);
+        self
+    }
+
+    /// The rate limit of messages received from a single peer on a topic. Messages beyond the limit
+    /// are dropped and each of them adds to the behaviour penalty (P₇) of the peer. Duplicates
+    /// are not counted. By default, received messages are not rate limited.
+    pub fn inbound_rate_limit(&mut self, topic: TopicHash, limit: RateLimit) -> &mut Self {
+        self.config.inbound_rate_limits.insert(topic, limit);
+        self
+    }
+
     /// Constructs a [`GossipsubConfig`] from the given configuration and validates the settings.
     pub fn build(&self) -> Result<GossipsubConfig, &'static str> {
         // check all constraints on config
@@ -803,6 +1030,44 @@ impl GossipsubConfigBuilder {
             return Err("The unsubscribe_backoff parameter should be positive.");
         }
 
+        if self.config.send_queue_len == 0 {
+            return Err("The send_queue_len parameter should be positive.");
+        }
+
+        if let Some(threshold) = self.config.score_change_event_threshold {
+            if threshold <= 0.0 {
+                return Err("The score_change_event_threshold parameter should be positive.");
+            }
+        }
+
+        if self.config.choke_mesh_peers {
+            if self.config.unchoke_latency_threshold > self.config.choke_latency_threshold {
+                return Err("The following inequality doesn't hold \
+                    unchoke_latency_threshold <= choke_latency_threshold");
+            }
+
+            if self.config.choke_min_deliveries == 0 {
+                return Err("The choke_min_deliveries parameter should be positive.");
+            }
+
+            if !(1 <= self.config.mesh_unchoked_min
+                && self.config.mesh_unchoked_min <= self.config.mesh_n_low)
+            {
+                return Err(
+                    "The following inequality doesn't hold 1 <= mesh_unchoked_min <= mesh_n_low",
+                );
+            }
+        }
+
+        for limit in self
+            .config
+            .publish_rate_limits
+            .values()
+            .chain(self.config.inbound_rate_limits.values())
+        {
+            limit.validate()?;
+        }
+
         Ok(self.config.clone())
     }
 }
@@ -846,6 +1111,25 @@ impl std::fmt::Debug for GossipsubConfig {
             "published_message_ids_cache_time",
             &self.published_message_ids_cache_time,
         );
+        let _ = builder.field(
+            "idontwant_message_size_threshold",
+            &self.idontwant_message_size_threshold,
+        );
+        let _ = builder.field("send_queue_len", &self.send_queue_len);
+        let _ = builder.field("send_queue_timeout", &self.send_queue_timeout);
+        let _ = builder.field("mcache_max_bytes", &self.mcache_max_bytes);
+        let _ = builder.field("mcache_max_topic_bytes", &self.mcache_max_topic_bytes);
+        let _ = builder.field(
+            "score_change_event_threshold",
+            &self.score_change_event_threshold,
+        );
+        let _ = builder.field("choke_mesh_peers", &self.choke_mesh_peers);
+        let _ = builder.field("choke_latency_threshold", &self.choke_latency_threshold);
+        let _ = builder.field("unchoke_latency_threshold", &self.unchoke_latency_threshold);
+        let _ = builder.field("choke_min_deliveries", &self.choke_min_deliveries);
+        let _ = builder.field("mesh_unchoked_min", &self.mesh_unchoked_min);
+        let _ = builder.field("publish_rate_limits", &self.publish_rate_limits);
+        let _ = builder.field("inbound_rate_limits", &self.inbound_rate_limits);
         builder.finish()
     }
 }
@@ -38,6 +38,8 @@ pub enum PublishError {
     MessageTooLarge,
     /// The compression algorithm failed.
     TransformFailed(std::io::Error),
+    /// The publish rate limit of the topic has been reached.
+    RateLimited,
 }
 
 impl std::fmt::Display for PublishError {
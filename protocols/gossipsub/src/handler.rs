@@ -21,7 +21,8 @@
 use crate::config::ValidationMode;
 use crate::error::{GossipsubHandlerError, ValidationError};
 use crate::protocol::{GossipsubCodec, ProtocolConfig};
-use crate::types::{GossipsubRpc, PeerKind, RawGossipsubMessage};
+use crate::topic::TopicHash;
+use crate::types::{GossipsubRpc, PeerKind, RawGossipsubMessage, SendFailure};
 use asynchronous_codec::Framed;
 use futures::prelude::*;
 use futures::StreamExt;
@@ -32,8 +33,7 @@ use libp2p_swarm::handler::{
     SubstreamProtocol,
 };
 use libp2p_swarm::NegotiatedSubstream;
-use log::{error, trace, warn};
-use smallvec::SmallVec;
+use log::{debug, error, trace, warn};
 use std::{
     collections::VecDeque,
     io,
@@ -41,6 +41,7 @@ use std::{
     task::{Context, Poll},
     time::Duration,
 };
+use wasm_timer::Delay;
 
 /// The initial time (in seconds) we set the keep alive for protocol negotiations to occur.
 const INITIAL_KEEP_ALIVE: u64 = 30;
@@ -61,6 +62,14 @@ pub enum HandlerEvent {
     /// An inbound or outbound substream has been established with the peer and this informs over
     /// which protocol. This message only occurs once per connection.
     PeerKind(PeerKind),
+    /// An RPC containing published or forwarded messages was dropped without being sent, as the
+    /// peer did not keep up.
+    MessageDropped {
+        /// The topic of each message that was not sent.
+        topics: Vec<TopicHash>,
+        /// The reason the RPC was dropped.
+        reason: SendFailure,
+    },
 }
 
 /// A message sent from the behaviour to the handler.
@@ -81,6 +90,10 @@ pub enum GossipsubHandlerIn {
 /// connection faulty and disconnect. This also prevents against potential substream creation loops.
 const MAX_SUBSTREAM_CREATION: usize = 5;
 
+/// The maximum number of dropped RPCs awaiting their report to the behaviour. Further dropped RPCs
+/// are not reported.
+const MAX_DROPPED_MESSAGES: usize = 1000;
+
 /// Protocol Handler that manages a single long-lived substream with a peer.
 pub struct GossipsubHandler {
     /// Upgrade configuration for the gossipsub protocol.
@@ -93,7 +106,10 @@ pub struct GossipsubHandler {
     inbound_substream: Option<InboundSubstreamState>,
 
     /// Queue of values that we want to send to the remote.
-    send_queue: SmallVec<[crate::rpc_proto::Rpc; 16]>,
+    send_queue: SendQueue,
+
+    /// RPCs dropped from the send queue that have yet to be reported to the behaviour.
+    dropped_messages: VecDeque<(Vec<TopicHash>, SendFailure)>,
 
     /// Flag indicating that an outbound substream is being established to prevent duplicate
     /// requests.
@@ -134,6 +150,101 @@ pub struct GossipsubHandler {
     in_mesh: bool,
 }
 
+/// Queue of the RPCs to send to the remote.
+///
+/// RPCs only carrying control messages and subscriptions have priority over RPCs carrying
+/// published or forwarded messages. Both are bounded separately and the latter are dropped once
+/// they waited in the queue for too long.
+struct SendQueue {
+    /// RPCs without messages, sent first.
+    priority: VecDeque<crate::rpc_proto::Rpc>,
+    /// RPCs with messages, along with the time they were queued.
+    non_priority: VecDeque<(crate::rpc_proto::Rpc, Instant)>,
+    /// The maximum number of queued RPCs of each kind.
+    max_len: usize,
+    /// The time after which a queued RPC with messages is dropped.
+    timeout: Duration,
+    /// Fires at the deadline of the oldest RPC with messages, along with that deadline.
+    expiry: Option<(Instant, Delay)>,
+}
+
+impl SendQueue {
+    fn new(max_len: usize, timeout: Duration) -> Self {
+        SendQueue {
+            priority: VecDeque::new(),
+            non_priority: VecDeque::new(),
+            max_len,
+            timeout,
+            expiry: None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.priority.is_empty() && self.non_priority.is_empty()
+    }
+
+    /// Queues an RPC, returning it if it has to be dropped as the queue is full.
+    fn push(&mut self, rpc: crate::rpc_proto::Rpc) -> Option<crate::rpc_proto::Rpc> {
+        if rpc.publish.is_empty() {
+            if self.priority.len() >= self.max_len {
+                return Some(rpc);
+            }
+            self.priority.push_back(rpc);
+        } else if self.non_priority.len() < self.max_len {
+            self.non_priority.push_back((rpc, Instant::now()));
+        } else {
+            return Some(rpc);
+        }
+        None
+    }
+
+    /// Puts an RPC that could not be sent back to the front of the queue.
+    fn push_front(&mut self, rpc: crate::rpc_proto::Rpc) {
+        if rpc.publish.is_empty() {
+            self.priority.push_front(rpc);
+        } else {
+            self.non_priority.push_front((rpc, Instant::now()));
+        }
+    }
+
+    /// Removes the oldest RPC if it timed out. Otherwise, the task is woken up once it does.
+    fn poll_expired(&mut self, cx: &mut Context<'_>) -> Option<crate::rpc_proto::Rpc> {
+        loop {
+            let deadline = match self.non_priority.front() {
+                Some((_, queued)) => *queued + self.timeout,
+                None => {
+                    self.expiry = None;
+                    return None;
+                }
+            };
+            let now = Instant::now();
+            if deadline <= now {
+                return self.non_priority.pop_front().map(|(rpc, _)| rpc);
+            }
+
+            let delay = match &mut self.expiry {
+                Some((armed, delay)) if *armed == deadline => delay,
+                expiry => {
+                    let (_, delay) = expiry.insert((deadline, Delay::new(deadline - now)));
+                    delay
+                }
+            };
+            if delay.poll_unpin(cx).is_pending() {
+                return None;
+            }
+            // The delay fired, re-check the deadline against the clock.
+            self.expiry = None;
+        }
+    }
+
+    /// Removes the next RPC to send.
+    fn pop(&mut self) -> Option<crate::rpc_proto::Rpc> {
+        self.priority
+            .pop_front()
+            .or_else(|| self.non_priority.pop_front().map(|(rpc, _)| rpc))
+    }
+}
+
 /// State of the inbound substream, opened either by us or by the remote.
 enum InboundSubstreamState {
     /// Waiting for a message from the remote. The idle state for an inbound substream.
@@ -161,7 +272,28 @@ enum OutboundSubstreamState {
     Poisoned,
 }
 
+/// Returns the topic of each message in a dropped RPC.
+fn dropped_topics(rpc: &crate::rpc_proto::Rpc) -> Vec<TopicHash> {
+    rpc.publish
+        .iter()
+        .map(|message| TopicHash::from_raw(message.topic.clone()))
+        .collect()
+}
+
 impl GossipsubHandler {
+    /// Queues the report of a dropped RPC to the behaviour, if it carries messages.
+    fn report_dropped(&mut self, rpc: &crate::rpc_proto::Rpc, reason: SendFailure) {
+        if rpc.publish.is_empty() {
+            return;
+        }
+        if self.dropped_messages.len() >= MAX_DROPPED_MESSAGES {
+            debug!("Too many dropped messages awaiting their report, not reporting message");
+            return;
+        }
+        self.dropped_messages
+            .push_back((dropped_topics(rpc), reason));
+    }
+
     /// Builds a new [`GossipsubHandler`].
     pub fn new(
         protocol_id_prefix: std::borrow::Cow<'static, str>,
@@ -169,6 +301,8 @@ impl GossipsubHandler {
         validation_mode: ValidationMode,
         idle_timeout: Duration,
         support_floodsub: bool,
+        send_queue_len: usize,
+        send_queue_timeout: Duration,
     ) -> Self {
         GossipsubHandler {
             listen_protocol: SubstreamProtocol::new(
@@ -185,7 +319,8 @@ impl GossipsubHandler {
             outbound_substream_establishing: false,
             outbound_substreams_created: 0,
             inbound_substreams_created: 0,
-            send_queue: SmallVec::new(),
+            send_queue: SendQueue::new(send_queue_len, send_queue_timeout),
+            dropped_messages: VecDeque::new(),
             peer_kind: None,
             peer_kind_sent: false,
             protocol_unsupported: false,
@@ -259,7 +394,7 @@ impl ConnectionHandler for GossipsubHandler {
         if self.outbound_substream.is_some() {
             warn!("Established an outbound substream with one already available");
             // Add the message back to the send queue
-            self.send_queue.push(message);
+            self.send_queue.push_front(message);
         } else {
             self.outbound_substream = Some(OutboundSubstreamState::PendingSend(substream, message));
         }
@@ -268,7 +403,12 @@ impl ConnectionHandler for GossipsubHandler {
     fn inject_event(&mut self, message: GossipsubHandlerIn) {
         if !self.protocol_unsupported {
             match message {
-                GossipsubHandlerIn::Message(m) => self.send_queue.push(m),
+                GossipsubHandlerIn::Message(m) => {
+                    if let Some(m) = self.send_queue.push(m) {
+                        debug!("Send queue full, dropping message");
+                        self.report_dropped(&m, SendFailure::QueueFull);
+                    }
+                }
                 // If we have joined the mesh, keep the connection alive.
                 GossipsubHandlerIn::JoinedMesh => {
                     self.in_mesh = true;
@@ -317,6 +457,11 @@ impl ConnectionHandler for GossipsubHandler {
                 ConnectionHandlerUpgrErr::Timeout | ConnectionHandlerUpgrErr::Timer => {
                     Some(GossipsubHandlerError::NegotiationTimeout)
                 }
+                // The substream was rejected locally, keep the connection.
+                ConnectionHandlerUpgrErr::ResourceLimitExceeded(e) => {
+                    debug!("Substream exceeded resource limits: {}", e);
+                    None
+                }
                 // There was an error post negotiation, close the connection.
                 ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Apply(e)) => Some(e),
                 ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Select(negotiation_error)) => {
@@ -359,6 +504,17 @@ impl ConnectionHandler for GossipsubHandler {
             }
         }
 
+        // Report messages that could not be sent.
+        if let Some(rpc) = self.send_queue.poll_expired(cx) {
+            debug!("Message timed out in the send queue, dropping message");
+            self.report_dropped(&rpc, SendFailure::Timeout);
+        }
+        if let Some((topics, reason)) = self.dropped_messages.pop_front() {
+            return Poll::Ready(ConnectionHandlerEvent::Custom(
+                HandlerEvent::MessageDropped { topics, reason },
+            ));
+        }
+
         if self.inbound_substreams_created > MAX_SUBSTREAM_CREATION {
             // Too many inbound substreams have been created, end the connection.
             return Poll::Ready(ConnectionHandlerEvent::Close(
@@ -376,8 +532,7 @@ impl ConnectionHandler for GossipsubHandler {
                     GossipsubHandlerError::MaxOutboundSubstreams,
                 ));
             }
-            let message = self.send_queue.remove(0);
-            self.send_queue.shrink_to_fit();
+            let message = self.send_queue.pop().expect("send queue not to be empty");
             self.outbound_substream_establishing = true;
             return Poll::Ready(ConnectionHandlerEvent::OutboundSubstreamRequest {
                 protocol: self.listen_protocol.clone().map_info(|()| message),
@@ -471,9 +626,7 @@ impl ConnectionHandler for GossipsubHandler {
             ) {
                 // outbound idle state
                 Some(OutboundSubstreamState::WaitingOutput(substream)) => {
-                    if !self.send_queue.is_empty() {
-                        let message = self.send_queue.remove(0);
-                        self.send_queue.shrink_to_fit();
+                    if let Some(message) = self.send_queue.pop() {
                         self.outbound_substream =
                             Some(OutboundSubstreamState::PendingSend(substream, message));
                     } else {
@@ -576,3 +729,77 @@ impl ConnectionHandler for GossipsubHandler {
         Poll::Pending
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_proto;
+
+    fn control_rpc() -> rpc_proto::Rpc {
+        rpc_proto::Rpc {
+            subscriptions: Vec::new(),
+            publish: Vec::new(),
+            control: Some(rpc_proto::ControlMessage::default()),
+        }
+    }
+
+    fn message_rpc(data: u8) -> rpc_proto::Rpc {
+        rpc_proto::Rpc {
+            subscriptions: Vec::new(),
+            publish: vec![rpc_proto::Message {
+                from: None,
+                data: Some(vec![data]),
+                seqno: None,
+                topic: String::from("topic"),
+                signature: None,
+                key: None,
+            }],
+            control: None,
+        }
+    }
+
+    #[test]
+    fn send_queue_sends_control_messages_first() {
+        let mut queue = SendQueue::new(2, Duration::from_secs(5));
+
+        assert_eq!(queue.push(message_rpc(1)), None);
+        assert_eq!(queue.push(message_rpc(2)), None);
+        assert_eq!(queue.push(control_rpc()), None);
+
+        assert_eq!(queue.pop(), Some(control_rpc()));
+        assert_eq!(queue.pop(), Some(message_rpc(1)));
+        queue.push_front(message_rpc(1));
+        assert_eq!(queue.pop(), Some(message_rpc(1)));
+        assert_eq!(queue.pop(), Some(message_rpc(2)));
+        assert_eq!(queue.pop(), None);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn send_queue_bounds_messages_and_control_messages_separately() {
+        let mut queue = SendQueue::new(1, Duration::from_secs(5));
+
+        assert_eq!(queue.push(message_rpc(1)), None);
+        assert_eq!(queue.push(message_rpc(2)), Some(message_rpc(2)));
+        assert_eq!(queue.push(control_rpc()), None);
+        assert_eq!(queue.push(control_rpc()), Some(control_rpc()));
+    }
+
+    #[test]
+    fn send_queue_expires_messages() {
+        let mut queue = SendQueue::new(2, Duration::from_millis(10));
+
+        assert_eq!(queue.push(message_rpc(1)), None);
+        assert_eq!(queue.push(control_rpc()), None);
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        assert_eq!(queue.poll_expired(&mut cx), None);
+
+        // The task is woken up once the message expired.
+        let expired = futures::executor::block_on(future::poll_fn(|cx| {
+            queue.poll_expired(cx).map_or(Poll::Pending, Poll::Ready)
+        }));
+        assert_eq!(expired, message_rpc(1));
+        assert_eq!(queue.poll_expired(&mut cx), None);
+        assert_eq!(queue.pop(), Some(control_rpc()));
+    }
+}
@@ -139,36 +139,50 @@ pub mod protocol;
 
 mod backoff;
 mod behaviour;
+mod choke;
 mod config;
 mod gossip_promises;
 mod handler;
 mod mcache;
 pub mod metrics;
 mod peer_score;
+mod rate_limit;
 pub mod subscription_filter;
 pub mod time_cache;
 mod topic;
+mod tracer;
 mod transform;
 mod types;
+mod validation;
 
 #[cfg(test)]
 #[macro_use]
 extern crate derive_builder;
+#[cfg(feature = "serde")]
+extern crate _serde as serde;
 
 mod rpc_proto;
 
 pub use self::behaviour::{Gossipsub, GossipsubEvent, MessageAuthenticity};
-pub use self::transform::{DataTransform, IdentityTransform};
+pub use self::mcache::{InMemoryBackend, MessageCacheBackend};
+pub use self::transform::{DataTransform, DeflateTransform, IdentityTransform};
+pub use self::validation::{MessageValidator, ValidatorConfig};
 
 pub use self::config::{GossipsubConfig, GossipsubConfigBuilder, ValidationMode};
 pub use self::peer_score::{
-    score_parameter_decay, score_parameter_decay_with_base, PeerScoreParams, PeerScoreThresholds,
-    TopicScoreParams,
+    score_parameter_decay, score_parameter_decay_with_base, PeerScoreBreakdown, PeerScoreParams,
+    PeerScoreThresholds, RejectReason, TopicScoreBreakdown, TopicScoreParams,
 };
+pub use self::rate_limit::RateLimit;
 pub use self::topic::{Hasher, Topic, TopicHash};
+#[cfg(feature = "serde")]
+pub use self::tracer::JsonTracer;
+pub use self::tracer::{
+    ControlMeta, GossipsubTracer, IHaveMeta, MessageMeta, PruneMeta, RpcMeta, SubscriptionMeta,
+};
 pub use self::types::{
-    FastMessageId, GossipsubMessage, GossipsubRpc, MessageAcceptance, MessageId,
-    RawGossipsubMessage,
+    FailedMessages, FastMessageId, GossipsubMessage, GossipsubRpc, MessageAcceptance, MessageId,
+    PeerKind, RawGossipsubMessage, SendFailure,
 };
 pub type IdentTopic = Topic<self::topic::IdentityHash>;
 pub type Sha256Topic = Topic<self::topic::Sha256Hash>;
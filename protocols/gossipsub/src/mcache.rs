@@ -22,13 +22,48 @@ use crate::topic::TopicHash;
 use crate::types::{MessageId, RawGossipsubMessage};
 use libp2p_core::PeerId;
 use log::{debug, trace};
-use std::collections::hash_map::Entry;
 use std::fmt::Debug;
 use std::{
     collections::{HashMap, HashSet},
     fmt,
 };
 
+/// Storage for the payloads of the messages held in the message cache.
+///
+/// The message cache keeps the metadata of its messages in memory and hands their payloads to the
+/// backend. By default the payloads are kept on the heap via [`InMemoryBackend`]. Implementations
+/// can hold them elsewhere, for example in a memory-mapped file.
+pub trait MessageCacheBackend: Send + 'static {
+    /// Stores the payload of the message with the given id.
+    fn insert(&mut self, message_id: &MessageId, data: Vec<u8>);
+
+    /// Returns the payload of the message with the given id, if stored.
+    fn get(&self, message_id: &MessageId) -> Option<Vec<u8>>;
+
+    /// Removes the payload of the message with the given id, returning it if stored.
+    fn remove(&mut self, message_id: &MessageId) -> Option<Vec<u8>>;
+}
+
+/// A [`MessageCacheBackend`] keeping the payloads on the heap.
+#[derive(Debug, Default)]
+pub struct InMemoryBackend {
+    payloads: HashMap<MessageId, Vec<u8>>,
+}
+
+impl MessageCacheBackend for InMemoryBackend {
+    fn insert(&mut self, message_id: &MessageId, data: Vec<u8>) {
+        self.payloads.insert(message_id.clone(), data);
+    }
+
+    fn get(&self, message_id: &MessageId) -> Option<Vec<u8>> {
+        self.payloads.get(message_id).cloned()
+    }
+
+    fn remove(&mut self, message_id: &MessageId) -> Option<Vec<u8>> {
+        self.payloads.remove(message_id)
+    }
+}
+
 /// CacheEntry stored in the history.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct CacheEntry {
@@ -36,10 +71,19 @@ pub struct CacheEntry {
     topic: TopicHash,
 }
 
+/// A message held in the cache, without its payload which is held by the backend.
+#[derive(Debug)]
+struct CachedMessage {
+    message: RawGossipsubMessage,
+    /// The size of the payload in bytes.
+    size: usize,
+    /// The peers that sent us the message before it got validated.
+    originating_peers: HashSet<PeerId>,
+}
+
 /// MessageCache struct holding history of messages.
-#[derive(Clone)]
 pub struct MessageCache {
-    msgs: HashMap<MessageId, (RawGossipsubMessage, HashSet<PeerId>)>,
+    msgs: HashMap<MessageId, CachedMessage>,
     /// For every message and peer the number of times this peer asked for the message
     iwant_counts: HashMap<MessageId, HashMap<PeerId, u32>>,
     history: Vec<Vec<CacheEntry>>,
@@ -47,6 +91,16 @@ pub struct MessageCache {
     /// won't get gossiped anymore when shift got called `gossip` many times after inserting the
     /// message in the cache.
     gossip: usize,
+    /// Storage of the message payloads.
+    backend: Box<dyn MessageCacheBackend>,
+    /// The maximum number of payload bytes held in the cache.
+    max_bytes: Option<usize>,
+    /// The maximum number of payload bytes held in the cache per topic.
+    max_topic_bytes: Option<usize>,
+    /// The number of payload bytes held in the cache.
+    total_bytes: usize,
+    /// The number of payload bytes held in the cache per topic.
+    topic_bytes: HashMap<TopicHash, usize>,
 }
 
 impl fmt::Debug for MessageCache {
@@ -55,6 +109,7 @@ impl fmt::Debug for MessageCache {
             .field("msgs", &self.msgs)
             .field("history", &self.history)
             .field("gossip", &self.gossip)
+            .field("total_bytes", &self.total_bytes)
             .finish()
     }
 }
@@ -67,49 +122,125 @@ impl MessageCache {
             msgs: HashMap::default(),
             iwant_counts: HashMap::default(),
             history: vec![Vec::new(); history_capacity],
+            backend: Box::new(InMemoryBackend::default()),
+            max_bytes: None,
+            max_topic_bytes: None,
+            total_bytes: 0,
+            topic_bytes: HashMap::default(),
         }
     }
 
+    /// Bounds the payload bytes held in the cache in total and per topic. Once a bound is
+    /// reached, the oldest messages are evicted to make room for new ones.
+    ///
+    /// Messages awaiting validation are always cached and never evicted, so that they can still
+    /// be forwarded once validated. While they are held, the bounds may be exceeded.
+    pub fn with_byte_limits(
+        mut self,
+        max_bytes: Option<usize>,
+        max_topic_bytes: Option<usize>,
+    ) -> Self {
+        self.max_bytes = max_bytes;
+        self.max_topic_bytes = max_topic_bytes;
+        self
+    }
+
+    /// Replaces the backend holding the message payloads, moving all cached payloads to it.
+    pub fn set_backend(&mut self, mut backend: Box<dyn MessageCacheBackend>) {
+        for message_id in self.msgs.keys() {
+            if let Some(data) = self.backend.remove(message_id) {
+                backend.insert(message_id, data);
+            }
+        }
+        self.backend = backend;
+    }
+
     /// Put a message into the memory cache.
     ///
-    /// Returns true if the message didn't already exist in the cache.
-    pub fn put(&mut self, message_id: &MessageId, msg: RawGossipsubMessage) -> bool {
-        match self.msgs.entry(message_id.clone()) {
-            Entry::Occupied(_) => {
-                // Don't add duplicate entries to the cache.
-                false
+    /// Returns true if the message got added, i.e. it didn't already exist in the cache and,
+    /// unless it awaits validation, it doesn't exceed the byte limits of the cache on its own.
+    pub fn put(&mut self, message_id: &MessageId, mut msg: RawGossipsubMessage) -> bool {
+        if self.msgs.contains_key(message_id) {
+            // Don't add duplicate entries to the cache.
+            return false;
+        }
+
+        let size = msg.data.len();
+        // Messages awaiting validation are always cached, to forward them once validated.
+        if msg.validated
+            && (self.max_bytes.map_or(false, |max| size > max)
+                || self.max_topic_bytes.map_or(false, |max| size > max))
+        {
+            debug!(
+                "Message {} exceeds the byte limits of the mcache, not caching it",
+                message_id
+            );
+            return false;
+        }
+
+        // Evict the oldest messages until the new message fits.
+        if let Some(max) = self.max_topic_bytes {
+            while self
+                .topic_bytes
+                .get(&msg.topic)
+                .copied()
+                .unwrap_or_default()
+                + size
+                > max
+            {
+                let topic = msg.topic.clone();
+                if !self.evict_oldest(|entry| entry.topic == topic) {
+                    break;
+                }
             }
-            Entry::Vacant(entry) => {
-                let cache_entry = CacheEntry {
-                    mid: message_id.clone(),
-                    topic: msg.topic.clone(),
-                };
-                entry.insert((msg, HashSet::default()));
-                self.history[0].push(cache_entry);
-
-                trace!("Put message {:?} in mcache", message_id);
-                true
+        }
+        if let Some(max) = self.max_bytes {
+            while self.total_bytes + size > max {
+                if !self.evict_oldest(|_| true) {
+                    break;
+                }
             }
         }
+
+        let cache_entry = CacheEntry {
+            mid: message_id.clone(),
+            topic: msg.topic.clone(),
+        };
+        self.backend
+            .insert(message_id, std::mem::take(&mut msg.data));
+        self.total_bytes += size;
+        *self.topic_bytes.entry(msg.topic.clone()).or_default() += size;
+        self.msgs.insert(
+            message_id.clone(),
+            CachedMessage {
+                message: msg,
+                size,
+                originating_peers: HashSet::default(),
+            },
+        );
+        self.history[0].push(cache_entry);
+
+        trace!("Put message {:?} in mcache", message_id);
+        true
     }
 
     /// Keeps track of peers we know have received the message to prevent forwarding to said peers.
     pub fn observe_duplicate(&mut self, message_id: &MessageId, source: &PeerId) {
-        if let Some((message, originating_peers)) = self.msgs.get_mut(message_id) {
+        if let Some(cached) = self.msgs.get_mut(message_id) {
             // if the message is already validated, we don't need to store extra peers sending us
             // duplicates as the message has already been forwarded
-            if message.validated {
+            if cached.message.validated {
                 return;
             }
 
-            originating_peers.insert(*source);
+            cached.originating_peers.insert(*source);
         }
     }
 
     /// Get a message with `message_id`
     #[cfg(test)]
-    pub fn get(&self, message_id: &MessageId) -> Option<&RawGossipsubMessage> {
-        self.msgs.get(message_id).map(|(message, _)| message)
+    pub fn get(&self, message_id: &MessageId) -> Option<RawGossipsubMessage> {
+        self.load(message_id)
     }
 
     /// Increases the iwant count for the given message by one and returns the message together
@@ -118,23 +249,16 @@ impl MessageCache {
         &mut self,
         message_id: &MessageId,
         peer: &PeerId,
-    ) -> Option<(&RawGossipsubMessage, u32)> {
-        let iwant_counts = &mut self.iwant_counts;
-        self.msgs.get(message_id).and_then(|(message, _)| {
-            if !message.validated {
-                None
-            } else {
-                Some((message, {
-                    let count = iwant_counts
-                        .entry(message_id.clone())
-                        .or_default()
-                        .entry(*peer)
-                        .or_default();
-                    *count += 1;
-                    *count
-                }))
-            }
-        })
+    ) -> Option<(RawGossipsubMessage, u32)> {
+        let message = self.load(message_id).filter(|message| message.validated)?;
+        let count = self
+            .iwant_counts
+            .entry(message_id.clone())
+            .or_default()
+            .entry(*peer)
+            .or_default();
+        *count += 1;
+        Some((message, *count))
     }
 
     /// Gets a message with [`MessageId`] and tags it as validated.
@@ -143,14 +267,14 @@ impl MessageCache {
     pub fn validate(
         &mut self,
         message_id: &MessageId,
-    ) -> Option<(&RawGossipsubMessage, HashSet<PeerId>)> {
-        self.msgs.get_mut(message_id).map(|(message, known_peers)| {
-            message.validated = true;
-            // Clear the known peers list (after a message is validated, it is forwarded and we no
-            // longer need to store the originating peers).
-            let originating_peers = std::mem::take(known_peers);
-            (&*message, originating_peers)
-        })
+    ) -> Option<(RawGossipsubMessage, HashSet<PeerId>)> {
+        let cached = self.msgs.get_mut(message_id)?;
+        cached.message.validated = true;
+        // Clear the known peers list (after a message is validated, it is forwarded and we no
+        // longer need to store the originating peers).
+        let originating_peers = std::mem::take(&mut cached.originating_peers);
+        self.load(message_id)
+            .map(|message| (message, originating_peers))
     }
 
     /// Get a list of [`MessageId`]s for a given topic.
@@ -165,7 +289,7 @@ impl MessageCache {
                         if &entry.topic == topic {
                             let mid = &entry.mid;
                             // Only gossip validated messages
-                            if let Some(true) = self.msgs.get(mid).map(|(msg, _)| msg.validated) {
+                            if let Some(true) = self.msgs.get(mid).map(|c| c.message.validated) {
                                 Some(mid.clone())
                             } else {
                                 None
@@ -186,8 +310,8 @@ impl MessageCache {
     /// last entry.
     pub fn shift(&mut self) {
         for entry in self.history.pop().expect("history is always > 1") {
-            if let Some((msg, _)) = self.msgs.remove(&entry.mid) {
-                if !msg.validated {
+            if let Some(cached) = self.forget(&entry.mid) {
+                if !cached.message.validated {
                     // If GossipsubConfig::validate_messages is true, the implementing
                     // application has to ensure that Gossipsub::validate_message gets called for
                     // each received message within the cache timeout time."
@@ -198,8 +322,6 @@ impl MessageCache {
                 }
             }
             trace!("Remove message from the cache: {}", &entry.mid);
-
-            self.iwant_counts.remove(&entry.mid);
         }
 
         // Insert an empty vec in position 0
@@ -213,9 +335,66 @@ impl MessageCache {
     ) -> Option<(RawGossipsubMessage, HashSet<PeerId>)> {
         //We only remove the message from msgs and iwant_count and keep the message_id in the
         // history vector. Zhe id in the history vector will simply be ignored on popping.
+        let data = self.backend.get(message_id);
+        self.forget(message_id).map(|mut cached| {
+            cached.message.data = data.unwrap_or_default();
+            (cached.message, cached.originating_peers)
+        })
+    }
 
+    /// Loads a message including its payload.
+    fn load(&self, message_id: &MessageId) -> Option<RawGossipsubMessage> {
+        let cached = self.msgs.get(message_id)?;
+        let data = self.backend.get(message_id)?;
+        Some(RawGossipsubMessage {
+            data,
+            ..cached.message.clone()
+        })
+    }
+
+    /// Removes a message and its bookkeeping from the cache, except for its history entry.
+    fn forget(&mut self, message_id: &MessageId) -> Option<CachedMessage> {
+        let cached = self.msgs.remove(message_id)?;
         self.iwant_counts.remove(message_id);
-        self.msgs.remove(message_id)
+        self.backend.remove(message_id);
+        self.total_bytes -= cached.size;
+        if let Some(bytes) = self.topic_bytes.get_mut(&cached.message.topic) {
+            *bytes -= cached.size;
+            if *bytes == 0 {
+                self.topic_bytes.remove(&cached.message.topic);
+            }
+        }
+        Some(cached)
+    }
+
+    /// Evicts the oldest validated message matching the predicate, including its history entry
+    /// so that it is no longer gossiped.
+    ///
+    /// Returns false if there is no such message.
+    fn evict_oldest(&mut self, predicate: impl Fn(&CacheEntry) -> bool) -> bool {
+        loop {
+            let msgs = &self.msgs;
+            // Entries of removed messages are evictable, so that they are cleaned up.
+            let evictable = |entry: &CacheEntry| {
+                predicate(entry) && msgs.get(&entry.mid).map_or(true, |c| c.message.validated)
+            };
+            let position = self
+                .history
+                .iter()
+                .enumerate()
+                .rev()
+                .find_map(|(i, entries)| entries.iter().position(evictable).map(|j| (i, j)));
+            let (i, j) = match position {
+                Some(position) => position,
+                None => return false,
+            };
+            let entry = self.history[i].remove(j);
+            // Entries of removed messages remain in the history and are skipped.
+            if self.forget(&entry.mid).is_some() {
+                debug!("Evicted message {} from the mcache", entry.mid);
+                return true;
+            }
+        }
     }
 }
 
@@ -225,6 +404,7 @@ mod tests {
     use crate::types::RawGossipsubMessage;
     use crate::{IdentTopic as Topic, TopicHash};
     use libp2p_core::PeerId;
+    use std::sync::{Arc, Mutex};
 
     fn gen_testm(x: u64, topic: TopicHash) -> (MessageId, RawGossipsubMessage) {
         let default_id = |message: &RawGossipsubMessage| {
@@ -284,7 +464,7 @@ mod tests {
 
         // Make sure it is the same fetched message
         match fetched {
-            Some(x) => assert_eq!(*x, m),
+            Some(x) => assert_eq!(x, m),
             _ => assert!(false),
         }
     }
@@ -392,4 +572,155 @@ mod tests {
         assert_eq!(mc.history[0].len(), 0);
         assert_eq!(mc.msgs.len(), 0);
     }
+
+    #[test]
+    /// Test that the oldest messages are evicted once the byte limit is reached.
+    fn test_evict_oldest_on_byte_limit() {
+        let mut mc = new_cache(3, 5).with_byte_limits(Some(5), None);
+
+        let topic1_hash = Topic::new("topic1").hash().clone();
+        let ids: Vec<_> = (0..7)
+            .map(|i| {
+                let (id, m) = gen_testm(i, topic1_hash.clone());
+                if i == 3 {
+                    mc.shift();
+                }
+                assert!(mc.put(&id, m));
+                mc.validate(&id);
+                id
+            })
+            .collect();
+
+        // The two oldest messages got evicted to make room for the newer ones.
+        assert_eq!(mc.total_bytes, 5);
+        assert!(mc.get(&ids[0]).is_none());
+        assert!(mc.get(&ids[1]).is_none());
+        assert!(ids[2..].iter().all(|id| mc.get(id).is_some()));
+        assert_eq!(mc.history[1].len(), 1);
+        assert_eq!(mc.history[0].len(), 4);
+        assert_eq!(
+            mc.get_gossip_message_ids(&topic1_hash),
+            [&ids[3..], &ids[2..3]].concat()
+        );
+    }
+
+    #[test]
+    /// Test that the per topic byte limit only evicts messages of the same topic.
+    fn test_evict_oldest_on_topic_byte_limit() {
+        let mut mc = new_cache(3, 5).with_byte_limits(None, Some(2));
+
+        let topic1_hash = Topic::new("topic1").hash().clone();
+        let topic2_hash = Topic::new("topic2").hash().clone();
+        let (id0, m0) = gen_testm(0, topic1_hash.clone());
+        let (id1, m1) = gen_testm(1, topic2_hash.clone());
+        let (id2, m2) = gen_testm(2, topic1_hash.clone());
+        let (id3, m3) = gen_testm(3, topic1_hash.clone());
+        for (id, m) in [(&id0, m0), (&id1, m1), (&id2, m2), (&id3, m3)] {
+            mc.put(id, m);
+            mc.validate(id);
+        }
+
+        assert!(mc.get(&id0).is_none());
+        assert!(mc.get(&id1).is_some());
+        assert!(mc.get(&id2).is_some());
+        assert!(mc.get(&id3).is_some());
+        assert_eq!(mc.topic_bytes[&topic1_hash], 2);
+        assert_eq!(mc.topic_bytes[&topic2_hash], 1);
+        assert_eq!(mc.history[0].len(), 3);
+    }
+
+    #[test]
+    /// Test that messages awaiting validation are not evicted.
+    fn test_unvalidated_messages_are_not_evicted() {
+        let mut mc = new_cache(3, 5).with_byte_limits(Some(2), None);
+
+        let topic1_hash = Topic::new("topic1").hash().clone();
+        let (id0, m0) = gen_testm(0, topic1_hash.clone());
+        let (id1, m1) = gen_testm(1, topic1_hash.clone());
+        let (id2, m2) = gen_testm(2, topic1_hash.clone());
+        let (id3, m3) = gen_testm(3, topic1_hash);
+
+        assert!(mc.put(&id0, m0));
+        assert!(mc.put(&id1, m1));
+        mc.validate(&id1);
+        // The validated message is evicted, the older unvalidated one is kept.
+        assert!(mc.put(&id2, m2));
+        assert!(mc.get(&id1).is_none());
+        // The limit is exceeded while no message can be evicted.
+        assert!(mc.put(&id3, m3));
+        assert_eq!(mc.total_bytes, 3);
+
+        // The unvalidated messages can still be validated and forwarded.
+        assert!(mc.validate(&id0).is_some());
+        assert!(mc.validate(&id2).is_some());
+    }
+
+    #[test]
+    /// Test that a validated message exceeding the byte limit on its own is not cached.
+    fn test_put_message_exceeding_byte_limit() {
+        let mut mc = new_cache(3, 5).with_byte_limits(Some(5), None);
+
+        let topic1_hash = Topic::new("topic1").hash().clone();
+        let (id0, m0) = gen_testm(0, topic1_hash.clone());
+        let (id1, mut m1) = gen_testm(1, topic1_hash);
+        m1.data = vec![0; 6];
+        m1.validated = true;
+
+        assert!(mc.put(&id0, m0));
+        assert!(!mc.put(&id1, m1));
+        assert!(mc.get(&id0).is_some());
+        assert!(mc.get(&id1).is_none());
+        assert_eq!(mc.total_bytes, 1);
+    }
+
+    #[test]
+    /// Test that a message awaiting validation is cached even if it exceeds the byte limit.
+    fn test_put_unvalidated_message_exceeding_byte_limit() {
+        let mut mc = new_cache(3, 5).with_byte_limits(Some(5), Some(5));
+
+        let topic1_hash = Topic::new("topic1").hash().clone();
+        let (id0, mut m0) = gen_testm(0, topic1_hash);
+        m0.data = vec![0; 6];
+
+        assert!(mc.put(&id0, m0));
+        assert_eq!(mc.total_bytes, 6);
+        assert!(mc.validate(&id0).is_some());
+    }
+
+    #[test]
+    /// Test that cached payloads are moved to a new backend.
+    fn test_set_backend() {
+        #[derive(Clone, Default)]
+        struct SharedBackend(Arc<Mutex<HashMap<MessageId, Vec<u8>>>>);
+
+        impl MessageCacheBackend for SharedBackend {
+            fn insert(&mut self, message_id: &MessageId, data: Vec<u8>) {
+                self.0.lock().unwrap().insert(message_id.clone(), data);
+            }
+
+            fn get(&self, message_id: &MessageId) -> Option<Vec<u8>> {
+                self.0.lock().unwrap().get(message_id).cloned()
+            }
+
+            fn remove(&mut self, message_id: &MessageId) -> Option<Vec<u8>> {
+                self.0.lock().unwrap().remove(message_id)
+            }
+        }
+
+        let mut mc = new_cache(3, 5);
+
+        let topic1_hash = Topic::new("topic1").hash().clone();
+        let (id0, m0) = gen_testm(0, topic1_hash.clone());
+        let (id1, m1) = gen_testm(1, topic1_hash);
+        mc.put(&id0, m0.clone());
+
+        let backend = SharedBackend::default();
+        mc.set_backend(Box::new(backend.clone()));
+        mc.put(&id1, m1.clone());
+
+        assert_eq!(backend.0.lock().unwrap().len(), 2);
+        assert_eq!(mc.get(&id0), Some(m0));
+        assert_eq!(mc.remove(&id1).map(|(m, _)| m), Some(m1));
+        assert_eq!(backend.0.lock().unwrap().len(), 1);
+    }
 }
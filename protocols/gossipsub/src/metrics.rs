@@ -31,7 +31,7 @@ use prometheus_client::metrics::histogram::{linear_buckets, Histogram};
 use prometheus_client::registry::Registry;
 
 use crate::topic::TopicHash;
-use crate::types::{MessageAcceptance, PeerKind};
+use crate::types::{MessageAcceptance, PeerKind, SendFailure};
 
 // Default value that limits for how many topics do we store metrics.
 const DEFAULT_MAX_TOPICS: usize = 300;
@@ -151,6 +151,14 @@ pub struct Metrics {
     topic_msg_recv_counts: Family<TopicHash, Counter>,
     /// Bytes received from gossip messages for each topic.
     topic_msg_recv_bytes: Family<TopicHash, Counter>,
+    /// Number of gossip messages not forwarded to peers on each topic, as the peers sent an
+    /// IDONTWANT for them.
+    topic_msg_suppressed_counts: Family<TopicHash, Counter>,
+    /// Bytes from gossip messages not forwarded to peers on each topic, as the peers sent an
+    /// IDONTWANT for them.
+    topic_msg_suppressed_bytes: Family<TopicHash, Counter>,
+    /// Number of gossip messages dropped on each topic, as the peer did not keep up.
+    topic_msg_send_failures: Family<SendFailureLabel, Counter>,
 
     /* Metrics related to scoring */
     /// Histogram of the scores for each mesh topic.
@@ -159,7 +167,7 @@ pub struct Metrics {
     scoring_penalties: Family<PenaltyLabel, Counter>,
 
     /* General Metrics */
-    /// Gossipsub supports floodsub, gossipsub v1.0 and gossipsub v1.1. Peers are classified based
+    /// Gossipsub supports floodsub, gossipsub v1.0, gossipsub v1.1 and gossipsub v1.2. Peers are classified based
     /// on which protocol they support. This metric keeps track of the number of peers that are
     /// connected of each type.
     peers_per_protocol: Family<ProtocolLabel, Gauge>,
@@ -174,6 +182,10 @@ pub struct Metrics {
     /// The number of times we have decided that an IWANT control message is required for this
     /// topic. A very high metric might indicate an underperforming network.
     topic_iwant_msgs: Family<TopicHash, Counter>,
+    /// The number of message ids we have received in IDONTWANT control messages.
+    idontwant_msg_ids_recvd: Counter,
+    /// The number of message ids we have sent in IDONTWANT control messages.
+    idontwant_msg_ids_sent: Counter,
 }
 
 impl Metrics {
@@ -260,6 +272,18 @@ impl Metrics {
             "topic_msg_recv_bytes",
             "Bytes received from gossip messages for each topic"
         );
+        let topic_msg_suppressed_counts = register_family!(
+            "topic_msg_suppressed_counts",
+            "Number of gossip messages not forwarded on each topic due to IDONTWANT"
+        );
+        let topic_msg_suppressed_bytes = register_family!(
+            "topic_msg_suppressed_bytes",
+            "Bytes from gossip messages not forwarded on each topic due to IDONTWANT"
+        );
+        let topic_msg_send_failures = register_family!(
+            "topic_msg_send_failures",
+            "Number of gossip messages dropped on each topic as the peer did not keep up"
+        );
 
         let hist_builder = HistBuilder {
             buckets: score_buckets,
@@ -301,6 +325,24 @@ impl Metrics {
             );
             metric
         };
+        let idontwant_msg_ids_recvd = {
+            let metric = Counter::default();
+            registry.register(
+                "idontwant_msg_ids_recvd",
+                "Number of message ids received in IDONTWANT control messages",
+                Box::new(metric.clone()),
+            );
+            metric
+        };
+        let idontwant_msg_ids_sent = {
+            let metric = Counter::default();
+            registry.register(
+                "idontwant_msg_ids_sent",
+                "Number of message ids sent in IDONTWANT control messages",
+                Box::new(metric.clone()),
+            );
+            metric
+        };
 
         Self {
             max_topics,
@@ -321,12 +363,17 @@ impl Metrics {
             topic_msg_recv_counts_unfiltered,
             topic_msg_recv_counts,
             topic_msg_recv_bytes,
+            topic_msg_suppressed_counts,
+            topic_msg_suppressed_bytes,
+            topic_msg_send_failures,
             score_per_mesh,
             scoring_penalties,
             peers_per_protocol,
             heartbeat_duration,
             memcache_misses,
             topic_iwant_msgs,
+            idontwant_msg_ids_recvd,
+            idontwant_msg_ids_sent,
         }
     }
 
@@ -471,6 +518,28 @@ impl Metrics {
         }
     }
 
+    /// Register not forwarding a message over a topic, as the peer sent an IDONTWANT for it.
+    pub fn msg_suppressed(&mut self, topic: &TopicHash, bytes: usize) {
+        if self.register_topic(topic).is_ok() {
+            self.topic_msg_suppressed_counts.get_or_create(topic).inc();
+            self.topic_msg_suppressed_bytes
+                .get_or_create(topic)
+                .inc_by(bytes as u64);
+        }
+    }
+
+    /// Register a message over a topic that could not be sent to a peer.
+    pub fn register_send_failure(&mut self, topic: &TopicHash, reason: SendFailure) {
+        if self.register_topic(topic).is_ok() {
+            self.topic_msg_send_failures
+                .get_or_create(&SendFailureLabel {
+                    hash: topic.to_string(),
+                    reason,
+                })
+                .inc();
+        }
+    }
+
     pub fn register_msg_validation(&mut self, topic: &TopicHash, validation: &MessageAcceptance) {
         if self.register_topic(topic).is_ok() {
             match validation {
@@ -493,6 +562,16 @@ impl Metrics {
         }
     }
 
+    /// Register receiving an IDONTWANT msg with the given number of message ids.
+    pub fn register_idontwant_recvd(&mut self, msg_ids: usize) {
+        self.idontwant_msg_ids_recvd.inc_by(msg_ids as u64);
+    }
+
+    /// Register sending an IDONTWANT msg with the given number of message ids.
+    pub fn register_idontwant_sent(&mut self, msg_ids: usize) {
+        self.idontwant_msg_ids_sent.inc_by(msg_ids as u64);
+    }
+
     /// Observes a heartbeat duration.
     pub fn observe_heartbeat_duration(&mut self, millis: u64) {
         self.heartbeat_duration.observe(millis as f64);
@@ -579,6 +658,13 @@ struct ChurnLabel {
     reason: Churn,
 }
 
+/// Label for the send failure metrics.
+#[derive(PartialEq, Eq, Hash, Encode, Clone)]
+struct SendFailureLabel {
+    hash: String,
+    reason: SendFailure,
+}
+
 /// Label for the kinds of protocols peers can connect as.
 #[derive(PartialEq, Eq, Hash, Encode, Clone)]
 struct ProtocolLabel {
@@ -31,6 +31,9 @@ use std::net::IpAddr;
 use std::time::Duration;
 use wasm_timer::Instant;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 mod params;
 use crate::error::ValidationError;
 pub use params::{
@@ -56,6 +59,46 @@ pub(crate) struct PeerScore {
     message_delivery_time_callback: Option<fn(&PeerId, &TopicHash, f64)>,
 }
 
+/// The score of a peer broken down into the weighted contribution of each scoring component.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(crate = "_serde"))]
+pub struct PeerScoreBreakdown {
+    /// The overall score of the peer.
+    pub score: f64,
+    /// The scores of the topics the peer is scored in.
+    pub topics: HashMap<TopicHash, TopicScoreBreakdown>,
+    /// The sum of the topic scores, capped at [`PeerScoreParams::topic_score_cap`].
+    pub topic_score: f64,
+    /// P₅: The application-specific score.
+    pub application_score: f64,
+    /// P₆: The IP colocation factor penalty.
+    pub ip_colocation_factor: f64,
+    /// P₇: The behaviour penalty.
+    pub behaviour_penalty: f64,
+}
+
+/// The score of a peer in a single topic broken down into the weighted contribution of each
+/// scoring component.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(crate = "_serde"))]
+pub struct TopicScoreBreakdown {
+    /// The score of the topic, i.e. the sum of the components below multiplied by
+    /// [`TopicScoreParams::topic_weight`].
+    pub score: f64,
+    /// P₁: The time in mesh score.
+    pub time_in_mesh: f64,
+    /// P₂: The first message deliveries score.
+    pub first_message_deliveries: f64,
+    /// P₃: The mesh message deliveries deficit penalty.
+    pub mesh_message_deliveries: f64,
+    /// P₃b: The mesh failure penalty.
+    pub mesh_failure_penalty: f64,
+    /// P₄: The invalid message deliveries penalty.
+    pub invalid_message_deliveries: f64,
+}
+
 /// General statistics for a given gossipsub peer.
 struct PeerStats {
     /// Connection status of the peer.
@@ -137,6 +180,9 @@ enum MeshStatus {
         graft_time: Instant,
         /// The time the peer has been in the mesh.
         mesh_time: Duration,
+        /// The time from which mesh message deliveries are expected from the peer, i.e. when it
+        /// was GRAFTed or last unchoked. `None` while we choke the peer.
+        deliveries_time: Option<Instant>,
     },
     InActive,
 }
@@ -144,9 +190,11 @@ enum MeshStatus {
 impl MeshStatus {
     /// Initialises a new [`MeshStatus::Active`] mesh status.
     pub fn new_active() -> Self {
+        let now = Instant::now();
         MeshStatus::Active {
-            graft_time: Instant::now(),
+            graft_time: now,
             mesh_time: Duration::from_secs(0),
+            deliveries_time: Some(now),
         }
     }
 }
@@ -220,10 +268,24 @@ impl PeerScore {
 
     /// Returns the score for a peer, logging metrics. This is called from the heartbeat and
     /// increments the metric counts for penalties.
-    pub fn metric_score(&self, peer_id: &PeerId, mut metrics: Option<&mut Metrics>) -> f64 {
+    pub fn metric_score(&self, peer_id: &PeerId, metrics: Option<&mut Metrics>) -> f64 {
+        self.metric_score_breakdown(peer_id, metrics).score
+    }
+
+    /// Returns the score of a peer together with the contribution of each scoring component.
+    pub fn score_breakdown(&self, peer_id: &PeerId) -> PeerScoreBreakdown {
+        self.metric_score_breakdown(peer_id, None)
+    }
+
+    fn metric_score_breakdown(
+        &self,
+        peer_id: &PeerId,
+        mut metrics: Option<&mut Metrics>,
+    ) -> PeerScoreBreakdown {
+        let mut breakdown = PeerScoreBreakdown::default();
         let peer_stats = match self.peer_stats.get(peer_id) {
             Some(v) => v,
-            None => return 0.0,
+            None => return breakdown,
         };
 
         let mut score = 0.0;
@@ -233,6 +295,7 @@ impl PeerScore {
             // topic parameters
             if let Some(topic_params) = self.params.topics.get(topic) {
                 // we are tracking the topic
+                let mut topic_breakdown = TopicScoreBreakdown::default();
 
                 // the topic score
                 let mut topic_score = 0.0;
@@ -248,7 +311,8 @@ impl PeerScore {
                             topic_params.time_in_mesh_cap
                         }
                     };
-                    topic_score += p1 * topic_params.time_in_mesh_weight;
+                    topic_breakdown.time_in_mesh = p1 * topic_params.time_in_mesh_weight;
+                    topic_score += topic_breakdown.time_in_mesh;
                 }
 
                 // P2: first message deliveries
@@ -260,7 +324,9 @@ impl PeerScore {
                         topic_params.first_message_deliveries_cap
                     }
                 };
-                topic_score += p2 * topic_params.first_message_deliveries_weight;
+                topic_breakdown.first_message_deliveries =
+                    p2 * topic_params.first_message_deliveries_weight;
+                topic_score += topic_breakdown.first_message_deliveries;
 
                 // P3: mesh message deliveries
                 if topic_stats.mesh_message_deliveries_active
@@ -270,7 +336,9 @@ impl PeerScore {
                     let deficit = topic_params.mesh_message_deliveries_threshold
                         - topic_stats.mesh_message_deliveries;
                     let p3 = deficit * deficit;
-                    topic_score += p3 * topic_params.mesh_message_deliveries_weight;
+                    topic_breakdown.mesh_message_deliveries =
+                        p3 * topic_params.mesh_message_deliveries_weight;
+                    topic_score += topic_breakdown.mesh_message_deliveries;
                     if let Some(metrics) = metrics.as_mut() {
                         metrics.register_score_penalty(Penalty::MessageDeficit);
                     }
@@ -287,16 +355,22 @@ impl PeerScore {
                 // P3b:
                 // NOTE: the weight of P3b is negative (validated in TopicScoreParams.validate), so this detracts.
                 let p3b = topic_stats.mesh_failure_penalty;
-                topic_score += p3b * topic_params.mesh_failure_penalty_weight;
+                topic_breakdown.mesh_failure_penalty =
+                    p3b * topic_params.mesh_failure_penalty_weight;
+                topic_score += topic_breakdown.mesh_failure_penalty;
 
                 // P4: invalid messages
                 // NOTE: the weight of P4 is negative (validated in TopicScoreParams.validate), so this detracts.
                 let p4 =
                     topic_stats.invalid_message_deliveries * topic_stats.invalid_message_deliveries;
-                topic_score += p4 * topic_params.invalid_message_deliveries_weight;
+                topic_breakdown.invalid_message_deliveries =
+                    p4 * topic_params.invalid_message_deliveries_weight;
+                topic_score += topic_breakdown.invalid_message_deliveries;
 
                 // update score, mixing with topic weight
-                score += topic_score * topic_params.topic_weight;
+                topic_breakdown.score = topic_score * topic_params.topic_weight;
+                score += topic_breakdown.score;
+                breakdown.topics.insert(topic.clone(), topic_breakdown);
             }
         }
 
@@ -304,10 +378,12 @@ impl PeerScore {
         if self.params.topic_score_cap > 0f64 && score > self.params.topic_score_cap {
             score = self.params.topic_score_cap;
         }
+        breakdown.topic_score = score;
 
         // P5: application-specific score
         let p5 = peer_stats.application_score;
-        score += p5 * self.params.app_specific_weight;
+        breakdown.application_score = p5 * self.params.app_specific_weight;
+        score += breakdown.application_score;
 
         // P6: IP collocation factor
         for ip in peer_stats.known_ips.iter() {
@@ -331,6 +407,7 @@ impl PeerScore {
                         The surplus is {}. ",
                         peer_id, ip, surplus
                     );
+                    breakdown.ip_colocation_factor += p6 * self.params.ip_colocation_factor_weight;
                     score += p6 * self.params.ip_colocation_factor_weight;
                 }
             }
@@ -340,9 +417,11 @@ impl PeerScore {
         if peer_stats.behaviour_penalty > self.params.behaviour_penalty_threshold {
             let excess = peer_stats.behaviour_penalty - self.params.behaviour_penalty_threshold;
             let p7 = excess * excess;
-            score += p7 * self.params.behaviour_penalty_weight;
+            breakdown.behaviour_penalty = p7 * self.params.behaviour_penalty_weight;
+            score += breakdown.behaviour_penalty;
         }
-        score
+        breakdown.score = score;
+        breakdown
     }
 
     pub fn add_penalty(&mut self, peer_id: &PeerId, count: usize) {
@@ -415,10 +494,14 @@ impl PeerScore {
                     if let MeshStatus::Active {
                         ref mut mesh_time,
                         ref mut graft_time,
+                        deliveries_time,
                     } = topic_stats.mesh_status
                     {
                         *mesh_time = now.duration_since(*graft_time);
-                        if *mesh_time > topic_params.mesh_message_deliveries_activation {
+                        if deliveries_time.map_or(false, |deliveries_time| {
+                            now.duration_since(deliveries_time)
+                                > topic_params.mesh_message_deliveries_activation
+                        }) {
                             topic_stats.mesh_message_deliveries_active = true;
                         }
                     }
@@ -538,6 +621,40 @@ impl PeerScore {
         }
     }
 
+    /// Handles scoring functionality as we choke a mesh peer on a topic. A choked peer only
+    /// delivers the messages we request, thus its mesh message deliveries are not scored until it
+    /// is unchoked.
+    pub fn choke(&mut self, peer_id: &PeerId, topic: TopicHash) {
+        if let Some(peer_stats) = self.peer_stats.get_mut(peer_id) {
+            if let Some(topic_stats) = peer_stats.stats_or_default_mut(topic, &self.params) {
+                if let MeshStatus::Active {
+                    ref mut deliveries_time,
+                    ..
+                } = topic_stats.mesh_status
+                {
+                    *deliveries_time = None;
+                    topic_stats.mesh_message_deliveries_active = false;
+                }
+            }
+        }
+    }
+
+    /// Handles scoring functionality as we unchoke a mesh peer on a topic. Its mesh message
+    /// deliveries are scored again after the activation time.
+    pub fn unchoke(&mut self, peer_id: &PeerId, topic: TopicHash) {
+        if let Some(peer_stats) = self.peer_stats.get_mut(peer_id) {
+            if let Some(topic_stats) = peer_stats.stats_or_default_mut(topic, &self.params) {
+                if let MeshStatus::Active {
+                    ref mut deliveries_time,
+                    ..
+                } = topic_stats.mesh_status
+                {
+                    *deliveries_time = Some(Instant::now());
+                }
+            }
+        }
+    }
+
     /// Handles scoring functionality as a peer PRUNEs from a topic.
     pub fn prune(&mut self, peer_id: &PeerId, topic: TopicHash) {
         if let Some(peer_stats) = self.peer_stats.get_mut(peer_id) {
@@ -890,8 +1007,8 @@ impl PeerScore {
 }
 
 /// The reason a Gossipsub message has been rejected.
-#[derive(Clone, Copy)]
-pub(crate) enum RejectReason {
+#[derive(Debug, Clone, Copy)]
+pub enum RejectReason {
     /// The message failed the configured validation during decoding.
     ValidationError(ValidationError),
     /// The message source is us.
@@ -555,6 +555,91 @@ fn test_score_mesh_failure_penalty() {
     assert_eq!(score_b, expected, "Peer B should have expected score",);
 }
 
+#[test]
+fn test_score_mesh_message_deliveries_choked() {
+    // Create parameters with reasonable default values
+    let topic = Topic::new("test");
+    let topic_hash = topic.hash();
+    let mut params = PeerScoreParams::default();
+
+    let topic_params = TopicScoreParams {
+        topic_weight: 1.0,
+        mesh_message_deliveries_weight: -1.0,
+        mesh_message_deliveries_activation: Duration::from_millis(100),
+        mesh_message_deliveries_window: Duration::from_millis(10),
+        mesh_message_deliveries_threshold: 20.0,
+        mesh_message_deliveries_cap: 100.0,
+        mesh_message_deliveries_decay: 1.0,
+        first_message_deliveries_weight: 0.0,
+        time_in_mesh_weight: 0.0,
+        mesh_failure_penalty_weight: -1.0,
+        mesh_failure_penalty_decay: 1.0,
+        ..Default::default()
+    };
+
+    params.topics.insert(topic_hash, topic_params.clone());
+    let mut peer_score = PeerScore::new(params);
+
+    // peer A is choked, thus doesn't deliver messages.
+    // peer B doesn't deliver messages either.
+    // we expect only peer B to be penalized for the missing mesh message deliveries.
+    let peer_id_a = PeerId::random();
+    let peer_id_b = PeerId::random();
+
+    for peer_id in &[peer_id_a, peer_id_b] {
+        peer_score.add_peer(*peer_id);
+        peer_score.graft(peer_id, topic.clone());
+    }
+    peer_score.choke(&peer_id_a, topic.hash());
+
+    // wait for the activation time to kick in
+    let activation = topic_params.mesh_message_deliveries_activation + Duration::from_millis(10);
+    std::thread::sleep(activation);
+    peer_score.refresh_scores();
+
+    let penalty = topic_params.mesh_message_deliveries_threshold
+        * topic_params.mesh_message_deliveries_threshold;
+    let expected =
+        topic_params.topic_weight * topic_params.mesh_message_deliveries_weight * penalty;
+    assert_eq!(
+        peer_score.score(&peer_id_a),
+        0.0,
+        "Choked peer A should not be penalized"
+    );
+    assert_eq!(
+        peer_score.score(&peer_id_b),
+        expected,
+        "Peer B should be penalized"
+    );
+
+    // unchoking peer A restarts the activation time
+    peer_score.unchoke(&peer_id_a, topic.hash());
+    peer_score.refresh_scores();
+    assert_eq!(
+        peer_score.score(&peer_id_a),
+        0.0,
+        "Peer A should not be penalized before activation"
+    );
+
+    std::thread::sleep(activation);
+    peer_score.refresh_scores();
+    assert_eq!(
+        peer_score.score(&peer_id_a),
+        expected,
+        "Unchoked peer A should be penalized"
+    );
+
+    // pruning a choked peer doesn't apply the mesh failure penalty
+    peer_score.choke(&peer_id_a, topic.hash());
+    peer_score.prune(&peer_id_a, topic.hash());
+    peer_score.refresh_scores();
+    assert_eq!(
+        peer_score.score(&peer_id_a),
+        0.0,
+        "Choked peer A should not get a mesh failure penalty"
+    );
+}
+
 #[test]
 fn test_score_invalid_message_deliveries() {
     // Create parameters with reasonable default values
@@ -978,3 +1063,68 @@ fn test_score_retention() {
         "Score should be the application specific score"
     );
 }
+
+#[test]
+fn test_score_breakdown() {
+    // Create parameters with reasonable default values
+    let topic = Topic::new("test");
+    let topic_hash = topic.hash();
+    let mut params = PeerScoreParams {
+        app_specific_weight: 2.0,
+        behaviour_penalty_weight: -1.0,
+        behaviour_penalty_threshold: 0.0,
+        behaviour_penalty_decay: 1.0,
+        ..Default::default()
+    };
+
+    let topic_params = TopicScoreParams {
+        topic_weight: 0.5,
+        time_in_mesh_weight: 0.0,
+        first_message_deliveries_weight: 1.0,
+        first_message_deliveries_decay: 1.0,
+        first_message_deliveries_cap: 2000.0,
+        invalid_message_deliveries_weight: -1.0,
+        invalid_message_deliveries_decay: 1.0,
+        ..Default::default()
+    };
+
+    params.topics.insert(topic_hash.clone(), topic_params);
+
+    let peer_id = PeerId::random();
+
+    let mut peer_score = PeerScore::new(params);
+    peer_score.add_peer(peer_id);
+    peer_score.graft(&peer_id, topic);
+
+    // deliver 10 messages and 2 invalid messages from the peer
+    for seq in 0..10 {
+        let (id, msg) = make_test_message(seq);
+        peer_score.validate_message(&peer_id, &id, &msg.topic);
+        peer_score.deliver_message(&peer_id, &id, &msg.topic);
+    }
+    for seq in 10..12 {
+        let (id, msg) = make_test_message(seq);
+        peer_score.reject_message(&peer_id, &id, &msg.topic, RejectReason::ValidationFailed);
+    }
+    peer_score.set_application_score(&peer_id, 3.0);
+    peer_score.add_penalty(&peer_id, 2);
+
+    let breakdown = peer_score.score_breakdown(&peer_id);
+    let topic_breakdown = &breakdown.topics[&topic_hash];
+    assert_eq!(topic_breakdown.time_in_mesh, 0.0);
+    assert_eq!(topic_breakdown.first_message_deliveries, 10.0);
+    assert_eq!(topic_breakdown.invalid_message_deliveries, -4.0);
+    assert_eq!(topic_breakdown.score, 3.0);
+    assert_eq!(breakdown.topic_score, 3.0);
+    assert_eq!(breakdown.application_score, 6.0);
+    assert_eq!(breakdown.ip_colocation_factor, 0.0);
+    assert_eq!(breakdown.behaviour_penalty, -4.0);
+    assert_eq!(breakdown.score, 5.0);
+    assert_eq!(breakdown.score, peer_score.score(&peer_id));
+
+    // unknown peers have an empty breakdown
+    assert_eq!(
+        peer_score.score_breakdown(&PeerId::random()),
+        PeerScoreBreakdown::default()
+    );
+}
@@ -34,7 +34,8 @@ use bytes::BytesMut;
 use futures::future;
 use futures::prelude::*;
 use libp2p_core::{
-    identity::PublicKey, InboundUpgrade, OutboundUpgrade, PeerId, ProtocolName, UpgradeInfo,
+    identity::PublicKey, InboundUpgrade, OutboundUpgrade, PeerId, PeerRecord, ProtocolName,
+    SignedEnvelope, UpgradeInfo,
 };
 use log::{debug, warn};
 use prost::Message as ProtobufMessage;
@@ -64,8 +65,10 @@ impl ProtocolConfig {
         validation_mode: ValidationMode,
         support_floodsub: bool,
     ) -> ProtocolConfig {
-        // support version 1.1.0 and 1.0.0 with user-customized prefix
+        // support episub, version 1.2.0, 1.1.0 and 1.0.0 with user-customized prefix
         let mut protocol_ids = vec![
+            ProtocolId::new(id_prefix.clone(), PeerKind::Episub),
+            ProtocolId::new(id_prefix.clone(), PeerKind::Gossipsubv1_2),
             ProtocolId::new(id_prefix.clone(), PeerKind::Gossipsubv1_1),
             ProtocolId::new(id_prefix, PeerKind::Gossipsub),
         ];
@@ -96,6 +99,8 @@ pub struct ProtocolId {
 impl ProtocolId {
     pub fn new(prefix: Cow<'static, str>, kind: PeerKind) -> Self {
         let protocol_id = match kind {
+            PeerKind::Episub => format!("/{}/{}", prefix, "1.2.0+episub"),
+            PeerKind::Gossipsubv1_2 => format!("/{}/{}", prefix, "1.2.0"),
             PeerKind::Gossipsubv1_1 => format!("/{}/{}", prefix, "1.1.0"),
             PeerKind::Gossipsub => format!("/{}/{}", prefix, "1.0.0"),
             PeerKind::Floodsub => format!("/{}/{}", "floodsub", "1.0.0"),
@@ -501,14 +506,16 @@ impl Decoder for GossipsubCodec {
                     .peers
                     .into_iter()
                     .filter_map(|info| {
-                        info.peer_id
+                        let peer_id = info
+                            .peer_id
                             .as_ref()
-                            .and_then(|id| PeerId::from_bytes(id).ok())
-                            .map(|peer_id|
-                                    //TODO signedPeerRecord, see https://github.com/libp2p/specs/pull/217
-                                    PeerInfo {
-                                        peer_id: Some(peer_id),
-                                    })
+                            .and_then(|id| PeerId::from_bytes(id).ok())?;
+                        Some(PeerInfo {
+                            peer_id: Some(peer_id),
+                            signed_peer_record: info
+                                .signed_peer_record
+                                .and_then(|bytes| decode_signed_peer_record(&peer_id, &bytes)),
+                        })
                     })
                     .collect::<Vec<PeerInfo>>();
 
@@ -520,10 +527,41 @@ impl Decoder for GossipsubCodec {
                 });
             }
 
+            let idontwant_msgs: Vec<GossipsubControlAction> = rpc_control
+                .idontwant
+                .into_iter()
+                .map(|idontwant| GossipsubControlAction::IDontWant {
+                    message_ids: idontwant
+                        .message_ids
+                        .into_iter()
+                        .map(MessageId::from)
+                        .collect::<Vec<_>>(),
+                })
+                .collect();
+
+            let choke_msgs: Vec<GossipsubControlAction> = rpc_control
+                .choke
+                .into_iter()
+                .map(|choke| GossipsubControlAction::Choke {
+                    topic_hash: TopicHash::from_raw(choke.topic_id.unwrap_or_default()),
+                })
+                .collect();
+
+            let unchoke_msgs: Vec<GossipsubControlAction> = rpc_control
+                .unchoke
+                .into_iter()
+                .map(|unchoke| GossipsubControlAction::Unchoke {
+                    topic_hash: TopicHash::from_raw(unchoke.topic_id.unwrap_or_default()),
+                })
+                .collect();
+
             control_msgs.extend(ihave_msgs);
             control_msgs.extend(iwant_msgs);
             control_msgs.extend(graft_msgs);
             control_msgs.extend(prune_msgs);
+            control_msgs.extend(idontwant_msgs);
+            control_msgs.extend(choke_msgs);
+            control_msgs.extend(unchoke_msgs);
         }
 
         Ok(Some(HandlerEvent::Message {
@@ -548,6 +586,32 @@ impl Decoder for GossipsubCodec {
     }
 }
 
+/// Decodes and verifies the signed peer record sent along with the given peer in a PRUNE.
+///
+/// Returns `None` if the record is invalid or does not belong to the peer.
+pub(crate) fn decode_signed_peer_record(peer_id: &PeerId, bytes: &[u8]) -> Option<PeerRecord> {
+    let record = match SignedEnvelope::from_protobuf_encoding(bytes)
+        .map_err(|e| e.to_string())
+        .and_then(|envelope| PeerRecord::from_signed_envelope(envelope).map_err(|e| e.to_string()))
+    {
+        Ok(record) => record,
+        Err(e) => {
+            debug!("Invalid signed peer record for peer {}: {}", peer_id, e);
+            return None;
+        }
+    };
+
+    if record.peer_id() != *peer_id {
+        debug!(
+            "Signed peer record of peer {} sent for peer {}",
+            record.peer_id(),
+            peer_id
+        );
+        return None;
+    }
+    Some(record)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -647,4 +711,23 @@ mod tests {
 
         QuickCheck::new().quickcheck(prop as fn(_) -> _)
     }
+
+    #[test]
+    fn decode_signed_peer_records() {
+        let keypair = Keypair::generate_ed25519();
+        let record =
+            PeerRecord::new(&keypair, vec!["/ip4/127.0.0.1/tcp/1234".parse().unwrap()]).unwrap();
+        let bytes = record.to_signed_envelope().into_protobuf_encoding();
+
+        assert_eq!(
+            decode_signed_peer_record(&record.peer_id(), &bytes),
+            Some(record)
+        );
+        // Records of other peers and invalid records are rejected.
+        assert_eq!(decode_signed_peer_record(&PeerId::random(), &bytes), None);
+        assert_eq!(
+            decode_signed_peer_record(&keypair.public().to_peer_id(), &bytes[1..]),
+            None
+        );
+    }
 }
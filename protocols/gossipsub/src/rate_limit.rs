@@ -0,0 +1,180 @@
+// Copyright 2022 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Token bucket rate limiting of published and received messages.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::Duration;
+use wasm_timer::Instant;
+
+/// The maximum rate of messages, allowing bursts of up to `messages` messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// The number of messages allowed per `interval`.
+    pub messages: u32,
+    /// The interval in which `messages` messages are allowed.
+    pub interval: Duration,
+}
+
+impl RateLimit {
+    /// Creates a rate limit of `messages` messages per `interval`.
+    pub fn new(messages: u32, interval: Duration) -> Self {
+        RateLimit { messages, interval }
+    }
+
+    /// Validates the rate limit.
+    pub(crate) fn validate(&self) -> Result<(), &'static str> {
+        if self.messages == 0 || self.interval.is_zero() {
+            return Err(
+                "Rate limits must allow a positive number of messages per positive interval",
+            );
+        }
+        Ok(())
+    }
+
+    /// The number of messages regained per second.
+    fn rate(&self) -> f64 {
+        f64::from(self.messages) / self.interval.as_secs_f64()
+    }
+}
+
+struct Bucket {
+    limit: RateLimit,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.limit.rate())
+            .min(f64::from(self.limit.messages));
+        self.last_refill = now;
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= f64::from(self.limit.messages)
+    }
+}
+
+/// Tracks the rate of messages per key with a token bucket each.
+pub(crate) struct RateLimiter<K> {
+    buckets: HashMap<K, Bucket>,
+}
+
+impl<K> Default for RateLimiter<K> {
+    fn default() -> Self {
+        RateLimiter {
+            buckets: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    /// Records a message for the key, returning false if the message exceeds the rate limit.
+    pub(crate) fn try_acquire(&mut self, key: K, limit: RateLimit) -> bool {
+        self.try_acquire_at(key, limit, Instant::now())
+    }
+
+    /// Returns whether a message for the key would be within the rate limit, without recording
+    /// it.
+    pub(crate) fn has_capacity(&mut self, key: &K, limit: RateLimit) -> bool {
+        self.has_capacity_at(key, limit, Instant::now())
+    }
+
+    /// Removes the buckets that have been refilled completely, as they behave like new ones.
+    pub(crate) fn prune(&mut self) {
+        self.prune_at(Instant::now())
+    }
+
+    fn try_acquire_at(&mut self, key: K, limit: RateLimit, now: Instant) -> bool {
+        let bucket = self.buckets.entry(key).or_insert_with(|| Bucket {
+            limit,
+            tokens: f64::from(limit.messages),
+            last_refill: now,
+        });
+        bucket.refill(now);
+        bucket.limit = limit;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn has_capacity_at(&mut self, key: &K, limit: RateLimit, now: Instant) -> bool {
+        match self.buckets.get_mut(key) {
+            Some(bucket) => {
+                bucket.refill(now);
+                bucket.limit = limit;
+                bucket.tokens >= 1.0
+            }
+            None => true,
+        }
+    }
+
+    fn prune_at(&mut self, now: Instant) {
+        self.buckets.retain(|_, bucket| {
+            bucket.refill(now);
+            !bucket.is_full()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_bursts_and_refills() {
+        let mut limiter = RateLimiter::default();
+        let limit = RateLimit::new(2, Duration::from_millis(100));
+        let start = Instant::now();
+
+        assert!(limiter.try_acquire_at("a", limit, start));
+        assert!(limiter.try_acquire_at("a", limit, start));
+        assert!(!limiter.has_capacity_at(&"a", limit, start));
+        assert!(!limiter.try_acquire_at("a", limit, start));
+        // Keys are limited independently.
+        assert!(limiter.has_capacity_at(&"b", limit, start));
+        assert!(limiter.try_acquire_at("b", limit, start));
+
+        let later = start + Duration::from_millis(60);
+        assert!(limiter.has_capacity_at(&"a", limit, later));
+        assert!(limiter.try_acquire_at("a", limit, later));
+        assert!(!limiter.try_acquire_at("a", limit, later));
+    }
+
+    #[test]
+    fn prunes_full_buckets() {
+        let mut limiter = RateLimiter::default();
+        let limit = RateLimit::new(1, Duration::from_millis(50));
+        let start = Instant::now();
+
+        assert!(limiter.try_acquire_at("a", limit, start));
+        limiter.prune_at(start);
+        assert_eq!(limiter.buckets.len(), 1);
+
+        limiter.prune_at(start + Duration::from_millis(60));
+        assert!(limiter.buckets.is_empty());
+    }
+}
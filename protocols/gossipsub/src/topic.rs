@@ -25,6 +25,9 @@ use prost::Message;
 use sha2::{Digest, Sha256};
 use std::fmt;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 /// A generic trait that can be extended for various hashing types for a topic.
 pub trait Hasher {
     /// The function that takes a topic string and creates a topic hash.
@@ -62,6 +65,8 @@ impl Hasher for Sha256Hash {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Encode)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(crate = "_serde", transparent))]
 pub struct TopicHash {
     /// The topic hash. Stored as a string to align with the protobuf API.
     hash: String,
@@ -0,0 +1,575 @@
+// Copyright 2022 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Tracing of the propagation of messages, in the spirit of the `RawTracer` of go-libp2p-pubsub.
+//!
+//! A [`GossipsubTracer`] set through [`crate::Gossipsub::with_tracer`] is notified of the messages
+//! published, delivered, rejected and received as duplicates, of changes to the mesh and of every
+//! RPC sent and received. With the `serde` feature, [`JsonTracer`] writes these events to a file
+//! as JSON lines.
+
+use crate::peer_score::RejectReason;
+use crate::rpc_proto;
+use crate::topic::TopicHash;
+use crate::types::{
+    GossipsubControlAction, GossipsubRpc, GossipsubSubscriptionAction, MessageId, PeerKind,
+};
+use libp2p_core::PeerId;
+
+/// Receives the events of a [`crate::Gossipsub`] behaviour. All methods do nothing by default.
+pub trait GossipsubTracer: Send + 'static {
+    /// A peer speaking the given protocol connected.
+    fn add_peer(&mut self, _peer_id: &PeerId, _kind: &PeerKind) {}
+
+    /// A peer disconnected.
+    fn remove_peer(&mut self, _peer_id: &PeerId) {}
+
+    /// We joined the mesh of a topic.
+    fn join(&mut self, _topic: &TopicHash) {}
+
+    /// We left the mesh of a topic.
+    fn leave(&mut self, _topic: &TopicHash) {}
+
+    /// A peer was added to the mesh of a topic.
+    fn graft(&mut self, _peer_id: &PeerId, _topic: &TopicHash) {}
+
+    /// A peer was removed from the mesh of a topic.
+    fn prune(&mut self, _peer_id: &PeerId, _topic: &TopicHash) {}
+
+    /// We published a message and sent it to our peers.
+    fn publish_message(&mut self, _message_id: &MessageId, _topic: &TopicHash) {}
+
+    /// A received message was delivered to the application.
+    fn deliver_message(
+        &mut self,
+        _message_id: &MessageId,
+        _propagation_source: &PeerId,
+        _topic: &TopicHash,
+    ) {
+    }
+
+    /// A received message was rejected or ignored. The message id is `None` for messages that
+    /// failed to decode or to be transformed.
+    fn reject_message(
+        &mut self,
+        _message_id: Option<&MessageId>,
+        _propagation_source: &PeerId,
+        _topic: &TopicHash,
+        _reason: RejectReason,
+    ) {
+    }
+
+    /// A message that was already received was received again.
+    fn duplicate_message(
+        &mut self,
+        _message_id: &MessageId,
+        _propagation_source: &PeerId,
+        _topic: &TopicHash,
+    ) {
+    }
+
+    /// An RPC was received from a peer. It is traced once its messages are handled.
+    fn recv_rpc(&mut self, _peer_id: &PeerId, _rpc: &RpcMeta) {}
+
+    /// An RPC was sent to a peer.
+    fn send_rpc(&mut self, _peer_id: &PeerId, _rpc: &RpcMeta) {}
+
+    /// Flushes the events buffered by the tracer, if any. Called at every heartbeat.
+    fn flush(&mut self) {}
+}
+
+/// A summary of an RPC sent or received.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RpcMeta {
+    /// The messages of the RPC.
+    pub messages: Vec<MessageMeta>,
+    /// The subscriptions of the RPC.
+    pub subscriptions: Vec<SubscriptionMeta>,
+    /// The control messages of the RPC.
+    pub control: ControlMeta,
+}
+
+/// A summary of a message in an RPC.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageMeta {
+    /// The id of the message, `None` if it was not handled, e.g. because it could not be
+    /// transformed.
+    pub message_id: Option<MessageId>,
+    /// The topic of the message.
+    pub topic: TopicHash,
+    /// The size of the data of the message.
+    pub size: usize,
+}
+
+/// A subscription in an RPC.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriptionMeta {
+    /// The topic subscribed to or unsubscribed from.
+    pub topic: TopicHash,
+    /// Whether it is a subscription or an unsubscription.
+    pub subscribe: bool,
+}
+
+/// A summary of the control messages in an RPC.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ControlMeta {
+    /// The IHAVE control messages.
+    pub ihave: Vec<IHaveMeta>,
+    /// The message ids of the IWANT control messages.
+    pub iwant: Vec<MessageId>,
+    /// The topics of the GRAFT control messages.
+    pub graft: Vec<TopicHash>,
+    /// The PRUNE control messages.
+    pub prune: Vec<PruneMeta>,
+    /// The message ids of the IDONTWANT control messages.
+    pub idontwant: Vec<MessageId>,
+    /// The topics of the CHOKE control messages.
+    pub choke: Vec<TopicHash>,
+    /// The topics of the UNCHOKE control messages.
+    pub unchoke: Vec<TopicHash>,
+}
+
+/// An IHAVE control message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IHaveMeta {
+    /// The topic of the announced messages.
+    pub topic: TopicHash,
+    /// The ids of the announced messages.
+    pub message_ids: Vec<MessageId>,
+}
+
+/// A PRUNE control message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PruneMeta {
+    /// The topic the peer is pruned from.
+    pub topic: TopicHash,
+    /// The peers exchanged along with the PRUNE.
+    pub peers: Vec<PeerId>,
+}
+
+impl RpcMeta {
+    /// Summarizes a received RPC. The ids of its messages are left unset until the messages
+    /// are handled.
+    pub(crate) fn from_rpc(rpc: &GossipsubRpc) -> Self {
+        let mut control = ControlMeta::default();
+        for action in &rpc.control_msgs {
+            match action {
+                GossipsubControlAction::IHave {
+                    topic_hash,
+                    message_ids,
+                } => control.ihave.push(IHaveMeta {
+                    topic: topic_hash.clone(),
+                    message_ids: message_ids.clone(),
+                }),
+                GossipsubControlAction::IWant { message_ids } => {
+                    control.iwant.extend(message_ids.iter().cloned())
+                }
+                GossipsubControlAction::Graft { topic_hash } => {
+                    control.graft.push(topic_hash.clone())
+                }
+                GossipsubControlAction::Prune {
+                    topic_hash, peers, ..
+                } => control.prune.push(PruneMeta {
+                    topic: topic_hash.clone(),
+                    peers: peers.iter().filter_map(|info| info.peer_id).collect(),
+                }),
+                GossipsubControlAction::IDontWant { message_ids } => {
+                    control.idontwant.extend(message_ids.iter().cloned())
+                }
+                GossipsubControlAction::Choke { topic_hash } => {
+                    control.choke.push(topic_hash.clone())
+                }
+                GossipsubControlAction::Unchoke { topic_hash } => {
+                    control.unchoke.push(topic_hash.clone())
+                }
+            }
+        }
+
+        RpcMeta {
+            messages: rpc
+                .messages
+                .iter()
+                .map(|message| MessageMeta {
+                    message_id: None,
+                    topic: message.topic.clone(),
+                    size: message.data.len(),
+                })
+                .collect(),
+            subscriptions: rpc
+                .subscriptions
+                .iter()
+                .map(|subscription| SubscriptionMeta {
+                    topic: subscription.topic_hash.clone(),
+                    subscribe: subscription.action == GossipsubSubscriptionAction::Subscribe,
+                })
+                .collect(),
+            control,
+        }
+    }
+
+    /// Summarizes an RPC to be sent, taking the ids of its messages in order from `message_ids`.
+    pub(crate) fn from_proto(
+        rpc: &rpc_proto::Rpc,
+        message_ids: &mut impl Iterator<Item = MessageId>,
+    ) -> Self {
+        let control_ids =
+            |ids: &[Vec<u8>]| ids.iter().cloned().map(MessageId::from).collect::<Vec<_>>();
+        let control = match &rpc.control {
+            Some(control) => ControlMeta {
+                ihave: control
+                    .ihave
+                    .iter()
+                    .map(|ihave| IHaveMeta {
+                        topic: TopicHash::from_raw(ihave.topic_id.clone().unwrap_or_default()),
+                        message_ids: control_ids(&ihave.message_ids),
+                    })
+                    .collect(),
+                iwant: control
+                    .iwant
+                    .iter()
+                    .flat_map(|iwant| control_ids(&iwant.message_ids))
+                    .collect(),
+                graft: control
+                    .graft
+                    .iter()
+                    .map(|graft| TopicHash::from_raw(graft.topic_id.clone().unwrap_or_default()))
+                    .collect(),
+                prune: control
+                    .prune
+                    .iter()
+                    .map(|prune| PruneMeta {
+                        topic: TopicHash::from_raw(prune.topic_id.clone().unwrap_or_default()),
+                        peers: prune
+                            .peers
+                            .iter()
+                            .filter_map(|info| {
+                                info.peer_id
+                                    .as_ref()
+                                    .and_then(|id| PeerId::from_bytes(id).ok())
+                            })
+                            .collect(),
+                    })
+                    .collect(),
+                idontwant: control
+                    .idontwant
+                    .iter()
+                    .flat_map(|idontwant| control_ids(&idontwant.message_ids))
+                    .collect(),
+                choke: control
+                    .choke
+                    .iter()
+                    .map(|choke| TopicHash::from_raw(choke.topic_id.clone().unwrap_or_default()))
+                    .collect(),
+                unchoke: control
+                    .unchoke
+                    .iter()
+                    .map(|unchoke| {
+                        TopicHash::from_raw(unchoke.topic_id.clone().unwrap_or_default())
+                    })
+                    .collect(),
+            },
+            None => ControlMeta::default(),
+        };
+
+        RpcMeta {
+            messages: rpc
+                .publish
+                .iter()
+                .map(|message| MessageMeta {
+                    message_id: message_ids.next(),
+                    topic: TopicHash::from_raw(message.topic.clone()),
+                    size: message.data.as_ref().map_or(0, Vec::len),
+                })
+                .collect(),
+            subscriptions: rpc
+                .subscriptions
+                .iter()
+                .map(|subscription| SubscriptionMeta {
+                    topic: TopicHash::from_raw(subscription.topic_id.clone().unwrap_or_default()),
+                    subscribe: subscription.subscribe == Some(true),
+                })
+                .collect(),
+            control,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+pub use json::JsonTracer;
+
+#[cfg(feature = "serde")]
+mod json {
+    use super::*;
+    use log::warn;
+    use serde_json::{json, Value};
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, BufWriter, Write};
+    use std::path::Path;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// A [`GossipsubTracer`] writing each event as a JSON object on its own line.
+    ///
+    /// Every object has a `type` naming the event, e.g. `"deliver_message"`, and a `timestamp` in
+    /// milliseconds since the Unix epoch. Peer ids are base58 encoded and message ids hex encoded.
+    ///
+    /// The events are buffered by the writer, which is flushed at every heartbeat.
+    pub struct JsonTracer<W = BufWriter<File>> {
+        writer: W,
+    }
+
+    impl JsonTracer {
+        /// Creates a tracer appending to the file at `path`, creating it if needed.
+        pub fn new(path: impl AsRef<Path>) -> io::Result<Self> {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            Ok(JsonTracer::from_writer(BufWriter::new(file)))
+        }
+    }
+
+    impl<W: Write> JsonTracer<W> {
+        /// Creates a tracer writing to `writer`.
+        pub fn from_writer(writer: W) -> Self {
+            JsonTracer { writer }
+        }
+
+        fn write(&mut self, event_type: &str, mut event: Value) {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX))
+                .unwrap_or_default();
+            event["type"] = json!(event_type);
+            event["timestamp"] = json!(timestamp);
+            let result = serde_json::to_writer(&mut self.writer, &event)
+                .map_err(io::Error::from)
+                .and_then(|()| self.writer.write_all(b"\n"));
+            if let Err(e) = result {
+                warn!("Failed to write gossipsub trace: {}", e);
+            }
+        }
+    }
+
+    fn message_ids(message_ids: &[MessageId]) -> Value {
+        message_ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    fn rpc(rpc: &RpcMeta) -> Value {
+        json!({
+            "messages": rpc.messages.iter().map(|message| json!({
+                "message_id": message.message_id.as_ref().map(|id| id.to_string()),
+                "topic": message.topic.as_str(),
+                "size": message.size,
+            })).collect::<Value>(),
+            "subscriptions": rpc.subscriptions.iter().map(|subscription| json!({
+                "topic": subscription.topic.as_str(),
+                "subscribe": subscription.subscribe,
+            })).collect::<Value>(),
+            "control": {
+                "ihave": rpc.control.ihave.iter().map(|ihave| json!({
+                    "topic": ihave.topic.as_str(),
+                    "message_ids": message_ids(&ihave.message_ids),
+                })).collect::<Value>(),
+                "iwant": message_ids(&rpc.control.iwant),
+                "graft": rpc.control.graft.iter().map(|topic| topic.as_str()).collect::<Value>(),
+                "prune": rpc.control.prune.iter().map(|prune| json!({
+                    "topic": prune.topic.as_str(),
+                    "peers": prune.peers.iter().map(|peer| peer.to_string()).collect::<Value>(),
+                })).collect::<Value>(),
+                "idontwant": message_ids(&rpc.control.idontwant),
+                "choke": rpc.control.choke.iter().map(|topic| topic.as_str()).collect::<Value>(),
+                "unchoke": rpc.control.unchoke.iter().map(|topic| topic.as_str()).collect::<Value>(),
+            },
+        })
+    }
+
+    impl<W: Write + Send + 'static> GossipsubTracer for JsonTracer<W> {
+        fn add_peer(&mut self, peer_id: &PeerId, kind: &PeerKind) {
+            self.write(
+                "add_peer",
+                json!({ "peer_id": peer_id.to_string(), "protocol": kind.to_string() }),
+            );
+        }
+
+        fn remove_peer(&mut self, peer_id: &PeerId) {
+            self.write("remove_peer", json!({ "peer_id": peer_id.to_string() }));
+        }
+
+        fn join(&mut self, topic: &TopicHash) {
+            self.write("join", json!({ "topic": topic.as_str() }));
+        }
+
+        fn leave(&mut self, topic: &TopicHash) {
+            self.write("leave", json!({ "topic": topic.as_str() }));
+        }
+
+        fn graft(&mut self, peer_id: &PeerId, topic: &TopicHash) {
+            self.write(
+                "graft",
+                json!({ "peer_id": peer_id.to_string(), "topic": topic.as_str() }),
+            );
+        }
+
+        fn prune(&mut self, peer_id: &PeerId, topic: &TopicHash) {
+            self.write(
+                "prune",
+                json!({ "peer_id": peer_id.to_string(), "topic": topic.as_str() }),
+            );
+        }
+
+        fn publish_message(&mut self, message_id: &MessageId, topic: &TopicHash) {
+            self.write(
+                "publish_message",
+                json!({ "message_id": message_id.to_string(), "topic": topic.as_str() }),
+            );
+        }
+
+        fn deliver_message(
+            &mut self,
+            message_id: &MessageId,
+            propagation_source: &PeerId,
+            topic: &TopicHash,
+        ) {
+            self.write(
+                "deliver_message",
+                json!({
+                    "message_id": message_id.to_string(),
+                    "propagation_source": propagation_source.to_string(),
+                    "topic": topic.as_str(),
+                }),
+            );
+        }
+
+        fn reject_message(
+            &mut self,
+            message_id: Option<&MessageId>,
+            propagation_source: &PeerId,
+            topic: &TopicHash,
+            reason: RejectReason,
+        ) {
+            self.write(
+                "reject_message",
+                json!({
+                    "message_id": message_id.map(|id| id.to_string()),
+                    "propagation_source": propagation_source.to_string(),
+                    "topic": topic.as_str(),
+                    "reason": format!("{:?}", reason),
+                }),
+            );
+        }
+
+        fn duplicate_message(
+            &mut self,
+            message_id: &MessageId,
+            propagation_source: &PeerId,
+            topic: &TopicHash,
+        ) {
+            self.write(
+                "duplicate_message",
+                json!({
+                    "message_id": message_id.to_string(),
+                    "propagation_source": propagation_source.to_string(),
+                    "topic": topic.as_str(),
+                }),
+            );
+        }
+
+        fn recv_rpc(&mut self, peer_id: &PeerId, meta: &RpcMeta) {
+            self.write(
+                "recv_rpc",
+                json!({ "peer_id": peer_id.to_string(), "rpc": rpc(meta) }),
+            );
+        }
+
+        fn send_rpc(&mut self, peer_id: &PeerId, meta: &RpcMeta) {
+            self.write(
+                "send_rpc",
+                json!({ "peer_id": peer_id.to_string(), "rpc": rpc(meta) }),
+            );
+        }
+
+        fn flush(&mut self) {
+            if let Err(e) = self.writer.flush() {
+                warn!("Failed to flush gossipsub traces: {}", e);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Clone, Default)]
+        struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for SharedBuffer {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        #[test]
+        fn writes_json_lines() {
+            let buffer = SharedBuffer::default();
+            let mut tracer = JsonTracer::from_writer(buffer.clone());
+            let peer_id = PeerId::random();
+            let topic = TopicHash::from_raw("topic");
+
+            tracer.graft(&peer_id, &topic);
+            tracer.send_rpc(
+                &peer_id,
+                &RpcMeta {
+                    control: ControlMeta {
+                        iwant: vec![MessageId::new(&[1, 2])],
+                        ..ControlMeta::default()
+                    },
+                    ..RpcMeta::default()
+                },
+            );
+
+            let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+            let lines: Vec<Value> = output
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+            assert_eq!(lines.len(), 2);
+            assert_eq!(lines[0]["type"], "graft");
+            assert_eq!(lines[0]["peer_id"], peer_id.to_string());
+            assert_eq!(lines[0]["topic"], "topic");
+            assert_eq!(lines[1]["type"], "send_rpc");
+            assert_eq!(lines[1]["rpc"]["control"]["iwant"], json!(["0102"]));
+        }
+
+        #[test]
+        fn buffers_events_until_flushed() {
+            let buffer = SharedBuffer::default();
+            let mut tracer = JsonTracer::from_writer(BufWriter::new(buffer.clone()));
+
+            tracer.join(&TopicHash::from_raw("topic"));
+            assert!(buffer.0.lock().unwrap().is_empty());
+
+            GossipsubTracer::flush(&mut tracer);
+            let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+            assert_eq!(output.lines().count(), 1);
+        }
+    }
+}
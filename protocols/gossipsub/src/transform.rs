@@ -24,8 +24,13 @@
 //! This is primarily designed to allow applications to implement their own custom compression
 //! algorithms that can be topic-specific. Once the raw data is transformed the message-id is then
 //! calculated, allowing for applications to employ message-id functions post compression.
+//!
+//! [`DeflateTransform`] is provided as a ready-made compressing transform.
 
 use crate::{GossipsubMessage, RawGossipsubMessage, TopicHash};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use std::collections::HashSet;
+use std::io::{self, Read, Write};
 
 /// A general trait of transforming a [`RawGossipsubMessage`] into a [`GossipsubMessage`]. The
 /// [`RawGossipsubMessage`] is obtained from the wire and the [`GossipsubMessage`] is used to
@@ -76,3 +81,177 @@ impl DataTransform for IdentityTransform {
         Ok(data)
     }
 }
+
+/// A transform compressing the data of messages with deflate.
+///
+/// By default the data of all topics is compressed. Use [`DeflateTransform::with_topics`] to only
+/// compress the data of some topics, the data of the remaining topics is propagated as is. All
+/// peers of a compressed topic must use the same transform for that topic.
+///
+/// Inbound data that decompresses to more than `max_decompressed_size` bytes is rejected as an
+/// invalid message.
+#[derive(Debug, Clone)]
+pub struct DeflateTransform {
+    topics: Option<HashSet<TopicHash>>,
+    level: Compression,
+    max_decompressed_size: usize,
+}
+
+impl DeflateTransform {
+    /// Creates a transform compressing the data of all topics, which rejects inbound data
+    /// decompressing to more than `max_decompressed_size` bytes.
+    pub fn new(max_decompressed_size: usize) -> Self {
+        DeflateTransform {
+            topics: None,
+            level: Compression::default(),
+            max_decompressed_size,
+        }
+    }
+
+    /// Only compresses the data of the given topics.
+    pub fn with_topics(mut self, topics: impl IntoIterator<Item = TopicHash>) -> Self {
+        self.topics = Some(topics.into_iter().collect());
+        self
+    }
+
+    /// Sets the compression level, between 0 (no compression) and 9 (best compression).
+    pub fn with_compression_level(mut self, level: u32) -> Self {
+        self.level = Compression::new(level.min(9));
+        self
+    }
+
+    fn is_compressed(&self, topic: &TopicHash) -> bool {
+        match &self.topics {
+            Some(topics) => topics.contains(topic),
+            None => true,
+        }
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, io::Error> {
+        let mut decompressed = Vec::new();
+        // Read at most one byte more than allowed to detect oversized data without decompressing
+        // all of it.
+        DeflateDecoder::new(data)
+            .take((self.max_decompressed_size as u64).saturating_add(1))
+            .read_to_end(&mut decompressed)?;
+        if decompressed.len() > self.max_decompressed_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Decompressed data exceeds the maximum size",
+            ));
+        }
+        Ok(decompressed)
+    }
+}
+
+impl DataTransform for DeflateTransform {
+    fn inbound_transform(
+        &self,
+        raw_message: RawGossipsubMessage,
+    ) -> Result<GossipsubMessage, std::io::Error> {
+        let data = if self.is_compressed(&raw_message.topic) {
+            self.decompress(&raw_message.data)?
+        } else {
+            raw_message.data
+        };
+        Ok(GossipsubMessage {
+            source: raw_message.source,
+            data,
+            sequence_number: raw_message.sequence_number,
+            topic: raw_message.topic,
+        })
+    }
+
+    fn outbound_transform(
+        &self,
+        topic: &TopicHash,
+        data: Vec<u8>,
+    ) -> Result<Vec<u8>, std::io::Error> {
+        if !self.is_compressed(topic) {
+            return Ok(data);
+        }
+        if data.len() > self.max_decompressed_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Data exceeds the maximum decompressed size",
+            ));
+        }
+        let mut encoder = DeflateEncoder::new(Vec::new(), self.level);
+        encoder.write_all(&data)?;
+        encoder.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw_message(topic: &str, data: Vec<u8>) -> RawGossipsubMessage {
+        RawGossipsubMessage {
+            source: None,
+            data,
+            sequence_number: None,
+            topic: TopicHash::from_raw(topic),
+            signature: None,
+            key: None,
+            validated: false,
+        }
+    }
+
+    #[test]
+    fn deflate_roundtrip_on_selected_topics() {
+        let transform =
+            DeflateTransform::new(1024).with_topics(vec![TopicHash::from_raw("compressed")]);
+        let data = vec![7; 512];
+
+        let compressed = transform
+            .outbound_transform(&TopicHash::from_raw("compressed"), data.clone())
+            .unwrap();
+        assert!(compressed.len() < data.len());
+        let message = transform
+            .inbound_transform(raw_message("compressed", compressed))
+            .unwrap();
+        assert_eq!(message.data, data);
+
+        let plain = transform
+            .outbound_transform(&TopicHash::from_raw("plain"), data.clone())
+            .unwrap();
+        assert_eq!(plain, data);
+        let message = transform
+            .inbound_transform(raw_message("plain", plain))
+            .unwrap();
+        assert_eq!(message.data, data);
+    }
+
+    #[test]
+    fn deflate_without_size_limit() {
+        let transform = DeflateTransform::new(usize::MAX);
+        let data = vec![7; 512];
+
+        let compressed = transform
+            .outbound_transform(&TopicHash::from_raw("topic"), data.clone())
+            .unwrap();
+        let message = transform
+            .inbound_transform(raw_message("topic", compressed))
+            .unwrap();
+        assert_eq!(message.data, data);
+    }
+
+    #[test]
+    fn deflate_rejects_oversized_data() {
+        let transform = DeflateTransform::new(1024);
+        let bomb = DeflateTransform::new(usize::MAX)
+            .outbound_transform(&TopicHash::from_raw("topic"), vec![0; 1024 * 1024])
+            .unwrap();
+
+        assert!(transform
+            .inbound_transform(raw_message("topic", bomb))
+            .is_err());
+        assert!(transform
+            .outbound_transform(&TopicHash::from_raw("topic"), vec![0; 1025])
+            .is_err());
+        assert!(transform
+            .inbound_transform(raw_message("topic", vec![1, 2, 3]))
+            .is_err());
+    }
+}
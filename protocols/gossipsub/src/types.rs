@@ -21,11 +21,14 @@
 //! A collection of types using the Gossipsub system.
 use crate::rpc_proto;
 use crate::TopicHash;
-use libp2p_core::{connection::ConnectionId, PeerId};
+use libp2p_core::{connection::ConnectionId, PeerId, PeerRecord};
 use prometheus_client::encoding::text::Encode;
 use prost::Message;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use wasm_timer::Instant;
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
@@ -46,6 +49,7 @@ pub enum MessageAcceptance {
 macro_rules! declare_message_id_type {
     ($name: ident, $name_string: expr) => {
         #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+        #[cfg_attr(feature = "serde", serde(crate = "_serde"))]
         #[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
         pub struct $name(pub Vec<u8>);
 
@@ -92,11 +96,20 @@ pub struct PeerConnections {
     pub kind: PeerKind,
     /// Its current connections.
     pub connections: Vec<ConnectionId>,
+    /// Messages the peer asked us not to forward via IDONTWANT, with the time of the request.
+    pub dont_send: HashMap<MessageId, Instant>,
+    /// The mesh topics on which the peer choked us via CHOKE. We only announce the messages we
+    /// forward on these topics to the peer via IHAVE.
+    pub choking: HashSet<TopicHash>,
 }
 
 /// Describes the types of peers that can exist in the gossipsub context.
 #[derive(Debug, Clone, PartialEq, Hash, Encode, Eq)]
 pub enum PeerKind {
+    /// A gossipsub 1.2 peer supporting the episub CHOKE and UNCHOKE control messages.
+    Episub,
+    /// A gossipsub 1.2 peer.
+    Gossipsubv1_2,
     /// A gossipsub 1.1 peer.
     Gossipsubv1_1,
     /// A gossipsub 1.0 peer.
@@ -107,6 +120,38 @@ pub enum PeerKind {
     NotSupported,
 }
 
+/// The reason a message could not be sent to a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Encode)]
+pub enum SendFailure {
+    /// The send queue of the connection was full.
+    QueueFull,
+    /// The message was not sent within the configured send queue timeout.
+    Timeout,
+}
+
+/// The number of messages that could not be sent to a peer, as its connection did not keep up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FailedMessages {
+    /// The number of messages dropped because the send queue was full.
+    pub queue_full: usize,
+    /// The number of messages dropped because they timed out in the send queue.
+    pub timeout: usize,
+}
+
+impl FailedMessages {
+    /// The total number of messages that could not be sent.
+    pub fn total(&self) -> usize {
+        self.queue_full + self.timeout
+    }
+
+    pub(crate) fn record(&mut self, reason: SendFailure, count: usize) {
+        match reason {
+            SendFailure::QueueFull => self.queue_full += count,
+            SendFailure::Timeout => self.timeout += count,
+        }
+    }
+}
+
 /// A message received by the gossipsub system and stored locally in caches..
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub struct RawGossipsubMessage {
@@ -196,12 +241,23 @@ pub enum GossipsubSubscriptionAction {
     Unsubscribe,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PeerInfo {
     pub peer_id: Option<PeerId>,
-    //TODO add this when RFC: Signed Address Records got added to the spec (see pull request
-    // https://github.com/libp2p/specs/pull/217)
-    //pub signed_peer_record: ?,
+    /// The verified signed peer record of the peer, carrying its addresses.
+    pub signed_peer_record: Option<PeerRecord>,
+}
+
+impl Eq for PeerInfo {}
+
+impl Hash for PeerInfo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.peer_id.hash(state);
+        if let Some(record) = &self.signed_peer_record {
+            record.seq().hash(state);
+            record.addresses().hash(state);
+        }
+    }
 }
 
 /// A Control message received by the gossipsub system.
@@ -233,6 +289,24 @@ pub enum GossipsubControlAction {
         /// The backoff time in seconds before we allow to reconnect
         backoff: Option<u64>,
     },
+    /// The node already received the messages and doesn't want them forwarded - IDontWant
+    /// control message.
+    IDontWant {
+        /// A list of message ids the node doesn't want to receive.
+        message_ids: Vec<MessageId>,
+    },
+    /// The node asks a mesh peer to only announce the messages of a topic via IHAVE instead of
+    /// forwarding them - Choke control message.
+    Choke {
+        /// The mesh topic the peer is choked on.
+        topic_hash: TopicHash,
+    },
+    /// The node asks a choked mesh peer to forward the messages of a topic again - Unchoke control
+    /// message.
+    Unchoke {
+        /// The mesh topic the peer is unchoked on.
+        topic_hash: TopicHash,
+    },
 }
 
 /// An RPC received/sent.
@@ -289,6 +363,9 @@ impl From<GossipsubRpc> for rpc_proto::Rpc {
             iwant: Vec::new(),
             graft: Vec::new(),
             prune: Vec::new(),
+            idontwant: Vec::new(),
+            choke: Vec::new(),
+            unchoke: Vec::new(),
         };
 
         let empty_control_msg = rpc.control_msgs.is_empty();
@@ -329,14 +406,33 @@ impl From<GossipsubRpc> for rpc_proto::Rpc {
                             .into_iter()
                             .map(|info| rpc_proto::PeerInfo {
                                 peer_id: info.peer_id.map(|id| id.to_bytes()),
-                                /// TODO, see https://github.com/libp2p/specs/pull/217
-                                signed_peer_record: None,
+                                signed_peer_record: info.signed_peer_record.map(|record| {
+                                    record.into_signed_envelope().into_protobuf_encoding()
+                                }),
                             })
                             .collect(),
                         backoff,
                     };
                     control.prune.push(rpc_prune);
                 }
+                GossipsubControlAction::IDontWant { message_ids } => {
+                    let rpc_idontwant = rpc_proto::ControlIDontWant {
+                        message_ids: message_ids.into_iter().map(|msg_id| msg_id.0).collect(),
+                    };
+                    control.idontwant.push(rpc_idontwant);
+                }
+                GossipsubControlAction::Choke { topic_hash } => {
+                    let rpc_choke = rpc_proto::ControlChoke {
+                        topic_id: Some(topic_hash.into_string()),
+                    };
+                    control.choke.push(rpc_choke);
+                }
+                GossipsubControlAction::Unchoke { topic_hash } => {
+                    let rpc_unchoke = rpc_proto::ControlUnChoke {
+                        topic_id: Some(topic_hash.into_string()),
+                    };
+                    control.unchoke.push(rpc_unchoke);
+                }
             }
         }
 
@@ -375,6 +471,8 @@ impl PeerKind {
             Self::Floodsub => "Floodsub",
             Self::Gossipsub => "Gossipsub v1.0",
             Self::Gossipsubv1_1 => "Gossipsub v1.1",
+            Self::Gossipsubv1_2 => "Gossipsub v1.2",
+            Self::Episub => "Episub",
         }
     }
 }
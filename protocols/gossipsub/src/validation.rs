@@ -0,0 +1,444 @@
+// Copyright 2022 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Asynchronous validation of received messages.
+//!
+//! Instead of validating every message of a topic through [`crate::GossipsubEvent::Message`] and
+//! [`crate::Gossipsub::report_message_validation_result`], applications can register a
+//! [`MessageValidator`] for the topic. Received messages of the topic are then validated in
+//! batches with a bounded number of concurrent validations, and only accepted messages are
+//! reported to the application.
+
+use crate::types::{GossipsubMessage, MessageAcceptance, MessageId};
+use crate::TopicHash;
+use futures::future::{self, BoxFuture, Either};
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, StreamExt};
+use libp2p_core::PeerId;
+use log::{debug, warn};
+use std::collections::{HashMap, VecDeque};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use wasm_timer::{Delay, Instant};
+
+/// Validates the received messages of a topic.
+pub trait MessageValidator: Send + 'static {
+    /// Validates a batch of messages, returning the acceptance of each message in the same order.
+    ///
+    /// Messages without an acceptance in the returned list are ignored.
+    fn validate(
+        &mut self,
+        messages: Vec<GossipsubMessage>,
+    ) -> BoxFuture<'static, Vec<MessageAcceptance>>;
+}
+
+impl<F> MessageValidator for F
+where
+    F: FnMut(Vec<GossipsubMessage>) -> BoxFuture<'static, Vec<MessageAcceptance>> + Send + 'static,
+{
+    fn validate(
+        &mut self,
+        messages: Vec<GossipsubMessage>,
+    ) -> BoxFuture<'static, Vec<MessageAcceptance>> {
+        self(messages)
+    }
+}
+
+/// Configuration of the validation of a topic with a [`MessageValidator`].
+#[derive(Debug, Clone)]
+pub struct ValidatorConfig {
+    /// The maximum number of messages passed to the validator at once. The default is 1, i.e.
+    /// messages are validated one by one.
+    pub max_batch_size: usize,
+
+    /// The maximum number of batches being validated concurrently. Further messages wait until a
+    /// validation completes. The default is 16.
+    pub max_concurrent_validations: usize,
+
+    /// The maximum time a message may wait for and take in validation. Messages that are not
+    /// validated in time are treated as [`MessageAcceptance::Ignore`]. The default is 5 seconds.
+    pub timeout: Duration,
+}
+
+impl Default for ValidatorConfig {
+    fn default() -> Self {
+        ValidatorConfig {
+            max_batch_size: 1,
+            max_concurrent_validations: 16,
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl ValidatorConfig {
+    /// Validates the configuration.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.max_batch_size == 0 {
+            return Err("The max_batch_size parameter should be positive.");
+        }
+        if self.max_concurrent_validations == 0 {
+            return Err("The max_concurrent_validations parameter should be positive.");
+        }
+        Ok(())
+    }
+}
+
+/// A received message waiting for its validation.
+pub(crate) struct PendingMessage {
+    pub(crate) message_id: MessageId,
+    pub(crate) propagation_source: PeerId,
+    pub(crate) message: GossipsubMessage,
+    received: Instant,
+}
+
+/// The validator of a topic and the messages waiting for it.
+struct TopicValidator {
+    validator: Box<dyn MessageValidator>,
+    config: ValidatorConfig,
+    queue: VecDeque<PendingMessage>,
+    in_flight: usize,
+    /// Identifies the registration, which is kept when the validator is replaced.
+    generation: u64,
+}
+
+type Validation = BoxFuture<'static, (TopicHash, u64, Vec<(PendingMessage, MessageAcceptance)>)>;
+
+/// Validates received messages with the validators registered for their topics.
+#[derive(Default)]
+pub(crate) struct ValidationPipeline {
+    validators: HashMap<TopicHash, TopicValidator>,
+    validations: FuturesUnordered<Validation>,
+    /// Messages that expired while waiting for their validation.
+    expired: Vec<(PendingMessage, MessageAcceptance)>,
+    /// The generation of the next registration of a validator.
+    next_generation: u64,
+}
+
+impl ValidationPipeline {
+    /// Registers the validator for a topic, replacing a previous one. Messages waiting for the
+    /// previous validator are validated by the new one.
+    pub(crate) fn register(
+        &mut self,
+        topic: TopicHash,
+        validator: Box<dyn MessageValidator>,
+        config: ValidatorConfig,
+    ) {
+        let (queue, in_flight, generation) = match self.validators.remove(&topic) {
+            Some(previous) => (previous.queue, previous.in_flight, previous.generation),
+            None => {
+                self.next_generation += 1;
+                (VecDeque::new(), 0, self.next_generation)
+            }
+        };
+        self.validators.insert(
+            topic,
+            TopicValidator {
+                validator,
+                config,
+                queue,
+                in_flight,
+                generation,
+            },
+        );
+    }
+
+    /// Removes the validator of a topic. The results of its ongoing validations are ignored.
+    ///
+    /// Returns the messages still waiting for their validation.
+    pub(crate) fn unregister(&mut self, topic: &TopicHash) -> Option<Vec<PendingMessage>> {
+        self.validators
+            .remove(topic)
+            .map(|validator| validator.queue.into())
+    }
+
+    /// Returns true if a validator is registered for the topic.
+    pub(crate) fn has_validator(&self, topic: &TopicHash) -> bool {
+        self.validators.contains_key(topic)
+    }
+
+    /// Queues a message for validation by the validator of its topic.
+    pub(crate) fn enqueue(
+        &mut self,
+        message_id: MessageId,
+        propagation_source: PeerId,
+        message: GossipsubMessage,
+    ) {
+        if let Some(validator) = self.validators.get_mut(&message.topic) {
+            validator.queue.push_back(PendingMessage {
+                message_id,
+                propagation_source,
+                message,
+                received: Instant::now(),
+            });
+        }
+    }
+
+    /// Starts the validation of queued messages and returns the results of a completed batch.
+    pub(crate) fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Vec<(PendingMessage, MessageAcceptance)>> {
+        for (topic, validator) in self.validators.iter_mut() {
+            let timeout = validator.config.timeout;
+            // Messages that waited for too long are ignored.
+            while let Some(pending) = validator.queue.front() {
+                if pending.received.elapsed() < timeout {
+                    break;
+                }
+                let pending = validator.queue.pop_front().expect("queue to be non-empty");
+                debug!(
+                    "Message {} timed out waiting for validation",
+                    pending.message_id
+                );
+                self.expired.push((pending, MessageAcceptance::Ignore));
+            }
+
+            while !validator.queue.is_empty()
+                && validator.in_flight < validator.config.max_concurrent_validations
+            {
+                let len = validator.queue.len().min(validator.config.max_batch_size);
+                let batch: Vec<_> = validator.queue.drain(..len).collect();
+                let validation = validator
+                    .validator
+                    .validate(batch.iter().map(|p| p.message.clone()).collect());
+                let remaining = timeout.saturating_sub(batch[0].received.elapsed());
+                let topic = topic.clone();
+                let generation = validator.generation;
+                validator.in_flight += 1;
+                self.validations.push(
+                    future::select(validation, Delay::new(remaining))
+                        .map(move |result| {
+                            let acceptances = match result {
+                                Either::Left((acceptances, _)) => acceptances,
+                                Either::Right(_) => {
+                                    debug!("Validation of {} messages timed out", batch.len());
+                                    Vec::new()
+                                }
+                            };
+                            if !acceptances.is_empty() && acceptances.len() != batch.len() {
+                                warn!(
+                                    "Validator returned {} results for {} messages",
+                                    acceptances.len(),
+                                    batch.len()
+                                );
+                            }
+                            let mut acceptances = acceptances.into_iter();
+                            let results = batch
+                                .into_iter()
+                                .map(|pending| {
+                                    let acceptance =
+                                        acceptances.next().unwrap_or(MessageAcceptance::Ignore);
+                                    (pending, acceptance)
+                                })
+                                .collect();
+                            (topic, generation, results)
+                        })
+                        .boxed(),
+                );
+            }
+        }
+
+        if !self.expired.is_empty() {
+            return Poll::Ready(std::mem::take(&mut self.expired));
+        }
+
+        match self.validations.poll_next_unpin(cx) {
+            Poll::Ready(Some((topic, generation, results))) => {
+                match self.validators.get_mut(&topic) {
+                    Some(validator) if validator.generation == generation => {
+                        validator.in_flight = validator.in_flight.saturating_sub(1);
+                        // Start the validation of further messages.
+                        if !validator.queue.is_empty() {
+                            cx.waker().wake_by_ref();
+                        }
+                        Poll::Ready(results)
+                    }
+                    // The validator has been unregistered since.
+                    _ => Poll::Ready(
+                        results
+                            .into_iter()
+                            .map(|(pending, _)| (pending, MessageAcceptance::Ignore))
+                            .collect(),
+                    ),
+                }
+            }
+            Poll::Ready(None) | Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::noop_waker;
+
+    fn message(topic: &TopicHash, data: u8) -> GossipsubMessage {
+        GossipsubMessage {
+            source: None,
+            data: vec![data],
+            sequence_number: None,
+            topic: topic.clone(),
+        }
+    }
+
+    fn poll_results(pipeline: &mut ValidationPipeline) -> Vec<(Vec<u8>, MessageAcceptance)> {
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut results = Vec::new();
+        while let Poll::Ready(batch) = pipeline.poll(&mut cx) {
+            results.extend(
+                batch
+                    .into_iter()
+                    .map(|(pending, acceptance)| (pending.message.data, acceptance)),
+            );
+        }
+        results
+    }
+
+    #[test]
+    fn validates_messages_in_batches() {
+        let topic = TopicHash::from_raw("topic");
+        let mut pipeline = ValidationPipeline::default();
+        let batch_sizes = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sizes = batch_sizes.clone();
+        pipeline.register(
+            topic.clone(),
+            Box::new(move |messages: Vec<GossipsubMessage>| {
+                sizes.lock().unwrap().push(messages.len());
+                let acceptances = messages
+                    .iter()
+                    .map(|m| {
+                        if m.data[0] % 2 == 0 {
+                            MessageAcceptance::Accept
+                        } else {
+                            MessageAcceptance::Reject
+                        }
+                    })
+                    .collect();
+                future::ready(acceptances).boxed()
+            }),
+            ValidatorConfig {
+                max_batch_size: 2,
+                ..ValidatorConfig::default()
+            },
+        );
+
+        for i in 0..5 {
+            pipeline.enqueue(MessageId::new(&[i]), PeerId::random(), message(&topic, i));
+        }
+
+        let results = poll_results(&mut pipeline);
+        assert_eq!(results.len(), 5);
+        for (data, acceptance) in results {
+            if data[0] % 2 == 0 {
+                assert!(matches!(acceptance, MessageAcceptance::Accept));
+            } else {
+                assert!(matches!(acceptance, MessageAcceptance::Reject));
+            }
+        }
+        assert_eq!(*batch_sizes.lock().unwrap(), vec![2, 2, 1]);
+    }
+
+    #[test]
+    fn limits_concurrent_validations() {
+        let topic = TopicHash::from_raw("topic");
+        let mut pipeline = ValidationPipeline::default();
+        pipeline.register(
+            topic.clone(),
+            Box::new(|_: Vec<GossipsubMessage>| future::pending().boxed()),
+            ValidatorConfig {
+                max_concurrent_validations: 2,
+                ..ValidatorConfig::default()
+            },
+        );
+
+        for i in 0..3 {
+            pipeline.enqueue(MessageId::new(&[i]), PeerId::random(), message(&topic, i));
+        }
+
+        assert!(poll_results(&mut pipeline).is_empty());
+        assert_eq!(pipeline.validations.len(), 2);
+        assert_eq!(pipeline.validators[&topic].queue.len(), 1);
+    }
+
+    #[test]
+    fn ignores_results_after_unregister() {
+        let topic = TopicHash::from_raw("topic");
+        let mut pipeline = ValidationPipeline::default();
+        let (tx, rx) = futures::channel::oneshot::channel();
+        let mut rx = Some(rx);
+        pipeline.register(
+            topic.clone(),
+            Box::new(move |_: Vec<GossipsubMessage>| {
+                rx.take()
+                    .unwrap()
+                    .map(|_| vec![MessageAcceptance::Accept])
+                    .boxed()
+            }),
+            ValidatorConfig::default(),
+        );
+
+        pipeline.enqueue(MessageId::new(&[0]), PeerId::random(), message(&topic, 0));
+        assert!(poll_results(&mut pipeline).is_empty());
+
+        // A new validator of the topic does not adopt the ongoing validation.
+        assert!(pipeline.unregister(&topic).unwrap().is_empty());
+        pipeline.register(
+            topic.clone(),
+            Box::new(|_: Vec<GossipsubMessage>| future::pending().boxed()),
+            ValidatorConfig::default(),
+        );
+        tx.send(()).unwrap();
+
+        let results = poll_results(&mut pipeline);
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0].1, MessageAcceptance::Ignore));
+    }
+
+    #[test]
+    fn ignores_messages_on_timeout() {
+        let topic = TopicHash::from_raw("topic");
+        let mut pipeline = ValidationPipeline::default();
+        pipeline.register(
+            topic.clone(),
+            Box::new(|_: Vec<GossipsubMessage>| future::pending().boxed()),
+            ValidatorConfig {
+                max_concurrent_validations: 1,
+                timeout: Duration::from_millis(50),
+                ..ValidatorConfig::default()
+            },
+        );
+
+        for i in 0..2 {
+            pipeline.enqueue(MessageId::new(&[i]), PeerId::random(), message(&topic, i));
+        }
+        assert!(poll_results(&mut pipeline).is_empty());
+
+        std::thread::sleep(Duration::from_millis(100));
+
+        // The message being validated times out and the queued one expires.
+        let mut results = poll_results(&mut pipeline);
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(results.len(), 2);
+        assert!(results
+            .iter()
+            .all(|(_, acceptance)| matches!(acceptance, MessageAcceptance::Ignore)));
+    }
+}
@@ -19,8 +19,8 @@
 // DEALINGS IN THE SOFTWARE.
 
 use crate::protocol::{
-    IdentifyInfo, IdentifyProtocol, IdentifyPushProtocol, InboundPush, OutboundPush,
-    ReplySubstream, UpgradeError,
+    IdentifyDelta, IdentifyDeltaProtocol, IdentifyInfo, IdentifyProtocol, IdentifyPushProtocol,
+    InboundDelta, InboundPush, OutboundDelta, OutboundPush, ReplySubstream, UpgradeError,
 };
 use futures::future::BoxFuture;
 use futures::prelude::*;
@@ -42,14 +42,10 @@ use std::{io, pin::Pin, task::Context, task::Poll, time::Duration};
 /// permitting the underlying connection to be closed.
 pub struct IdentifyHandler {
     inbound_identify_push: Option<BoxFuture<'static, Result<IdentifyInfo, UpgradeError>>>,
+    inbound_identify_delta: Option<BoxFuture<'static, Result<IdentifyDelta, UpgradeError>>>,
     /// Pending events to yield.
     events: SmallVec<
-        [ConnectionHandlerEvent<
-            EitherUpgrade<IdentifyProtocol, IdentifyPushProtocol<OutboundPush>>,
-            (),
-            IdentifyHandlerEvent,
-            io::Error,
-        >; 4],
+        [ConnectionHandlerEvent<OutboundProtocol, (), IdentifyHandlerEvent, io::Error>; 4],
     >,
 
     /// Future that fires when we need to identify the node again.
@@ -62,11 +58,19 @@ pub struct IdentifyHandler {
     interval: Duration,
 }
 
+/// The outbound upgrade of identify requests, pushes and delta pushes.
+type OutboundProtocol = EitherUpgrade<
+    IdentifyProtocol,
+    EitherUpgrade<IdentifyPushProtocol<OutboundPush>, IdentifyDeltaProtocol<OutboundDelta>>,
+>;
+
 /// Event produced by the `IdentifyHandler`.
 #[derive(Debug)]
 pub enum IdentifyHandlerEvent {
     /// We obtained identification information from the remote.
     Identified(IdentifyInfo),
+    /// We obtained changes of the supported protocols from the remote.
+    IdentifiedDelta(IdentifyDelta),
     /// We actively pushed our identification information to the remote.
     IdentificationPushed,
     /// We received a request for identification.
@@ -76,14 +80,21 @@ pub enum IdentifyHandlerEvent {
 }
 
 /// Identifying information of the local node that is pushed to a remote.
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
-pub struct IdentifyPush(pub IdentifyInfo);
+pub enum IdentifyPush {
+    /// The complete identifying information.
+    Info(IdentifyInfo),
+    /// The changes of the supported protocols.
+    Delta(IdentifyDelta),
+}
 
 impl IdentifyHandler {
     /// Creates a new `IdentifyHandler`.
     pub fn new(initial_delay: Duration, interval: Duration) -> Self {
         IdentifyHandler {
             inbound_identify_push: Default::default(),
+            inbound_identify_delta: Default::default(),
             events: SmallVec::new(),
             trigger_next_identify: Delay::new(initial_delay),
             keep_alive: KeepAlive::Yes,
@@ -96,14 +107,23 @@ impl ConnectionHandler for IdentifyHandler {
     type InEvent = IdentifyPush;
     type OutEvent = IdentifyHandlerEvent;
     type Error = io::Error;
-    type InboundProtocol = SelectUpgrade<IdentifyProtocol, IdentifyPushProtocol<InboundPush>>;
-    type OutboundProtocol = EitherUpgrade<IdentifyProtocol, IdentifyPushProtocol<OutboundPush>>;
+    type InboundProtocol = SelectUpgrade<
+        IdentifyProtocol,
+        SelectUpgrade<IdentifyPushProtocol<InboundPush>, IdentifyDeltaProtocol<InboundDelta>>,
+    >;
+    type OutboundProtocol = OutboundProtocol;
     type OutboundOpenInfo = ();
     type InboundOpenInfo = ();
 
     fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
         SubstreamProtocol::new(
-            SelectUpgrade::new(IdentifyProtocol, IdentifyPushProtocol::inbound()),
+            SelectUpgrade::new(
+                IdentifyProtocol,
+                SelectUpgrade::new(
+                    IdentifyPushProtocol::inbound(),
+                    IdentifyDeltaProtocol::inbound(),
+                ),
+            ),
             (),
         )
     }
@@ -117,7 +137,7 @@ impl ConnectionHandler for IdentifyHandler {
             EitherOutput::First(substream) => self.events.push(ConnectionHandlerEvent::Custom(
                 IdentifyHandlerEvent::Identify(substream),
             )),
-            EitherOutput::Second(fut) => {
+            EitherOutput::Second(EitherOutput::First(fut)) => {
                 if self.inbound_identify_push.replace(fut).is_some() {
                     warn!(
                         "New inbound identify push stream while still upgrading previous one. \
@@ -125,6 +145,14 @@ impl ConnectionHandler for IdentifyHandler {
                     );
                 }
             }
+            EitherOutput::Second(EitherOutput::Second(fut)) => {
+                if self.inbound_identify_delta.replace(fut).is_some() {
+                    warn!(
+                        "New inbound identify delta stream while still upgrading previous one. \
+                        Replacing previous with new.",
+                    );
+                }
+            }
         }
     }
 
@@ -140,19 +168,22 @@ impl ConnectionHandler for IdentifyHandler {
                 ));
                 self.keep_alive = KeepAlive::No;
             }
-            EitherOutput::Second(()) => self.events.push(ConnectionHandlerEvent::Custom(
-                IdentifyHandlerEvent::IdentificationPushed,
-            )),
+            EitherOutput::Second(EitherOutput::First(()) | EitherOutput::Second(())) => {
+                self.events.push(ConnectionHandlerEvent::Custom(
+                    IdentifyHandlerEvent::IdentificationPushed,
+                ))
+            }
         }
     }
 
-    fn inject_event(&mut self, IdentifyPush(push): Self::InEvent) {
+    fn inject_event(&mut self, push: Self::InEvent) {
+        let upgrade = match push {
+            IdentifyPush::Info(info) => EitherUpgrade::A(IdentifyPushProtocol::outbound(info)),
+            IdentifyPush::Delta(delta) => EitherUpgrade::B(IdentifyDeltaProtocol::outbound(delta)),
+        };
         self.events
             .push(ConnectionHandlerEvent::OutboundSubstreamRequest {
-                protocol: SubstreamProtocol::new(
-                    EitherUpgrade::B(IdentifyPushProtocol::outbound(push)),
-                    (),
-                ),
+                protocol: SubstreamProtocol::new(EitherUpgrade::B(upgrade), ()),
             });
     }
 
@@ -168,7 +199,8 @@ impl ConnectionHandler for IdentifyHandler {
         let err = err.map_upgrade_err(|e| match e {
             UpgradeError::Select(e) => UpgradeError::Select(e),
             UpgradeError::Apply(EitherError::A(ioe)) => UpgradeError::Apply(ioe),
-            UpgradeError::Apply(EitherError::B(ioe)) => UpgradeError::Apply(ioe),
+            UpgradeError::Apply(EitherError::B(EitherError::A(ioe))) => UpgradeError::Apply(ioe),
+            UpgradeError::Apply(EitherError::B(EitherError::B(ioe))) => UpgradeError::Apply(ioe),
         });
         self.events.push(ConnectionHandlerEvent::Custom(
             IdentifyHandlerEvent::IdentificationError(err),
@@ -222,6 +254,20 @@ impl ConnectionHandler for IdentifyHandler {
             }
         }
 
+        if let Some(Poll::Ready(res)) = self
+            .inbound_identify_delta
+            .as_mut()
+            .map(|f| f.poll_unpin(cx))
+        {
+            self.inbound_identify_delta.take();
+
+            if let Ok(delta) = res {
+                return Poll::Ready(ConnectionHandlerEvent::Custom(
+                    IdentifyHandlerEvent::IdentifiedDelta(delta),
+                ));
+            }
+        }
+
         Poll::Pending
     }
 }
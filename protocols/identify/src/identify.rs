@@ -19,8 +19,11 @@
 // DEALINGS IN THE SOFTWARE.
 
 use crate::handler::{IdentifyHandler, IdentifyHandlerEvent, IdentifyPush};
-use crate::protocol::{IdentifyInfo, ReplySubstream, UpgradeError};
+use crate::protocol::{
+    IdentifyDelta, IdentifyInfo, ReplySubstream, UpgradeError, DELTA_PROTOCOL_NAME,
+};
 use futures::prelude::*;
+use futures_timer::Delay;
 use libp2p_core::{
     connection::{ConnectionId, ListenerId},
     multiaddr::Protocol,
@@ -60,6 +63,19 @@ pub struct Identify {
     pending_push: HashSet<PeerId>,
     /// The addresses of all peers that we have discovered.
     discovered_peers: LruCache<PeerId, HashSet<Multiaddr>>,
+    /// Connected peers that support identify delta pushes.
+    delta_peers: HashSet<PeerId>,
+    /// The supported protocols of the local node as last observed, if
+    /// observed yet.
+    protocols: Option<HashSet<String>>,
+    /// The supported protocols of the local node as last announced with
+    /// an automatic push.
+    announced_protocols: HashSet<String>,
+    /// Whether the external addresses of the local node changed since the
+    /// last automatic push.
+    external_addrs_changed: bool,
+    /// Fires when the debounce delay of an automatic push has elapsed.
+    push_update: Option<Delay>,
 }
 
 /// A pending reply to an inbound identification request.
@@ -113,6 +129,29 @@ pub struct IdentifyConfig {
     /// Disabled by default.
     pub push_listen_addr_updates: bool,
 
+    /// Whether changes of the supported protocols or the external addresses
+    /// of the local node should trigger an active push of an identify message
+    /// to all connected peers.
+    ///
+    /// Pushes are debounced by [`IdentifyConfig::push_update_delay`].
+    ///
+    /// Disabled by default.
+    pub push_info_updates: bool,
+
+    /// The delay after the last change of the local node before the push
+    /// triggered by [`IdentifyConfig::push_info_updates`] is sent, such that
+    /// changes in quick succession are pushed at once.
+    ///
+    /// Defaults to 500ms.
+    pub push_update_delay: Duration,
+
+    /// Whether pushes triggered by [`IdentifyConfig::push_info_updates`]
+    /// only send the changes of the supported protocols to peers that support
+    /// identify delta messages, if the external addresses did not change.
+    ///
+    /// Disabled by default.
+    pub push_deltas: bool,
+
     /// How many entries of discovered peers to keep before we discard
     /// the least-recently used one.
     ///
@@ -131,6 +170,9 @@ impl IdentifyConfig {
             initial_delay: Duration::from_millis(500),
             interval: Duration::from_secs(5 * 60),
             push_listen_addr_updates: false,
+            push_info_updates: false,
+            push_update_delay: Duration::from_millis(500),
+            push_deltas: false,
             cache_size: 0,
         }
     }
@@ -163,6 +205,28 @@ impl IdentifyConfig {
         self
     }
 
+    /// Configures whether changes of the supported protocols or the external
+    /// addresses of the local node should trigger an active push of an
+    /// identify message to all connected peers.
+    pub fn with_push_info_updates(mut self, b: bool) -> Self {
+        self.push_info_updates = b;
+        self
+    }
+
+    /// Configures the debounce delay of pushes triggered by changes of the
+    /// local node.
+    pub fn with_push_update_delay(mut self, d: Duration) -> Self {
+        self.push_update_delay = d;
+        self
+    }
+
+    /// Configures whether only the changes of the supported protocols are
+    /// pushed to peers that support identify delta messages.
+    pub fn with_push_deltas(mut self, b: bool) -> Self {
+        self.push_deltas = b;
+        self
+    }
+
     /// Configures the size of the LRU cache, caching addresses of discovered peers.
     ///
     /// The [`Swarm`](libp2p_swarm::Swarm) may extend the set of addresses of an outgoing connection attempt via
@@ -185,6 +249,11 @@ impl Identify {
             events: VecDeque::new(),
             pending_push: HashSet::new(),
             discovered_peers,
+            delta_peers: HashSet::new(),
+            protocols: None,
+            announced_protocols: HashSet::new(),
+            external_addrs_changed: false,
+            push_update: None,
         }
     }
 
@@ -205,6 +274,57 @@ impl Identify {
             }
         }
     }
+
+    /// Schedules an automatic push after the debounce delay, resetting
+    /// the delay of an already scheduled push.
+    fn schedule_push_update(&mut self) {
+        self.push_update = Some(Delay::new(self.config.push_update_delay));
+    }
+
+    /// Performs an automatic push of the changes since the last one to all
+    /// connected peers, as a delta push to the peers that support it if only
+    /// the supported protocols changed.
+    fn push_updates(&mut self) {
+        let protocols = self
+            .protocols
+            .clone()
+            .unwrap_or_else(|| self.announced_protocols.clone());
+        let delta = IdentifyDelta {
+            added_protocols: protocols
+                .difference(&self.announced_protocols)
+                .cloned()
+                .collect(),
+            removed_protocols: self
+                .announced_protocols
+                .difference(&protocols)
+                .cloned()
+                .collect(),
+        };
+        let protocols_changed =
+            !delta.added_protocols.is_empty() || !delta.removed_protocols.is_empty();
+        if !protocols_changed && !self.external_addrs_changed {
+            return;
+        }
+
+        for peer_id in self.connected.keys() {
+            if self.config.push_deltas
+                && !self.external_addrs_changed
+                && self.delta_peers.contains(peer_id)
+            {
+                self.events
+                    .push_back(NetworkBehaviourAction::NotifyHandler {
+                        peer_id: *peer_id,
+                        event: IdentifyPush::Delta(delta.clone()),
+                        handler: NotifyHandler::Any,
+                    });
+            } else {
+                self.pending_push.insert(*peer_id);
+            }
+        }
+
+        self.announced_protocols = protocols;
+        self.external_addrs_changed = false;
+    }
 }
 
 impl NetworkBehaviour for Identify {
@@ -254,6 +374,7 @@ impl NetworkBehaviour for Identify {
         if remaining_established == 0 {
             self.connected.remove(peer_id);
             self.pending_push.remove(peer_id);
+            self.delta_peers.remove(peer_id);
         } else if let Some(addrs) = self.connected.get_mut(peer_id) {
             addrs.remove(conn);
         }
@@ -292,6 +413,37 @@ impl NetworkBehaviour for Identify {
         }
     }
 
+    fn inject_new_external_addr(&mut self, _addr: &Multiaddr) {
+        if self.config.push_info_updates {
+            self.external_addrs_changed = true;
+            self.schedule_push_update();
+        }
+    }
+
+    fn inject_expired_external_addr(&mut self, _addr: &Multiaddr) {
+        if self.config.push_info_updates {
+            self.external_addrs_changed = true;
+            self.schedule_push_update();
+        }
+    }
+
+    fn inject_supported_protocols_change(&mut self, protocols: &[Vec<u8>]) {
+        if !self.config.push_info_updates {
+            return;
+        }
+        let protocols = protocols
+            .iter()
+            .map(|p| protocol_name(p))
+            .collect::<HashSet<_>>();
+        if self.protocols.is_none() {
+            // Not polled yet, thus no peer learned the previous protocols.
+            self.announced_protocols.clone_from(&protocols);
+        } else if self.protocols.as_ref() != Some(&protocols) {
+            self.schedule_push_update();
+        }
+        self.protocols = Some(protocols);
+    }
+
     fn inject_event(
         &mut self,
         peer_id: PeerId,
@@ -304,6 +456,12 @@ impl NetworkBehaviour for Identify {
                 info.listen_addrs
                     .retain(|addr| multiaddr_matches_peer_id(addr, &peer_id));
 
+                if info.protocols.iter().any(|p| p == DELTA_PROTOCOL_NAME) {
+                    self.delta_peers.insert(peer_id);
+                } else {
+                    self.delta_peers.remove(&peer_id);
+                }
+
                 // Replace existing addresses to prevent other peer from filling up our memory.
                 self.discovered_peers
                     .put(peer_id, HashSet::from_iter(info.listen_addrs.clone()));
@@ -318,6 +476,11 @@ impl NetworkBehaviour for Identify {
                         score: AddressScore::Finite(1),
                     });
             }
+            IdentifyHandlerEvent::IdentifiedDelta(delta) => {
+                self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                    IdentifyEvent::ReceivedDelta { peer_id, delta },
+                ));
+            }
             IdentifyHandlerEvent::IdentificationPushed => {
                 self.events.push_back(NetworkBehaviourAction::GenerateEvent(
                     IdentifyEvent::Pushed { peer_id },
@@ -351,6 +514,24 @@ impl NetworkBehaviour for Identify {
         cx: &mut Context<'_>,
         params: &mut impl PollParameters,
     ) -> Poll<NetworkBehaviourAction<Self::OutEvent, Self::ConnectionHandler>> {
+        if self.config.push_info_updates {
+            if self.protocols.is_none() {
+                // Peers learn the initial protocols with their identify requests.
+                // Changes are reported via `inject_supported_protocols_change`.
+                let protocols = params
+                    .supported_protocols()
+                    .map(|p| protocol_name(&p))
+                    .collect::<HashSet<_>>();
+                self.announced_protocols.clone_from(&protocols);
+                self.protocols = Some(protocols);
+            }
+
+            if let Some(Poll::Ready(())) = self.push_update.as_mut().map(|d| d.poll_unpin(cx)) {
+                self.push_update = None;
+                self.push_updates();
+            }
+        }
+
         if let Some(event) = self.events.pop_front() {
             return Poll::Ready(event);
         }
@@ -376,7 +557,7 @@ impl NetworkBehaviour for Identify {
                     observed_addr,
                 };
 
-                (*peer, IdentifyPush(info))
+                (*peer, IdentifyPush::Info(info))
             })
         });
 
@@ -463,6 +644,14 @@ pub enum IdentifyEvent {
         /// The information provided by the peer.
         info: IdentifyInfo,
     },
+    /// Changes of the supported protocols have been received from a peer in
+    /// an identify delta push.
+    ReceivedDelta {
+        /// The peer that sent the changes.
+        peer_id: PeerId,
+        /// The changes of the supported protocols of the peer.
+        delta: IdentifyDelta,
+    },
     /// Identification information of the local node has been sent to a peer in
     /// response to an identification request.
     Sent {
@@ -485,14 +674,18 @@ pub enum IdentifyEvent {
 }
 
 fn supported_protocols(params: &impl PollParameters) -> Vec<String> {
-    // The protocol names can be bytes, but the identify protocol except UTF-8 strings.
-    // There's not much we can do to solve this conflict except strip non-UTF-8 characters.
     params
         .supported_protocols()
-        .map(|p| String::from_utf8_lossy(&p).to_string())
+        .map(|p| protocol_name(&p))
         .collect()
 }
 
+fn protocol_name(protocol: &[u8]) -> String {
+    // The protocol names can be bytes, but the identify protocol except UTF-8 strings.
+    // There's not much we can do to solve this conflict except strip non-UTF-8 characters.
+    String::from_utf8_lossy(protocol).to_string()
+}
+
 fn listen_addrs(params: &impl PollParameters) -> Vec<Multiaddr> {
     let mut listen_addrs: Vec<_> = params.external_addresses().map(|r| r.addr).collect();
     listen_addrs.extend(params.listened_addresses());
@@ -694,6 +887,80 @@ mod tests {
         })
     }
 
+    #[test]
+    fn push_info_updates() {
+        let _ = env_logger::try_init();
+
+        let mut swarm1 = {
+            let (pubkey, transport) = transport();
+            let protocol = Identify::new(
+                IdentifyConfig::new("a".to_string(), pubkey.clone())
+                    .with_push_info_updates(true)
+                    .with_push_update_delay(Duration::from_millis(50)),
+            );
+            Swarm::new(transport, protocol, pubkey.to_peer_id())
+        };
+
+        let mut swarm2 = {
+            let (pubkey, transport) = transport();
+            let protocol = Identify::new(IdentifyConfig::new("a".to_string(), pubkey.clone()));
+            Swarm::new(transport, protocol, pubkey.to_peer_id())
+        };
+
+        let swarm2_peer_id = *swarm2.local_peer_id();
+        let external_addr: Multiaddr = "/ip4/1.2.3.4/tcp/4001".parse().unwrap();
+
+        swarm1
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+
+        let listen_addr = async_std::task::block_on(async {
+            loop {
+                if let SwarmEvent::NewListenAddr { address, .. } = swarm1.select_next_some().await {
+                    return address;
+                }
+            }
+        });
+
+        swarm2.dial(listen_addr).unwrap();
+
+        async_std::task::block_on(async move {
+            let mut pushed = false;
+            let mut received = false;
+            while !pushed || !received {
+                let swarm1_fut = swarm1.select_next_some();
+                let swarm2_fut = swarm2.select_next_some();
+
+                let event = {
+                    pin_mut!(swarm1_fut);
+                    pin_mut!(swarm2_fut);
+                    future::select(swarm1_fut, swarm2_fut)
+                        .await
+                        .factor_second()
+                        .0
+                };
+                match event {
+                    future::Either::Left(SwarmEvent::ConnectionEstablished { .. }) => {
+                        // The new external address triggers an automatic push,
+                        // before the initial identify request.
+                        swarm1.add_external_address(external_addr.clone(), AddressScore::Infinite);
+                    }
+                    future::Either::Left(SwarmEvent::Behaviour(IdentifyEvent::Pushed {
+                        peer_id,
+                    })) => {
+                        assert_eq!(peer_id, swarm2_peer_id);
+                        pushed = true;
+                    }
+                    future::Either::Right(SwarmEvent::Behaviour(IdentifyEvent::Received {
+                        info,
+                        ..
+                    })) if info.listen_addrs.contains(&external_addr) => received = true,
+                    _ => {}
+                }
+            }
+        })
+    }
+
     #[test]
     fn discover_peer_after_disconnect() {
         let _ = env_logger::try_init();
@@ -26,6 +26,10 @@
 //! At least one identification request is sent on a newly established
 //! connection, beyond which the behaviour does not keep connections alive.
 //!
+//! Changes of the local node, e.g. of its supported protocols or external
+//! addresses, can be actively pushed to connected peers, optionally as
+//! identify delta messages. See [`IdentifyConfig`].
+//!
 //! # Important Discrepancies
 //!
 //! - **Using Identify with other protocols** Unlike some other libp2p implementations,
@@ -43,9 +47,10 @@
 //! [`Identify`]: self::Identify
 //! [`IdentifyEvent`]: self::IdentifyEvent
 //! [`IdentifyInfo`]: self::IdentifyInfo
+//! [`IdentifyConfig`]: self::IdentifyConfig
 
 pub use self::identify::{Identify, IdentifyConfig, IdentifyEvent};
-pub use self::protocol::{IdentifyInfo, UpgradeError};
+pub use self::protocol::{IdentifyDelta, IdentifyInfo, UpgradeError};
 
 mod handler;
 mod identify;
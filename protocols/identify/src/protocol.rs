@@ -34,6 +34,9 @@ use void::Void;
 
 const MAX_MESSAGE_SIZE_BYTES: usize = 4096;
 
+/// The protocol name of identify delta pushes.
+pub(crate) const DELTA_PROTOCOL_NAME: &str = "/p2p/id/delta/1.0.0";
+
 /// Substream upgrade protocol for `/ipfs/id/1.0.0`.
 #[derive(Debug, Clone)]
 pub struct IdentifyProtocol;
@@ -56,6 +59,24 @@ impl IdentifyPushProtocol<OutboundPush> {
     }
 }
 
+/// Substream upgrade protocol for `/p2p/id/delta/1.0.0`.
+#[derive(Debug, Clone)]
+pub struct IdentifyDeltaProtocol<T>(T);
+pub struct InboundDelta();
+pub struct OutboundDelta(IdentifyDelta);
+
+impl IdentifyDeltaProtocol<InboundDelta> {
+    pub fn inbound() -> Self {
+        IdentifyDeltaProtocol(InboundDelta())
+    }
+}
+
+impl IdentifyDeltaProtocol<OutboundDelta> {
+    pub fn outbound(delta: IdentifyDelta) -> Self {
+        IdentifyDeltaProtocol(OutboundDelta(delta))
+    }
+}
+
 /// Information of a peer sent in protocol messages.
 #[derive(Debug, Clone)]
 pub struct IdentifyInfo {
@@ -75,6 +96,15 @@ pub struct IdentifyInfo {
     pub observed_addr: Multiaddr,
 }
 
+/// Changes of the protocols supported by a peer, sent in delta push messages.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IdentifyDelta {
+    /// The protocols newly supported by the peer.
+    pub added_protocols: Vec<String>,
+    /// The protocols no longer supported by the peer.
+    pub removed_protocols: Vec<String>,
+}
+
 /// The substream on which a reply is expected to be sent.
 pub struct ReplySubstream<T> {
     inner: T,
@@ -167,6 +197,42 @@ where
     }
 }
 
+impl<T> UpgradeInfo for IdentifyDeltaProtocol<T> {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(DELTA_PROTOCOL_NAME.as_bytes())
+    }
+}
+
+impl<C> InboundUpgrade<C> for IdentifyDeltaProtocol<InboundDelta>
+where
+    C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Output = BoxFuture<'static, Result<IdentifyDelta, UpgradeError>>;
+    type Error = Void;
+    type Future = future::Ready<Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, socket: C, _: Self::Info) -> Self::Future {
+        // Lazily upgrade stream, thus allowing upgrade to happen within identify's handler.
+        future::ok(recv_delta(socket).boxed())
+    }
+}
+
+impl<C> OutboundUpgrade<C> for IdentifyDeltaProtocol<OutboundDelta>
+where
+    C: AsyncWrite + Unpin + Send + 'static,
+{
+    type Output = ();
+    type Error = UpgradeError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + Send>>;
+
+    fn upgrade_outbound(self, socket: C, _: Self::Info) -> Self::Future {
+        send_delta(socket, self.0 .0).boxed()
+    }
+}
+
 async fn send<T>(io: T, info: IdentifyInfo) -> Result<(), UpgradeError>
 where
     T: AsyncWrite + Unpin,
@@ -188,8 +254,33 @@ where
         listen_addrs,
         observed_addr: Some(info.observed_addr.to_vec()),
         protocols: info.protocols,
+        delta: None,
     };
 
+    send_message(io, message).await
+}
+
+async fn send_delta<T>(io: T, delta: IdentifyDelta) -> Result<(), UpgradeError>
+where
+    T: AsyncWrite + Unpin,
+{
+    trace!("Sending: {:?}", delta);
+
+    let message = structs_proto::Identify {
+        delta: Some(structs_proto::Delta {
+            added_protocols: delta.added_protocols,
+            rm_protocols: delta.removed_protocols,
+        }),
+        ..Default::default()
+    };
+
+    send_message(io, message).await
+}
+
+async fn send_message<T>(io: T, message: structs_proto::Identify) -> Result<(), UpgradeError>
+where
+    T: AsyncWrite + Unpin,
+{
     let mut framed_io = FramedWrite::new(
         io,
         prost_codec::Codec::<structs_proto::Identify>::new(MAX_MESSAGE_SIZE_BYTES),
@@ -201,24 +292,51 @@ where
     Ok(())
 }
 
-async fn recv<T>(mut socket: T) -> Result<IdentifyInfo, UpgradeError>
+async fn recv<T>(socket: T) -> Result<IdentifyInfo, UpgradeError>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let info = recv_message(socket).await?.try_into()?;
+
+    trace!("Received: {:?}", info);
+
+    Ok(info)
+}
+
+async fn recv_delta<T>(socket: T) -> Result<IdentifyDelta, UpgradeError>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    // A message without a delta is treated like a delta without changes.
+    let delta = recv_message(socket)
+        .await?
+        .delta
+        .map(|delta| IdentifyDelta {
+            added_protocols: delta.added_protocols,
+            removed_protocols: delta.rm_protocols,
+        })
+        .unwrap_or_default();
+
+    trace!("Received: {:?}", delta);
+
+    Ok(delta)
+}
+
+async fn recv_message<T>(mut socket: T) -> Result<structs_proto::Identify, UpgradeError>
 where
     T: AsyncRead + AsyncWrite + Unpin,
 {
     socket.close().await?;
 
-    let info = FramedRead::new(
+    let message = FramedRead::new(
         socket,
         prost_codec::Codec::<structs_proto::Identify>::new(MAX_MESSAGE_SIZE_BYTES),
     )
     .next()
     .await
-    .ok_or(UpgradeError::StreamClosed)??
-    .try_into()?;
-
-    trace!("Received: {:?}", info);
+    .ok_or(UpgradeError::StreamClosed)??;
 
-    Ok(info)
+    Ok(message)
 }
 
 impl TryFrom<structs_proto::Identify> for IdentifyInfo {
@@ -377,4 +495,64 @@ mod tests {
             bg_task.await;
         });
     }
+
+    #[test]
+    fn correct_delta_transfer() {
+        let (tx, rx) = oneshot::channel();
+
+        let bg_task = async_std::task::spawn(async move {
+            let mut transport = TcpConfig::new();
+
+            let mut listener = transport
+                .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .unwrap();
+
+            let addr = listener
+                .next()
+                .await
+                .expect("some event")
+                .expect("no error")
+                .into_new_address()
+                .expect("listen address");
+            tx.send(addr).unwrap();
+
+            let socket = listener
+                .next()
+                .await
+                .unwrap()
+                .unwrap()
+                .into_upgrade()
+                .unwrap()
+                .0
+                .await
+                .unwrap();
+
+            let delta = apply_inbound(socket, IdentifyDeltaProtocol::inbound())
+                .await
+                .unwrap()
+                .await
+                .unwrap();
+            assert_eq!(delta.added_protocols, &["proto1".to_string()]);
+            assert_eq!(delta.removed_protocols, &["proto2".to_string()]);
+        });
+
+        async_std::task::block_on(async move {
+            let mut transport = TcpConfig::new();
+
+            let socket = transport.dial(rx.await.unwrap()).unwrap().await.unwrap();
+            let delta = IdentifyDelta {
+                added_protocols: vec!["proto1".to_string()],
+                removed_protocols: vec!["proto2".to_string()],
+            };
+            apply_outbound(
+                socket,
+                IdentifyDeltaProtocol::outbound(delta),
+                upgrade::Version::V1,
+            )
+            .await
+            .unwrap();
+
+            bg_task.await;
+        });
+    }
 }
@@ -0,0 +1,145 @@
+// Copyright 2022 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use futures::{future, pin_mut, StreamExt};
+use libp2p::core::identity;
+use libp2p::core::multiaddr::{Multiaddr, Protocol};
+use libp2p::core::transport::{upgrade::Version, MemoryTransport, Transport};
+use libp2p::identify::{Identify, IdentifyConfig, IdentifyEvent};
+use libp2p::kad::{store::MemoryStore, Kademlia, KademliaConfig, KademliaEvent, KademliaMode};
+use libp2p::mplex::MplexConfig;
+use libp2p::plaintext::PlainText2Config;
+use libp2p::swarm::{Swarm, SwarmEvent};
+use libp2p::NetworkBehaviour;
+use std::time::Duration;
+
+const KAD_PROTOCOL: &str = "/ipfs/kad/1.0.0";
+
+#[test]
+fn pushes_delta_on_protocol_change() {
+    let _ = env_logger::try_init();
+
+    let mut swarm1 = build_swarm();
+    let mut swarm2 = build_swarm();
+    let swarm1_peer_id = *swarm1.local_peer_id();
+
+    let addr = Multiaddr::empty().with(Protocol::Memory(rand::random::<u64>()));
+    swarm1.listen_on(addr.clone()).unwrap();
+    swarm2.dial(addr).unwrap();
+
+    async_std::task::block_on(async move {
+        let mut identified = (false, false);
+        loop {
+            let swarm1_fut = swarm1.select_next_some();
+            let swarm2_fut = swarm2.select_next_some();
+            let event = {
+                pin_mut!(swarm1_fut);
+                pin_mut!(swarm2_fut);
+                future::select(swarm1_fut, swarm2_fut)
+                    .await
+                    .factor_second()
+                    .0
+            };
+            match event {
+                future::Either::Left(SwarmEvent::Behaviour(Event::Identify(
+                    IdentifyEvent::Received { .. },
+                ))) => identified.0 = true,
+                future::Either::Right(SwarmEvent::Behaviour(Event::Identify(
+                    IdentifyEvent::Received { info, .. },
+                ))) => {
+                    assert!(!info.protocols.iter().any(|p| p == KAD_PROTOCOL));
+                    identified.1 = true;
+                }
+                future::Either::Left(SwarmEvent::Behaviour(Event::Kademlia(
+                    KademliaEvent::ModeChanged { new_mode },
+                ))) => assert_eq!(new_mode, KademliaMode::Server),
+                future::Either::Right(SwarmEvent::Behaviour(Event::Identify(
+                    IdentifyEvent::ReceivedDelta { peer_id, delta },
+                ))) => {
+                    assert_eq!(peer_id, swarm1_peer_id);
+                    assert_eq!(delta.added_protocols, vec![KAD_PROTOCOL.to_string()]);
+                    assert!(delta.removed_protocols.is_empty());
+                    return;
+                }
+                _ => {}
+            }
+            if identified == (true, true) {
+                // Switching to server mode advertises the Kademlia protocol,
+                // which is pushed to the peer as a delta.
+                swarm1
+                    .behaviour_mut()
+                    .kad
+                    .set_mode(Some(KademliaMode::Server));
+                identified = (false, false);
+            }
+        }
+    })
+}
+
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "Event", event_process = false)]
+struct Behaviour {
+    identify: Identify,
+    kad: Kademlia<MemoryStore>,
+}
+
+#[derive(Debug)]
+enum Event {
+    Identify(IdentifyEvent),
+    Kademlia(KademliaEvent),
+}
+
+impl From<IdentifyEvent> for Event {
+    fn from(event: IdentifyEvent) -> Self {
+        Event::Identify(event)
+    }
+}
+
+impl From<KademliaEvent> for Event {
+    fn from(event: KademliaEvent) -> Self {
+        Event::Kademlia(event)
+    }
+}
+
+fn build_swarm() -> Swarm<Behaviour> {
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_public_key = local_key.public();
+    let local_peer_id = local_public_key.to_peer_id();
+
+    let transport = MemoryTransport
+        .upgrade(Version::V1)
+        .authenticate(PlainText2Config {
+            local_public_key: local_public_key.clone(),
+        })
+        .multiplex(MplexConfig::new())
+        .boxed();
+
+    let identify = Identify::new(
+        IdentifyConfig::new("a".to_string(), local_public_key)
+            .with_push_info_updates(true)
+            .with_push_deltas(true)
+            .with_push_update_delay(Duration::from_millis(50)),
+    );
+    let mut kad_config = KademliaConfig::default();
+    kad_config.set_mode(Some(KademliaMode::Client));
+    let kad = Kademlia::with_config(local_peer_id, MemoryStore::new(local_peer_id), kad_config);
+
+    Swarm::new(transport, Behaviour { identify, kad }, local_peer_id)
+}
@@ -23,6 +23,7 @@
 mod test;
 
 use crate::addresses::Addresses;
+use crate::bootstrap::BootstrapStatus;
 use crate::handler::{
     KademliaHandlerConfig, KademliaHandlerEvent, KademliaHandlerIn, KademliaHandlerProto,
     KademliaRequestId,
@@ -33,11 +34,14 @@ use crate::protocol::{KadConnectionType, KadPeer, KademliaProtocolConfig};
 use crate::query::{Query, QueryConfig, QueryId, QueryPool, QueryPoolState};
 use crate::record::{
     self,
-    store::{self, RecordStore},
+    store::{self, AsyncRecordStore, StoreOp},
+    validator::RecordValidator,
     ProviderRecord, Record,
 };
 use crate::K_VALUE;
 use fnv::{FnvHashMap, FnvHashSet};
+use futures::future::{BoxFuture, FutureExt};
+use futures::stream::{FuturesUnordered, StreamExt};
 use instant::Instant;
 use libp2p_core::{
     connection::{ConnectionId, ListenerId},
@@ -48,6 +52,8 @@ use libp2p_swarm::{
     DialError, NetworkBehaviour, NetworkBehaviourAction, NotifyHandler, PollParameters,
 };
 use log::{debug, info, warn};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
 use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::fmt;
@@ -71,7 +77,7 @@ pub struct Kademlia<TStore> {
     /// Configuration of the wire protocol.
     protocol_config: KademliaProtocolConfig,
 
-    /// Configuration of [`RecordStore`] filtering.
+    /// Configuration of [`RecordStore`](store::RecordStore) filtering.
     record_filtering: KademliaStoreInserts,
 
     /// The currently active (i.e. in-progress) queries.
@@ -82,6 +88,21 @@ pub struct Kademlia<TStore> {
     /// This is a superset of the connected peers currently in the routing table.
     connected_peers: FnvHashSet<PeerId>,
 
+    /// The currently established connections.
+    connections: FnvHashMap<ConnectionId, PeerId>,
+
+    /// Peers imported into the routing table via [`Kademlia::import_routing_table`]
+    /// that have not yet been re-verified through a connection.
+    unverified_peers: FnvHashSet<PeerId>,
+
+    /// The current mode of the local node.
+    mode: KademliaMode,
+
+    /// Whether the mode is determined automatically.
+    ///
+    /// See [`Kademlia::set_mode`].
+    auto_mode: bool,
+
     /// Periodic job for re-publication of provider records for keys
     /// provided by the local node.
     add_provider_job: Option<AddProviderJob>,
@@ -110,6 +131,19 @@ pub struct Kademlia<TStore> {
 
     /// The record storage.
     store: TStore,
+
+    /// Operations on the record store that did not complete immediately.
+    store_ops: FuturesUnordered<BoxFuture<'static, StoreOutcome>>,
+
+    /// Queries that finished or timed out while their lookup in the
+    /// record store is still in progress, along with whether they timed out.
+    awaiting_store: FnvHashMap<QueryId, (Query<QueryInner>, bool)>,
+
+    /// Tracks the lookups per bucket and when to bootstrap automatically.
+    bootstrap_status: BootstrapStatus,
+
+    /// The validator for records received from other peers, if any.
+    record_validator: Option<Box<dyn RecordValidator>>,
 }
 
 /// The configurable strategies for the insertion of peers
@@ -146,16 +180,17 @@ pub enum KademliaBucketInserts {
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum KademliaStoreInserts {
     /// Whenever a (provider) record is received,
-    /// the record is forwarded immediately to the [`RecordStore`].
+    /// the record is forwarded immediately to the record store.
     Unfiltered,
     /// Whenever a (provider) record is received, an event is emitted.
     /// Provider records generate a [`InboundRequest::AddProvider`] under [`KademliaEvent::InboundRequest`],
     /// normal records generate a [`InboundRequest::PutRecord`] under [`KademliaEvent::InboundRequest`].
     ///
     /// When deemed valid, a (provider) record needs to be explicitly stored in
-    /// the [`RecordStore`] via [`RecordStore::put`] or [`RecordStore::add_provider`],
-    /// whichever is applicable. A mutable reference to the [`RecordStore`] can
-    /// be retrieved via [`Kademlia::store_mut`].
+    /// the record store via [`RecordStore::put`](store::RecordStore::put) or
+    /// [`RecordStore::add_provider`](store::RecordStore::add_provider), whichever
+    /// is applicable. A mutable reference to the record store can be retrieved
+    /// via [`Kademlia::store_mut`].
     FilterBoth,
 }
 
@@ -176,6 +211,9 @@ pub struct KademliaConfig {
     connection_idle_timeout: Duration,
     kbucket_inserts: KademliaBucketInserts,
     caching: KademliaCaching,
+    mode: Option<KademliaMode>,
+    periodic_bootstrap_interval: Option<Duration>,
+    automatic_bootstrap_threshold: Option<usize>,
 }
 
 /// The configuration for Kademlia "write-back" caching after successful
@@ -195,6 +233,30 @@ pub enum KademliaCaching {
     Enabled { max_peers: u16 },
 }
 
+/// The mode of operation of a `Kademlia` node in the DHT.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KademliaMode {
+    /// The node only issues requests to other nodes. It does not accept
+    /// inbound requests and thus does not advertise the configured protocol
+    /// name, so that other nodes do not add it to their routing tables.
+    ///
+    /// This is the appropriate mode for nodes that are not publicly
+    /// reachable, e.g. because they are behind a NAT.
+    Client,
+    /// The node issues requests to other nodes and answers inbound
+    /// requests, i.e. it is a full participant of the DHT.
+    Server,
+}
+
+impl fmt::Display for KademliaMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KademliaMode::Client => write!(f, "client"),
+            KademliaMode::Server => write!(f, "server"),
+        }
+    }
+}
+
 impl Default for KademliaConfig {
     fn default() -> Self {
         KademliaConfig {
@@ -210,6 +272,9 @@ impl Default for KademliaConfig {
             connection_idle_timeout: Duration::from_secs(10),
             kbucket_inserts: KademliaBucketInserts::OnConnected,
             caching: KademliaCaching::Enabled { max_peers: 1 },
+            mode: Some(KademliaMode::Server),
+            periodic_bootstrap_interval: None,
+            automatic_bootstrap_threshold: None,
         }
     }
 }
@@ -391,12 +456,51 @@ impl KademliaConfig {
         self.caching = c;
         self
     }
+
+    /// Sets the [`KademliaMode`] of the local node.
+    ///
+    /// `None` means that the mode is determined automatically: The node
+    /// operates in [`KademliaMode::Server`] for as long as the `Swarm` has at
+    /// least one confirmed external address and in [`KademliaMode::Client`]
+    /// otherwise. See [`Kademlia::set_mode`] for changing the mode later on.
+    ///
+    /// The default is [`KademliaMode::Server`].
+    pub fn set_mode(&mut self, mode: Option<KademliaMode>) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the interval at which the local node bootstraps automatically.
+    ///
+    /// Every interval, a bootstrap is started that refreshes all buckets
+    /// of the routing table which have not been the target of a lookup
+    /// within the interval, as described in the Kademlia paper. See
+    /// [`Kademlia::bootstrap`].
+    ///
+    /// `None` means that no periodic bootstrap is performed.
+    /// The default is `None`.
+    pub fn set_periodic_bootstrap_interval(&mut self, interval: Option<Duration>) -> &mut Self {
+        self.periodic_bootstrap_interval = interval;
+        self
+    }
+
+    /// Sets the number of peers in the routing table below which the local
+    /// node bootstraps automatically.
+    ///
+    /// A bootstrap is started whenever the routing table shrinks to less than
+    /// the given number of peers, unless a bootstrap is already in progress.
+    ///
+    /// `None` means that the size of the routing table does not trigger a
+    /// bootstrap. The default is `None`.
+    pub fn set_automatic_bootstrap_threshold(&mut self, threshold: Option<usize>) -> &mut Self {
+        self.automatic_bootstrap_threshold = threshold;
+        self
+    }
 }
 
 impl<TStore> Kademlia<TStore>
 where
-    for<'a> TStore: RecordStore<'a>,
-    TStore: Send + 'static,
+    TStore: AsyncRecordStore + Send + 'static,
 {
     /// Creates a new `Kademlia` network behaviour with a default configuration.
     pub fn new(id: PeerId, store: TStore) -> Self {
@@ -437,6 +541,10 @@ where
             queued_events: VecDeque::with_capacity(config.query_config.replication_factor.get()),
             queries: QueryPool::new(config.query_config),
             connected_peers: Default::default(),
+            connections: Default::default(),
+            unverified_peers: Default::default(),
+            mode: config.mode.unwrap_or(KademliaMode::Client),
+            auto_mode: config.mode.is_none(),
             add_provider_job,
             put_record_job,
             record_ttl: config.record_ttl,
@@ -444,9 +552,135 @@ where
             connection_idle_timeout: config.connection_idle_timeout,
             local_addrs: HashSet::new(),
             caching: config.caching,
+            store_ops: Default::default(),
+            awaiting_store: Default::default(),
+            bootstrap_status: BootstrapStatus::new(
+                config.periodic_bootstrap_interval,
+                config.automatic_bootstrap_threshold,
+            ),
+            record_validator: None,
+        }
+    }
+
+    /// Gets the current [`KademliaMode`] of the local node.
+    pub fn mode(&self) -> KademliaMode {
+        self.mode
+    }
+
+    /// Sets the [`KademliaMode`] of the local node.
+    ///
+    /// `None` means that the mode is determined automatically based on
+    /// whether the `Swarm` has confirmed external addresses. See
+    /// [`KademliaConfig::set_mode`].
+    ///
+    /// Changing the mode affects all established connections. A
+    /// [`KademliaEvent::ModeChanged`] is emitted whenever the mode changes.
+    pub fn set_mode(&mut self, mode: Option<KademliaMode>) {
+        match mode {
+            Some(mode) => {
+                self.auto_mode = false;
+                self.reconfigure_mode(mode);
+            }
+            // The mode is determined on the next call to `poll`.
+            None => self.auto_mode = true,
+        }
+    }
+
+    /// Sets the [`RecordValidator`] for records received from other peers.
+    ///
+    /// Inbound requests to store invalid records are rejected and invalid
+    /// records returned by peers during [`Kademlia::get_record`] are ignored.
+    /// Among the valid records found by a lookup, the validator selects the
+    /// best one, which is then sent to the peers that returned other records.
+    /// A selected index out of range is ignored and no records are repaired.
+    ///
+    /// Without a validator, all records are considered valid.
+    pub fn set_record_validator<V>(&mut self, validator: V)
+    where
+        V: RecordValidator,
+    {
+        self.record_validator = Some(Box::new(validator));
+    }
+
+    /// Checks a record received from the given peer with the
+    /// configured [`RecordValidator`], if any.
+    fn is_valid(&self, source: &PeerId, record: &Record) -> bool {
+        match self.record_validator.as_ref().map(|v| v.validate(record)) {
+            Some(Err(e)) => {
+                debug!("Invalid record {:?} from {}: {}", record.key, source, e);
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Orders the given records such that the best record, as selected by the
+    /// configured [`RecordValidator`], comes first and sends it to all peers
+    /// that returned an outdated record.
+    fn select_and_repair(&mut self, key: &record::Key, records: &mut [PeerRecord]) {
+        let validator = match self.record_validator.as_ref() {
+            Some(validator) if records.len() > 1 => validator,
+            _ => return,
+        };
+        let best = validator.select(key, &records.iter().map(|r| &r.record).collect::<Vec<_>>());
+        if best >= records.len() {
+            warn!(
+                "Record validator selected index {} of {} records for {:?}.",
+                best,
+                records.len(),
+                key
+            );
+            return;
+        }
+        records.swap(0, best);
+
+        let best = &records[0].record;
+        let outdated = records[1..]
+            .iter()
+            .filter(|r| r.record.value != best.value || r.record.publisher != best.publisher)
+            .filter_map(|r| r.peer)
+            .collect::<Vec<_>>();
+        if let Some(quorum) = NonZeroUsize::new(outdated.len()) {
+            debug!("Repairing record {:?} at {} peers.", key, outdated.len());
+            let info = QueryInfo::PutRecord {
+                context: PutRecordContext::Repair,
+                record: best.clone(),
+                quorum,
+                phase: PutRecordPhase::PutRecord {
+                    success: vec![],
+                    get_closest_peers_stats: QueryStats::empty(),
+                },
+            };
+            let inner = QueryInner::new(info);
+            self.queries.add_fixed(outdated, inner);
         }
     }
 
+    /// Switches the local node to the given mode, if it is not already in it.
+    fn reconfigure_mode(&mut self, new_mode: KademliaMode) {
+        if self.mode == new_mode {
+            return;
+        }
+        debug!("Switching to Kademlia {} mode.", new_mode);
+        self.mode = new_mode;
+
+        for (connection, peer_id) in &self.connections {
+            self.queued_events
+                .push_back(NetworkBehaviourAction::NotifyHandler {
+                    peer_id: *peer_id,
+                    handler: NotifyHandler::One(*connection),
+                    event: KademliaHandlerIn::ReconfigureMode { new_mode },
+                });
+        }
+        // Advertise the Kademlia protocol only while accepting inbound requests.
+        self.queued_events
+            .push_back(NetworkBehaviourAction::RefreshSupportedProtocols);
+        self.queued_events
+            .push_back(NetworkBehaviourAction::GenerateEvent(
+                KademliaEvent::ModeChanged { new_mode },
+            ));
+    }
+
     /// Gets an iterator over immutable references to all running queries.
     pub fn iter_queries(&self) -> impl Iterator<Item = QueryRef<'_>> {
         self.queries.iter().filter_map(|query| {
@@ -595,7 +829,7 @@ where
         address: &Multiaddr,
     ) -> Option<kbucket::EntryView<kbucket::Key<PeerId>, Addresses>> {
         let key = kbucket::Key::from(*peer);
-        match self.kbuckets.entry(&key) {
+        let removed = match self.kbuckets.entry(&key) {
             kbucket::Entry::Present(mut entry, _) => {
                 if entry.value().remove(address).is_err() {
                     Some(entry.remove()) // it is the last address, thus remove the peer.
@@ -611,7 +845,11 @@ where
                 }
             }
             kbucket::Entry::Absent(..) | kbucket::Entry::SelfEntry => None,
+        };
+        if removed.is_some() {
+            self.unverified_peers.remove(peer);
         }
+        removed
     }
 
     /// Removes a peer from the routing table.
@@ -622,6 +860,7 @@ where
         &mut self,
         peer: &PeerId,
     ) -> Option<kbucket::EntryView<kbucket::Key<PeerId>, Addresses>> {
+        self.unverified_peers.remove(peer);
         let key = kbucket::Key::from(*peer);
         match self.kbuckets.entry(&key) {
             kbucket::Entry::Present(entry, _) => Some(entry.remove()),
@@ -637,6 +876,123 @@ where
         self.kbuckets.iter().filter(|b| !b.is_empty())
     }
 
+    /// Exports the entries of the routing table into a [`RoutingTableSnapshot`],
+    /// e.g. to persist the routing table across restarts of the local node.
+    ///
+    /// See [`Kademlia::import_routing_table`].
+    pub fn export_routing_table(&mut self) -> RoutingTableSnapshot {
+        let local_peer_id = *self.kbuckets.local_key().preimage();
+        let entries = self
+            .kbuckets
+            .iter()
+            .flat_map(|bucket| {
+                let index = bucket
+                    .range()
+                    .0
+                    .ilog2()
+                    .expect("Bucket ranges exclude zero.");
+                bucket
+                    .iter()
+                    .enumerate()
+                    .map(|(position, entry)| RoutingTableEntry {
+                        peer_id: *entry.node.key.preimage(),
+                        addresses: entry.node.value.iter().cloned().collect(),
+                        bucket: index,
+                        position: position as u32,
+                        status: entry.status,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        RoutingTableSnapshot {
+            local_peer_id,
+            entries,
+        }
+    }
+
+    /// Imports the entries of a [`RoutingTableSnapshot`] into the routing table,
+    /// e.g. to warm-start the local node with the routing table exported by
+    /// [`Kademlia::export_routing_table`] before a restart.
+    ///
+    /// Regardless of their status in the snapshot, imported peers are inserted
+    /// as disconnected, preserving their relative position within a bucket, and
+    /// are pending re-verification: An imported peer is verified once a connection
+    /// to it confirms the Kademlia protocol, whereas it is removed from the routing
+    /// table if dialing it fails before that. Addresses of peers already in the
+    /// routing table are merged.
+    ///
+    /// Entries are inserted into the buckets as per their distance to the local key,
+    /// hence a snapshot taken by another node may be imported as well, although
+    /// peers may not fit into the routing table in that case.
+    ///
+    /// Returns the number of peers that have been inserted into the routing table.
+    /// A [`KademliaEvent::RoutingUpdated`] is emitted for each of them.
+    pub fn import_routing_table(&mut self, snapshot: RoutingTableSnapshot) -> usize {
+        let mut entries = snapshot.entries;
+        entries.sort_by_key(|e| (e.bucket, e.position));
+
+        let mut num_inserted = 0;
+        for RoutingTableEntry {
+            peer_id, addresses, ..
+        } in entries
+        {
+            let mut addresses = addresses.into_iter();
+            let mut addrs = match addresses.next() {
+                Some(address) => Addresses::new(address),
+                None => continue,
+            };
+            for address in addresses {
+                addrs.insert(address);
+            }
+
+            let key = kbucket::Key::from(peer_id);
+            match self.kbuckets.entry(&key) {
+                kbucket::Entry::Present(mut entry, _) => {
+                    for address in addrs.into_vec() {
+                        entry.value().insert(address);
+                    }
+                }
+                kbucket::Entry::Pending(mut entry, _) => {
+                    for address in addrs.into_vec() {
+                        entry.value().insert(address);
+                    }
+                }
+                kbucket::Entry::Absent(entry) => {
+                    match entry.insert(addrs.clone(), NodeStatus::Disconnected) {
+                        kbucket::InsertResult::Inserted => {
+                            num_inserted += 1;
+                            self.unverified_peers.insert(peer_id);
+                            self.queued_events
+                                .push_back(NetworkBehaviourAction::GenerateEvent(
+                                    KademliaEvent::RoutingUpdated {
+                                        peer: peer_id,
+                                        is_new_peer: true,
+                                        addresses: addrs,
+                                        old_peer: None,
+                                        bucket_range: self
+                                            .kbuckets
+                                            .bucket(&key)
+                                            .map(|b| b.range())
+                                            .expect("Not kbucket::Entry::SelfEntry."),
+                                    },
+                                ));
+                        }
+                        kbucket::InsertResult::Pending { .. } => {
+                            self.unverified_peers.insert(peer_id);
+                        }
+                        kbucket::InsertResult::Full => {
+                            debug!("Bucket full. Peer not imported: {}", peer_id);
+                        }
+                    }
+                }
+                kbucket::Entry::SelfEntry => {}
+            }
+        }
+
+        num_inserted
+    }
+
     /// Returns the k-bucket for the distance to the given key.
     ///
     /// Returns `None` if the given key refers to the local key.
@@ -662,6 +1018,7 @@ where
             key: key.clone().into(),
         };
         let target: kbucket::Key<K> = key.into();
+        self.record_lookup(&target);
         let peers = self.kbuckets.closest_keys(&target);
         let inner = QueryInner::new(info);
         self.queries.add_iter_closest(target.clone(), peers, inner)
@@ -679,22 +1036,26 @@ where
     ///
     /// The result of this operation is delivered in a
     /// [`KademliaEvent::OutboundQueryCompleted{QueryResult::GetRecord}`].
+    ///
+    /// If the lookup in the local record store does not complete immediately,
+    /// the query proceeds in the DHT in the meantime but does not complete
+    /// before the local lookup does.
     pub fn get_record(&mut self, key: record::Key, quorum: Quorum) -> QueryId {
         let quorum = quorum.eval(self.queries.config().replication_factor);
         let mut records = Vec::with_capacity(quorum.get());
+        let mut lookup = None;
 
-        if let Some(record) = self.store.get(&key) {
-            if record.is_expired(Instant::now()) {
-                self.store.remove(&key)
-            } else {
-                records.push(PeerRecord {
-                    peer: None,
-                    record: record.into_owned(),
-                });
+        match self.store.get_record(&key) {
+            StoreOp::Ready(record) => {
+                if let Some(record) = self.unexpired(record) {
+                    records.push(PeerRecord { peer: None, record });
+                }
             }
+            StoreOp::Pending(pending) => lookup = Some(pending),
         }
 
         let done = records.len() >= quorum.get();
+        let local = records.first().cloned();
         let target = kbucket::Key::new(key.clone());
         let info = QueryInfo::GetRecord {
             key,
@@ -702,15 +1063,32 @@ where
             quorum,
             cache_candidates: BTreeMap::new(),
         };
+        self.record_lookup(&target);
         let peers = self.kbuckets.closest_keys(&target);
-        let inner = QueryInner::new(info);
+        let mut inner = QueryInner::new(info);
+        inner.pending_lookup = lookup.is_some();
         let id = self.queries.add_iter_closest(target.clone(), peers, inner); // (*)
 
+        if let Some(record) = local {
+            self.query_progressed(id, QueryStats::empty(), QueryProgress::GetRecord(record));
+        }
+
         // Instantly finish the query if we already have enough records.
         if done {
             self.queries.get_mut(&id).expect("by (*)").finish();
         }
 
+        if let Some(lookup) = lookup {
+            self.store_ops.push(
+                lookup
+                    .map(move |record| StoreOutcome::GetRecord {
+                        query_id: id,
+                        record,
+                    })
+                    .boxed(),
+            );
+        }
+
         id
     }
 
@@ -721,6 +1099,9 @@ where
     /// `QueryId` of the initial query that replicates the record in the DHT.
     /// The result of the query is eventually reported as a
     /// [`KademliaEvent::OutboundQueryCompleted{QueryResult::PutRecord}`].
+    /// If the record store does not complete storing the record immediately,
+    /// the query is started right away and a failure to store the record
+    /// locally is only logged.
     ///
     /// The record is always stored locally with the given expiration. If the record's
     /// expiration is `None`, the common case, it does not expire in local storage
@@ -738,12 +1119,22 @@ where
         quorum: Quorum,
     ) -> Result<QueryId, store::Error> {
         record.publisher = Some(*self.kbuckets.local_key().preimage());
-        self.store.put(record.clone())?;
+        match self.store.put_record(record.clone()) {
+            StoreOp::Ready(result) => result?,
+            StoreOp::Pending(put) => {
+                let key = record.key.clone();
+                self.store_ops.push(
+                    put.map(move |result| StoreOutcome::PutRecord { key, result })
+                        .boxed(),
+                );
+            }
+        }
         record.expires = record
             .expires
             .or_else(|| self.record_ttl.map(|ttl| Instant::now() + ttl));
         let quorum = quorum.eval(self.queries.config().replication_factor);
         let target = kbucket::Key::new(record.key.clone());
+        self.record_lookup(&target);
         let peers = self.kbuckets.closest_keys(&target);
         let context = PutRecordContext::Publish;
         let info = QueryInfo::PutRecord {
@@ -815,9 +1206,23 @@ where
     /// the record will no longer be periodically re-published, allowing the
     /// record to eventually expire throughout the DHT.
     pub fn remove_record(&mut self, key: &record::Key) {
-        if let Some(r) = self.store.get(key) {
+        match self.store.get_record(key) {
+            StoreOp::Ready(record) => self.remove_published_record(record),
+            StoreOp::Pending(lookup) => self.store_ops.push(
+                lookup
+                    .map(|record| StoreOutcome::RemoveRecord { record })
+                    .boxed(),
+            ),
+        }
+    }
+
+    /// Removes the given record from local storage, if the local
+    /// node is the publisher of the record.
+    fn remove_published_record(&mut self, record: Option<Record>) {
+        if let Some(r) = record {
             if r.publisher.as_ref() == Some(self.kbuckets.local_key().preimage()) {
-                self.store.remove(key)
+                let op = self.store.remove_record(&r.key);
+                self.drive_store_op(op)
             }
         }
     }
@@ -847,6 +1252,14 @@ where
     /// > **Note**: Bootstrapping requires at least one node of the DHT to be known.
     /// > See [`Kademlia::add_address`].
     pub fn bootstrap(&mut self) -> Result<QueryId, NoKnownPeers> {
+        self.start_bootstrap(false)
+    }
+
+    /// Starts a bootstrap, either on request or automatically.
+    ///
+    /// See [`KademliaConfig::set_periodic_bootstrap_interval`] and
+    /// [`KademliaConfig::set_automatic_bootstrap_threshold`].
+    fn start_bootstrap(&mut self, automatic: bool) -> Result<QueryId, NoKnownPeers> {
         let local_key = self.kbuckets.local_key().clone();
         let info = QueryInfo::Bootstrap {
             peer: *local_key.preimage(),
@@ -857,7 +1270,16 @@ where
             Err(NoKnownPeers())
         } else {
             let inner = QueryInner::new(info);
-            Ok(self.queries.add_iter_closest(local_key, peers, inner))
+            let id = self.queries.add_iter_closest(local_key, peers, inner);
+            self.bootstrap_status.on_started(id, automatic);
+            Ok(id)
+        }
+    }
+
+    /// Records a lookup of the given key for the purpose of bucket refreshes.
+    fn record_lookup<T>(&mut self, target: &kbucket::Key<T>) {
+        if let Some(bucket) = self.kbuckets.local_key().distance(target).ilog2() {
+            self.bootstrap_status.on_lookup(bucket, Instant::now());
         }
     }
 
@@ -869,6 +1291,8 @@ where
     ///
     /// Returns `Ok` if a provider record has been stored locally, providing the
     /// `QueryId` of the initial query that announces the local node as a provider.
+    /// As with [`Kademlia::put_record`], a failure of a record store that does
+    /// not complete storing the provider record immediately is only logged.
     ///
     /// The publication of the provider records is periodically repeated as per the
     /// configured interval, to renew the expiry and account for changes to the DHT
@@ -893,8 +1317,18 @@ where
             *self.kbuckets.local_key().preimage(),
             local_addrs,
         );
-        self.store.add_provider(record)?;
+        match self.store.add_provider_record(record) {
+            StoreOp::Ready(result) => result?,
+            StoreOp::Pending(add) => {
+                let key = key.clone();
+                self.store_ops.push(
+                    add.map(move |result| StoreOutcome::StartProviding { key, result })
+                        .boxed(),
+                );
+            }
+        }
         let target = kbucket::Key::new(key.clone());
+        self.record_lookup(&target);
         let peers = self.kbuckets.closest_keys(&target);
         let context = AddProviderContext::Publish;
         let info = QueryInfo::AddProvider {
@@ -912,30 +1346,77 @@ where
     /// This is a local operation. The local node will still be considered as a
     /// provider for the key by other nodes until these provider records expire.
     pub fn stop_providing(&mut self, key: &record::Key) {
-        self.store
-            .remove_provider(key, self.kbuckets.local_key().preimage());
+        let op = self
+            .store
+            .remove_provider_record(key, self.kbuckets.local_key().preimage());
+        self.drive_store_op(op)
     }
 
     /// Performs a lookup for providers of a value to the given key.
     ///
     /// The result of this operation is delivered in a
     /// reported via [`KademliaEvent::OutboundQueryCompleted{QueryResult::GetProviders}`].
+    ///
+    /// As with [`Kademlia::get_record`], the query does not complete before
+    /// the lookup in the local record store does.
     pub fn get_providers(&mut self, key: record::Key) -> QueryId {
-        let providers = self
-            .store
-            .providers(&key)
-            .into_iter()
-            .filter(|p| !p.is_expired(Instant::now()))
-            .map(|p| p.provider)
-            .collect();
+        let (providers, lookup) = match self.store.provider_records(&key) {
+            StoreOp::Ready(providers) => (unexpired_providers(providers), None),
+            StoreOp::Pending(lookup) => (HashSet::new(), Some(lookup)),
+        };
+        let local = (!providers.is_empty()).then(|| QueryProgress::GetProviders {
+            key: key.clone(),
+            providers: providers.clone(),
+        });
         let info = QueryInfo::GetProviders {
             key: key.clone(),
             providers,
         };
         let target = kbucket::Key::new(key);
+        self.record_lookup(&target);
         let peers = self.kbuckets.closest_keys(&target);
-        let inner = QueryInner::new(info);
-        self.queries.add_iter_closest(target.clone(), peers, inner)
+        let mut inner = QueryInner::new(info);
+        inner.pending_lookup = lookup.is_some();
+        let id = self.queries.add_iter_closest(target.clone(), peers, inner);
+
+        if let Some(progress) = local {
+            self.query_progressed(id, QueryStats::empty(), progress);
+        }
+
+        if let Some(lookup) = lookup {
+            self.store_ops.push(
+                lookup
+                    .map(move |providers| StoreOutcome::GetProviders {
+                        query_id: id,
+                        providers,
+                    })
+                    .boxed(),
+            );
+        }
+
+        id
+    }
+
+    /// Reports intermediate results of a running query.
+    fn query_progressed(&mut self, id: QueryId, stats: QueryStats, result: QueryProgress) {
+        self.queued_events
+            .push_back(NetworkBehaviourAction::GenerateEvent(
+                KademliaEvent::OutboundQueryProgressed { id, result, stats },
+            ));
+    }
+
+    /// Reports the result of a query that completed, after any
+    /// progress of the query that has already been reported.
+    fn query_completed_after_progress(
+        &mut self,
+        query: Query<QueryInner>,
+        timed_out: bool,
+        params: &mut impl PollParameters,
+    ) {
+        if let Some(event) = self.query_completed(query, timed_out, params) {
+            self.queued_events
+                .push_back(NetworkBehaviourAction::GenerateEvent(event));
+        }
     }
 
     /// Processes discovered peers from a successful request in an iterative `Query`.
@@ -982,12 +1463,11 @@ where
     }
 
     /// Collects all peers who are known to be providers of the value for a given `Multihash`.
-    fn provider_peers(&mut self, key: &record::Key, source: &PeerId) -> Vec<KadPeer> {
+    fn provider_peers(&mut self, providers: Vec<ProviderRecord>, source: &PeerId) -> Vec<KadPeer> {
         let kbuckets = &mut self.kbuckets;
         let connected = &mut self.connected_peers;
         let local_addrs = &self.local_addrs;
-        self.store
-            .providers(key)
+        providers
             .into_iter()
             .filter_map(move |p| {
                 if &p.provider != source {
@@ -1038,6 +1518,7 @@ where
             phase: AddProviderPhase::GetClosestPeers,
         };
         let target = kbucket::Key::new(key);
+        self.record_lookup(&target);
         let peers = self.kbuckets.closest_keys(&target);
         let inner = QueryInner::new(info);
         self.queries.add_iter_closest(target.clone(), peers, inner);
@@ -1047,6 +1528,7 @@ where
     fn start_put_record(&mut self, record: Record, quorum: Quorum, context: PutRecordContext) {
         let quorum = quorum.eval(self.queries.config().replication_factor);
         let target = kbucket::Key::new(record.key.clone());
+        self.record_lookup(&target);
         let peers = self.kbuckets.closest_keys(&target);
         let info = QueryInfo::PutRecord {
             record,
@@ -1189,16 +1671,27 @@ where
         match result.inner.info {
             QueryInfo::Bootstrap { peer, remaining } => {
                 let local_key = self.kbuckets.local_key().clone();
+                let automatic = self.bootstrap_status.is_automatic(query_id);
+                let now = Instant::now();
+                let bootstrap_status = &self.bootstrap_status;
                 let mut remaining = remaining.unwrap_or_else(|| {
                     debug_assert_eq!(&peer, local_key.preimage());
                     // The lookup for the local key finished. To complete the bootstrap process,
                     // a bucket refresh should be performed for every bucket farther away than
                     // the first non-empty bucket (which are most likely no more than the last
-                    // few, i.e. farthest, buckets).
+                    // few, i.e. farthest, buckets). An automatic bootstrap only refreshes
+                    // the buckets that have not been looked up recently.
                     self.kbuckets
                         .iter()
                         .skip_while(|b| b.is_empty())
                         .skip(1) // Skip the bucket with the closest neighbour.
+                        .filter(|b| {
+                            !automatic
+                                || b.range()
+                                    .0
+                                    .ilog2()
+                                    .map_or(true, |i| bootstrap_status.is_stale(i, now))
+                        })
                         .map(|b| {
                             // Try to find a key that falls into the bucket. While such keys can
                             // be generated fully deterministically, the current libp2p kademlia
@@ -1234,10 +1727,13 @@ where
                         peer: *target.preimage(),
                         remaining: Some(remaining),
                     };
+                    self.record_lookup(&target);
                     let peers = self.kbuckets.closest_keys(&target);
                     let inner = QueryInner::new(info);
                     self.queries
                         .continue_iter_closest(query_id, target.clone(), peers, inner);
+                } else {
+                    self.bootstrap_status.on_finished(query_id);
                 }
 
                 Some(KademliaEvent::OutboundQueryCompleted {
@@ -1314,10 +1810,11 @@ where
 
             QueryInfo::GetRecord {
                 key,
-                records,
+                mut records,
                 quorum,
                 cache_candidates,
             } => {
+                self.select_and_repair(&key, &mut records);
                 let results = if records.len() >= quorum.get() {
                     // [not empty]
                     if quorum.get() == 1 && !cache_candidates.is_empty() {
@@ -1424,6 +1921,10 @@ where
                         debug!("Record cached: {:?}", record.key);
                         None
                     }
+                    PutRecordContext::Repair => {
+                        debug!("Record repaired: {:?}", record.key);
+                        None
+                    }
                 }
             }
         }
@@ -1440,6 +1941,7 @@ where
                 mut remaining,
             } => {
                 let num_remaining = remaining.as_ref().map(|r| r.len().saturating_sub(1) as u32);
+                let mut continued = false;
 
                 if let Some(mut remaining) = remaining.take() {
                     // Continue with the next bootstrap query if `remaining` is not empty.
@@ -1448,13 +1950,19 @@ where
                             peer: target.clone().into_preimage(),
                             remaining: Some(remaining),
                         };
+                        self.record_lookup(&target);
                         let peers = self.kbuckets.closest_keys(&target);
                         let inner = QueryInner::new(info);
                         self.queries
                             .continue_iter_closest(query_id, target.clone(), peers, inner);
+                        continued = true;
                     }
                 }
 
+                if !continued {
+                    self.bootstrap_status.on_finished(query_id);
+                }
+
                 Some(KademliaEvent::OutboundQueryCompleted {
                     id: query_id,
                     stats: result.stats,
@@ -1536,6 +2044,17 @@ where
                             None
                         }
                     },
+                    PutRecordContext::Repair => match phase {
+                        PutRecordPhase::GetClosestPeers => {
+                            // Repairing outdated records is a direct query to the
+                            // peers that returned them, as with caching.
+                            unreachable!()
+                        }
+                        PutRecordPhase::PutRecord { .. } => {
+                            debug!("Repairing record failed: {:?}", err);
+                            None
+                        }
+                    },
                 }
             }
 
@@ -1633,36 +2152,25 @@ where
             // requirement to send back the value in the response, although this
             // is a waste of resources.
             match self.record_filtering {
-                KademliaStoreInserts::Unfiltered => match self.store.put(record.clone()) {
-                    Ok(()) => {
-                        debug!(
-                            "Record stored: {:?}; {} bytes",
-                            record.key,
-                            record.value.len()
-                        );
-                        self.queued_events
-                            .push_back(NetworkBehaviourAction::GenerateEvent(
-                                KademliaEvent::InboundRequest {
-                                    request: InboundRequest::PutRecord {
-                                        source,
-                                        connection,
-                                        record: None,
-                                    },
-                                },
-                            ));
-                    }
-                    Err(e) => {
-                        info!("Record not stored: {:?}", e);
-                        self.queued_events
-                            .push_back(NetworkBehaviourAction::NotifyHandler {
-                                peer_id: source,
-                                handler: NotifyHandler::One(connection),
-                                event: KademliaHandlerIn::Reset(request_id),
-                            });
-
-                        return;
+                KademliaStoreInserts::Unfiltered => {
+                    // The response is sent once the store completes.
+                    match self.store.put_record(record.clone()) {
+                        StoreOp::Ready(result) => {
+                            self.record_stored(source, connection, request_id, record, result)
+                        }
+                        StoreOp::Pending(put) => self.store_ops.push(
+                            put.map(move |result| StoreOutcome::InboundPutRecord {
+                                source,
+                                connection,
+                                request_id,
+                                record,
+                                result,
+                            })
+                            .boxed(),
+                        ),
                     }
-                },
+                    return;
+                }
                 KademliaStoreInserts::FilterBoth => {
                     self.queued_events
                         .push_back(NetworkBehaviourAction::GenerateEvent(
@@ -1697,6 +2205,126 @@ where
             })
     }
 
+    /// Answers an inbound `PUT_VALUE` request once the record has been
+    /// put into the record store.
+    fn record_stored(
+        &mut self,
+        source: PeerId,
+        connection: ConnectionId,
+        request_id: KademliaRequestId,
+        record: Record,
+        result: store::Result<()>,
+    ) {
+        match result {
+            Ok(()) => {
+                debug!(
+                    "Record stored: {:?}; {} bytes",
+                    record.key,
+                    record.value.len()
+                );
+                self.queued_events
+                    .push_back(NetworkBehaviourAction::GenerateEvent(
+                        KademliaEvent::InboundRequest {
+                            request: InboundRequest::PutRecord {
+                                source,
+                                connection,
+                                record: None,
+                            },
+                        },
+                    ));
+                self.queued_events
+                    .push_back(NetworkBehaviourAction::NotifyHandler {
+                        peer_id: source,
+                        handler: NotifyHandler::One(connection),
+                        event: KademliaHandlerIn::PutRecordRes {
+                            key: record.key,
+                            value: record.value,
+                            request_id,
+                        },
+                    })
+            }
+            Err(e) => {
+                info!("Record not stored: {:?}", e);
+                self.queued_events
+                    .push_back(NetworkBehaviourAction::NotifyHandler {
+                        peer_id: source,
+                        handler: NotifyHandler::One(connection),
+                        event: KademliaHandlerIn::Reset(request_id),
+                    });
+            }
+        }
+    }
+
+    /// Answers an inbound `GET_VALUE` request once the record store has
+    /// been consulted.
+    fn answer_get_record(
+        &mut self,
+        source: PeerId,
+        connection: ConnectionId,
+        request_id: KademliaRequestId,
+        key: record::Key,
+        record: Option<Record>,
+    ) {
+        let record = self.unexpired(record);
+        let closer_peers = self.find_closest(&kbucket::Key::new(key), &source);
+
+        self.queued_events
+            .push_back(NetworkBehaviourAction::GenerateEvent(
+                KademliaEvent::InboundRequest {
+                    request: InboundRequest::GetRecord {
+                        num_closer_peers: closer_peers.len(),
+                        present_locally: record.is_some(),
+                    },
+                },
+            ));
+
+        self.queued_events
+            .push_back(NetworkBehaviourAction::NotifyHandler {
+                peer_id: source,
+                handler: NotifyHandler::One(connection),
+                event: KademliaHandlerIn::GetRecordRes {
+                    record,
+                    closer_peers,
+                    request_id,
+                },
+            });
+    }
+
+    /// Answers an inbound `GET_PROVIDERS` request once the record store
+    /// has been consulted.
+    fn answer_get_providers(
+        &mut self,
+        source: PeerId,
+        connection: ConnectionId,
+        request_id: KademliaRequestId,
+        key: record::Key,
+        providers: Vec<ProviderRecord>,
+    ) {
+        let provider_peers = self.provider_peers(providers, &source);
+        let closer_peers = self.find_closest(&kbucket::Key::new(key), &source);
+
+        self.queued_events
+            .push_back(NetworkBehaviourAction::GenerateEvent(
+                KademliaEvent::InboundRequest {
+                    request: InboundRequest::GetProvider {
+                        num_closer_peers: closer_peers.len(),
+                        num_provider_peers: provider_peers.len(),
+                    },
+                },
+            ));
+
+        self.queued_events
+            .push_back(NetworkBehaviourAction::NotifyHandler {
+                peer_id: source,
+                handler: NotifyHandler::One(connection),
+                event: KademliaHandlerIn::GetProvidersRes {
+                    closer_peers,
+                    provider_peers,
+                    request_id,
+                },
+            });
+    }
+
     /// Processes a provider record received from a peer.
     fn provider_received(&mut self, key: record::Key, provider: KadPeer) {
         if &provider.node_id != self.kbuckets.local_key().preimage() {
@@ -1707,19 +2335,13 @@ where
                 addresses: provider.multiaddrs,
             };
             match self.record_filtering {
-                KademliaStoreInserts::Unfiltered => {
-                    if let Err(e) = self.store.add_provider(record) {
-                        info!("Provider record not stored: {:?}", e);
-                        return;
-                    }
-
-                    self.queued_events
-                        .push_back(NetworkBehaviourAction::GenerateEvent(
-                            KademliaEvent::InboundRequest {
-                                request: InboundRequest::AddProvider { record: None },
-                            },
-                        ));
-                }
+                KademliaStoreInserts::Unfiltered => match self.store.add_provider_record(record) {
+                    StoreOp::Ready(result) => self.provider_stored(result),
+                    StoreOp::Pending(add) => self.store_ops.push(
+                        add.map(|result| StoreOutcome::InboundAddProvider { result })
+                            .boxed(),
+                    ),
+                },
                 KademliaStoreInserts::FilterBoth => {
                     self.queued_events
                         .push_back(NetworkBehaviourAction::GenerateEvent(
@@ -1734,6 +2356,169 @@ where
         }
     }
 
+    /// Reports a provider record received from a peer once it has been
+    /// added to the record store.
+    fn provider_stored(&mut self, result: store::Result<()>) {
+        if let Err(e) = result {
+            info!("Provider record not stored: {:?}", e);
+            return;
+        }
+
+        self.queued_events
+            .push_back(NetworkBehaviourAction::GenerateEvent(
+                KademliaEvent::InboundRequest {
+                    request: InboundRequest::AddProvider { record: None },
+                },
+            ));
+    }
+
+    /// Returns the given record from the local store unless it has expired,
+    /// in which case it is removed from the store.
+    fn unexpired(&mut self, record: Option<Record>) -> Option<Record> {
+        match record {
+            Some(record) if record.is_expired(Instant::now()) => {
+                let op = self.store.remove_record(&record.key);
+                self.drive_store_op(op);
+                None
+            }
+            record => record,
+        }
+    }
+
+    /// Drives an operation on the record store to completion, if it
+    /// does not complete immediately.
+    fn drive_store_op(&mut self, op: StoreOp<()>) {
+        if let StoreOp::Pending(op) = op {
+            self.store_ops.push(op.map(|()| StoreOutcome::Done).boxed())
+        }
+    }
+
+    /// Continues with an operation on the record store that completed.
+    fn store_op_completed(
+        &mut self,
+        outcome: StoreOutcome,
+        params: &mut impl PollParameters,
+    ) -> Option<KademliaEvent> {
+        match outcome {
+            StoreOutcome::GetRecord { query_id, record } => {
+                let record = self
+                    .unexpired(record)
+                    .map(|record| PeerRecord { peer: None, record });
+                if let Some(query) = self.queries.get_mut(&query_id) {
+                    query.inner.pending_lookup = false;
+                    let stats = query.stats().clone();
+                    if let QueryInfo::GetRecord {
+                        records, quorum, ..
+                    } = &mut query.inner.info
+                    {
+                        records.extend(record.clone());
+                        if records.len() >= quorum.get() {
+                            query.finish();
+                        }
+                    }
+                    if let Some(record) = record {
+                        self.query_progressed(query_id, stats, QueryProgress::GetRecord(record));
+                    }
+                } else if let Some((mut query, timed_out)) = self.awaiting_store.remove(&query_id) {
+                    if let QueryInfo::GetRecord { records, .. } = &mut query.inner.info {
+                        records.extend(record.clone());
+                    }
+                    if let Some(record) = record {
+                        let stats = query.stats().clone();
+                        self.query_progressed(query_id, stats, QueryProgress::GetRecord(record));
+                    }
+                    self.query_completed_after_progress(query, timed_out, params);
+                }
+            }
+            StoreOutcome::GetProviders {
+                query_id,
+                providers,
+            } => {
+                let local = unexpired_providers(providers);
+                let query = match self.queries.get_mut(&query_id) {
+                    Some(query) => {
+                        query.inner.pending_lookup = false;
+                        query
+                    }
+                    None => match self.awaiting_store.get_mut(&query_id) {
+                        Some((query, _)) => query,
+                        None => return None,
+                    },
+                };
+                let stats = query.stats().clone();
+                let mut progress = None;
+                if let QueryInfo::GetProviders { key, providers } = &mut query.inner.info {
+                    let new = local
+                        .into_iter()
+                        .filter(|p| providers.insert(*p))
+                        .collect::<HashSet<_>>();
+                    if !new.is_empty() {
+                        progress = Some(QueryProgress::GetProviders {
+                            key: key.clone(),
+                            providers: new,
+                        });
+                    }
+                }
+                if let Some(progress) = progress {
+                    self.query_progressed(query_id, stats, progress);
+                }
+                if let Some((query, timed_out)) = self.awaiting_store.remove(&query_id) {
+                    self.query_completed_after_progress(query, timed_out, params);
+                }
+            }
+            StoreOutcome::PutRecord { key, result } => {
+                if let Err(e) = result {
+                    warn!("Record {:?} not stored locally: {:?}", key, e);
+                }
+            }
+            StoreOutcome::StartProviding { key, result } => {
+                if let Err(e) = result {
+                    warn!("Provider record for {:?} not stored locally: {:?}", key, e);
+                }
+            }
+            StoreOutcome::RemoveRecord { record } => self.remove_published_record(record),
+            StoreOutcome::InboundGetRecord {
+                source,
+                connection,
+                request_id,
+                key,
+                record,
+            } => self.answer_get_record(source, connection, request_id, key, record),
+            StoreOutcome::InboundGetProviders {
+                source,
+                connection,
+                request_id,
+                key,
+                providers,
+            } => self.answer_get_providers(source, connection, request_id, key, providers),
+            StoreOutcome::InboundPutRecord {
+                source,
+                connection,
+                request_id,
+                record,
+                result,
+            } => self.record_stored(source, connection, request_id, record, result),
+            StoreOutcome::InboundAddProvider { result } => self.provider_stored(result),
+            StoreOutcome::Done => {}
+        }
+
+        None
+    }
+
+    /// Handles a query that finished or timed out.
+    fn query_completed(
+        &mut self,
+        query: Query<QueryInner>,
+        timed_out: bool,
+        params: &mut impl PollParameters,
+    ) -> Option<KademliaEvent> {
+        if timed_out {
+            self.query_timeout(query)
+        } else {
+            self.query_finished(query, params)
+        }
+    }
+
     fn address_failed(&mut self, peer_id: PeerId, address: &Multiaddr) {
         let key = kbucket::Key::from(peer_id);
 
@@ -1780,8 +2565,7 @@ fn exp_decrease(ttl: Duration, exp: u32) -> Duration {
 
 impl<TStore> NetworkBehaviour for Kademlia<TStore>
 where
-    for<'a> TStore: RecordStore<'a>,
-    TStore: Send + 'static,
+    TStore: AsyncRecordStore + Send + 'static,
 {
     type ConnectionHandler = KademliaHandlerProto<QueryId>;
     type OutEvent = KademliaEvent;
@@ -1789,7 +2573,7 @@ where
     fn new_handler(&mut self) -> Self::ConnectionHandler {
         KademliaHandlerProto::new(KademliaHandlerConfig {
             protocol_config: self.protocol_config.clone(),
-            allow_listening: true,
+            allow_listening: self.mode == KademliaMode::Server,
             idle_timeout: self.connection_idle_timeout,
         })
     }
@@ -1820,7 +2604,7 @@ where
     fn inject_connection_established(
         &mut self,
         peer_id: &PeerId,
-        _: &ConnectionId,
+        connection: &ConnectionId,
         _: &ConnectedPoint,
         errors: Option<&Vec<Multiaddr>>,
         other_established: usize,
@@ -1829,6 +2613,8 @@ where
             self.address_failed(*peer_id, addr);
         }
 
+        self.connections.insert(*connection, *peer_id);
+
         // When a connection is established, we don't know yet whether the
         // remote supports the configured protocol name. Only once a connection
         // handler reports [`KademliaHandlerEvent::ProtocolConfirmed`] do we
@@ -1933,6 +2719,8 @@ where
             | DialError::WrongPeerId { .. }
             | DialError::Aborted
             | DialError::ConnectionIo(_)
+            | DialError::Denied(_)
+            | DialError::ResourceLimitExceeded(_)
             | DialError::Transport(_)
             | DialError::NoAddresses => {
                 if let DialError::Transport(addresses) = error {
@@ -1941,6 +2729,24 @@ where
                     }
                 }
 
+                // A peer imported into the routing table that turns out to be
+                // unreachable does not retain its slot.
+                if self.unverified_peers.contains(&peer_id)
+                    && matches!(
+                        error,
+                        DialError::Transport(_)
+                            | DialError::WrongPeerId { .. }
+                            | DialError::ConnectionIo(_)
+                            | DialError::NoAddresses
+                    )
+                {
+                    debug!(
+                        "Removing unverified peer {} from the routing table.",
+                        peer_id
+                    );
+                    self.remove_peer(&peer_id);
+                }
+
                 for query in self.queries.iter_mut() {
                     query.on_failure(&peer_id);
                 }
@@ -1960,17 +2766,28 @@ where
     fn inject_connection_closed(
         &mut self,
         id: &PeerId,
-        _: &ConnectionId,
+        connection: &ConnectionId,
         _: &ConnectedPoint,
         _: <Self::ConnectionHandler as libp2p_swarm::IntoConnectionHandler>::Handler,
         remaining_established: usize,
     ) {
+        self.connections.remove(connection);
         if remaining_established == 0 {
             for query in self.queries.iter_mut() {
                 query.on_failure(id);
             }
             self.connection_updated(*id, None, NodeStatus::Disconnected);
             self.connected_peers.remove(id);
+            // An unverified peer that is no longer in the routing table, e.g.
+            // as its pending insertion was dropped, need not be tracked anymore.
+            if self.unverified_peers.contains(id)
+                && matches!(
+                    self.kbuckets.entry(&kbucket::Key::from(*id)),
+                    kbucket::Entry::Absent(..)
+                )
+            {
+                self.unverified_peers.remove(id);
+            }
         }
     }
 
@@ -1991,9 +2808,29 @@ where
                     ConnectedPoint::Dialer { address, .. } => Some(address),
                     ConnectedPoint::Listener { .. } => None,
                 };
+                self.unverified_peers.remove(&source);
                 self.connection_updated(source, address, NodeStatus::Connected);
             }
 
+            KademliaHandlerEvent::ProtocolUnsupported { .. } => {
+                // Only peers accepting the configured protocol name, i.e. peers
+                // in server mode, belong into the routing table.
+                let key = kbucket::Key::from(source);
+                let removed = match self.kbuckets.entry(&key) {
+                    kbucket::Entry::Present(entry, _) => Some(entry.remove()),
+                    kbucket::Entry::Pending(entry, _) => Some(entry.remove()),
+                    kbucket::Entry::Absent(..) | kbucket::Entry::SelfEntry => None,
+                };
+                if removed.is_some() {
+                    debug!(
+                        "Peer {} removed from routing table as it does not accept the \
+                         Kademlia protocol.",
+                        source
+                    );
+                }
+                self.unverified_peers.remove(&source);
+            }
+
             KademliaHandlerEvent::FindNodeReq { key, request_id } => {
                 let closer_peers = self.find_closest(&kbucket::Key::new(key), &source);
 
@@ -2025,29 +2862,24 @@ where
             }
 
             KademliaHandlerEvent::GetProvidersReq { key, request_id } => {
-                let provider_peers = self.provider_peers(&key, &source);
-                let closer_peers = self.find_closest(&kbucket::Key::new(key), &source);
-
-                self.queued_events
-                    .push_back(NetworkBehaviourAction::GenerateEvent(
-                        KademliaEvent::InboundRequest {
-                            request: InboundRequest::GetProvider {
-                                num_closer_peers: closer_peers.len(),
-                                num_provider_peers: provider_peers.len(),
-                            },
-                        },
-                    ));
-
-                self.queued_events
-                    .push_back(NetworkBehaviourAction::NotifyHandler {
-                        peer_id: source,
-                        handler: NotifyHandler::One(connection),
-                        event: KademliaHandlerIn::GetProvidersRes {
-                            closer_peers,
-                            provider_peers,
-                            request_id,
-                        },
-                    });
+                // Lookup the providers locally, answering the request once
+                // the lookup completes.
+                match self.store.provider_records(&key) {
+                    StoreOp::Ready(providers) => {
+                        self.answer_get_providers(source, connection, request_id, key, providers)
+                    }
+                    StoreOp::Pending(lookup) => self.store_ops.push(
+                        lookup
+                            .map(move |providers| StoreOutcome::InboundGetProviders {
+                                source,
+                                connection,
+                                request_id,
+                                key,
+                                providers,
+                            })
+                            .boxed(),
+                    ),
+                }
             }
 
             KademliaHandlerEvent::GetProvidersRes {
@@ -2058,9 +2890,19 @@ where
                 let peers = closer_peers.iter().chain(provider_peers.iter());
                 self.discovered(&user_data, &source, peers);
                 if let Some(query) = self.queries.get_mut(&user_data) {
-                    if let QueryInfo::GetProviders { providers, .. } = &mut query.inner.info {
-                        for peer in provider_peers {
-                            providers.insert(peer.node_id);
+                    let stats = query.stats().clone();
+                    if let QueryInfo::GetProviders { key, providers } = &mut query.inner.info {
+                        let new = provider_peers
+                            .into_iter()
+                            .map(|peer| peer.node_id)
+                            .filter(|peer| providers.insert(*peer))
+                            .collect::<HashSet<_>>();
+                        if !new.is_empty() {
+                            let progress = QueryProgress::GetProviders {
+                                key: key.clone(),
+                                providers: new,
+                            };
+                            self.query_progressed(user_data, stats, progress);
                         }
                     }
                 }
@@ -2090,41 +2932,24 @@ where
             }
 
             KademliaHandlerEvent::GetRecord { key, request_id } => {
-                // Lookup the record locally.
-                let record = match self.store.get(&key) {
-                    Some(record) => {
-                        if record.is_expired(Instant::now()) {
-                            self.store.remove(&key);
-                            None
-                        } else {
-                            Some(record.into_owned())
-                        }
+                // Lookup the record locally, answering the request once
+                // the lookup completes.
+                match self.store.get_record(&key) {
+                    StoreOp::Ready(record) => {
+                        self.answer_get_record(source, connection, request_id, key, record)
                     }
-                    None => None,
-                };
-
-                let closer_peers = self.find_closest(&kbucket::Key::new(key), &source);
-
-                self.queued_events
-                    .push_back(NetworkBehaviourAction::GenerateEvent(
-                        KademliaEvent::InboundRequest {
-                            request: InboundRequest::GetRecord {
-                                num_closer_peers: closer_peers.len(),
-                                present_locally: record.is_some(),
-                            },
-                        },
-                    ));
-
-                self.queued_events
-                    .push_back(NetworkBehaviourAction::NotifyHandler {
-                        peer_id: source,
-                        handler: NotifyHandler::One(connection),
-                        event: KademliaHandlerIn::GetRecordRes {
-                            record,
-                            closer_peers,
-                            request_id,
-                        },
-                    });
+                    StoreOp::Pending(lookup) => self.store_ops.push(
+                        lookup
+                            .map(move |record| StoreOutcome::InboundGetRecord {
+                                source,
+                                connection,
+                                request_id,
+                                key,
+                                record,
+                            })
+                            .boxed(),
+                    ),
+                }
             }
 
             KademliaHandlerEvent::GetRecordRes {
@@ -2132,7 +2957,11 @@ where
                 closer_peers,
                 user_data,
             } => {
+                // Invalid records are treated as if the peer did not have the record.
+                let record = record.filter(|record| self.is_valid(&source, record));
+                let mut progress = None;
                 if let Some(query) = self.queries.get_mut(&user_data) {
+                    let stats = query.stats().clone();
                     if let QueryInfo::GetRecord {
                         key,
                         records,
@@ -2141,10 +2970,12 @@ where
                     } = &mut query.inner.info
                     {
                         if let Some(record) = record {
-                            records.push(PeerRecord {
+                            let record = PeerRecord {
                                 peer: Some(source),
                                 record,
-                            });
+                            };
+                            progress = Some((stats, record.clone()));
+                            records.push(record);
 
                             let quorum = quorum.get();
                             if records.len() >= quorum {
@@ -2186,11 +3017,24 @@ where
                     }
                 }
 
+                if let Some((stats, record)) = progress {
+                    self.query_progressed(user_data, stats, QueryProgress::GetRecord(record));
+                }
+
                 self.discovered(&user_data, &source, closer_peers.iter());
             }
 
             KademliaHandlerEvent::PutRecord { record, request_id } => {
-                self.record_received(source, connection, request_id, record);
+                if self.is_valid(&source, &record) {
+                    self.record_received(source, connection, request_id, record);
+                } else {
+                    self.queued_events
+                        .push_back(NetworkBehaviourAction::NotifyHandler {
+                            peer_id: source,
+                            handler: NotifyHandler::One(connection),
+                            event: KademliaHandlerIn::Reset(request_id),
+                        });
+                }
             }
 
             KademliaHandlerEvent::PutRecordRes { user_data, .. } => {
@@ -2246,6 +3090,15 @@ where
     ) -> Poll<NetworkBehaviourAction<Self::OutEvent, Self::ConnectionHandler>> {
         let now = Instant::now();
 
+        if self.auto_mode {
+            let mode = if parameters.external_addresses().next().is_some() {
+                KademliaMode::Server
+            } else {
+                KademliaMode::Client
+            };
+            self.reconfigure_mode(mode);
+        }
+
         // Calculate the available capacity for queries triggered by background jobs.
         let mut jobs_query_capacity = JOBS_MAX_QUERIES.saturating_sub(self.queries.size());
 
@@ -2282,6 +3135,14 @@ where
             self.put_record_job = Some(job);
         }
 
+        // Bootstrap automatically, if due.
+        let num_peers = self.kbuckets.num_entries();
+        if self.bootstrap_status.poll(cx, num_peers).is_ready() {
+            if let Err(e) = self.start_bootstrap(true) {
+                debug!("Automatic bootstrap failed: {:?}", e);
+            }
+        }
+
         loop {
             // Drain queued events first.
             if let Some(event) = self.queued_events.pop_front() {
@@ -2290,6 +3151,9 @@ where
 
             // Drain applied pending entries from the routing table.
             if let Some(entry) = self.kbuckets.take_applied_pending() {
+                if let Some(evicted) = &entry.evicted {
+                    self.unverified_peers.remove(evicted.key.preimage());
+                }
                 let kbucket::Node { key, value } = entry.inserted;
                 let event = KademliaEvent::RoutingUpdated {
                     bucket_range: self
@@ -2305,9 +3169,22 @@ where
                 return Poll::Ready(NetworkBehaviourAction::GenerateEvent(event));
             }
 
+            // Continue with completed operations on the record store.
+            while let Poll::Ready(Some(outcome)) = self.store_ops.poll_next_unpin(cx) {
+                if let Some(event) = self.store_op_completed(outcome, parameters) {
+                    return Poll::Ready(NetworkBehaviourAction::GenerateEvent(event));
+                }
+            }
+
             // Look for a finished query.
             loop {
                 match self.queries.poll(now) {
+                    QueryPoolState::Finished(q) if q.inner.pending_lookup => {
+                        self.awaiting_store.insert(q.id(), (q, false));
+                    }
+                    QueryPoolState::Timeout(q) if q.inner.pending_lookup => {
+                        self.awaiting_store.insert(q.id(), (q, true));
+                    }
                     QueryPoolState::Finished(q) => {
                         if let Some(event) = self.query_finished(q, parameters) {
                             return Poll::Ready(NetworkBehaviourAction::GenerateEvent(event));
@@ -2422,6 +3299,23 @@ pub enum KademliaEvent {
         stats: QueryStats,
     },
 
+    /// An outbound query has produced an intermediate result.
+    ///
+    /// Progress is reported for every record found by [`Kademlia::get_record`]
+    /// and for every batch of newly found providers of [`Kademlia::get_providers`].
+    /// Such a query can be stopped early via [`QueryMut::finish`], e.g. once
+    /// a satisfying result has been found. Either way, the query eventually
+    /// reports its aggregated result as a [`KademliaEvent::OutboundQueryCompleted`]
+    /// after all of its progress.
+    OutboundQueryProgressed {
+        /// The ID of the query that made progress.
+        id: QueryId,
+        /// The intermediate result of the query.
+        result: QueryProgress,
+        /// Execution statistics from the query so far.
+        stats: QueryStats,
+    },
+
     /// The routing table has been updated with a new peer and / or
     /// address, thereby possibly evicting another peer.
     RoutingUpdated {
@@ -2471,6 +3365,11 @@ pub enum KademliaEvent {
     /// See [`Kademlia::kbucket`] for insight into the contents of
     /// the k-bucket of `peer`.
     PendingRoutablePeer { peer: PeerId, address: Multiaddr },
+
+    /// The [`KademliaMode`] of the local node changed.
+    ///
+    /// See [`Kademlia::set_mode`].
+    ModeChanged { new_mode: KademliaMode },
 }
 
 /// Information about a received and handled inbound request.
@@ -2506,6 +3405,21 @@ pub enum InboundRequest {
     },
 }
 
+/// The intermediate results of Kademlia queries.
+///
+/// See [`KademliaEvent::OutboundQueryProgressed`].
+#[derive(Debug, Clone)]
+pub enum QueryProgress {
+    /// A record found by a [`Kademlia::get_record`] query.
+    GetRecord(PeerRecord),
+    /// Providers found by a [`Kademlia::get_providers`] query that
+    /// have not been reported before.
+    GetProviders {
+        key: record::Key,
+        providers: HashSet<PeerId>,
+    },
+}
+
 /// The results of Kademlia queries.
 #[derive(Debug, Clone)]
 pub enum QueryResult {
@@ -2541,6 +3455,9 @@ pub type GetRecordResult = Result<GetRecordOk, GetRecordError>;
 #[derive(Debug, Clone)]
 pub struct GetRecordOk {
     /// The records found, including the peer that returned them.
+    ///
+    /// If a [`RecordValidator`] is set, the first record is the best one
+    /// as per [`RecordValidator::select`].
     pub records: Vec<PeerRecord>,
     /// If caching is enabled, these are the peers closest
     /// _to the record key_ (not the local node) that were queried but
@@ -2795,6 +3712,10 @@ struct QueryInner {
     /// A request is pending if the targeted peer is not currently connected
     /// and these requests are sent as soon as a connection to the peer is established.
     pending_rpcs: SmallVec<[(PeerId, KademliaHandlerIn<QueryId>); K_VALUE.get()]>,
+    /// Whether a lookup in the local record store is still in progress.
+    ///
+    /// The query does not complete until the lookup does.
+    pending_lookup: bool,
 }
 
 impl QueryInner {
@@ -2803,10 +3724,76 @@ impl QueryInner {
             info,
             addresses: Default::default(),
             pending_rpcs: SmallVec::default(),
+            pending_lookup: false,
         }
     }
 }
 
+/// Collects the providers of the given provider records that have not expired.
+fn unexpired_providers(providers: Vec<ProviderRecord>) -> HashSet<PeerId> {
+    let now = Instant::now();
+    providers
+        .into_iter()
+        .filter(|p| !p.is_expired(now))
+        .map(|p| p.provider)
+        .collect()
+}
+
+/// An operation on an [`AsyncRecordStore`] that completed asynchronously,
+/// along with the context required to continue with its result.
+enum StoreOutcome {
+    /// The local lookup of a [`Kademlia::get_record`] query.
+    GetRecord {
+        query_id: QueryId,
+        record: Option<Record>,
+    },
+    /// The local lookup of a [`Kademlia::get_providers`] query.
+    GetProviders {
+        query_id: QueryId,
+        providers: Vec<ProviderRecord>,
+    },
+    /// Storing a record published via [`Kademlia::put_record`].
+    PutRecord {
+        key: record::Key,
+        result: store::Result<()>,
+    },
+    /// Storing a provider record of [`Kademlia::start_providing`].
+    StartProviding {
+        key: record::Key,
+        result: store::Result<()>,
+    },
+    /// The local lookup of [`Kademlia::remove_record`].
+    RemoveRecord { record: Option<Record> },
+    /// The local lookup for an inbound `GET_VALUE` request.
+    InboundGetRecord {
+        source: PeerId,
+        connection: ConnectionId,
+        request_id: KademliaRequestId,
+        key: record::Key,
+        record: Option<Record>,
+    },
+    /// The local lookup for an inbound `GET_PROVIDERS` request.
+    InboundGetProviders {
+        source: PeerId,
+        connection: ConnectionId,
+        request_id: KademliaRequestId,
+        key: record::Key,
+        providers: Vec<ProviderRecord>,
+    },
+    /// Storing a record received with an inbound `PUT_VALUE` request.
+    InboundPutRecord {
+        source: PeerId,
+        connection: ConnectionId,
+        request_id: KademliaRequestId,
+        record: Record,
+        result: store::Result<()>,
+    },
+    /// Storing a provider record received with an inbound `ADD_PROVIDER` request.
+    InboundAddProvider { result: store::Result<()> },
+    /// An operation whose result requires no further action.
+    Done,
+}
+
 /// The context of a [`QueryInfo::AddProvider`] query.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum AddProviderContext {
@@ -2836,6 +3823,10 @@ pub enum PutRecordContext {
     /// The context is a custom store operation targeting specific
     /// peers initiated by [`Kademlia::put_record_to`].
     Custom,
+    /// The context is an automatic repair of outdated records, i.e. the
+    /// best record found via [`Kademlia::get_record`] is sent to the peers
+    /// that returned other records. See [`Kademlia::set_record_validator`].
+    Repair,
 }
 
 /// Information about a running query.
@@ -3072,6 +4063,38 @@ pub enum RoutingUpdate {
     Failed,
 }
 
+/// A snapshot of the routing table of a [`Kademlia`] behaviour.
+///
+/// See [`Kademlia::export_routing_table`] and [`Kademlia::import_routing_table`].
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(crate = "_serde"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoutingTableSnapshot {
+    /// The ID of the local node whose routing table was exported.
+    pub local_peer_id: PeerId,
+    /// The entries of the routing table.
+    pub entries: Vec<RoutingTableEntry>,
+}
+
+/// An entry of a [`RoutingTableSnapshot`].
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(crate = "_serde"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoutingTableEntry {
+    /// The ID of the peer.
+    pub peer_id: PeerId,
+    /// The known addresses of the peer.
+    pub addresses: Vec<Multiaddr>,
+    /// The index of the bucket of the peer, i.e. the base-2 logarithm
+    /// of its distance to the local key.
+    pub bucket: u32,
+    /// The position of the peer within its bucket, starting with the least
+    /// recently connected peer.
+    pub position: u32,
+    /// The status of the peer at the time the snapshot was taken.
+    pub status: NodeStatus,
+}
+
 /// The maximum number of local external addresses. When reached any
 /// further externally reported addresses are ignored. The behaviour always
 /// tracks all its listen addresses.
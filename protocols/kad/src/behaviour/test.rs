@@ -23,7 +23,11 @@
 use super::*;
 
 use crate::kbucket::Distance;
-use crate::record::{store::MemoryStore, Key};
+use crate::record::{
+    store::{MemoryStore, PersistentStore, RecordStore},
+    validator::{RecordValidator, ValidationError},
+    Key,
+};
 use crate::K_VALUE;
 use futures::{executor::block_on, future::poll_fn, prelude::*};
 use futures_timer::Delay;
@@ -36,13 +40,14 @@ use libp2p_core::{
     upgrade, Endpoint, PeerId, Transport,
 };
 use libp2p_noise as noise;
-use libp2p_swarm::{Swarm, SwarmEvent};
+use libp2p_swarm::{AddressScore, Swarm, SwarmEvent};
 use libp2p_yamux as yamux;
 use quickcheck::*;
 use rand::{random, rngs::StdRng, thread_rng, Rng, SeedableRng};
 use std::{
     collections::{HashMap, HashSet},
     num::NonZeroUsize,
+    sync::{Arc, Mutex},
     time::Duration,
     u64,
 };
@@ -55,9 +60,22 @@ fn build_node() -> (Multiaddr, TestSwarm) {
 
 fn build_node_with_config(cfg: KademliaConfig) -> (Multiaddr, TestSwarm) {
     let local_key = identity::Keypair::generate_ed25519();
-    let local_public_key = local_key.public();
+    let local_id = local_key.public().to_peer_id();
+    let store = MemoryStore::new(local_id);
+    let behaviour = Kademlia::with_config(local_id, store, cfg.clone());
+
+    build_swarm(&local_key, behaviour)
+}
+
+fn build_swarm<TStore>(
+    local_key: &identity::Keypair,
+    behaviour: Kademlia<TStore>,
+) -> (Multiaddr, Swarm<Kademlia<TStore>>)
+where
+    TStore: AsyncRecordStore + Send + 'static,
+{
     let noise_keys = noise::Keypair::<noise::X25519>::new()
-        .into_authentic(&local_key)
+        .into_authentic(local_key)
         .unwrap();
     let transport = MemoryTransport::default()
         .upgrade(upgrade::Version::V1)
@@ -65,11 +83,7 @@ fn build_node_with_config(cfg: KademliaConfig) -> (Multiaddr, TestSwarm) {
         .multiplex(yamux::YamuxConfig::default())
         .boxed();
 
-    let local_id = local_public_key.to_peer_id();
-    let store = MemoryStore::new(local_id.clone());
-    let behaviour = Kademlia::with_config(local_id.clone(), store, cfg.clone());
-
-    let mut swarm = Swarm::new(transport, behaviour, local_id);
+    let mut swarm = Swarm::new(transport, behaviour, local_key.public().to_peer_id());
 
     let address: Multiaddr = Protocol::Memory(random::<u64>()).into();
     swarm.listen_on(address.clone()).unwrap();
@@ -800,6 +814,251 @@ fn get_record() {
     }))
 }
 
+#[test]
+fn get_record_from_restarted_persistent_store() {
+    let path = std::env::temp_dir().join(format!("libp2p-kad-test-{}.log", random::<u64>()));
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_id = local_key.public().to_peer_id();
+    let record = Record::new(random_multihash(), vec![4, 5, 6]);
+
+    {
+        let store = PersistentStore::open(local_id, &path).unwrap();
+        let (_addr, mut swarm) = build_swarm(&local_key, Kademlia::new(local_id, store));
+        swarm.behaviour_mut().store.put(record.clone()).unwrap();
+    }
+
+    // Restart the node on the same store.
+    let store = PersistentStore::open(local_id, &path).unwrap();
+    let (addr, mut restarted) = build_swarm(&local_key, Kademlia::new(local_id, store));
+    let (_, mut swarm) = build_node();
+    swarm.behaviour_mut().add_address(&local_id, addr);
+    let qid = swarm
+        .behaviour_mut()
+        .get_record(record.key.clone(), Quorum::One);
+
+    block_on(poll_fn(move |ctx| {
+        while let Poll::Ready(Some(_)) = restarted.poll_next_unpin(ctx) {}
+        loop {
+            match swarm.poll_next_unpin(ctx) {
+                Poll::Ready(Some(SwarmEvent::Behaviour(
+                    KademliaEvent::OutboundQueryCompleted {
+                        id,
+                        result: QueryResult::GetRecord(Ok(GetRecordOk { records, .. })),
+                        ..
+                    },
+                ))) => {
+                    assert_eq!(id, qid);
+                    assert_eq!(records.len(), 1);
+                    assert_eq!(records[0].record, record);
+                    assert_eq!(records[0].peer, Some(local_id));
+                    return Poll::Ready(());
+                }
+                // Ignore any other event.
+                Poll::Ready(Some(_)) => (),
+                e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }));
+
+    std::fs::remove_file(path).unwrap();
+}
+
+/// A record store whose operations only complete after a delay, as
+/// if the records were stored in a remote database.
+#[derive(Clone)]
+struct DelayedStore {
+    inner: Arc<Mutex<MemoryStore>>,
+}
+
+impl DelayedStore {
+    fn new(local_id: PeerId) -> Self {
+        DelayedStore {
+            inner: Arc::new(Mutex::new(MemoryStore::new(local_id))),
+        }
+    }
+
+    fn delayed<T, F>(&self, op: F) -> StoreOp<T>
+    where
+        F: FnOnce(&mut MemoryStore) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let inner = self.inner.clone();
+        StoreOp::Pending(
+            Delay::new(Duration::from_millis(10))
+                .map(move |()| op(&mut inner.lock().unwrap()))
+                .boxed(),
+        )
+    }
+}
+
+impl AsyncRecordStore for DelayedStore {
+    fn get_record(&mut self, k: &Key) -> StoreOp<Option<Record>> {
+        let k = k.clone();
+        self.delayed(move |s| s.get(&k).map(Cow::into_owned))
+    }
+
+    fn put_record(&mut self, r: Record) -> StoreOp<store::Result<()>> {
+        self.delayed(move |s| s.put(r))
+    }
+
+    fn remove_record(&mut self, k: &Key) -> StoreOp<()> {
+        let k = k.clone();
+        self.delayed(move |s| s.remove(&k))
+    }
+
+    fn all_records(&mut self) -> StoreOp<Vec<Record>> {
+        self.delayed(|s| s.records().map(Cow::into_owned).collect())
+    }
+
+    fn add_provider_record(&mut self, record: ProviderRecord) -> StoreOp<store::Result<()>> {
+        self.delayed(move |s| s.add_provider(record))
+    }
+
+    fn provider_records(&mut self, key: &Key) -> StoreOp<Vec<ProviderRecord>> {
+        let key = key.clone();
+        self.delayed(move |s| s.providers(&key))
+    }
+
+    fn provided_records(&mut self) -> StoreOp<Vec<ProviderRecord>> {
+        self.delayed(|s| s.provided().map(Cow::into_owned).collect())
+    }
+
+    fn remove_provider_record(&mut self, k: &Key, p: &PeerId) -> StoreOp<()> {
+        let (k, p) = (k.clone(), *p);
+        self.delayed(move |s| s.remove_provider(&k, &p))
+    }
+}
+
+fn build_node_with_delayed_store() -> (Multiaddr, DelayedStore, Swarm<Kademlia<DelayedStore>>) {
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_id = local_key.public().to_peer_id();
+    let store = DelayedStore::new(local_id);
+    let behaviour = Kademlia::new(local_id, store.clone());
+    let (addr, swarm) = build_swarm(&local_key, behaviour);
+    (addr, store, swarm)
+}
+
+#[test]
+fn get_record_from_async_store() {
+    let (addr, store, mut remote) = build_node_with_delayed_store();
+    let (_, _, mut swarm) = build_node_with_delayed_store();
+    let remote_id = *remote.local_peer_id();
+    swarm.behaviour_mut().add_address(&remote_id, addr);
+
+    let record = Record::new(random_multihash(), vec![4, 5, 6]);
+    store.inner.lock().unwrap().put(record.clone()).unwrap();
+
+    // Without any known peers, the local lookup must still complete the query.
+    let local_qid = remote
+        .behaviour_mut()
+        .get_record(record.key.clone(), Quorum::One);
+    // The inbound request must be answered once the lookup completes.
+    let qid = swarm
+        .behaviour_mut()
+        .get_record(record.key.clone(), Quorum::One);
+
+    let mut found = (false, false);
+    block_on(poll_fn(move |ctx| {
+        for (i, swarm) in [&mut remote, &mut swarm].into_iter().enumerate() {
+            loop {
+                match swarm.poll_next_unpin(ctx) {
+                    Poll::Ready(Some(SwarmEvent::Behaviour(
+                        KademliaEvent::OutboundQueryCompleted {
+                            id,
+                            result: QueryResult::GetRecord(Ok(GetRecordOk { records, .. })),
+                            ..
+                        },
+                    ))) => {
+                        assert_eq!(records.len(), 1);
+                        assert_eq!(records[0].record, record);
+                        if i == 0 {
+                            assert_eq!(id, local_qid);
+                            assert_eq!(records[0].peer, None);
+                            found.0 = true;
+                        } else {
+                            assert_eq!(id, qid);
+                            assert_eq!(records[0].peer, Some(remote_id));
+                            found.1 = true;
+                        }
+                    }
+                    Poll::Ready(Some(SwarmEvent::Behaviour(
+                        KademliaEvent::OutboundQueryCompleted {
+                            result: QueryResult::GetRecord(Err(e)),
+                            ..
+                        },
+                    ))) => panic!("Unexpected error: {:?}", e),
+                    // Ignore any other event.
+                    Poll::Ready(Some(_)) => (),
+                    e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                    Poll::Pending => break,
+                }
+            }
+        }
+
+        if found == (true, true) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }))
+}
+
+#[test]
+fn put_record_to_async_store() {
+    let (_, publisher_store, mut publisher) = build_node_with_delayed_store();
+    let (addr, remote_store, remote) = build_node_with_delayed_store();
+    publisher
+        .behaviour_mut()
+        .add_address(remote.local_peer_id(), addr);
+    let mut swarms = vec![publisher, remote];
+
+    let record = Record::new(random_multihash(), vec![4, 5, 6]);
+    let qid = swarms[0]
+        .behaviour_mut()
+        .put_record(record.clone(), Quorum::One)
+        .unwrap();
+
+    block_on(poll_fn(|ctx| {
+        for swarm in &mut swarms {
+            loop {
+                match swarm.poll_next_unpin(ctx) {
+                    Poll::Ready(Some(SwarmEvent::Behaviour(
+                        KademliaEvent::OutboundQueryCompleted {
+                            id,
+                            result: QueryResult::PutRecord(res),
+                            ..
+                        },
+                    ))) => {
+                        assert_eq!(id, qid);
+                        assert_eq!(res.unwrap().key, record.key);
+                        return Poll::Ready(());
+                    }
+                    // Ignore any other event.
+                    Poll::Ready(Some(_)) => (),
+                    e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                    Poll::Pending => break,
+                }
+            }
+        }
+
+        Poll::Pending
+    }));
+
+    // Both the publisher and the remote peer have stored the record
+    // by the time the query completes.
+    for store in [publisher_store, remote_store] {
+        let stored = store
+            .inner
+            .lock()
+            .unwrap()
+            .get(&record.key)
+            .unwrap()
+            .into_owned();
+        assert_eq!(stored.value, record.value);
+    }
+}
+
 #[test]
 fn get_record_many() {
     // TODO: Randomise
@@ -1355,6 +1614,21 @@ fn get_providers() {
         let query_id = single_swarm.behaviour_mut().get_providers(key.clone());
 
         block_on(async {
+            // The local provider record is reported as progress first.
+            match single_swarm.next().await.unwrap() {
+                SwarmEvent::Behaviour(KademliaEvent::OutboundQueryProgressed {
+                    id,
+                    result: QueryProgress::GetProviders { providers, .. },
+                    ..
+                }) if id == query_id => {
+                    assert_eq!(
+                        single_swarm.local_peer_id(),
+                        providers.iter().next().unwrap()
+                    );
+                }
+                SwarmEvent::Behaviour(e) => panic!("Unexpected event: {:?}", e),
+                _ => {}
+            }
             match single_swarm.next().await.unwrap() {
                 SwarmEvent::Behaviour(KademliaEvent::OutboundQueryCompleted {
                     id,
@@ -1379,3 +1653,625 @@ fn get_providers() {
     }
     QuickCheck::new().tests(10).quickcheck(prop as fn(_))
 }
+
+#[test]
+fn get_record_progress_and_finish() {
+    let mut swarms = build_fully_connected_nodes_with_config(4, Default::default())
+        .into_iter()
+        .map(|(_addr, swarm)| swarm)
+        .collect::<Vec<_>>();
+
+    let record = Record::new(random_multihash(), vec![4, 5, 6]);
+    for swarm in &mut swarms[1..] {
+        swarm.behaviour_mut().store.put(record.clone()).unwrap();
+    }
+
+    // A quorum that cannot be reached, so that the query only
+    // finishes once it is stopped.
+    let qid = swarms[0]
+        .behaviour_mut()
+        .get_record(record.key.clone(), Quorum::All);
+
+    let mut progressed = 0;
+    block_on(poll_fn(move |ctx| {
+        for swarm in &mut swarms {
+            loop {
+                match swarm.poll_next_unpin(ctx) {
+                    Poll::Ready(Some(SwarmEvent::Behaviour(
+                        KademliaEvent::OutboundQueryProgressed {
+                            id,
+                            result: QueryProgress::GetRecord(found),
+                            ..
+                        },
+                    ))) => {
+                        assert_eq!(id, qid);
+                        assert_eq!(found.record, record);
+                        if progressed == 0 {
+                            swarm.behaviour_mut().query_mut(&id).unwrap().finish();
+                        }
+                        progressed += 1;
+                    }
+                    Poll::Ready(Some(SwarmEvent::Behaviour(
+                        KademliaEvent::OutboundQueryCompleted {
+                            id,
+                            result:
+                                QueryResult::GetRecord(Err(GetRecordError::QuorumFailed {
+                                    records,
+                                    ..
+                                })),
+                            ..
+                        },
+                    ))) => {
+                        assert_eq!(id, qid);
+                        // All records found are reported as progress
+                        // before the query completes.
+                        assert_eq!(records.len(), progressed);
+                        return Poll::Ready(());
+                    }
+                    // Ignore any other event.
+                    Poll::Ready(Some(_)) => (),
+                    e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                    Poll::Pending => break,
+                }
+            }
+        }
+
+        Poll::Pending
+    }))
+}
+
+#[test]
+fn get_providers_progress() {
+    let mut swarms = build_fully_connected_nodes_with_config(3, Default::default())
+        .into_iter()
+        .map(|(_addr, swarm)| swarm)
+        .collect::<Vec<_>>();
+
+    let key = record::Key::from(random_multihash());
+    let mut expected = HashSet::new();
+    for swarm in &mut swarms[1..] {
+        let provider = *swarm.local_peer_id();
+        let record = ProviderRecord::new(key.clone(), provider, Vec::new());
+        swarm.behaviour_mut().store.add_provider(record).unwrap();
+        expected.insert(provider);
+    }
+
+    let qid = swarms[0].behaviour_mut().get_providers(key.clone());
+
+    let mut progressed = HashSet::new();
+    block_on(poll_fn(move |ctx| {
+        for swarm in &mut swarms {
+            loop {
+                match swarm.poll_next_unpin(ctx) {
+                    Poll::Ready(Some(SwarmEvent::Behaviour(
+                        KademliaEvent::OutboundQueryProgressed {
+                            id,
+                            result:
+                                QueryProgress::GetProviders {
+                                    key: found,
+                                    providers,
+                                },
+                            ..
+                        },
+                    ))) => {
+                        assert_eq!(id, qid);
+                        assert_eq!(found, key);
+                        for provider in providers {
+                            // Every provider is only reported once.
+                            assert!(progressed.insert(provider));
+                        }
+                    }
+                    Poll::Ready(Some(SwarmEvent::Behaviour(
+                        KademliaEvent::OutboundQueryCompleted {
+                            id,
+                            result: QueryResult::GetProviders(Ok(GetProvidersOk { providers, .. })),
+                            ..
+                        },
+                    ))) => {
+                        assert_eq!(id, qid);
+                        assert_eq!(providers, expected);
+                        assert_eq!(progressed, expected);
+                        return Poll::Ready(());
+                    }
+                    // Ignore any other event.
+                    Poll::Ready(Some(_)) => (),
+                    e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                    Poll::Pending => break,
+                }
+            }
+        }
+
+        Poll::Pending
+    }))
+}
+
+/// Accepts records with non-empty values and prefers the largest value.
+struct LargestValue;
+
+impl RecordValidator for LargestValue {
+    fn validate(&self, record: &Record) -> Result<(), ValidationError> {
+        if record.value.is_empty() {
+            Err(ValidationError::Invalid("empty value".into()))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn select(&self, _: &Key, records: &[&Record]) -> usize {
+        (0..records.len())
+            .max_by_key(|i| &records[*i].value)
+            .unwrap()
+    }
+}
+
+#[test]
+fn get_record_validates_and_repairs() {
+    let mut swarms = build_fully_connected_nodes_with_config(4, Default::default())
+        .into_iter()
+        .map(|(_addr, swarm)| swarm)
+        .collect::<Vec<_>>();
+    swarms[0].behaviour_mut().set_record_validator(LargestValue);
+
+    let key = Key::from(random_multihash());
+    for (swarm, value) in swarms[1..].iter_mut().zip([vec![1], vec![2], vec![]]) {
+        swarm
+            .behaviour_mut()
+            .store
+            .put(Record::new(key.clone(), value))
+            .unwrap();
+    }
+
+    let qid = swarms[0]
+        .behaviour_mut()
+        .get_record(key.clone(), Quorum::All);
+
+    let mut completed = false;
+    block_on(poll_fn(move |ctx| {
+        for swarm in &mut swarms {
+            loop {
+                match swarm.poll_next_unpin(ctx) {
+                    Poll::Ready(Some(SwarmEvent::Behaviour(
+                        KademliaEvent::OutboundQueryCompleted {
+                            id,
+                            result:
+                                QueryResult::GetRecord(Err(GetRecordError::QuorumFailed {
+                                    records,
+                                    ..
+                                })),
+                            ..
+                        },
+                    ))) => {
+                        assert_eq!(id, qid);
+                        // The invalid record is ignored and the best record comes first.
+                        assert_eq!(records.len(), 2);
+                        assert_eq!(records[0].record.value, vec![2]);
+                        completed = true;
+                    }
+                    // Ignore any other event.
+                    Poll::Ready(Some(_)) => (),
+                    e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                    Poll::Pending => break,
+                }
+            }
+        }
+
+        // The peer that returned the outdated record is sent the best one.
+        let repaired = swarms[1].behaviour_mut().store.get(&key).unwrap();
+        if completed && repaired.value == vec![2] {
+            return Poll::Ready(());
+        }
+
+        Poll::Pending
+    }))
+}
+
+#[test]
+fn invalid_inbound_put_record_rejected() {
+    let mut swarms = build_fully_connected_nodes_with_config(3, Default::default())
+        .into_iter()
+        .map(|(_addr, swarm)| swarm)
+        .collect::<Vec<_>>();
+    swarms[1].behaviour_mut().set_record_validator(LargestValue);
+    let accepting = *swarms[2].local_peer_id();
+
+    let record = Record::new(random_multihash(), vec![]);
+    let qid = swarms[0]
+        .behaviour_mut()
+        .put_record(record.clone(), Quorum::All)
+        .unwrap();
+
+    block_on(poll_fn(move |ctx| {
+        for swarm in &mut swarms {
+            loop {
+                match swarm.poll_next_unpin(ctx) {
+                    Poll::Ready(Some(SwarmEvent::Behaviour(
+                        KademliaEvent::OutboundQueryCompleted {
+                            id,
+                            result:
+                                QueryResult::PutRecord(Err(PutRecordError::QuorumFailed {
+                                    success,
+                                    ..
+                                })),
+                            ..
+                        },
+                    ))) => {
+                        assert_eq!(id, qid);
+                        assert_eq!(success, vec![accepting]);
+                        return Poll::Ready(());
+                    }
+                    // Ignore any other event.
+                    Poll::Ready(Some(_)) => (),
+                    e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                    Poll::Pending => break,
+                }
+            }
+        }
+
+        Poll::Pending
+    }))
+}
+
+#[test]
+fn client_mode_peer_removed_from_routing_table() {
+    let mut cfg = KademliaConfig::default();
+    cfg.set_mode(Some(KademliaMode::Client));
+    let (client_addr, mut client) = build_node_with_config(cfg);
+    let (_, mut server) = build_node();
+    let client_id = *client.local_peer_id();
+
+    // The server learns about the client, e.g. through another protocol.
+    server.behaviour_mut().add_address(&client_id, client_addr);
+    assert_eq!(server.behaviour_mut().kbuckets().count(), 1);
+
+    let qid = server.behaviour_mut().get_closest_peers(PeerId::random());
+
+    block_on(poll_fn(|ctx| {
+        loop {
+            match client.poll_next_unpin(ctx) {
+                Poll::Ready(Some(SwarmEvent::Behaviour(KademliaEvent::InboundRequest {
+                    ..
+                }))) => panic!("Client must not accept inbound requests."),
+                Poll::Ready(Some(_)) => {}
+                e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                Poll::Pending => break,
+            }
+        }
+        loop {
+            match server.poll_next_unpin(ctx) {
+                Poll::Ready(Some(SwarmEvent::Behaviour(
+                    KademliaEvent::OutboundQueryCompleted { id, .. },
+                ))) => {
+                    assert_eq!(id, qid);
+                    return Poll::Ready(());
+                }
+                Poll::Ready(Some(_)) => {}
+                e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }));
+
+    assert_eq!(server.behaviour_mut().kbuckets().count(), 0);
+}
+
+#[test]
+fn automatic_mode_follows_external_addresses() {
+    let mut cfg = KademliaConfig::default();
+    cfg.set_mode(None);
+    let (_, mut swarm) = build_node_with_config(cfg);
+    assert_eq!(swarm.behaviour().mode(), KademliaMode::Client);
+
+    let external_addr: Multiaddr = Protocol::Memory(random::<u64>()).into();
+    swarm.add_external_address(external_addr.clone(), AddressScore::Infinite);
+
+    fn wait_for_mode(swarm: &mut TestSwarm, expected: KademliaMode) {
+        block_on(poll_fn(|ctx| loop {
+            match swarm.poll_next_unpin(ctx) {
+                Poll::Ready(Some(SwarmEvent::Behaviour(KademliaEvent::ModeChanged {
+                    new_mode,
+                }))) => {
+                    assert_eq!(new_mode, expected);
+                    return Poll::Ready(());
+                }
+                Poll::Ready(Some(_)) => {}
+                e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                Poll::Pending => return Poll::Pending,
+            }
+        }));
+    }
+
+    wait_for_mode(&mut swarm, KademliaMode::Server);
+    swarm.remove_external_address(&external_addr);
+    wait_for_mode(&mut swarm, KademliaMode::Client);
+}
+
+#[test]
+fn kademlia_protocol_advertised_in_server_mode_only() {
+    /// Records the protocols the `Swarm` advertises around a `Kademlia` behaviour.
+    struct Advertised {
+        kad: Kademlia<MemoryStore>,
+        protocols: Vec<Vec<u8>>,
+    }
+
+    impl NetworkBehaviour for Advertised {
+        type ConnectionHandler = KademliaHandlerProto<QueryId>;
+        type OutEvent = KademliaEvent;
+
+        fn new_handler(&mut self) -> Self::ConnectionHandler {
+            self.kad.new_handler()
+        }
+
+        fn inject_event(
+            &mut self,
+            peer_id: PeerId,
+            connection: ConnectionId,
+            event: KademliaHandlerEvent<QueryId>,
+        ) {
+            self.kad.inject_event(peer_id, connection, event)
+        }
+
+        fn poll(
+            &mut self,
+            cx: &mut Context<'_>,
+            params: &mut impl PollParameters,
+        ) -> Poll<NetworkBehaviourAction<Self::OutEvent, Self::ConnectionHandler>> {
+            self.protocols = params.supported_protocols().collect();
+            self.kad.poll(cx, params)
+        }
+    }
+
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_id = local_key.public().to_peer_id();
+    let mut cfg = KademliaConfig::default();
+    cfg.set_mode(None);
+    let transport = MemoryTransport::default()
+        .upgrade(upgrade::Version::V1)
+        .authenticate(
+            noise::NoiseConfig::xx(
+                noise::Keypair::<noise::X25519>::new()
+                    .into_authentic(&local_key)
+                    .unwrap(),
+            )
+            .into_authenticated(),
+        )
+        .multiplex(yamux::YamuxConfig::default())
+        .boxed();
+    let behaviour = Advertised {
+        kad: Kademlia::with_config(local_id, MemoryStore::new(local_id), cfg),
+        protocols: Vec::new(),
+    };
+    let mut swarm = Swarm::new(transport, behaviour, local_id);
+
+    fn poll_mode(swarm: &mut Swarm<Advertised>, expected: KademliaMode) -> Vec<Vec<u8>> {
+        block_on(poll_fn(|ctx| loop {
+            match swarm.poll_next_unpin(ctx) {
+                Poll::Ready(Some(SwarmEvent::Behaviour(KademliaEvent::ModeChanged {
+                    new_mode,
+                }))) => {
+                    assert_eq!(new_mode, expected);
+                    // Let the behaviour observe the protocols once more.
+                    let _ = swarm.poll_next_unpin(ctx);
+                    return Poll::Ready(swarm.behaviour().protocols.clone());
+                }
+                Poll::Ready(Some(_)) => {}
+                e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                Poll::Pending => return Poll::Pending,
+            }
+        }))
+    }
+
+    let kad_protocol = b"/ipfs/kad/1.0.0".to_vec();
+    assert!(!swarm.behaviour().protocols.contains(&kad_protocol));
+
+    let external_addr: Multiaddr = Protocol::Memory(random::<u64>()).into();
+    swarm.add_external_address(external_addr.clone(), AddressScore::Infinite);
+    assert!(poll_mode(&mut swarm, KademliaMode::Server).contains(&kad_protocol));
+
+    swarm.remove_external_address(&external_addr);
+    assert!(!poll_mode(&mut swarm, KademliaMode::Client).contains(&kad_protocol));
+}
+
+#[test]
+fn export_and_import_routing_table() {
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_id = local_key.public().to_peer_id();
+    let (_, mut swarm) = build_swarm(
+        &local_key,
+        Kademlia::new(local_id, MemoryStore::new(local_id)),
+    );
+    for _ in 0..50 {
+        let address = Protocol::Memory(random::<u64>()).into();
+        swarm
+            .behaviour_mut()
+            .add_address(&PeerId::random(), address);
+    }
+
+    let snapshot = swarm.behaviour_mut().export_routing_table();
+    assert_eq!(snapshot.local_peer_id, local_id);
+    assert!(!snapshot.entries.is_empty());
+
+    // Restart the node with the exported routing table.
+    let (_, mut restarted) = build_swarm(
+        &local_key,
+        Kademlia::new(local_id, MemoryStore::new(local_id)),
+    );
+    let num_imported = restarted
+        .behaviour_mut()
+        .import_routing_table(snapshot.clone());
+    assert_eq!(num_imported, snapshot.entries.len());
+    assert_eq!(restarted.behaviour_mut().export_routing_table(), snapshot);
+}
+
+#[test]
+fn imported_peers_are_reverified() {
+    let (live_addr, mut live) = build_node();
+    let (_, mut swarm) = build_node();
+    let live_id = *live.local_peer_id();
+    let unreachable_id = PeerId::random();
+
+    let entry = |peer_id, address| RoutingTableEntry {
+        peer_id,
+        addresses: vec![address],
+        bucket: 0,
+        position: 0,
+        status: NodeStatus::Connected,
+    };
+    let snapshot = RoutingTableSnapshot {
+        local_peer_id: PeerId::random(),
+        entries: vec![
+            entry(live_id, live_addr),
+            entry(unreachable_id, Protocol::Memory(random::<u64>()).into()),
+        ],
+    };
+    assert_eq!(swarm.behaviour_mut().import_routing_table(snapshot), 2);
+
+    // All imported peers are pending re-verification.
+    let statuses = |swarm: &mut TestSwarm| {
+        swarm
+            .behaviour_mut()
+            .export_routing_table()
+            .entries
+            .into_iter()
+            .map(|e| (e.peer_id, e.status))
+            .collect::<HashMap<_, _>>()
+    };
+    assert!(statuses(&mut swarm)
+        .values()
+        .all(|s| *s == NodeStatus::Disconnected));
+
+    swarm.behaviour_mut().bootstrap().unwrap();
+
+    block_on(poll_fn(|ctx| {
+        while let Poll::Ready(Some(_)) = live.poll_next_unpin(ctx) {}
+        loop {
+            match swarm.poll_next_unpin(ctx) {
+                Poll::Ready(Some(SwarmEvent::Behaviour(
+                    KademliaEvent::OutboundQueryCompleted {
+                        result: QueryResult::Bootstrap(Ok(BootstrapOk { num_remaining, .. })),
+                        ..
+                    },
+                ))) => {
+                    if num_remaining == 0 {
+                        return Poll::Ready(());
+                    }
+                }
+                Poll::Ready(Some(_)) => {}
+                e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }));
+
+    // The reachable peer has been verified, the unreachable one removed.
+    let statuses = statuses(&mut swarm);
+    assert_eq!(statuses.get(&live_id), Some(&NodeStatus::Connected));
+    assert_eq!(statuses.get(&unreachable_id), None);
+    assert!(swarm.behaviour().unverified_peers.is_empty());
+}
+
+#[test]
+fn removed_imported_peers_are_forgotten() {
+    let (_, mut swarm) = build_node();
+    let peer_id = PeerId::random();
+    let address: Multiaddr = Protocol::Memory(random::<u64>()).into();
+    let snapshot = RoutingTableSnapshot {
+        local_peer_id: PeerId::random(),
+        entries: vec![RoutingTableEntry {
+            peer_id,
+            addresses: vec![address.clone()],
+            bucket: 0,
+            position: 0,
+            status: NodeStatus::Connected,
+        }],
+    };
+    assert_eq!(swarm.behaviour_mut().import_routing_table(snapshot), 1);
+    assert!(swarm.behaviour().unverified_peers.contains(&peer_id));
+
+    // Removing the last address removes the peer from the routing table.
+    assert!(swarm
+        .behaviour_mut()
+        .remove_address(&peer_id, &address)
+        .is_some());
+    assert!(swarm.behaviour().unverified_peers.is_empty());
+}
+
+#[test]
+fn periodic_bootstrap() {
+    let mut cfg = KademliaConfig::default();
+    cfg.set_periodic_bootstrap_interval(Some(Duration::from_millis(100)));
+    let (_, mut swarm) = build_node_with_config(cfg);
+    let (addr, mut other) = build_node();
+    let local_id = *swarm.local_peer_id();
+    swarm
+        .behaviour_mut()
+        .add_address(other.local_peer_id(), addr);
+
+    // Without any call to `Kademlia::bootstrap`, the node bootstraps repeatedly.
+    let mut bootstraps = HashSet::new();
+    block_on(poll_fn(|ctx| {
+        while let Poll::Ready(Some(_)) = other.poll_next_unpin(ctx) {}
+        loop {
+            match swarm.poll_next_unpin(ctx) {
+                Poll::Ready(Some(SwarmEvent::Behaviour(
+                    KademliaEvent::OutboundQueryCompleted {
+                        id,
+                        result: QueryResult::Bootstrap(Ok(ok)),
+                        ..
+                    },
+                ))) => {
+                    if ok.peer == local_id {
+                        bootstraps.insert(id);
+                    }
+                    if bootstraps.len() == 2 {
+                        return Poll::Ready(());
+                    }
+                }
+                Poll::Ready(Some(_)) => {}
+                e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }));
+}
+
+#[test]
+fn bootstrap_when_routing_table_drops_below_threshold() {
+    let mut cfg = KademliaConfig::default();
+    cfg.set_automatic_bootstrap_threshold(Some(2));
+    let (_, mut swarm) = build_node_with_config(cfg);
+    let mut others = build_nodes(2);
+    for (addr, other) in &others {
+        swarm
+            .behaviour_mut()
+            .add_address(other.local_peer_id(), addr.clone());
+    }
+
+    // A routing table at the threshold does not trigger a bootstrap.
+    block_on(poll_fn(|ctx| {
+        while let Poll::Ready(Some(_)) = swarm.poll_next_unpin(ctx) {}
+        Poll::Ready(())
+    }));
+    assert_eq!(swarm.behaviour().queries.size(), 0);
+
+    // Shrinking below the threshold does.
+    let removed = *others[1].1.local_peer_id();
+    swarm.behaviour_mut().remove_peer(&removed);
+    block_on(poll_fn(|ctx| {
+        for (_, other) in &mut others {
+            while let Poll::Ready(Some(_)) = other.poll_next_unpin(ctx) {}
+        }
+        loop {
+            match swarm.poll_next_unpin(ctx) {
+                Poll::Ready(Some(SwarmEvent::Behaviour(
+                    KademliaEvent::OutboundQueryCompleted {
+                        result: QueryResult::Bootstrap(Ok(_)),
+                        ..
+                    },
+                ))) => return Poll::Ready(()),
+                Poll::Ready(Some(_)) => {}
+                e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }));
+}
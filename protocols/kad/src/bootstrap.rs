@@ -0,0 +1,202 @@
+// Copyright 2022 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Automatic bootstrapping of the routing table.
+//!
+//! Besides explicit calls to [`Kademlia::bootstrap`](crate::Kademlia::bootstrap),
+//! a node may bootstrap automatically, either periodically or whenever the
+//! number of peers in its routing table drops below a given threshold.
+//!
+//! As per the Kademlia paper, an automatic bootstrap only refreshes the buckets
+//! that have not been the target of a lookup within the bootstrap interval,
+//! since buckets that were recently looked up are presumed to be fresh.
+
+use crate::kbucket::NUM_BUCKETS;
+use crate::query::QueryId;
+use futures::prelude::*;
+use futures_timer::Delay;
+use instant::Instant;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// Keeps track of when the local node bootstraps automatically.
+pub struct BootstrapStatus {
+    /// The interval of periodic bootstraps, if enabled.
+    interval: Option<Duration>,
+    /// The delay until the next periodic bootstrap.
+    delay: Option<Delay>,
+    /// The number of peers in the routing table below which
+    /// a bootstrap is triggered, if enabled.
+    threshold: Option<usize>,
+    /// The number of peers in the routing table when last polled.
+    num_peers: usize,
+    /// Whether the routing table dropped below the threshold while
+    /// a bootstrap was running, to bootstrap once it finishes.
+    below_threshold: bool,
+    /// The currently running bootstrap, if any, and whether it
+    /// has been started automatically.
+    current: Option<(QueryId, bool)>,
+    /// The instant of the most recent lookup per bucket.
+    last_lookups: Vec<Option<Instant>>,
+}
+
+impl BootstrapStatus {
+    /// Creates a new `BootstrapStatus` for the given bootstrap interval
+    /// and routing table size threshold.
+    pub fn new(interval: Option<Duration>, threshold: Option<usize>) -> Self {
+        BootstrapStatus {
+            interval,
+            delay: interval.map(Delay::new),
+            threshold,
+            num_peers: 0,
+            below_threshold: false,
+            current: None,
+            last_lookups: vec![None; NUM_BUCKETS],
+        }
+    }
+
+    /// Records a lookup of a key in the bucket with the given index.
+    pub fn on_lookup(&mut self, bucket: u32, now: Instant) {
+        self.last_lookups[bucket as usize] = Some(now);
+    }
+
+    /// Checks whether the bucket with the given index has not been the
+    /// target of a lookup within the bootstrap interval.
+    pub fn is_stale(&self, bucket: u32, now: Instant) -> bool {
+        match (self.interval, self.last_lookups[bucket as usize]) {
+            (Some(interval), Some(last)) => now >= last + interval,
+            _ => true,
+        }
+    }
+
+    /// Records the start of a bootstrap.
+    pub fn on_started(&mut self, id: QueryId, automatic: bool) {
+        self.current = Some((id, automatic))
+    }
+
+    /// Records the completion of a bootstrap, i.e. of all its queries.
+    pub fn on_finished(&mut self, id: QueryId) {
+        if self.current.map_or(false, |(current, _)| current == id) {
+            self.current = None
+        }
+    }
+
+    /// Checks whether the bootstrap with the given ID has been started
+    /// automatically.
+    pub fn is_automatic(&self, id: QueryId) -> bool {
+        self.current == Some((id, true))
+    }
+
+    /// Polls for whether a bootstrap should be started automatically, given
+    /// the current number of peers in the routing table.
+    ///
+    /// A drop of the routing table below the threshold while a bootstrap is
+    /// running triggers a bootstrap once the running one finishes.
+    ///
+    /// Must be called in the context of a task. When `Pending` is returned,
+    /// the current task is registered to be notified when the next periodic
+    /// bootstrap is due.
+    pub fn poll(&mut self, cx: &mut Context<'_>, num_peers: usize) -> Poll<()> {
+        if let Some(threshold) = self.threshold {
+            if num_peers < threshold && num_peers < self.num_peers {
+                self.below_threshold = true;
+            }
+            self.num_peers = num_peers;
+        }
+        let mut ready = self.below_threshold;
+
+        if let (Some(interval), Some(delay)) = (self.interval, self.delay.as_mut()) {
+            if delay.poll_unpin(cx).is_ready() {
+                ready = true;
+                delay.reset(interval);
+                // Register the task for the next periodic bootstrap.
+                let _ = delay.poll_unpin(cx);
+            }
+        }
+
+        if ready && self.current.is_none() {
+            self.below_threshold = false;
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::block_on, future::poll_fn};
+
+    #[test]
+    fn stale_buckets() {
+        let now = Instant::now();
+        let interval = Duration::from_secs(60);
+        let mut status = BootstrapStatus::new(Some(interval), None);
+        assert!(status.is_stale(0, now));
+        status.on_lookup(0, now);
+        assert!(!status.is_stale(0, now + interval / 2));
+        assert!(status.is_stale(0, now + interval));
+        assert!(status.is_stale(1, now));
+    }
+
+    #[test]
+    fn bootstrap_when_routing_table_drops_below_threshold() {
+        let mut status = BootstrapStatus::new(None, Some(5));
+        block_on(poll_fn(|cx| {
+            // Growing routing tables do not trigger a bootstrap.
+            assert!(status.poll(cx, 3).is_pending());
+            assert!(status.poll(cx, 6).is_pending());
+            // Neither do shrinking ones above the threshold.
+            assert!(status.poll(cx, 5).is_pending());
+            assert!(status.poll(cx, 4).is_ready());
+            assert!(status.poll(cx, 4).is_pending());
+
+            Poll::Ready(())
+        }))
+    }
+
+    #[test]
+    fn periodic_bootstrap() {
+        let interval = Duration::from_millis(10);
+        let mut status = BootstrapStatus::new(Some(interval), None);
+        for _ in 0..2 {
+            block_on(poll_fn(|cx| status.poll(cx, 0)));
+        }
+    }
+
+    #[test]
+    fn bootstrap_below_threshold_after_running_bootstrap() {
+        let mut status = BootstrapStatus::new(None, Some(5));
+        let id = QueryId(1);
+        block_on(poll_fn(|cx| {
+            assert!(status.poll(cx, 6).is_pending());
+            status.on_started(id, false);
+            // The drop below the threshold is remembered while bootstrapping.
+            assert!(status.poll(cx, 4).is_pending());
+            assert!(status.poll(cx, 4).is_pending());
+            status.on_finished(id);
+            assert!(status.poll(cx, 4).is_ready());
+            assert!(status.poll(cx, 4).is_pending());
+
+            Poll::Ready(())
+        }))
+    }
+}
@@ -23,11 +23,12 @@ use crate::protocol::{
     KademliaProtocolConfig,
 };
 use crate::record::{self, Record};
+use crate::KademliaMode;
 use futures::prelude::*;
 use instant::Instant;
 use libp2p_core::{
     either::EitherOutput,
-    upgrade::{self, InboundUpgrade, OutboundUpgrade},
+    upgrade::{self, InboundUpgrade, NegotiationError, OutboundUpgrade, UpgradeError},
     ConnectedPoint, PeerId,
 };
 use libp2p_swarm::{
@@ -108,6 +109,10 @@ pub struct KademliaHandler<TUserData> {
 
 /// The states of protocol confirmation that a connection
 /// handler transitions through.
+///
+/// Only outbound substreams tell whether the remote accepts the configured
+/// protocol name, since a remote in [`KademliaMode::Client`] may open inbound
+/// substreams without accepting any itself.
 enum ProtocolStatus {
     /// It is as yet unknown whether the remote supports the
     /// configured protocol name.
@@ -115,9 +120,12 @@ enum ProtocolStatus {
     /// The configured protocol name has been confirmed by the remote
     /// but has not yet been reported to the `Kademlia` behaviour.
     Confirmed,
-    /// The configured protocol has been confirmed by the remote
-    /// and the confirmation reported to the `Kademlia` behaviour.
-    Reported,
+    /// The configured protocol name has been refused by the remote
+    /// but this has not yet been reported to the `Kademlia` behaviour.
+    Refused,
+    /// The support of the configured protocol by the remote has been
+    /// reported to the `Kademlia` behaviour.
+    Reported { supported: bool },
 }
 
 /// Configuration of a [`KademliaHandler`].
@@ -228,12 +236,18 @@ pub enum KademliaHandlerEvent<TUserData> {
     /// The configured protocol name has been confirmed by the peer through
     /// a successfully negotiated substream.
     ///
-    /// This event is only emitted once by a handler upon the first
-    /// successfully negotiated inbound or outbound substream and
-    /// indicates that the connected peer participates in the Kademlia
-    /// overlay network identified by the configured protocol name.
+    /// This event is emitted by a handler upon the first successfully
+    /// negotiated outbound substream and indicates that the connected peer
+    /// participates in the Kademlia overlay network identified by the
+    /// configured protocol name and accepts requests, i.e. is in
+    /// [`KademliaMode::Server`].
     ProtocolConfirmed { endpoint: ConnectedPoint },
 
+    /// The peer refused to negotiate the configured protocol name on an
+    /// outbound substream, i.e. it does not participate in the Kademlia
+    /// overlay network or is in [`KademliaMode::Client`].
+    ProtocolUnsupported { endpoint: ConnectedPoint },
+
     /// Request for the list of nodes whose IDs are the closest to `key`. The number of nodes
     /// returned is not specified, but should be around 20.
     FindNodeReq {
@@ -380,6 +394,12 @@ pub enum KademliaHandlerIn<TUserData> {
     /// for the query on the remote.
     Reset(KademliaRequestId),
 
+    /// Changes the mode of the handler, i.e. whether it accepts
+    /// inbound substreams.
+    ///
+    /// Inbound substreams that are already open are not affected.
+    ReconfigureMode { new_mode: KademliaMode },
+
     /// Request for the list of nodes whose IDs are the closest to `key`. The number of nodes
     /// returned is not specified, but should be around 20.
     FindNodeReq {
@@ -532,11 +552,14 @@ where
             .push(OutboundSubstreamState::PendingSend(
                 protocol, msg, user_data,
             ));
-        if let ProtocolStatus::Unconfirmed = self.protocol_status {
-            // Upon the first successfully negotiated substream, we know that the
-            // remote is configured with the same protocol name and we want
-            // the behaviour to add this peer to the routing table, if possible.
-            self.protocol_status = ProtocolStatus::Confirmed;
+        match self.protocol_status {
+            ProtocolStatus::Confirmed | ProtocolStatus::Reported { supported: true } => {}
+            _ => {
+                // Upon the first successfully negotiated outbound substream, we know
+                // that the remote is configured with the same protocol name and we
+                // want the behaviour to add this peer to the routing table, if possible.
+                self.protocol_status = ProtocolStatus::Confirmed;
+            }
         }
     }
 
@@ -552,11 +575,10 @@ where
             EitherOutput::Second(p) => void::unreachable(p),
         };
 
-        if let ProtocolStatus::Unconfirmed = self.protocol_status {
-            // Upon the first successfully negotiated substream, we know that the
-            // remote is configured with the same protocol name and we want
-            // the behaviour to add this peer to the routing table, if possible.
-            self.protocol_status = ProtocolStatus::Confirmed;
+        if !self.config.allow_listening {
+            // The handler was reconfigured to `KademliaMode::Client` while
+            // the substream was being negotiated.
+            return;
         }
 
         if self.inbound_substreams.len() == MAX_NUM_INBOUND_SUBSTREAMS {
@@ -583,7 +605,6 @@ where
             }
         }
 
-        debug_assert!(self.config.allow_listening);
         let connec_unique_id = self.next_connec_unique_id;
         self.next_connec_unique_id.0 += 1;
         self.inbound_substreams
@@ -613,6 +634,9 @@ where
                     let _ = self.inbound_substreams.remove(pos).try_close(&mut cx);
                 }
             }
+            KademliaHandlerIn::ReconfigureMode { new_mode } => {
+                self.config.allow_listening = new_mode == KademliaMode::Server;
+            }
             KademliaHandlerIn::FindNodeReq { key, user_data } => {
                 let msg = KadRequestMsg::FindNode { key };
                 self.outbound_substreams
@@ -769,6 +793,14 @@ where
     ) {
         // TODO: cache the fact that the remote doesn't support kademlia at all, so that we don't
         //       continue trying
+        if let ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Select(NegotiationError::Failed)) =
+            error
+        {
+            match self.protocol_status {
+                ProtocolStatus::Refused | ProtocolStatus::Reported { supported: false } => {}
+                _ => self.protocol_status = ProtocolStatus::Refused,
+            }
+        }
         if let Some(user_data) = user_data {
             self.outbound_substreams
                 .push(OutboundSubstreamState::ReportError(error.into(), user_data));
@@ -790,17 +822,28 @@ where
             Self::Error,
         >,
     > {
-        if self.outbound_substreams.is_empty() && self.inbound_substreams.is_empty() {
-            return Poll::Pending;
+        match self.protocol_status {
+            ProtocolStatus::Confirmed => {
+                self.protocol_status = ProtocolStatus::Reported { supported: true };
+                return Poll::Ready(ConnectionHandlerEvent::Custom(
+                    KademliaHandlerEvent::ProtocolConfirmed {
+                        endpoint: self.endpoint.clone(),
+                    },
+                ));
+            }
+            ProtocolStatus::Refused => {
+                self.protocol_status = ProtocolStatus::Reported { supported: false };
+                return Poll::Ready(ConnectionHandlerEvent::Custom(
+                    KademliaHandlerEvent::ProtocolUnsupported {
+                        endpoint: self.endpoint.clone(),
+                    },
+                ));
+            }
+            ProtocolStatus::Unconfirmed | ProtocolStatus::Reported { .. } => {}
         }
 
-        if let ProtocolStatus::Confirmed = self.protocol_status {
-            self.protocol_status = ProtocolStatus::Reported;
-            return Poll::Ready(ConnectionHandlerEvent::Custom(
-                KademliaHandlerEvent::ProtocolConfirmed {
-                    endpoint: self.endpoint.clone(),
-                },
-            ));
+        if self.outbound_substreams.is_empty() && self.inbound_substreams.is_empty() {
+            return Poll::Pending;
         }
 
         // We remove each element from `outbound_substreams` one by one and add them back.
@@ -61,8 +61,14 @@
 //! > to the size of all stored records. As a job runs, the records are moved
 //! > out of the job to the consumer, where they can be dropped after being sent.
 
-use crate::record::{self, store::RecordStore, ProviderRecord, Record};
+use crate::record::{
+    self,
+    store::{AsyncRecordStore, StoreOp},
+    ProviderRecord, Record,
+};
+use futures::future::BoxFuture;
 use futures::prelude::*;
+use futures::stream::FuturesUnordered;
 use futures_timer::Delay;
 use instant::Instant;
 use libp2p_core::PeerId;
@@ -82,20 +88,53 @@ pub const JOBS_MAX_QUERIES: usize = 100;
 pub const JOBS_MAX_NEW_QUERIES: usize = 10;
 
 /// A background job run periodically.
-#[derive(Debug)]
 struct PeriodicJob<T> {
     interval: Duration,
     state: PeriodicJobState<T>,
+    /// Removals of expired records that are still in progress
+    /// in an [`AsyncRecordStore`].
+    removals: FuturesUnordered<BoxFuture<'static, ()>>,
 }
 
 impl<T> PeriodicJob<T> {
+    fn new(interval: Duration, state: PeriodicJobState<T>) -> Self {
+        PeriodicJob {
+            interval,
+            state,
+            removals: FuturesUnordered::new(),
+        }
+    }
+
     fn is_running(&self) -> bool {
         match self.state {
-            PeriodicJobState::Running(..) => true,
+            PeriodicJobState::Loading(..) | PeriodicJobState::Running(..) => true,
             PeriodicJobState::Waiting(..) => false,
         }
     }
 
+    /// Drives the removals of expired records to completion.
+    fn poll_removals(&mut self, cx: &mut Context<'_>) {
+        while let Poll::Ready(Some(())) = self.removals.poll_next_unpin(cx) {}
+    }
+
+    /// Polls the records of the current run, if they are being loaded
+    /// from the store.
+    fn poll_loading(&mut self, cx: &mut Context<'_>) -> Poll<Option<Vec<T>>> {
+        if let PeriodicJobState::Loading(records) = &mut self.state {
+            records.as_mut().poll(cx).map(Some)
+        } else {
+            Poll::Ready(None)
+        }
+    }
+
+    /// Puts the job into the waiting state until the next run.
+    fn wait(&mut self, cx: &mut Context<'_>, now: Instant) {
+        let deadline = now + self.interval;
+        let delay = Delay::new(self.interval);
+        self.state = PeriodicJobState::Waiting(delay, deadline);
+        assert!(!self.check_ready(cx, now));
+    }
+
     /// Cuts short the remaining delay, if the job is currently waiting
     /// for the delay to expire.
     fn asap(&mut self) {
@@ -119,9 +158,9 @@ impl<T> PeriodicJob<T> {
 }
 
 /// The state of a background job run periodically.
-#[derive(Debug)]
 enum PeriodicJobState<T> {
-    Running(T),
+    Loading(BoxFuture<'static, Vec<T>>),
+    Running(vec::IntoIter<T>),
     Waiting(Delay, Instant),
 }
 
@@ -135,7 +174,7 @@ pub struct PutRecordJob {
     publish_interval: Option<Duration>,
     record_ttl: Option<Duration>,
     skipped: HashSet<record::Key>,
-    inner: PeriodicJob<vec::IntoIter<Record>>,
+    inner: PeriodicJob<Record>,
 }
 
 impl PutRecordJob {
@@ -157,10 +196,10 @@ impl PutRecordJob {
             publish_interval,
             record_ttl,
             skipped: HashSet::new(),
-            inner: PeriodicJob {
-                interval: replicate_interval,
-                state: PeriodicJobState::Waiting(delay, deadline),
-            },
+            inner: PeriodicJob::new(
+                replicate_interval,
+                PeriodicJobState::Waiting(delay, deadline),
+            ),
         }
     }
 
@@ -193,57 +232,73 @@ impl PutRecordJob {
     /// to be run.
     pub fn poll<T>(&mut self, cx: &mut Context<'_>, store: &mut T, now: Instant) -> Poll<Record>
     where
-        for<'a> T: RecordStore<'a>,
+        T: AsyncRecordStore,
     {
+        self.inner.poll_removals(cx);
+
         if self.inner.check_ready(cx, now) {
-            let publish = self.next_publish.map_or(false, |t_pub| now >= t_pub);
-            let records = store
-                .records()
-                .filter_map(|r| {
-                    let is_publisher = r.publisher.as_ref() == Some(&self.local_id);
-                    if self.skipped.contains(&r.key) || (!publish && is_publisher) {
-                        None
-                    } else {
-                        let mut record = r.into_owned();
-                        if publish && is_publisher {
-                            record.expires = record
-                                .expires
-                                .or_else(|| self.record_ttl.map(|ttl| now + ttl));
-                        }
-                        Some(record)
-                    }
-                })
-                .collect::<Vec<_>>()
-                .into_iter();
+            self.inner.state = match store.all_records() {
+                StoreOp::Ready(records) => PeriodicJobState::Running(self.select(records, now)),
+                StoreOp::Pending(records) => PeriodicJobState::Loading(records),
+            };
+        }
 
-            // Schedule the next publishing run.
-            if publish {
-                self.next_publish = self.publish_interval.map(|i| now + i);
+        match self.inner.poll_loading(cx) {
+            Poll::Ready(Some(records)) => {
+                self.inner.state = PeriodicJobState::Running(self.select(records, now))
             }
-
-            self.skipped.clear();
-
-            self.inner.state = PeriodicJobState::Running(records);
+            Poll::Ready(None) => {}
+            Poll::Pending => return Poll::Pending,
         }
 
         if let PeriodicJobState::Running(records) = &mut self.inner.state {
             for r in records {
                 if r.is_expired(now) {
-                    store.remove(&r.key)
+                    if let StoreOp::Pending(removal) = store.remove_record(&r.key) {
+                        self.inner.removals.push(removal)
+                    }
                 } else {
                     return Poll::Ready(r);
                 }
             }
 
             // Wait for the next run.
-            let deadline = now + self.inner.interval;
-            let delay = Delay::new(self.inner.interval);
-            self.inner.state = PeriodicJobState::Waiting(delay, deadline);
-            assert!(!self.inner.check_ready(cx, now));
+            self.inner.wait(cx, now);
         }
 
         Poll::Pending
     }
+
+    /// Selects the records to replicate or publish on the current run.
+    fn select(&mut self, records: Vec<Record>, now: Instant) -> vec::IntoIter<Record> {
+        let publish = self.next_publish.map_or(false, |t_pub| now >= t_pub);
+        let records = records
+            .into_iter()
+            .filter_map(|mut record| {
+                let is_publisher = record.publisher.as_ref() == Some(&self.local_id);
+                if self.skipped.contains(&record.key) || (!publish && is_publisher) {
+                    None
+                } else {
+                    if publish && is_publisher {
+                        record.expires = record
+                            .expires
+                            .or_else(|| self.record_ttl.map(|ttl| now + ttl));
+                    }
+                    Some(record)
+                }
+            })
+            .collect::<Vec<_>>()
+            .into_iter();
+
+        // Schedule the next publishing run.
+        if publish {
+            self.next_publish = self.publish_interval.map(|i| now + i);
+        }
+
+        self.skipped.clear();
+
+        records
+    }
 }
 
 //////////////////////////////////////////////////////////////////////////////
@@ -251,7 +306,7 @@ impl PutRecordJob {
 
 /// Periodic job for replicating provider records.
 pub struct AddProviderJob {
-    inner: PeriodicJob<vec::IntoIter<ProviderRecord>>,
+    inner: PeriodicJob<ProviderRecord>,
 }
 
 impl AddProviderJob {
@@ -259,13 +314,10 @@ impl AddProviderJob {
     pub fn new(interval: Duration) -> Self {
         let now = Instant::now();
         Self {
-            inner: PeriodicJob {
-                interval,
-                state: {
-                    let deadline = now + interval;
-                    PeriodicJobState::Waiting(Delay::new(interval), deadline)
-                },
-            },
+            inner: PeriodicJob::new(interval, {
+                let deadline = now + interval;
+                PeriodicJobState::Waiting(Delay::new(interval), deadline)
+            }),
         }
     }
 
@@ -294,30 +346,39 @@ impl AddProviderJob {
         now: Instant,
     ) -> Poll<ProviderRecord>
     where
-        for<'a> T: RecordStore<'a>,
+        T: AsyncRecordStore,
     {
+        self.inner.poll_removals(cx);
+
         if self.inner.check_ready(cx, now) {
-            let records = store
-                .provided()
-                .map(|r| r.into_owned())
-                .collect::<Vec<_>>()
-                .into_iter();
-            self.inner.state = PeriodicJobState::Running(records);
+            self.inner.state = match store.provided_records() {
+                StoreOp::Ready(records) => PeriodicJobState::Running(records.into_iter()),
+                StoreOp::Pending(records) => PeriodicJobState::Loading(records),
+            };
+        }
+
+        match self.inner.poll_loading(cx) {
+            Poll::Ready(Some(records)) => {
+                self.inner.state = PeriodicJobState::Running(records.into_iter())
+            }
+            Poll::Ready(None) => {}
+            Poll::Pending => return Poll::Pending,
         }
 
         if let PeriodicJobState::Running(keys) = &mut self.inner.state {
             for r in keys {
                 if r.is_expired(now) {
-                    store.remove_provider(&r.key, &r.provider)
+                    if let StoreOp::Pending(removal) =
+                        store.remove_provider_record(&r.key, &r.provider)
+                    {
+                        self.inner.removals.push(removal)
+                    }
                 } else {
                     return Poll::Ready(r);
                 }
             }
 
-            let deadline = now + self.inner.interval;
-            let delay = Delay::new(self.inner.interval);
-            self.inner.state = PeriodicJobState::Waiting(delay, deadline);
-            assert!(!self.inner.check_ready(cx, now));
+            self.inner.wait(cx, now);
         }
 
         Poll::Pending
@@ -327,7 +388,7 @@ impl AddProviderJob {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::record::store::MemoryStore;
+    use crate::record::store::{MemoryStore, RecordStore};
     use futures::{executor::block_on, future::poll_fn};
     use quickcheck::*;
     use rand::Rng;
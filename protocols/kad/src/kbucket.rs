@@ -80,7 +80,7 @@ use std::collections::VecDeque;
 use std::time::{Duration, Instant};
 
 /// Maximum number of k-buckets.
-const NUM_BUCKETS: usize = 256;
+pub(crate) const NUM_BUCKETS: usize = 256;
 
 /// A `KBucketsTable` represents a Kademlia routing table.
 #[derive(Debug, Clone)]
@@ -92,6 +92,21 @@ pub struct KBucketsTable<TKey, TVal> {
     /// The list of evicted entries that have been replaced with pending
     /// entries since the last call to [`KBucketsTable::take_applied_pending`].
     applied_pending: VecDeque<AppliedPending<TKey, TVal>>,
+    /// The number of entries in all buckets, excluding pending entries.
+    num_entries: usize,
+}
+
+/// Records a pending entry that has been applied to its bucket, counting it
+/// as a new entry unless it replaced an evicted one.
+fn record_applied<TKey, TVal>(
+    applied: AppliedPending<TKey, TVal>,
+    applied_pending: &mut VecDeque<AppliedPending<TKey, TVal>>,
+    num_entries: &mut usize,
+) {
+    if applied.evicted.is_none() {
+        *num_entries += 1;
+    }
+    applied_pending.push_back(applied)
 }
 
 /// A (type-safe) index into a `KBucketsTable`, i.e. a non-negative integer in the
@@ -161,6 +176,7 @@ where
                 .map(|_| KBucket::new(pending_timeout))
                 .collect(),
             applied_pending: VecDeque::new(),
+            num_entries: 0,
         }
     }
 
@@ -176,9 +192,9 @@ where
         if let Some(i) = index {
             let bucket = &mut self.buckets[i.get()];
             if let Some(applied) = bucket.apply_pending() {
-                self.applied_pending.push_back(applied)
+                record_applied(applied, &mut self.applied_pending, &mut self.num_entries)
             }
-            Entry::new(bucket, key)
+            Entry::new(bucket, key, &mut self.num_entries)
         } else {
             Entry::SelfEntry
         }
@@ -190,9 +206,10 @@ where
     /// bucket is the closest bucket (containing at most one key).
     pub fn iter(&mut self) -> impl Iterator<Item = KBucketRef<'_, TKey, TVal>> + '_ {
         let applied_pending = &mut self.applied_pending;
+        let num_entries = &mut self.num_entries;
         self.buckets.iter_mut().enumerate().map(move |(i, b)| {
             if let Some(applied) = b.apply_pending() {
-                applied_pending.push_back(applied)
+                record_applied(applied, applied_pending, num_entries)
             }
             KBucketRef {
                 index: BucketIndex(i),
@@ -212,7 +229,7 @@ where
         if let Some(index) = BucketIndex::new(&d) {
             let bucket = &mut self.buckets[index.0];
             if let Some(applied) = bucket.apply_pending() {
-                self.applied_pending.push_back(applied)
+                record_applied(applied, &mut self.applied_pending, &mut self.num_entries)
             }
             Some(KBucketRef { bucket, index })
         } else {
@@ -220,6 +237,12 @@ where
         }
     }
 
+    /// Returns the number of entries in the routing table, excluding entries
+    /// pending insertion.
+    pub fn num_entries(&self) -> usize {
+        self.num_entries
+    }
+
     /// Consumes the next applied pending entry, if any.
     ///
     /// When an entry is attempted to be inserted and the respective bucket is full,
@@ -441,7 +464,11 @@ where
                     if let Some(i) = self.buckets_iter.next() {
                         let bucket = &mut self.table.buckets[i.get()];
                         if let Some(applied) = bucket.apply_pending() {
-                            self.table.applied_pending.push_back(applied)
+                            record_applied(
+                                applied,
+                                &mut self.table.applied_pending,
+                                &mut self.table.num_entries,
+                            )
                         }
                         let mut v = (self.fmap)(bucket);
                         v.sort_by(|a, b| {
@@ -534,10 +561,12 @@ mod tests {
             let timeout = Duration::from_secs(g.gen_range(1, 360));
             let mut table = TestTable::new(local_key.clone().into(), timeout);
             let mut num_total = g.gen_range(0, 100);
+            let mut num_entries = 0;
             for (i, b) in &mut table.buckets.iter_mut().enumerate().rev() {
                 let ix = BucketIndex(i);
                 let num = g.gen_range(0, usize::min(K_VALUE.get(), num_total) + 1);
                 num_total -= num;
+                num_entries += num;
                 for _ in 0..num {
                     let distance = ix.rand_distance(g);
                     let key = local_key.for_distance(distance);
@@ -552,6 +581,7 @@ mod tests {
                     }
                 }
             }
+            table.num_entries = num_entries;
             table
         }
     }
@@ -733,6 +763,31 @@ mod tests {
 
         assert_eq!(Some(expected_applied), table.take_applied_pending());
         assert_eq!(None, table.take_applied_pending());
+        // The inserted entry replaced the evicted one.
+        let num_entries = table.iter().map(|b| b.num_entries()).sum::<usize>();
+        assert_eq!(num_entries, table.num_entries());
+    }
+
+    #[test]
+    fn num_entries() {
+        fn prop(mut table: TestTable) -> bool {
+            let keys = table
+                .iter()
+                .flat_map(|b| b.iter().map(|e| e.node.key.clone()).collect::<Vec<_>>())
+                .collect::<Vec<_>>();
+            let count =
+                |table: &mut TestTable| table.iter().map(|b| b.num_entries()).sum::<usize>();
+            let mut ok = count(&mut table) == table.num_entries();
+            for key in keys {
+                if let Entry::Present(entry, _) = table.entry(&key) {
+                    entry.remove();
+                }
+                ok &= count(&mut table) == table.num_entries();
+            }
+            ok && table.num_entries() == 0
+        }
+
+        QuickCheck::new().tests(10).quickcheck(prop as fn(_) -> _)
     }
 
     #[test]
@@ -46,6 +46,8 @@ pub struct PendingNode<TKey, TVal> {
 /// The status of a node in a bucket together with the time of the
 /// last status change determines the position of the node in a
 /// bucket.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(crate = "_serde"))]
 #[derive(PartialEq, Eq, Debug, Copy, Clone)]
 pub enum NodeStatus {
     /// The node is considered connected.
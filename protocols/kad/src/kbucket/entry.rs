@@ -91,6 +91,8 @@ pub enum Entry<'a, TPeerId, TVal> {
 struct EntryRef<'a, TKey, TVal> {
     bucket: &'a mut KBucket<TKey, TVal>,
     key: &'a TKey,
+    /// The number of entries in the routing table the bucket belongs to.
+    num_entries: &'a mut usize,
 }
 
 impl<'a, TKey, TVal> Entry<'a, TKey, TVal>
@@ -99,15 +101,19 @@ where
     TVal: Clone,
 {
     /// Creates a new `Entry` for a `Key`, encapsulating access to a bucket.
-    pub(super) fn new(bucket: &'a mut KBucket<TKey, TVal>, key: &'a TKey) -> Self {
+    pub(super) fn new(
+        bucket: &'a mut KBucket<TKey, TVal>,
+        key: &'a TKey,
+        num_entries: &'a mut usize,
+    ) -> Self {
         if let Some(pos) = bucket.position(key) {
             let status = bucket.status(pos);
-            Entry::Present(PresentEntry::new(bucket, key), status)
+            Entry::Present(PresentEntry::new(bucket, key, num_entries), status)
         } else if let Some(pending) = bucket.as_pending(key) {
             let status = pending.status();
-            Entry::Pending(PendingEntry::new(bucket, key), status)
+            Entry::Pending(PendingEntry::new(bucket, key, num_entries), status)
         } else {
-            Entry::Absent(AbsentEntry::new(bucket, key))
+            Entry::Absent(AbsentEntry::new(bucket, key, num_entries))
         }
     }
 
@@ -172,8 +178,12 @@ where
     TKey: Clone + AsRef<KeyBytes>,
     TVal: Clone,
 {
-    fn new(bucket: &'a mut KBucket<TKey, TVal>, key: &'a TKey) -> Self {
-        PresentEntry(EntryRef { bucket, key })
+    fn new(bucket: &'a mut KBucket<TKey, TVal>, key: &'a TKey, num_entries: &'a mut usize) -> Self {
+        PresentEntry(EntryRef {
+            bucket,
+            key,
+            num_entries,
+        })
     }
 
     /// Returns the key of the entry.
@@ -203,6 +213,7 @@ where
             .bucket
             .remove(self.0.key)
             .expect("We can only build a PresentEntry if the entry is in the bucket; QED");
+        *self.0.num_entries -= 1;
         EntryView { node, status }
     }
 }
@@ -216,8 +227,12 @@ where
     TKey: Clone + AsRef<KeyBytes>,
     TVal: Clone,
 {
-    fn new(bucket: &'a mut KBucket<TKey, TVal>, key: &'a TKey) -> Self {
-        PendingEntry(EntryRef { bucket, key })
+    fn new(bucket: &'a mut KBucket<TKey, TVal>, key: &'a TKey, num_entries: &'a mut usize) -> Self {
+        PendingEntry(EntryRef {
+            bucket,
+            key,
+            num_entries,
+        })
     }
 
     /// Returns the key of the entry.
@@ -237,7 +252,7 @@ where
     /// Updates the status of the pending entry.
     pub fn update(self, status: NodeStatus) -> PendingEntry<'a, TKey, TVal> {
         self.0.bucket.update_pending(status);
-        PendingEntry::new(self.0.bucket, self.0.key)
+        PendingEntry::new(self.0.bucket, self.0.key, self.0.num_entries)
     }
 
     /// Removes the pending entry from the bucket.
@@ -261,8 +276,12 @@ where
     TKey: Clone + AsRef<KeyBytes>,
     TVal: Clone,
 {
-    fn new(bucket: &'a mut KBucket<TKey, TVal>, key: &'a TKey) -> Self {
-        AbsentEntry(EntryRef { bucket, key })
+    fn new(bucket: &'a mut KBucket<TKey, TVal>, key: &'a TKey, num_entries: &'a mut usize) -> Self {
+        AbsentEntry(EntryRef {
+            bucket,
+            key,
+            num_entries,
+        })
     }
 
     /// Returns the key of the entry.
@@ -272,12 +291,16 @@ where
 
     /// Attempts to insert the entry into a bucket.
     pub fn insert(self, value: TVal, status: NodeStatus) -> InsertResult<TKey> {
-        self.0.bucket.insert(
+        let result = self.0.bucket.insert(
             Node {
                 key: self.0.key.clone(),
                 value,
             },
             status,
-        )
+        );
+        if let InsertResult::Inserted = result {
+            *self.0.num_entries += 1;
+        }
+        result
     }
 }
@@ -49,6 +49,7 @@ pub mod record;
 
 mod addresses;
 mod behaviour;
+mod bootstrap;
 mod jobs;
 mod query;
 
@@ -62,16 +63,16 @@ pub use behaviour::{
     BootstrapError, BootstrapOk, BootstrapResult, GetClosestPeersError, GetClosestPeersOk,
     GetClosestPeersResult, GetProvidersError, GetProvidersOk, GetProvidersResult, GetRecordError,
     GetRecordOk, GetRecordResult, InboundRequest, PeerRecord, PutRecordContext, PutRecordError,
-    PutRecordOk, PutRecordPhase, PutRecordResult, QueryInfo, QueryMut, QueryRef, QueryResult,
-    QueryStats,
+    PutRecordOk, PutRecordPhase, PutRecordResult, QueryInfo, QueryMut, QueryProgress, QueryRef,
+    QueryResult, QueryStats,
 };
 pub use behaviour::{
-    Kademlia, KademliaBucketInserts, KademliaCaching, KademliaConfig, KademliaEvent,
-    KademliaStoreInserts, Quorum,
+    Kademlia, KademliaBucketInserts, KademliaCaching, KademliaConfig, KademliaEvent, KademliaMode,
+    KademliaStoreInserts, Quorum, RoutingTableEntry, RoutingTableSnapshot,
 };
 pub use protocol::KadConnectionType;
 pub use query::QueryId;
-pub use record::{store, ProviderRecord, Record};
+pub use record::{store, validator, ProviderRecord, Record};
 
 use std::num::NonZeroUsize;
 
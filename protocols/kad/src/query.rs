@@ -218,7 +218,7 @@ impl<TInner> QueryPool<TInner> {
 
 /// Unique identifier for an active query.
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
-pub struct QueryId(usize);
+pub struct QueryId(pub(crate) usize);
 
 /// The configuration for queries in a `QueryPool`.
 #[derive(Debug, Clone)]
@@ -19,13 +19,19 @@
 // DEALINGS IN THE SOFTWARE.
 
 mod memory;
+mod persistent;
 
 pub use memory::{MemoryStore, MemoryStoreConfig};
+pub use persistent::{PersistentStore, PersistentStoreConfig};
 use thiserror::Error;
 
 use super::*;
 use crate::K_VALUE;
+use futures::future::BoxFuture;
 use std::borrow::Cow;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
 
 /// The result of an operation on a `RecordStore`.
 pub type Result<T> = std::result::Result<T, Error>;
@@ -44,6 +50,16 @@ pub enum Error {
     /// The store cannot store this value because it is too large.
     #[error("the value is too large to be stored")]
     ValueTooLarge,
+
+    /// The store failed to persist the change to its storage backend.
+    #[error("the store failed to persist the change: {0}")]
+    Io(Arc<io::Error>),
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(Arc::new(e))
+    }
 }
 
 /// Trait for types implementing a record store.
@@ -97,3 +113,103 @@ pub trait RecordStore<'a> {
     /// Removes a provider record from the store.
     fn remove_provider(&'a mut self, k: &Key, p: &PeerId);
 }
+
+/// The outcome of starting an operation on an [`AsyncRecordStore`].
+pub enum StoreOp<T> {
+    /// The operation completed immediately.
+    Ready(T),
+    /// The operation completes when the future resolves.
+    Pending(BoxFuture<'static, T>),
+}
+
+impl<T> fmt::Debug for StoreOp<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreOp::Ready(t) => f.debug_tuple("Ready").field(t).finish(),
+            StoreOp::Pending(_) => f.debug_tuple("Pending").finish(),
+        }
+    }
+}
+
+/// Trait for record stores whose operations may complete asynchronously,
+/// e.g. because they are backed by a database or another process.
+///
+/// Every operation either completes immediately, returning [`StoreOp::Ready`],
+/// or returns a [`StoreOp::Pending`] future which the `Kademlia` behaviour
+/// drives to completion as part of its own `poll`, without blocking other
+/// queries or requests in the meantime. Outbound queries incorporate the
+/// result of a local lookup once it completes and inbound requests that
+/// depend on the store are answered only after the store has responded.
+///
+/// Every [`RecordStore`] is also an `AsyncRecordStore` whose operations
+/// always complete immediately.
+pub trait AsyncRecordStore {
+    /// Gets a record from the store, given its key.
+    fn get_record(&mut self, k: &Key) -> StoreOp<Option<Record>>;
+
+    /// Puts a record into the store.
+    fn put_record(&mut self, r: Record) -> StoreOp<Result<()>>;
+
+    /// Removes the record with the given key from the store.
+    fn remove_record(&mut self, k: &Key) -> StoreOp<()>;
+
+    /// Gets all (value-) records currently stored.
+    fn all_records(&mut self) -> StoreOp<Vec<Record>>;
+
+    /// Adds a provider record to the store.
+    ///
+    /// See [`RecordStore::add_provider`].
+    fn add_provider_record(&mut self, record: ProviderRecord) -> StoreOp<Result<()>>;
+
+    /// Gets the stored provider records for the given key.
+    fn provider_records(&mut self, key: &Key) -> StoreOp<Vec<ProviderRecord>>;
+
+    /// Gets all stored provider records for which the node owning the
+    /// store is itself the provider.
+    fn provided_records(&mut self) -> StoreOp<Vec<ProviderRecord>>;
+
+    /// Removes a provider record from the store.
+    fn remove_provider_record(&mut self, k: &Key, p: &PeerId) -> StoreOp<()>;
+}
+
+impl<T> AsyncRecordStore for T
+where
+    for<'a> T: RecordStore<'a>,
+{
+    fn get_record(&mut self, k: &Key) -> StoreOp<Option<Record>> {
+        StoreOp::Ready(self.get(k).map(Cow::into_owned))
+    }
+
+    fn put_record(&mut self, r: Record) -> StoreOp<Result<()>> {
+        StoreOp::Ready(self.put(r))
+    }
+
+    fn remove_record(&mut self, k: &Key) -> StoreOp<()> {
+        self.remove(k);
+        StoreOp::Ready(())
+    }
+
+    fn all_records(&mut self) -> StoreOp<Vec<Record>> {
+        StoreOp::Ready(self.records().map(Cow::into_owned).collect())
+    }
+
+    fn add_provider_record(&mut self, record: ProviderRecord) -> StoreOp<Result<()>> {
+        StoreOp::Ready(self.add_provider(record))
+    }
+
+    fn provider_records(&mut self, key: &Key) -> StoreOp<Vec<ProviderRecord>> {
+        StoreOp::Ready(self.providers(key))
+    }
+
+    fn provided_records(&mut self) -> StoreOp<Vec<ProviderRecord>> {
+        StoreOp::Ready(self.provided().map(Cow::into_owned).collect())
+    }
+
+    fn remove_provider_record(&mut self, k: &Key, p: &PeerId) -> StoreOp<()> {
+        self.remove_provider(k, p);
+        StoreOp::Ready(())
+    }
+}
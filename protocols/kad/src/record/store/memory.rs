@@ -94,6 +94,11 @@ impl MemoryStore {
     {
         self.records.retain(f);
     }
+
+    /// Gets an iterator over all stored provider records.
+    pub(super) fn all_providers(&self) -> impl Iterator<Item = &ProviderRecord> {
+        self.providers.values().flat_map(|ps| ps.iter())
+    }
 }
 
 impl<'a> RecordStore<'a> for MemoryStore {
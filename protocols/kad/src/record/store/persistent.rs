@@ -0,0 +1,703 @@
+// Copyright 2022 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use super::*;
+
+use libp2p_core::{Multiaddr, PeerId};
+use prost::Message;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+mod proto {
+    include!(concat!(env!("OUT_DIR"), "/store.pb.rs"));
+}
+
+/// Magic bytes at the start of every log file, identifying the log format.
+const LOG_HEADER: &[u8] = b"/libp2p/kad/store/1\n";
+
+/// Number of bytes of the SHA-256 digest of an entry stored as its checksum.
+const CHECKSUM_LEN: usize = 4;
+
+/// [`RecordStore`] implementation that persists records to disk.
+///
+/// All records are held in memory, backed by a [`MemoryStore`], and every
+/// modification is appended to a log file. When the store is opened, the log
+/// is replayed to restore the records stored before, skipping any that have
+/// expired in the meantime.
+///
+/// Every log entry is checksummed, so that a torn write, e.g. due to a crash
+/// while appending, only loses the entry being written. The log is
+/// periodically compacted by atomically replacing it with a snapshot of the
+/// current, non-expired records.
+pub struct PersistentStore {
+    /// The configuration of the store.
+    config: PersistentStoreConfig,
+    /// The path of the log file.
+    path: PathBuf,
+    /// The in-memory view of the stored records.
+    memory: MemoryStore,
+    /// The log file, opened for appending.
+    log: File,
+    /// The number of entries appended to the log since it was last compacted.
+    writes_since_compaction: usize,
+    /// Whether appending to the log failed, in which case the log no longer
+    /// reflects the in-memory state and must be rewritten.
+    needs_compaction: bool,
+}
+
+/// Configuration for a `PersistentStore`.
+#[derive(Debug, Clone)]
+pub struct PersistentStoreConfig {
+    /// The maximum number of records.
+    pub max_records: usize,
+    /// The maximum size of record values, in bytes.
+    pub max_value_bytes: usize,
+    /// The maximum number of providers stored for a key.
+    ///
+    /// This should match up with the chosen replication factor.
+    pub max_providers_per_key: usize,
+    /// The maximum number of provider records for which the
+    /// local node is the provider.
+    pub max_provided_keys: usize,
+    /// The number of entries appended to the log after which
+    /// the log is compacted.
+    pub compaction_threshold: usize,
+    /// Whether to flush every log entry to the storage device before
+    /// a modification returns.
+    ///
+    /// Without this, a modification that completed shortly before a
+    /// crash of the operating system may be lost.
+    pub sync_writes: bool,
+}
+
+impl Default for PersistentStoreConfig {
+    fn default() -> Self {
+        let memory = MemoryStoreConfig::default();
+        Self {
+            max_records: memory.max_records,
+            max_value_bytes: memory.max_value_bytes,
+            max_providers_per_key: memory.max_providers_per_key,
+            max_provided_keys: memory.max_provided_keys,
+            compaction_threshold: 4096,
+            sync_writes: false,
+        }
+    }
+}
+
+impl PersistentStoreConfig {
+    fn memory_config(&self) -> MemoryStoreConfig {
+        MemoryStoreConfig {
+            max_records: self.max_records,
+            max_value_bytes: self.max_value_bytes,
+            max_providers_per_key: self.max_providers_per_key,
+            max_provided_keys: self.max_provided_keys,
+        }
+    }
+}
+
+impl PersistentStore {
+    /// Opens the `PersistentStore` backed by the log file at the given path
+    /// with a default configuration, creating the file if it does not exist.
+    pub fn open<P: AsRef<Path>>(local_id: PeerId, path: P) -> io::Result<Self> {
+        Self::open_with_config(local_id, path, Default::default())
+    }
+
+    /// Opens the `PersistentStore` backed by the log file at the given path
+    /// with the given configuration, creating the file if it does not exist.
+    ///
+    /// Records in the log that exceed the limits of the configuration are
+    /// dropped.
+    pub fn open_with_config<P: AsRef<Path>>(
+        local_id: PeerId,
+        path: P,
+        config: PersistentStoreConfig,
+    ) -> io::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let mut memory = MemoryStore::with_config(local_id, config.memory_config());
+
+        match File::open(&path) {
+            Ok(mut file) => {
+                let mut buf = Vec::new();
+                file.read_to_end(&mut buf)?;
+                replay(&mut memory, &buf)?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let mut store = PersistentStore {
+            log: write_snapshot(&path, &memory)?,
+            config,
+            path,
+            memory,
+            writes_since_compaction: 0,
+            needs_compaction: false,
+        };
+        store.remove_expired();
+        Ok(store)
+    }
+
+    /// Gets the path of the log file backing the store.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Compacts the log, replacing it with a snapshot of the current records.
+    ///
+    /// Expired records are removed from the store in the process.
+    pub fn compact(&mut self) -> io::Result<()> {
+        self.remove_expired();
+        match write_snapshot(&self.path, &self.memory) {
+            Ok(log) => {
+                self.log = log;
+                self.writes_since_compaction = 0;
+                self.needs_compaction = false;
+                Ok(())
+            }
+            Err(e) => {
+                self.needs_compaction = true;
+                Err(e)
+            }
+        }
+    }
+
+    /// Removes all expired records from the in-memory view of the store.
+    fn remove_expired(&mut self) {
+        let now = Instant::now();
+        self.memory.retain(|_, r| !r.is_expired(now));
+        let expired = self
+            .memory
+            .all_providers()
+            .filter(|p| p.is_expired(now))
+            .map(|p| (p.key.clone(), p.provider))
+            .collect::<Vec<_>>();
+        for (key, provider) in expired {
+            self.memory.remove_provider(&key, &provider);
+        }
+    }
+
+    /// Restores the providers of a key in the in-memory view to the given ones.
+    fn restore_providers(&mut self, key: &Key, providers: Vec<ProviderRecord>) {
+        for record in self.memory.providers(key) {
+            self.memory.remove_provider(key, &record.provider);
+        }
+        for record in providers {
+            self.memory
+                .add_provider(record)
+                .expect("Restoring providers to not exceed the limits.");
+        }
+    }
+
+    /// Persists a modification that has already been applied to the in-memory view.
+    fn persist(&mut self, entry: proto::log_entry::Entry) -> io::Result<()> {
+        if self.needs_compaction || self.writes_since_compaction >= self.config.compaction_threshold
+        {
+            // The snapshot includes the modification.
+            return self.compact();
+        }
+
+        let mut buf = Vec::new();
+        encode_entry(entry, &mut buf);
+        let result = self.log.write_all(&buf).and_then(|()| {
+            if self.config.sync_writes {
+                self.log.sync_data()
+            } else {
+                Ok(())
+            }
+        });
+        match result {
+            Ok(()) => {
+                self.writes_since_compaction += 1;
+                Ok(())
+            }
+            Err(e) => {
+                self.needs_compaction = true;
+                Err(e)
+            }
+        }
+    }
+}
+
+impl<'a> RecordStore<'a> for PersistentStore {
+    type RecordsIter = <MemoryStore as RecordStore<'a>>::RecordsIter;
+    type ProvidedIter = <MemoryStore as RecordStore<'a>>::ProvidedIter;
+
+    fn get(&'a self, k: &Key) -> Option<Cow<'a, Record>> {
+        self.memory.get(k)
+    }
+
+    fn put(&'a mut self, r: Record) -> Result<()> {
+        let entry = proto::log_entry::Entry::PutRecord(record_to_proto(&r));
+        let key = r.key.clone();
+        let previous = self.memory.get(&key).map(Cow::into_owned);
+        self.memory.put(r)?;
+        if let Err(e) = self.persist(entry) {
+            // Roll back, so that records are only stored once they are persisted.
+            match previous {
+                Some(previous) => self
+                    .memory
+                    .put(previous)
+                    .expect("Restoring a record to not exceed the limits."),
+                None => self.memory.remove(&key),
+            }
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    fn remove(&'a mut self, k: &Key) {
+        if self.memory.get(k).is_none() {
+            return;
+        }
+        self.memory.remove(k);
+        if let Err(e) = self.persist(proto::log_entry::Entry::RemoveRecord(k.to_vec())) {
+            log::warn!("Failed to persist removal of record {:?}: {}", k, e);
+        }
+    }
+
+    fn records(&'a self) -> Self::RecordsIter {
+        self.memory.records()
+    }
+
+    fn add_provider(&'a mut self, record: ProviderRecord) -> Result<()> {
+        let entry = proto::log_entry::Entry::AddProvider(provider_to_proto(&record));
+        let key = record.key.clone();
+        let previous = self.memory.providers(&key);
+        self.memory.add_provider(record)?;
+        if let Err(e) = self.persist(entry) {
+            // Roll back, so that providers are only stored once they are persisted.
+            self.restore_providers(&key, previous);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    fn providers(&'a self, key: &Key) -> Vec<ProviderRecord> {
+        self.memory.providers(key)
+    }
+
+    fn provided(&'a self) -> Self::ProvidedIter {
+        self.memory.provided()
+    }
+
+    fn remove_provider(&'a mut self, k: &Key, p: &PeerId) {
+        if !self.memory.providers(k).iter().any(|r| &r.provider == p) {
+            return;
+        }
+        self.memory.remove_provider(k, p);
+        let entry = proto::log_entry::Entry::RemoveProvider(proto::ProviderRecord {
+            key: k.to_vec(),
+            provider: p.to_bytes(),
+            ..proto::ProviderRecord::default()
+        });
+        if let Err(e) = self.persist(entry) {
+            log::warn!(
+                "Failed to persist removal of provider {} for {:?}: {}",
+                p,
+                k,
+                e
+            );
+        }
+    }
+}
+
+/// Replays the entries of a log onto the given store.
+///
+/// Replaying stops at the first incomplete or corrupt entry, which is
+/// the result of an interrupted write.
+fn replay(store: &mut MemoryStore, mut buf: &[u8]) -> io::Result<()> {
+    if buf.len() < LOG_HEADER.len() && LOG_HEADER.starts_with(buf) {
+        // The log was never completely initialised.
+        return Ok(());
+    }
+    if !buf.starts_with(LOG_HEADER) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a Kademlia record store log",
+        ));
+    }
+    buf = &buf[LOG_HEADER.len()..];
+
+    let now = Instant::now();
+    while !buf.is_empty() {
+        let entry = match decode_entry(&mut buf) {
+            Some(entry) => entry,
+            None => {
+                log::debug!("Discarding incomplete or corrupt record store log entry.");
+                break;
+            }
+        };
+        match entry {
+            proto::log_entry::Entry::PutRecord(r) => match record_from_proto(r) {
+                Some(r) if !r.is_expired(now) => {
+                    let _ = store.put(r);
+                }
+                Some(r) => store.remove(&r.key),
+                None => {}
+            },
+            proto::log_entry::Entry::RemoveRecord(k) => store.remove(&Key::from(k)),
+            proto::log_entry::Entry::AddProvider(p) => match provider_from_proto(p) {
+                Some(p) if !p.is_expired(now) => {
+                    let _ = store.add_provider(p);
+                }
+                Some(p) => store.remove_provider(&p.key, &p.provider),
+                None => {}
+            },
+            proto::log_entry::Entry::RemoveProvider(p) => {
+                if let Ok(provider) = PeerId::from_bytes(&p.provider) {
+                    store.remove_provider(&Key::from(p.key), &provider)
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Atomically replaces the log at `path` with a snapshot of the records
+/// in the given store, returning the new log opened for appending.
+fn write_snapshot(path: &Path, store: &MemoryStore) -> io::Result<File> {
+    let now = Instant::now();
+    let mut buf = LOG_HEADER.to_vec();
+    for r in store.records() {
+        if !r.is_expired(now) {
+            encode_entry(
+                proto::log_entry::Entry::PutRecord(record_to_proto(&r)),
+                &mut buf,
+            );
+        }
+    }
+    for p in store.all_providers() {
+        if !p.is_expired(now) {
+            encode_entry(
+                proto::log_entry::Entry::AddProvider(provider_to_proto(p)),
+                &mut buf,
+            );
+        }
+    }
+
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    {
+        let mut tmp = File::create(&tmp_path)?;
+        tmp.write_all(&buf)?;
+        tmp.sync_all()?;
+    }
+    fs::rename(&tmp_path, path)?;
+    #[cfg(unix)]
+    {
+        // Make the rename itself durable.
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            File::open(dir)?.sync_all()?;
+        }
+    }
+
+    OpenOptions::new().append(true).open(path)
+}
+
+/// Appends the framed encoding of a log entry to `buf`.
+///
+/// An entry is framed as its length, encoded as an unsigned varint,
+/// followed by the protobuf encoding of the entry and its checksum.
+fn encode_entry(entry: proto::log_entry::Entry, buf: &mut Vec<u8>) {
+    let entry = proto::LogEntry { entry: Some(entry) };
+    let mut len_buf = unsigned_varint::encode::usize_buffer();
+    buf.extend_from_slice(unsigned_varint::encode::usize(
+        entry.encoded_len(),
+        &mut len_buf,
+    ));
+    let start = buf.len();
+    entry
+        .encode(buf)
+        .expect("Vec<u8> provides capacity as needed");
+    let checksum = Sha256::digest(&buf[start..]);
+    buf.extend_from_slice(&checksum[..CHECKSUM_LEN]);
+}
+
+/// Decodes the next framed log entry from `buf`, advancing it past the entry.
+///
+/// Returns `None` if the entry is incomplete or corrupt.
+fn decode_entry(buf: &mut &[u8]) -> Option<proto::log_entry::Entry> {
+    let (len, rest) = unsigned_varint::decode::usize(buf).ok()?;
+    if rest.len() < len.checked_add(CHECKSUM_LEN)? {
+        return None;
+    }
+    let (payload, rest) = rest.split_at(len);
+    let (checksum, rest) = rest.split_at(CHECKSUM_LEN);
+    if Sha256::digest(payload)[..CHECKSUM_LEN] != *checksum {
+        return None;
+    }
+    let entry = proto::LogEntry::decode(payload).ok()?.entry?;
+    *buf = rest;
+    Some(entry)
+}
+
+fn record_to_proto(record: &Record) -> proto::Record {
+    proto::Record {
+        key: record.key.to_vec(),
+        value: record.value.clone(),
+        publisher: record.publisher.map(|id| id.to_bytes()).unwrap_or_default(),
+        expires: record.expires.map(instant_to_timestamp).unwrap_or(0),
+    }
+}
+
+fn record_from_proto(record: proto::Record) -> Option<Record> {
+    let publisher = if record.publisher.is_empty() {
+        None
+    } else {
+        Some(PeerId::from_bytes(&record.publisher).ok()?)
+    };
+    Some(Record {
+        key: Key::from(record.key),
+        value: record.value,
+        publisher,
+        expires: timestamp_to_instant(record.expires),
+    })
+}
+
+fn provider_to_proto(record: &ProviderRecord) -> proto::ProviderRecord {
+    proto::ProviderRecord {
+        key: record.key.to_vec(),
+        provider: record.provider.to_bytes(),
+        addresses: record.addresses.iter().map(|a| a.to_vec()).collect(),
+        expires: record.expires.map(instant_to_timestamp).unwrap_or(0),
+    }
+}
+
+fn provider_from_proto(record: proto::ProviderRecord) -> Option<ProviderRecord> {
+    Some(ProviderRecord {
+        key: Key::from(record.key),
+        provider: PeerId::from_bytes(&record.provider).ok()?,
+        addresses: record
+            .addresses
+            .into_iter()
+            .filter_map(|a| Multiaddr::try_from(a).ok())
+            .collect(),
+        expires: timestamp_to_instant(record.expires),
+    })
+}
+
+/// Converts an `Instant` into a Unix timestamp in milliseconds.
+///
+/// The result is never 0, which denotes the absence of an expiration time.
+fn instant_to_timestamp(t: Instant) -> u64 {
+    let now = Instant::now();
+    let system_now = SystemTime::now();
+    let t = if t >= now {
+        system_now + (t - now)
+    } else {
+        system_now.checked_sub(now - t).unwrap_or(UNIX_EPOCH)
+    };
+    let millis = t.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    u64::try_from(millis).unwrap_or(u64::MAX).max(1)
+}
+
+/// Converts a Unix timestamp in milliseconds into an `Instant`.
+///
+/// Timestamps in the past are mapped to the current `Instant`.
+fn timestamp_to_instant(timestamp: u64) -> Option<Instant> {
+    if timestamp == 0 {
+        return None;
+    }
+    let t = UNIX_EPOCH + Duration::from_millis(timestamp);
+    let remaining = t.duration_since(SystemTime::now()).unwrap_or_default();
+    Some(Instant::now() + remaining)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p_core::multihash::{Code, Multihash};
+    use rand::Rng;
+    use std::env;
+
+    fn random_multihash() -> Multihash {
+        Multihash::wrap(Code::Sha2_256.into(), &rand::thread_rng().gen::<[u8; 32]>()).unwrap()
+    }
+
+    fn temp_path() -> PathBuf {
+        env::temp_dir().join(format!(
+            "libp2p-kad-store-{}.log",
+            rand::thread_rng().gen::<u64>()
+        ))
+    }
+
+    #[test]
+    fn records_survive_reopen() {
+        let path = temp_path();
+        let id = PeerId::random();
+        let record = Record::new(random_multihash(), vec![1, 2, 3]);
+        let removed = Record::new(random_multihash(), vec![4, 5, 6]);
+        let provider = ProviderRecord::new(random_multihash(), id, Vec::new());
+
+        {
+            let mut store = PersistentStore::open(id, &path).unwrap();
+            store.put(record.clone()).unwrap();
+            store.put(removed.clone()).unwrap();
+            store.remove(&removed.key);
+            store.add_provider(provider.clone()).unwrap();
+        }
+
+        let store = PersistentStore::open(id, &path).unwrap();
+        assert_eq!(Some(Cow::Borrowed(&record)), store.get(&record.key));
+        assert!(store.get(&removed.key).is_none());
+        assert_eq!(vec![provider.clone()], store.providers(&provider.key));
+        assert_eq!(
+            vec![Cow::Borrowed(&provider)],
+            store.provided().collect::<Vec<_>>()
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn torn_write_loses_last_entry_only() {
+        let path = temp_path();
+        let id = PeerId::random();
+        let first = Record::new(random_multihash(), vec![1]);
+        let second = Record::new(random_multihash(), vec![2]);
+
+        {
+            let mut store = PersistentStore::open(id, &path).unwrap();
+            store.put(first.clone()).unwrap();
+            store.put(second.clone()).unwrap();
+        }
+
+        // Simulate a crash in the middle of appending the second entry.
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 2)
+            .unwrap();
+
+        let mut store = PersistentStore::open(id, &path).unwrap();
+        assert!(store.get(&first.key).is_some());
+        assert!(store.get(&second.key).is_none());
+
+        // The log remains usable after recovery.
+        store.put(second.clone()).unwrap();
+        drop(store);
+        let store = PersistentStore::open(id, &path).unwrap();
+        assert!(store.get(&first.key).is_some());
+        assert!(store.get(&second.key).is_some());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn compaction_removes_expired_records() {
+        let path = temp_path();
+        let id = PeerId::random();
+        let config = PersistentStoreConfig {
+            compaction_threshold: 2,
+            ..Default::default()
+        };
+        let mut expired = Record::new(random_multihash(), vec![1]);
+        expired.expires = Some(Instant::now());
+        let record = Record::new(random_multihash(), vec![2]);
+
+        let mut store = PersistentStore::open_with_config(id, &path, config.clone()).unwrap();
+        store.put(expired.clone()).unwrap();
+        for i in 0..4 {
+            store.put(Record::new(record.key.clone(), vec![i])).unwrap();
+        }
+        assert!(store.get(&expired.key).is_none());
+        assert_eq!(store.get(&record.key).unwrap().value, vec![3]);
+        drop(store);
+
+        let store = PersistentStore::open_with_config(id, &path, config).unwrap();
+        assert!(store.get(&expired.key).is_none());
+        assert_eq!(store.get(&record.key).unwrap().value, vec![3]);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn failed_writes_are_rolled_back() {
+        let path = temp_path();
+        let id = PeerId::random();
+        let record = Record::new(random_multihash(), vec![1]);
+        let provider = ProviderRecord::new(random_multihash(), id, Vec::new());
+
+        let mut store = PersistentStore::open(id, &path).unwrap();
+        store.put(record.clone()).unwrap();
+
+        // Appending to a log opened read-only fails.
+        store.log = File::open(&path).unwrap();
+        assert!(matches!(
+            store.put(Record::new(record.key.clone(), vec![2])),
+            Err(Error::Io(_))
+        ));
+        assert_eq!(Some(Cow::Borrowed(&record)), store.get(&record.key));
+        store.log = File::open(&path).unwrap();
+        store.needs_compaction = false;
+        assert!(matches!(
+            store.add_provider(provider.clone()),
+            Err(Error::Io(_))
+        ));
+        assert!(store.providers(&provider.key).is_empty());
+        assert_eq!(0, store.provided().count());
+
+        // The next write rewrites the log.
+        store.add_provider(provider.clone()).unwrap();
+        drop(store);
+        let store = PersistentStore::open(id, &path).unwrap();
+        assert_eq!(Some(Cow::Borrowed(&record)), store.get(&record.key));
+        assert_eq!(vec![provider.clone()], store.providers(&provider.key));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn max_records() {
+        let path = temp_path();
+        let config = PersistentStoreConfig {
+            max_records: 1,
+            ..Default::default()
+        };
+        let mut store = PersistentStore::open_with_config(PeerId::random(), &path, config).unwrap();
+        store
+            .put(Record::new(random_multihash(), Vec::new()))
+            .unwrap();
+        match store.put(Record::new(random_multihash(), Vec::new())) {
+            Err(Error::MaxRecords) => {}
+            _ => panic!("Unexpected result"),
+        }
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_foreign_file() {
+        let path = temp_path();
+        fs::write(&path, b"definitely not a record store log").unwrap();
+        assert!(PersistentStore::open(PeerId::random(), &path).is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+}
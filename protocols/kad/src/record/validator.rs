@@ -0,0 +1,158 @@
+// Copyright 2022 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Validation of records received from other peers.
+//!
+//! A [`RecordValidator`] checks records before they are accepted, be it as part
+//! of an inbound `PUT_VALUE` request or as the result of a lookup via
+//! [`Kademlia::get_record`](crate::Kademlia::get_record), and selects the best
+//! record among conflicting ones for the same key.
+//!
+//! Applications that store records of different types in the DHT can combine
+//! validators per namespace with a [`NamespacedValidator`], like the `/pk/` and
+//! `/ipns/` namespaces of IPFS.
+
+use super::*;
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// The possible reasons for a record to be rejected by a [`RecordValidator`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The key of the record is not in a known namespace.
+    #[error("the record key is not in a known namespace")]
+    UnknownNamespace,
+
+    /// The record is invalid for the given reason.
+    #[error("the record is invalid: {0}")]
+    Invalid(String),
+}
+
+/// Validates records and selects the best of conflicting records.
+pub trait RecordValidator: Send + 'static {
+    /// Checks whether the given record is valid.
+    fn validate(&self, record: &Record) -> Result<(), ValidationError>;
+
+    /// Selects the best record among the given valid records for the same key,
+    /// returning its index. The given records are never empty.
+    ///
+    /// The default implementation selects the first record.
+    fn select(&self, _key: &Key, _records: &[&Record]) -> usize {
+        0
+    }
+}
+
+/// A [`RecordValidator`] that dispatches to other validators based
+/// on the namespace of record keys.
+///
+/// The namespace of a key of the form `/<namespace>/<rest>` is `<namespace>`.
+/// Records with keys outside of any registered namespace are invalid.
+#[derive(Default)]
+pub struct NamespacedValidator {
+    validators: HashMap<Vec<u8>, Box<dyn RecordValidator>>,
+}
+
+impl NamespacedValidator {
+    /// Creates a new `NamespacedValidator` without any namespaces.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the validator for records in the given namespace,
+    /// replacing any previous validator of that namespace.
+    pub fn add<V>(&mut self, namespace: impl Into<Vec<u8>>, validator: V) -> &mut Self
+    where
+        V: RecordValidator,
+    {
+        self.validators
+            .insert(namespace.into(), Box::new(validator));
+        self
+    }
+
+    fn validator(&self, key: &Key) -> Option<&dyn RecordValidator> {
+        let key = key.as_ref().strip_prefix(b"/")?;
+        let end = key.iter().position(|b| *b == b'/')?;
+        if end == 0 || end + 1 == key.len() {
+            return None;
+        }
+        self.validators.get(&key[..end]).map(|v| &**v)
+    }
+}
+
+impl RecordValidator for NamespacedValidator {
+    fn validate(&self, record: &Record) -> Result<(), ValidationError> {
+        self.validator(&record.key)
+            .ok_or(ValidationError::UnknownNamespace)?
+            .validate(record)
+    }
+
+    fn select(&self, key: &Key, records: &[&Record]) -> usize {
+        self.validator(key)
+            .map_or(0, |validator| validator.select(key, records))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Accepts non-empty values and prefers the largest one.
+    struct Largest;
+
+    impl RecordValidator for Largest {
+        fn validate(&self, record: &Record) -> Result<(), ValidationError> {
+            if record.value.is_empty() {
+                Err(ValidationError::Invalid("empty value".into()))
+            } else {
+                Ok(())
+            }
+        }
+
+        fn select(&self, _: &Key, records: &[&Record]) -> usize {
+            (0..records.len())
+                .max_by_key(|i| &records[*i].value)
+                .expect("records not empty")
+        }
+    }
+
+    #[test]
+    fn namespaces() {
+        let mut validator = NamespacedValidator::new();
+        validator.add("ns", Largest);
+
+        let record = |key: &str, value: &[u8]| Record::new(Key::new(&key), value.to_vec());
+
+        assert_eq!(validator.validate(&record("/ns/foo", &[1])), Ok(()));
+        assert!(matches!(
+            validator.validate(&record("/ns/foo", &[])),
+            Err(ValidationError::Invalid(_))
+        ));
+        for key in ["/other/foo", "/ns/", "//foo", "ns/foo", "/ns"] {
+            assert_eq!(
+                validator.validate(&record(key, &[1])),
+                Err(ValidationError::UnknownNamespace)
+            );
+        }
+
+        let (a, b) = (record("/ns/foo", &[1]), record("/ns/foo", &[2]));
+        assert_eq!(validator.select(&a.key, &[&a, &b]), 1);
+        assert_eq!(validator.select(&Key::new(&"/other/foo"), &[&a, &b]), 0);
+    }
+}
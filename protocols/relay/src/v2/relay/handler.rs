@@ -615,6 +615,9 @@ impl ConnectionHandler for Handler {
         let non_fatal_error = match error {
             ConnectionHandlerUpgrErr::Timeout => ConnectionHandlerUpgrErr::Timeout,
             ConnectionHandlerUpgrErr::Timer => ConnectionHandlerUpgrErr::Timer,
+            ConnectionHandlerUpgrErr::ResourceLimitExceeded(e) => {
+                ConnectionHandlerUpgrErr::ResourceLimitExceeded(e)
+            }
             ConnectionHandlerUpgrErr::Upgrade(upgrade::UpgradeError::Select(
                 upgrade::NegotiationError::Failed,
             )) => ConnectionHandlerUpgrErr::Upgrade(upgrade::UpgradeError::Select(
@@ -657,6 +660,10 @@ impl ConnectionHandler for Handler {
             ConnectionHandlerUpgrErr::Timer => {
                 (ConnectionHandlerUpgrErr::Timer, Status::ConnectionFailed)
             }
+            ConnectionHandlerUpgrErr::ResourceLimitExceeded(e) => (
+                ConnectionHandlerUpgrErr::ResourceLimitExceeded(e),
+                Status::ResourceLimitExceeded,
+            ),
             ConnectionHandlerUpgrErr::Upgrade(upgrade::UpgradeError::Select(
                 upgrade::NegotiationError::Failed,
             )) => {
@@ -0,0 +1,47 @@
+// Copyright 2022 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! A [`RequestResponseCodec`](crate::RequestResponseCodec) for requests and
+//! responses of any serde types, encoded as CBOR.
+//!
+//! See [`crate::serde_codec`] for the framing and size limits of messages.
+
+use crate::serde_codec::{self, Format};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// The CBOR [`Format`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cbor;
+
+impl Format for Cbor {
+    fn encode(message: &impl Serialize) -> Result<Vec<u8>, String> {
+        let mut data = Vec::new();
+        ciborium::ser::into_writer(message, &mut data).map_err(|e| e.to_string())?;
+        Ok(data)
+    }
+
+    fn decode<M: DeserializeOwned>(data: &[u8]) -> Result<M, String> {
+        ciborium::de::from_reader(data).map_err(|e| e.to_string())
+    }
+}
+
+/// A codec encoding requests of type `Req` and responses of type `Resp` as CBOR.
+pub type Codec<Req, Resp> = serde_codec::Codec<Cbor, Req, Resp>;
@@ -22,7 +22,100 @@ pub use libp2p_core::ProtocolName;
 
 use async_trait::async_trait;
 use futures::prelude::*;
-use std::io;
+use std::{error, fmt, io};
+
+/// An error encoding or decoding a request or response.
+///
+/// Codecs report it by returning an [`io::Error`] wrapping it, e.g. via
+/// `io::Error::from(CodecError::Decode(..))`, in which case only the
+/// affected request fails with an [`InboundFailure::Codec`](crate::InboundFailure::Codec)
+/// or [`OutboundFailure::Codec`](crate::OutboundFailure::Codec) instead of
+/// the connection being closed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CodecError {
+    /// The message exceeds the maximum size.
+    MessageTooLarge {
+        /// The size of the message in bytes.
+        size: usize,
+        /// The maximum size of the message in bytes.
+        max_size: usize,
+    },
+    /// The message could not be encoded.
+    Encode(String),
+    /// The message could not be decoded.
+    Decode(String),
+}
+
+impl CodecError {
+    /// Extracts the codec error wrapped by the given I/O error, if any.
+    pub(crate) fn from_io_error(error: &io::Error) -> Option<CodecError> {
+        error.get_ref()?.downcast_ref::<CodecError>().cloned()
+    }
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::MessageTooLarge { size, max_size } => write!(
+                f,
+                "Message of {} bytes exceeds the maximum size of {} bytes",
+                size, max_size
+            ),
+            CodecError::Encode(e) => write!(f, "Failed to encode message: {}", e),
+            CodecError::Decode(e) => write!(f, "Failed to decode message: {}", e),
+        }
+    }
+}
+
+impl error::Error for CodecError {}
+
+impl From<CodecError> for io::Error {
+    fn from(error: CodecError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+/// Reads a message prefixed with its length as an unsigned varint,
+/// failing if it exceeds `max_size` bytes.
+#[cfg(any(feature = "cbor", feature = "json"))]
+pub(crate) async fn read_length_prefixed<T>(io: &mut T, max_size: usize) -> io::Result<Vec<u8>>
+where
+    T: AsyncRead + Unpin + Send,
+{
+    let size = unsigned_varint::aio::read_usize(&mut *io)
+        .await
+        .map_err(Into::<io::Error>::into)?;
+    if size > max_size {
+        return Err(CodecError::MessageTooLarge { size, max_size }.into());
+    }
+    let mut data = vec![0; size];
+    io.read_exact(&mut data).await?;
+    Ok(data)
+}
+
+/// Writes a message prefixed with its length as an unsigned varint,
+/// failing if it exceeds `max_size` bytes.
+#[cfg(any(feature = "cbor", feature = "json"))]
+pub(crate) async fn write_length_prefixed<T>(
+    io: &mut T,
+    data: &[u8],
+    max_size: usize,
+) -> io::Result<()>
+where
+    T: AsyncWrite + Unpin + Send,
+{
+    if data.len() > max_size {
+        return Err(CodecError::MessageTooLarge {
+            size: data.len(),
+            max_size,
+        }
+        .into());
+    }
+    let mut buf = unsigned_varint::encode::usize_buffer();
+    io.write_all(unsigned_varint::encode::usize(data.len(), &mut buf))
+        .await?;
+    io.write_all(data).await
+}
 
 /// A `RequestResponseCodec` defines the request and response types
 /// for a [`RequestResponse`](crate::RequestResponse) protocol or
@@ -78,3 +171,65 @@ pub trait RequestResponseCodec {
     where
         T: AsyncWrite + Unpin + Send;
 }
+
+/// A `StreamingCodec` defines the request and response chunk types
+/// for a [`StreamingRequestResponse`](crate::streaming::StreamingRequestResponse)
+/// protocol or protocol family and how they are encoded / decoded on an
+/// I/O stream.
+///
+/// Contrary to a [`RequestResponseCodec`], a response consists of any number
+/// of chunks which are read and written one at a time. The end of a response
+/// is marked by the protocol itself, so the codec only needs to delimit the
+/// request and the individual chunks and must not close the I/O stream after
+/// writing either of them.
+#[async_trait]
+pub trait StreamingCodec {
+    /// The type of protocol(s) or protocol versions being negotiated.
+    type Protocol: ProtocolName + Send + Clone;
+    /// The type of inbound and outbound requests.
+    type Request: Send;
+    /// The type of the chunks of inbound and outbound responses.
+    type Chunk: Send;
+
+    /// Reads a request from the given I/O stream according to the
+    /// negotiated protocol.
+    async fn read_request<T>(
+        &mut self,
+        protocol: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Request>
+    where
+        T: AsyncRead + Unpin + Send;
+
+    /// Reads a single response chunk from the given I/O stream according
+    /// to the negotiated protocol.
+    async fn read_chunk<T>(
+        &mut self,
+        protocol: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Chunk>
+    where
+        T: AsyncRead + Unpin + Send;
+
+    /// Writes a request to the given I/O stream according to the
+    /// negotiated protocol.
+    async fn write_request<T>(
+        &mut self,
+        protocol: &Self::Protocol,
+        io: &mut T,
+        req: Self::Request,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send;
+
+    /// Writes a single response chunk to the given I/O stream according
+    /// to the negotiated protocol.
+    async fn write_chunk<T>(
+        &mut self,
+        protocol: &Self::Protocol,
+        io: &mut T,
+        chunk: Self::Chunk,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send;
+}
@@ -20,10 +20,10 @@
 
 mod protocol;
 
-use crate::codec::RequestResponseCodec;
+use crate::codec::{CodecError, RequestResponseCodec};
 use crate::{RequestId, EMPTY_QUEUE_SHRINK_THRESHOLD};
 
-pub use protocol::{ProtocolSupport, RequestProtocol, ResponseProtocol};
+pub use protocol::{ProtocolSupport, RequestProtocol, ResponseOutcome, ResponseProtocol};
 
 use futures::{channel::oneshot, future::BoxFuture, prelude::*, stream::FuturesUnordered};
 use instant::Instant;
@@ -34,7 +34,7 @@ use libp2p_swarm::{
 };
 use smallvec::SmallVec;
 use std::{
-    collections::VecDeque,
+    collections::{HashSet, VecDeque},
     fmt, io,
     sync::{
         atomic::{AtomicU64, Ordering},
@@ -68,6 +68,11 @@ where
     pending_events: VecDeque<RequestResponseHandlerEvent<TCodec>>,
     /// Outbound upgrades waiting to be emitted as an `OutboundSubstreamRequest`.
     outbound: VecDeque<RequestProtocol<TCodec>>,
+    /// The maximum number of concurrent inbound requests, i.e. of `inbound`
+    /// and `answering` together.
+    max_inbound_requests: usize,
+    /// Inbound requests that have been received and not yet answered.
+    answering: HashSet<RequestId>,
     /// Inbound upgrades waiting for the incoming request.
     inbound: FuturesUnordered<
         BoxFuture<
@@ -93,6 +98,7 @@ where
         codec: TCodec,
         keep_alive_timeout: Duration,
         substream_timeout: Duration,
+        max_inbound_requests: usize,
         inbound_request_id: Arc<AtomicU64>,
     ) -> Self {
         Self {
@@ -102,6 +108,8 @@ where
             keep_alive_timeout,
             substream_timeout,
             outbound: VecDeque::new(),
+            max_inbound_requests,
+            answering: HashSet::new(),
             inbound: FuturesUnordered::new(),
             pending_events: VecDeque::new(),
             pending_error: None,
@@ -142,6 +150,13 @@ where
     InboundTimeout(RequestId),
     /// An inbound request failed to negotiate a mutually supported protocol.
     InboundUnsupportedProtocols(RequestId),
+    /// An inbound request was rejected without reading it, since the limit
+    /// of concurrent inbound requests of the connection is reached.
+    InboundLimitReached(RequestId),
+    /// An outbound request failed to be encoded or its response to be decoded.
+    OutboundCodecError(RequestId, CodecError),
+    /// An inbound request failed to be decoded or its response to be encoded.
+    InboundCodecError(RequestId, CodecError),
 }
 
 impl<TCodec: RequestResponseCodec> fmt::Debug for RequestResponseHandlerEvent<TCodec> {
@@ -186,6 +201,20 @@ impl<TCodec: RequestResponseCodec> fmt::Debug for RequestResponseHandlerEvent<TC
                 .debug_tuple("RequestResponseHandlerEvent::InboundUnsupportedProtocols")
                 .field(request_id)
                 .finish(),
+            RequestResponseHandlerEvent::InboundLimitReached(request_id) => f
+                .debug_tuple("RequestResponseHandlerEvent::InboundLimitReached")
+                .field(request_id)
+                .finish(),
+            RequestResponseHandlerEvent::OutboundCodecError(request_id, error) => f
+                .debug_tuple("RequestResponseHandlerEvent::OutboundCodecError")
+                .field(request_id)
+                .field(error)
+                .finish(),
+            RequestResponseHandlerEvent::InboundCodecError(request_id, error) => f
+                .debug_tuple("RequestResponseHandlerEvent::InboundCodecError")
+                .field(request_id)
+                .field(error)
+                .finish(),
         }
     }
 }
@@ -213,6 +242,10 @@ where
 
         let request_id = RequestId(self.inbound_request_id.fetch_add(1, Ordering::Relaxed));
 
+        // Substreams beyond the limit are dropped by the upgrade without
+        // reading the request and thus without waiting for it here.
+        let accept = self.inbound.len() + self.answering.len() < self.max_inbound_requests;
+
         // By keeping all I/O inside the `ResponseProtocol` and thus the
         // inbound substream upgrade via above channels, we ensure that it
         // is all subject to the configured timeout without extra bookkeeping
@@ -225,25 +258,30 @@ where
             request_sender: rq_send,
             response_receiver: rs_recv,
             request_id,
+            accept,
         };
 
         // The handler waits for the request to come in. It then emits
         // `RequestResponseHandlerEvent::Request` together with a
         // `ResponseChannel`.
-        self.inbound
-            .push(rq_recv.map_ok(move |rq| (rq, rs_send)).boxed());
+        if accept {
+            self.inbound
+                .push(rq_recv.map_ok(move |rq| (rq, rs_send)).boxed());
+        }
 
         SubstreamProtocol::new(proto, request_id).with_timeout(self.substream_timeout)
     }
 
-    fn inject_fully_negotiated_inbound(&mut self, sent: bool, request_id: RequestId) {
-        if sent {
-            self.pending_events
-                .push_back(RequestResponseHandlerEvent::ResponseSent(request_id))
-        } else {
-            self.pending_events
-                .push_back(RequestResponseHandlerEvent::ResponseOmission(request_id))
-        }
+    fn inject_fully_negotiated_inbound(&mut self, outcome: ResponseOutcome, request_id: RequestId) {
+        self.answering.remove(&request_id);
+        let event = match outcome {
+            ResponseOutcome::Sent => RequestResponseHandlerEvent::ResponseSent(request_id),
+            ResponseOutcome::Omitted => RequestResponseHandlerEvent::ResponseOmission(request_id),
+            ResponseOutcome::Rejected => {
+                RequestResponseHandlerEvent::InboundLimitReached(request_id)
+            }
+        };
+        self.pending_events.push_back(event);
     }
 
     fn inject_fully_negotiated_outbound(
@@ -283,6 +321,15 @@ where
                     RequestResponseHandlerEvent::OutboundUnsupportedProtocols(info),
                 );
             }
+            ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Apply(ref e))
+                if CodecError::from_io_error(e).is_some() =>
+            {
+                // The codec failed to encode the request or decode the response,
+                // which only fails this request.
+                let error = CodecError::from_io_error(e).expect("checked above");
+                self.pending_events
+                    .push_back(RequestResponseHandlerEvent::OutboundCodecError(info, error));
+            }
             _ => {
                 // Anything else is considered a fatal error or misbehaviour of
                 // the remote peer and results in closing the connection.
@@ -296,6 +343,7 @@ where
         info: RequestId,
         error: ConnectionHandlerUpgrErr<io::Error>,
     ) {
+        self.answering.remove(&info);
         match error {
             ConnectionHandlerUpgrErr::Timeout => self
                 .pending_events
@@ -310,6 +358,15 @@ where
                     RequestResponseHandlerEvent::InboundUnsupportedProtocols(info),
                 );
             }
+            ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Apply(ref e))
+                if CodecError::from_io_error(e).is_some() =>
+            {
+                // The codec failed to decode the request or encode the response,
+                // which only fails this request.
+                let error = CodecError::from_io_error(e).expect("checked above");
+                self.pending_events
+                    .push_back(RequestResponseHandlerEvent::InboundCodecError(info, error));
+            }
             _ => {
                 // Anything else is considered a fatal error or misbehaviour of
                 // the remote peer and results in closing the connection.
@@ -346,6 +403,7 @@ where
                 Ok(((id, rq), rs_sender)) => {
                     // We received an inbound request.
                     self.keep_alive = KeepAlive::Yes;
+                    self.answering.insert(id);
                     return Poll::Ready(ConnectionHandlerEvent::Custom(
                         RequestResponseHandlerEvent::Request {
                             request_id: id,
@@ -61,6 +61,18 @@ impl ProtocolSupport {
     }
 }
 
+/// The outcome of an inbound substream of a [`ResponseProtocol`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseOutcome {
+    /// The request was received and the response sent.
+    Sent,
+    /// The request was received but no response was sent.
+    Omitted,
+    /// The substream was dropped without reading the request, since the
+    /// limit of concurrent inbound requests of the connection is reached.
+    Rejected,
+}
+
 /// Response substream upgrade protocol.
 ///
 /// Receives a request and sends a response.
@@ -74,6 +86,9 @@ where
     pub(crate) request_sender: oneshot::Sender<(RequestId, TCodec::Request)>,
     pub(crate) response_receiver: oneshot::Receiver<TCodec::Response>,
     pub(crate) request_id: RequestId,
+    /// Whether the request is read, as opposed to the substream being
+    /// dropped right away.
+    pub(crate) accept: bool,
 }
 
 impl<TCodec> UpgradeInfo for ResponseProtocol<TCodec>
@@ -92,7 +107,7 @@ impl<TCodec> InboundUpgrade<NegotiatedSubstream> for ResponseProtocol<TCodec>
 where
     TCodec: RequestResponseCodec + Send + 'static,
 {
-    type Output = bool;
+    type Output = ResponseOutcome;
     type Error = io::Error;
     type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
 
@@ -101,6 +116,11 @@ where
         mut io: NegotiatedSubstream,
         protocol: Self::Info,
     ) -> Self::Future {
+        if !self.accept {
+            // Dropping the substream resets it.
+            return future::ready(Ok(ResponseOutcome::Rejected)).boxed();
+        }
+
         async move {
             let read = self.codec.read_request(&protocol, &mut io);
             let request = read.await?;
@@ -117,11 +137,11 @@ where
 
                 io.close().await?;
                 // Response was sent. Indicate to handler to emit a `ResponseSent` event.
-                Ok(true)
+                Ok(ResponseOutcome::Sent)
             } else {
                 io.close().await?;
                 // No response was sent. Indicate to handler to emit a `ResponseOmission` event.
-                Ok(false)
+                Ok(ResponseOutcome::Omitted)
             }
         }.boxed()
     }
@@ -55,24 +55,55 @@
 //! family can be configured in this way. Such protocols will not be
 //! advertised during inbound respectively outbound protocol negotiation
 //! on the substreams.
+//!
+//! ## Streamed Responses
+//!
+//! Responses that are too large to be held in memory at once can be
+//! streamed in chunks with a [`StreamingRequestResponse`] and a
+//! [`StreamingCodec`]. See the [`streaming`] module for details.
+//!
+//! ## Serde Codecs
+//!
+//! Requests and responses of any serde types can be sent without writing
+//! a codec by using `cbor::Codec` or `json::Codec`, which are enabled by the
+//! `cbor` and `json` features respectively. Both are instances of the generic
+//! `serde_codec::Codec` for a serde data `serde_codec::Format`.
 
+#[cfg(feature = "cbor")]
+pub mod cbor;
 pub mod codec;
 pub mod handler;
+#[cfg(feature = "json")]
+pub mod json;
+mod peers;
+#[cfg(any(feature = "cbor", feature = "json"))]
+pub mod serde_codec;
+pub mod streaming;
 
-pub use codec::{ProtocolName, RequestResponseCodec};
+pub use codec::{CodecError, ProtocolName, RequestResponseCodec, StreamingCodec};
 pub use handler::ProtocolSupport;
+pub use streaming::{
+    ResponseSink, ResponseStream, StreamingRequestResponse, StreamingRequestResponseEvent,
+    StreamingRequestResponseMessage,
+};
 
-use futures::channel::oneshot;
+use futures::{
+    channel::oneshot,
+    future::BoxFuture,
+    stream::{FuturesUnordered, StreamExt},
+    FutureExt,
+};
+use futures_timer::Delay;
 use handler::{RequestProtocol, RequestResponseHandler, RequestResponseHandlerEvent};
 use libp2p_core::{connection::ConnectionId, ConnectedPoint, Multiaddr, PeerId};
 use libp2p_swarm::{
-    dial_opts::{self, DialOpts},
     DialError, IntoConnectionHandler, NetworkBehaviour, NetworkBehaviourAction, NotifyHandler,
     PollParameters,
 };
+use peers::Peers;
 use smallvec::SmallVec;
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
+    collections::{HashMap, VecDeque},
     fmt,
     sync::{atomic::AtomicU64, Arc},
     task::{Context, Poll},
@@ -164,6 +195,13 @@ pub enum OutboundFailure {
     ConnectionClosed,
     /// The remote supports none of the requested protocols.
     UnsupportedProtocols,
+    /// The request could not be encoded or the response could not be decoded.
+    Codec(CodecError),
+    /// The request was not sent because the queue of requests waiting for
+    /// the outbound request limits of the peer is full.
+    ///
+    /// See [`RequestResponseConfig::set_max_queued_outbound_requests`].
+    QueueFull,
 }
 
 impl fmt::Display for OutboundFailure {
@@ -177,6 +215,10 @@ impl fmt::Display for OutboundFailure {
             OutboundFailure::UnsupportedProtocols => {
                 write!(f, "The remote supports none of the requested protocols")
             }
+            OutboundFailure::Codec(e) => write!(f, "Codec error: {}", e),
+            OutboundFailure::QueueFull => {
+                write!(f, "The queue of requests waiting to be sent is full")
+            }
         }
     }
 }
@@ -201,6 +243,17 @@ pub enum InboundFailure {
     /// due to the [`ResponseChannel`] being dropped instead of
     /// being passed to [`RequestResponse::send_response`].
     ResponseOmission,
+    /// Sending a streamed response failed after it was started, e.g.
+    /// because the remote cancelled it by dropping the [`ResponseStream`].
+    ResponseAborted,
+    /// The request could not be decoded or the response could not be encoded.
+    Codec(CodecError),
+    /// The inbound request was rejected because the limit of concurrent
+    /// inbound requests of the connection or the peer was reached.
+    ///
+    /// The substream is closed without a response, just like for a
+    /// [`InboundFailure::ResponseOmission`].
+    TooManyRequests,
 }
 
 impl fmt::Display for InboundFailure {
@@ -220,6 +273,16 @@ impl fmt::Display for InboundFailure {
                 f,
                 "The response channel was dropped without sending a response to the remote"
             ),
+            InboundFailure::ResponseAborted => {
+                write!(
+                    f,
+                    "The response stream was aborted before it was sent completely"
+                )
+            }
+            InboundFailure::Codec(e) => write!(f, "Codec error: {}", e),
+            InboundFailure::TooManyRequests => {
+                write!(f, "The limit of concurrent inbound requests was reached")
+            }
         }
     }
 }
@@ -268,6 +331,12 @@ impl fmt::Display for RequestId {
 pub struct RequestResponseConfig {
     request_timeout: Duration,
     connection_keep_alive: Duration,
+    max_inbound_requests_per_connection: usize,
+    max_inbound_requests_per_peer: usize,
+    max_outbound_requests_per_connection: usize,
+    max_outbound_requests_per_peer: usize,
+    max_queued_outbound_requests: usize,
+    retry_policy: Option<RetryPolicy>,
 }
 
 impl Default for RequestResponseConfig {
@@ -275,6 +344,12 @@ impl Default for RequestResponseConfig {
         Self {
             connection_keep_alive: Duration::from_secs(10),
             request_timeout: Duration::from_secs(10),
+            max_inbound_requests_per_connection: 100,
+            max_inbound_requests_per_peer: usize::MAX,
+            max_outbound_requests_per_connection: usize::MAX,
+            max_outbound_requests_per_peer: usize::MAX,
+            max_queued_outbound_requests: 100,
+            retry_policy: None,
         }
     }
 }
@@ -291,6 +366,106 @@ impl RequestResponseConfig {
         self.request_timeout = v;
         self
     }
+
+    /// Sets the maximum number of concurrent inbound requests on a single
+    /// connection, i.e. of requests being received or not yet answered.
+    ///
+    /// The connection handler drops the substreams of further requests
+    /// without reading them and they are reported as
+    /// [`InboundFailure::TooManyRequests`]. Defaults to 100. Not applied by
+    /// [`StreamingRequestResponse`].
+    pub fn set_max_inbound_requests_per_connection(&mut self, v: usize) -> &mut Self {
+        self.max_inbound_requests_per_connection = v;
+        self
+    }
+
+    /// Sets the maximum number of concurrent inbound requests from a single
+    /// peer across all its connections.
+    ///
+    /// Further requests are rejected with [`InboundFailure::TooManyRequests`].
+    /// Unlimited by default. Not applied by [`StreamingRequestResponse`].
+    pub fn set_max_inbound_requests_per_peer(&mut self, v: usize) -> &mut Self {
+        self.max_inbound_requests_per_peer = v;
+        self
+    }
+
+    /// Sets the maximum number of concurrent outbound requests on a single
+    /// connection, i.e. of requests sent and not yet answered.
+    ///
+    /// Further requests are queued, see
+    /// [`RequestResponseConfig::set_max_queued_outbound_requests`].
+    /// Unlimited by default. Not applied by [`StreamingRequestResponse`].
+    pub fn set_max_outbound_requests_per_connection(&mut self, v: usize) -> &mut Self {
+        self.max_outbound_requests_per_connection = v;
+        self
+    }
+
+    /// Sets the maximum number of concurrent outbound requests to a single
+    /// peer across all its connections.
+    ///
+    /// Further requests are queued, see
+    /// [`RequestResponseConfig::set_max_queued_outbound_requests`].
+    /// Unlimited by default. Not applied by [`StreamingRequestResponse`].
+    pub fn set_max_outbound_requests_per_peer(&mut self, v: usize) -> &mut Self {
+        self.max_outbound_requests_per_peer = v;
+        self
+    }
+
+    /// Sets the maximum number of outbound requests per peer that are queued
+    /// while the outbound request limits of the peer are reached.
+    ///
+    /// Queued requests are sent in order as soon as earlier requests complete.
+    /// Requests exceeding the queue fail with [`OutboundFailure::QueueFull`].
+    /// Defaults to 100.
+    pub fn set_max_queued_outbound_requests(&mut self, v: usize) -> &mut Self {
+        self.max_queued_outbound_requests = v;
+        self
+    }
+
+    /// Sets the policy for retrying outbound requests that failed with
+    /// [`OutboundFailure::DialFailure`] or [`OutboundFailure::Timeout`].
+    ///
+    /// The policy applies to requests sent with
+    /// [`RequestResponse::send_request_with_retry`] and
+    /// [`RequestResponse::send_request_to_any`], which the behaviour can
+    /// clone for each attempt. No requests are retried by default.
+    pub fn set_retry_policy(&mut self, v: Option<RetryPolicy>) -> &mut Self {
+        self.retry_policy = v;
+        self
+    }
+}
+
+/// The policy for retrying outbound requests to a peer.
+///
+/// See [`RequestResponseConfig::set_retry_policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts to send a request to a peer,
+    /// including the first one.
+    pub max_attempts: u32,
+    /// The delay before the first retry, doubled for every further retry.
+    pub backoff: Duration,
+    /// Whether retries of a timed out request avoid the connections to the
+    /// peer on which earlier attempts timed out, if there are other ones.
+    pub retry_on_other_connections: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            backoff: Duration::from_secs(1),
+            retry_on_other_connections: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay before the next attempt after the given number of attempts.
+    fn backoff(&self, attempts: u32) -> Duration {
+        self.backoff
+            .saturating_mul(1 << attempts.saturating_sub(1).min(16))
+    }
 }
 
 /// A request/response protocol for some message codec.
@@ -317,14 +492,16 @@ where
             RequestResponseHandler<TCodec>,
         >,
     >,
-    /// The currently connected peers, their pending outbound and inbound responses and their known,
-    /// reachable addresses, if any.
-    connected: HashMap<PeerId, SmallVec<[Connection; 2]>>,
-    /// Externally managed addresses via `add_address` and `remove_address`.
-    addresses: HashMap<PeerId, SmallVec<[Multiaddr; 6]>>,
-    /// Requests that have not yet been sent and are waiting for a connection
-    /// to be established.
-    pending_outbound_requests: HashMap<PeerId, SmallVec<[RequestProtocol<TCodec>; 10]>>,
+    /// The connections and addresses of peers and the requests waiting for
+    /// a connection to be established.
+    peers: Peers<RequestProtocol<TCodec>>,
+    /// Requests to connected peers that are waiting for the outbound request
+    /// limits of the peer to permit sending them.
+    queued_outbound_requests: HashMap<PeerId, VecDeque<RequestProtocol<TCodec>>>,
+    /// Outbound requests that are retried or failed over to other peers.
+    retries: HashMap<RequestId, Retry<TCodec::Request>>,
+    /// The backoff delays of requests awaiting their next attempt.
+    retry_delays: FuturesUnordered<BoxFuture<'static, RequestId>>,
 }
 
 impl<TCodec> RequestResponse<TCodec>
@@ -355,9 +532,10 @@ where
             config: cfg,
             codec,
             pending_events: VecDeque::new(),
-            connected: HashMap::new(),
-            pending_outbound_requests: HashMap::new(),
-            addresses: HashMap::new(),
+            peers: Peers::new(),
+            queued_outbound_requests: HashMap::new(),
+            retries: HashMap::new(),
+            retry_delays: FuturesUnordered::new(),
         }
     }
 
@@ -365,7 +543,9 @@ where
     ///
     /// If the targeted peer is currently not connected, a dialing
     /// attempt is initiated and the request is sent as soon as a
-    /// connection is established.
+    /// connection is established. If the outbound request limits of
+    /// the peer are reached, the request is queued. Failed requests are
+    /// not retried, see [`RequestResponse::send_request_with_retry`].
     ///
     /// > **Note**: In order for such a dialing attempt to succeed,
     /// > the `RequestResonse` protocol must either be embedded
@@ -375,6 +555,17 @@ where
     /// > [`RequestResponse::remove_address`].
     pub fn send_request(&mut self, peer: &PeerId, request: TCodec::Request) -> RequestId {
         let request_id = self.next_request_id();
+        self.send_request_with_id(peer, request_id, request);
+        request_id
+    }
+
+    /// Sends a request with the given ID, dialing the peer if it is not connected.
+    fn send_request_with_id(
+        &mut self,
+        peer: &PeerId,
+        request_id: RequestId,
+        request: TCodec::Request,
+    ) {
         let request = RequestProtocol {
             request_id,
             codec: self.codec.clone(),
@@ -384,19 +575,10 @@ where
 
         if let Some(request) = self.try_send_request(peer, request) {
             let handler = self.new_handler();
-            self.pending_events.push_back(NetworkBehaviourAction::Dial {
-                opts: DialOpts::peer_id(*peer)
-                    .condition(dial_opts::PeerCondition::Disconnected)
-                    .build(),
-                handler,
-            });
-            self.pending_outbound_requests
-                .entry(*peer)
-                .or_default()
-                .push(request);
+            let opts = self.peers.dial(peer, request);
+            self.pending_events
+                .push_back(NetworkBehaviourAction::Dial { opts, handler });
         }
-
-        request_id
     }
 
     /// Initiates sending a response to an inbound request.
@@ -424,28 +606,17 @@ where
     ///
     /// Addresses added in this way are only removed by `remove_address`.
     pub fn add_address(&mut self, peer: &PeerId, address: Multiaddr) {
-        self.addresses.entry(*peer).or_default().push(address);
+        self.peers.add_address(peer, address)
     }
 
     /// Removes an address of a peer previously added via `add_address`.
     pub fn remove_address(&mut self, peer: &PeerId, address: &Multiaddr) {
-        let mut last = false;
-        if let Some(addresses) = self.addresses.get_mut(peer) {
-            addresses.retain(|a| a != address);
-            last = addresses.is_empty();
-        }
-        if last {
-            self.addresses.remove(peer);
-        }
+        self.peers.remove_address(peer, address)
     }
 
     /// Checks whether a peer is currently connected.
     pub fn is_connected(&self, peer: &PeerId) -> bool {
-        if let Some(connections) = self.connected.get(peer) {
-            !connections.is_empty()
-        } else {
-            false
-        }
+        self.peers.is_connected(peer)
     }
 
     /// Checks whether an outbound request to the peer with the provided
@@ -454,34 +625,40 @@ where
     pub fn is_pending_outbound(&self, peer: &PeerId, request_id: &RequestId) -> bool {
         // Check if request is already sent on established connection.
         let est_conn = self
-            .connected
-            .get(peer)
-            .map(|cs| {
-                cs.iter()
-                    .any(|c| c.pending_inbound_responses.contains(request_id))
-            })
-            .unwrap_or(false);
+            .peers
+            .connections(peer)
+            .iter()
+            .any(|c| c.pending_inbound_responses.contains(request_id));
         // Check if request is still pending to be sent.
         let pen_conn = self
-            .pending_outbound_requests
+            .peers
+            .pending_requests(peer)
+            .iter()
+            .any(|rp| rp.request_id == *request_id);
+        // Check if request is queued due to the outbound request limits.
+        let queued = self
+            .queued_outbound_requests
             .get(peer)
             .map(|rps| rps.iter().any(|rp| rp.request_id == *request_id))
             .unwrap_or(false);
+        // Check if request is awaiting a retry.
+        let retry = self
+            .retries
+            .get(request_id)
+            .map(|r| r.peer == *peer)
+            .unwrap_or(false);
 
-        est_conn || pen_conn
+        est_conn || pen_conn || queued || retry
     }
 
     /// Checks whether an inbound request from the peer with the provided
     /// [`PeerId`] is still pending, i.e. waiting for a response by the local
     /// node through [`RequestResponse::send_response`].
     pub fn is_pending_inbound(&self, peer: &PeerId, request_id: &RequestId) -> bool {
-        self.connected
-            .get(peer)
-            .map(|cs| {
-                cs.iter()
-                    .any(|c| c.pending_outbound_responses.contains(request_id))
-            })
-            .unwrap_or(false)
+        self.peers
+            .connections(peer)
+            .iter()
+            .any(|c| c.pending_outbound_responses.contains(request_id))
     }
 
     /// Returns the next request ID.
@@ -494,28 +671,189 @@ where
     /// Tries to send a request by queueing an appropriate event to be
     /// emitted to the `Swarm`. If the peer is not currently connected,
     /// the given request is return unchanged.
+    ///
+    /// If the outbound request limits of the peer are reached, the request
+    /// is queued, or fails if the queue is full.
     fn try_send_request(
         &mut self,
         peer: &PeerId,
         request: RequestProtocol<TCodec>,
     ) -> Option<RequestProtocol<TCodec>> {
-        if let Some(connections) = self.connected.get_mut(peer) {
-            if connections.is_empty() {
-                return Some(request);
-            }
-            let ix = (request.request_id.0 as usize) % connections.len();
-            let conn = &mut connections[ix];
-            conn.pending_inbound_responses.insert(request.request_id);
-            self.pending_events
-                .push_back(NetworkBehaviourAction::NotifyHandler {
-                    peer_id: *peer,
-                    handler: NotifyHandler::One(conn.id),
-                    event: request,
-                });
-            None
+        if !self.peers.is_connected(peer) {
+            return Some(request);
+        }
+
+        // Queued requests are sent first. Otherwise, return early if
+        // the request could be sent right away.
+        let request = if self.queued_outbound_requests.contains_key(peer) {
+            request
+        } else {
+            self.send_request_within_limits(peer, request)?
+        };
+
+        let queue = self.queued_outbound_requests.entry(*peer).or_default();
+        if queue.len() < self.config.max_queued_outbound_requests {
+            queue.push_back(request);
         } else {
-            Some(request)
+            if queue.is_empty() {
+                self.queued_outbound_requests.remove(peer);
+            }
+            self.fail_outbound(*peer, None, request.request_id, OutboundFailure::QueueFull);
         }
+        None
+    }
+
+    /// Sends a request on a connection to the peer that is below the outbound
+    /// request limits. If there is none, the given request is returned unchanged.
+    fn send_request_within_limits(
+        &mut self,
+        peer: &PeerId,
+        request: RequestProtocol<TCodec>,
+    ) -> Option<RequestProtocol<TCodec>> {
+        let connections = match self.peers.connections_mut(peer) {
+            Some(connections) => connections,
+            None => return Some(request),
+        };
+        let pending = connections
+            .iter()
+            .map(|c| c.pending_inbound_responses.len())
+            .sum::<usize>();
+        if pending >= self.config.max_outbound_requests_per_peer {
+            return Some(request);
+        }
+        // Starting from the connection picked by the request ID, use the
+        // first connection that is below its limit, preferring connections
+        // on which no earlier attempt of the request failed.
+        let failed = self
+            .retries
+            .get(&request.request_id)
+            .map(|r| r.failed_connections.as_slice())
+            .unwrap_or(&[]);
+        let ix = (request.request_id.0 as usize) % connections.len();
+        let mut candidates = (0..connections.len())
+            .map(|i| (ix + i) % connections.len())
+            .filter(|&i| {
+                connections[i].pending_inbound_responses.len()
+                    < self.config.max_outbound_requests_per_connection
+            })
+            .peekable();
+        let first = match candidates.peek() {
+            Some(&ix) => ix,
+            None => return Some(request),
+        };
+        let ix = candidates
+            .find(|&i| !failed.contains(&connections[i].id))
+            .unwrap_or(first);
+        let conn = &mut connections[ix];
+        conn.pending_inbound_responses.insert(request.request_id);
+        self.pending_events
+            .push_back(NetworkBehaviourAction::NotifyHandler {
+                peer_id: *peer,
+                handler: NotifyHandler::One(conn.id),
+                event: request,
+            });
+        None
+    }
+
+    /// Sends the queued requests to the peer as far as the outbound
+    /// request limits of the peer permit.
+    fn send_queued_requests(&mut self, peer: &PeerId) {
+        while let Some(request) = self
+            .queued_outbound_requests
+            .get_mut(peer)
+            .and_then(|queue| queue.pop_front())
+        {
+            if let Some(request) = self.send_request_within_limits(peer, request) {
+                self.queued_outbound_requests
+                    .entry(*peer)
+                    .or_default()
+                    .push_front(request);
+                return;
+            }
+        }
+        self.queued_outbound_requests.remove(peer);
+    }
+
+    /// Checks whether the inbound request limit of the peer across all its
+    /// connections is reached.
+    ///
+    /// The limit per connection is enforced by the connection handler.
+    fn inbound_limit_reached(&self, peer: &PeerId) -> bool {
+        let pending = self
+            .peers
+            .connections(peer)
+            .iter()
+            .map(|c| c.pending_outbound_responses.len())
+            .sum::<usize>();
+        pending >= self.config.max_inbound_requests_per_peer
+    }
+
+    /// Reports the failure of an outbound request, unless the request is
+    /// retried according to the [`RetryPolicy`] or failed over to another
+    /// candidate peer.
+    fn fail_outbound(
+        &mut self,
+        peer: PeerId,
+        connection: Option<ConnectionId>,
+        request_id: RequestId,
+        error: OutboundFailure,
+    ) {
+        if let Some(mut retry) = self.retries.remove(&request_id) {
+            let retryable = matches!(
+                error,
+                OutboundFailure::DialFailure | OutboundFailure::Timeout
+            );
+            match self.config.retry_policy {
+                Some(policy) if retryable && retry.attempts < policy.max_attempts => {
+                    if let (true, Some(connection)) =
+                        (policy.retry_on_other_connections, connection)
+                    {
+                        retry.failed_connections.push(connection);
+                    }
+                    let delay = Delay::new(policy.backoff(retry.attempts));
+                    retry.attempts += 1;
+                    self.retries.insert(request_id, retry);
+                    self.retry_delays
+                        .push(delay.map(move |()| request_id).boxed());
+                    return;
+                }
+                _ => {}
+            }
+            if let Some(next) = retry.candidates.pop_front() {
+                let request = (retry.clone_request)(&retry.request);
+                retry.peer = next;
+                retry.attempts = 1;
+                retry.failed_connections.clear();
+                self.retries.insert(request_id, retry);
+                self.send_request_with_id(&next, request_id, request);
+                return;
+            }
+        }
+
+        self.pending_events
+            .push_back(NetworkBehaviourAction::GenerateEvent(
+                RequestResponseEvent::OutboundFailure {
+                    peer,
+                    request_id,
+                    error,
+                },
+            ));
+    }
+
+    /// Remove a rejected inbound request for the given peer and connection.
+    ///
+    /// Returns `true` if the [`RequestId`] was previously present and is now
+    /// removed, i.e. the request was rejected due to the inbound request limits.
+    fn remove_rejected_inbound_request(
+        &mut self,
+        peer: &PeerId,
+        connection: ConnectionId,
+        request: RequestId,
+    ) -> bool {
+        self.peers
+            .get_connection_mut(peer, connection)
+            .map(|c| c.rejected_inbound_requests.remove(&request))
+            .unwrap_or(false)
     }
 
     /// Remove pending outbound response for the given peer and connection.
@@ -529,7 +867,8 @@ where
         connection: ConnectionId,
         request: RequestId,
     ) -> bool {
-        self.get_connection_mut(peer, connection)
+        self.peers
+            .get_connection_mut(peer, connection)
             .map(|c| c.pending_outbound_responses.remove(&request))
             .unwrap_or(false)
     }
@@ -545,21 +884,79 @@ where
         connection: ConnectionId,
         request: &RequestId,
     ) -> bool {
-        self.get_connection_mut(peer, connection)
+        self.peers
+            .get_connection_mut(peer, connection)
             .map(|c| c.pending_inbound_responses.remove(request))
             .unwrap_or(false)
     }
+}
 
-    /// Returns a mutable reference to the connection in `self.connected`
-    /// corresponding to the given [`PeerId`] and [`ConnectionId`].
-    fn get_connection_mut(
+impl<TCodec> RequestResponse<TCodec>
+where
+    TCodec: RequestResponseCodec + Clone + Send + 'static,
+    TCodec::Request: Clone,
+{
+    /// Initiates sending a request like [`RequestResponse::send_request`],
+    /// retrying it according to the [`RetryPolicy`] of the configuration,
+    /// if any.
+    pub fn send_request_with_retry(
         &mut self,
         peer: &PeerId,
-        connection: ConnectionId,
-    ) -> Option<&mut Connection> {
-        self.connected
-            .get_mut(peer)
-            .and_then(|connections| connections.iter_mut().find(|c| c.id == connection))
+        request: TCodec::Request,
+    ) -> RequestId {
+        let request_id = self.next_request_id();
+        if self.config.retry_policy.is_some() {
+            self.retries.insert(
+                request_id,
+                Retry {
+                    peer: *peer,
+                    candidates: VecDeque::new(),
+                    attempts: 1,
+                    failed_connections: SmallVec::new(),
+                    request: request.clone(),
+                    clone_request: TCodec::Request::clone,
+                },
+            );
+        }
+        self.send_request_with_id(peer, request_id, request);
+        request_id
+    }
+
+    /// Initiates sending a request to any of the given candidate peers.
+    ///
+    /// The request is sent to the first candidate. Failed attempts are
+    /// retried according to the [`RetryPolicy`] of the configuration, after
+    /// which the request is sent to the next candidate. The peer that
+    /// eventually answers is reported as the `peer` of the
+    /// [`RequestResponseEvent::Message`] with the response. Only the failure
+    /// of the last candidate is reported as
+    /// [`RequestResponseEvent::OutboundFailure`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `peers` is empty.
+    pub fn send_request_to_any<I>(&mut self, peers: I, request: TCodec::Request) -> RequestId
+    where
+        I: IntoIterator<Item = PeerId>,
+    {
+        let mut candidates = peers.into_iter().collect::<VecDeque<_>>();
+        let peer = candidates
+            .pop_front()
+            .expect("Expected at least one candidate peer.");
+        let request_id = self.next_request_id();
+        self.retries.insert(
+            request_id,
+            Retry {
+                peer,
+                candidates,
+                attempts: 1,
+                failed_connections: SmallVec::new(),
+                request: request.clone(),
+                clone_request: TCodec::Request::clone,
+            },
+        );
+        self.send_request_with_id(&peer, request_id, request);
+        request_id
     }
 }
 
@@ -576,19 +973,13 @@ where
             self.codec.clone(),
             self.config.connection_keep_alive,
             self.config.request_timeout,
+            self.config.max_inbound_requests_per_connection,
             self.next_inbound_id.clone(),
         )
     }
 
     fn addresses_of_peer(&mut self, peer: &PeerId) -> Vec<Multiaddr> {
-        let mut addresses = Vec::new();
-        if let Some(connections) = self.connected.get(peer) {
-            addresses.extend(connections.iter().filter_map(|c| c.address.clone()))
-        }
-        if let Some(more) = self.addresses.get(peer) {
-            addresses.extend(more.into_iter().cloned());
-        }
-        addresses
+        self.peers.addresses_of_peer(peer)
     }
 
     fn inject_address_change(
@@ -598,20 +989,7 @@ where
         _old: &ConnectedPoint,
         new: &ConnectedPoint,
     ) {
-        let new_address = match new {
-            ConnectedPoint::Dialer { address, .. } => Some(address.clone()),
-            ConnectedPoint::Listener { .. } => None,
-        };
-        let connections = self
-            .connected
-            .get_mut(peer)
-            .expect("Address change can only happen on an established connection.");
-
-        let connection = connections
-            .iter_mut()
-            .find(|c| &c.id == conn)
-            .expect("Address change can only happen on an established connection.");
-        connection.address = new_address;
+        self.peers.address_change(peer, *conn, new);
     }
 
     fn inject_connection_established(
@@ -622,22 +1000,18 @@ where
         _errors: Option<&Vec<Multiaddr>>,
         other_established: usize,
     ) {
-        let address = match endpoint {
-            ConnectedPoint::Dialer { address, .. } => Some(address.clone()),
-            ConnectedPoint::Listener { .. } => None,
-        };
-        self.connected
-            .entry(*peer)
-            .or_default()
-            .push(Connection::new(*conn, address));
+        let pending = self
+            .peers
+            .connection_established(peer, *conn, endpoint, other_established);
 
         if other_established == 0 {
-            if let Some(pending) = self.pending_outbound_requests.remove(peer) {
-                for request in pending {
-                    let request = self.try_send_request(peer, request);
-                    assert!(request.is_none());
-                }
+            for request in pending {
+                let request = self.try_send_request(peer, request);
+                assert!(request.is_none());
             }
+        } else {
+            // The new connection may permit sending queued requests.
+            self.send_queued_requests(peer);
         }
     }
 
@@ -649,21 +1023,9 @@ where
         _: <Self::ConnectionHandler as IntoConnectionHandler>::Handler,
         remaining_established: usize,
     ) {
-        let connections = self
-            .connected
-            .get_mut(peer_id)
-            .expect("Expected some established connection to peer before closing.");
-
-        let connection = connections
-            .iter()
-            .position(|c| &c.id == conn)
-            .map(|p: usize| connections.remove(p))
-            .expect("Expected connection to be established before closing.");
-
-        debug_assert_eq!(connections.is_empty(), remaining_established == 0);
-        if connections.is_empty() {
-            self.connected.remove(peer_id);
-        }
+        let connection = self
+            .peers
+            .connection_closed(peer_id, *conn, remaining_established);
 
         for request_id in connection.pending_outbound_responses {
             self.pending_events
@@ -677,14 +1039,25 @@ where
         }
 
         for request_id in connection.pending_inbound_responses {
-            self.pending_events
-                .push_back(NetworkBehaviourAction::GenerateEvent(
-                    RequestResponseEvent::OutboundFailure {
-                        peer: *peer_id,
-                        request_id,
-                        error: OutboundFailure::ConnectionClosed,
-                    },
-                ));
+            self.fail_outbound(
+                *peer_id,
+                Some(connection.id),
+                request_id,
+                OutboundFailure::ConnectionClosed,
+            );
+        }
+
+        if remaining_established > 0 {
+            self.send_queued_requests(peer_id);
+        } else if let Some(queued) = self.queued_outbound_requests.remove(peer_id) {
+            for request in queued {
+                self.fail_outbound(
+                    *peer_id,
+                    None,
+                    request.request_id,
+                    OutboundFailure::ConnectionClosed,
+                );
+            }
         }
     }
 
@@ -695,23 +1068,8 @@ where
         _: &DialError,
     ) {
         if let Some(peer) = peer {
-            // If there are pending outgoing requests when a dial failure occurs,
-            // it is implied that we are not connected to the peer, since pending
-            // outgoing requests are drained when a connection is established and
-            // only created when a peer is not connected when a request is made.
-            // Thus these requests must be considered failed, even if there is
-            // another, concurrent dialing attempt ongoing.
-            if let Some(pending) = self.pending_outbound_requests.remove(&peer) {
-                for request in pending {
-                    self.pending_events
-                        .push_back(NetworkBehaviourAction::GenerateEvent(
-                            RequestResponseEvent::OutboundFailure {
-                                peer,
-                                request_id: request.request_id,
-                                error: OutboundFailure::DialFailure,
-                            },
-                        ));
-                }
+            for request in self.peers.dial_failed(&peer) {
+                self.fail_outbound(peer, None, request.request_id, OutboundFailure::DialFailure);
             }
         }
     }
@@ -733,6 +1091,8 @@ where
                     "Expect request_id to be pending before receiving response.",
                 );
 
+                self.retries.remove(&request_id);
+
                 let message = RequestResponseMessage::Response {
                     request_id,
                     response,
@@ -741,12 +1101,29 @@ where
                     .push_back(NetworkBehaviourAction::GenerateEvent(
                         RequestResponseEvent::Message { peer, message },
                     ));
+                self.send_queued_requests(&peer);
             }
             RequestResponseHandlerEvent::Request {
                 request_id,
                 request,
                 sender,
             } => {
+                if self.inbound_limit_reached(&peer) {
+                    // Dropping the `sender` closes the substream without a response.
+                    if let Some(connection) = self.peers.get_connection_mut(&peer, connection) {
+                        connection.rejected_inbound_requests.insert(request_id);
+                    }
+                    self.pending_events
+                        .push_back(NetworkBehaviourAction::GenerateEvent(
+                            RequestResponseEvent::InboundFailure {
+                                peer,
+                                request_id,
+                                error: InboundFailure::TooManyRequests,
+                            },
+                        ));
+                    return;
+                }
+
                 let channel = ResponseChannel { sender };
                 let message = RequestResponseMessage::Request {
                     request_id,
@@ -758,7 +1135,7 @@ where
                         RequestResponseEvent::Message { peer, message },
                     ));
 
-                match self.get_connection_mut(&peer, connection) {
+                match self.peers.get_connection_mut(&peer, connection) {
                     Some(connection) => {
                         let inserted = connection.pending_outbound_responses.insert(request_id);
                         debug_assert!(inserted, "Expect id of new request to be unknown.");
@@ -789,6 +1166,11 @@ where
                     ));
             }
             RequestResponseHandlerEvent::ResponseOmission(request_id) => {
+                if self.remove_rejected_inbound_request(&peer, connection, request_id) {
+                    // The failure of the rejected request has already been reported.
+                    return;
+                }
+
                 let removed = self.remove_pending_outbound_response(&peer, connection, request_id);
                 debug_assert!(
                     removed,
@@ -811,16 +1193,15 @@ where
                     "Expect request_id to be pending before request times out."
                 );
 
-                self.pending_events
-                    .push_back(NetworkBehaviourAction::GenerateEvent(
-                        RequestResponseEvent::OutboundFailure {
-                            peer,
-                            request_id,
-                            error: OutboundFailure::Timeout,
-                        },
-                    ));
+                self.fail_outbound(peer, Some(connection), request_id, OutboundFailure::Timeout);
+                self.send_queued_requests(&peer);
             }
             RequestResponseHandlerEvent::InboundTimeout(request_id) => {
+                if self.remove_rejected_inbound_request(&peer, connection, request_id) {
+                    // The failure of the rejected request has already been reported.
+                    return;
+                }
+
                 // Note: `RequestResponseHandlerEvent::InboundTimeout` is emitted both for timing
                 // out to receive the request and for timing out sending the response. In the former
                 // case the request is never added to `pending_outbound_responses` and thus one can
@@ -836,22 +1217,50 @@ where
                         },
                     ));
             }
-            RequestResponseHandlerEvent::OutboundUnsupportedProtocols(request_id) => {
+            RequestResponseHandlerEvent::OutboundCodecError(request_id, error) => {
                 let removed = self.remove_pending_inbound_response(&peer, connection, &request_id);
                 debug_assert!(
                     removed,
-                    "Expect request_id to be pending before failing to connect.",
+                    "Expect request_id to be pending before the codec fails."
                 );
 
+                self.fail_outbound(
+                    peer,
+                    Some(connection),
+                    request_id,
+                    OutboundFailure::Codec(error),
+                );
+                self.send_queued_requests(&peer);
+            }
+            RequestResponseHandlerEvent::InboundCodecError(request_id, error) => {
+                // Note: As with `RequestResponseHandlerEvent::InboundTimeout`, the request
+                // is only pending if it was decoded and the response failed to be encoded.
+                self.remove_pending_outbound_response(&peer, connection, request_id);
+
                 self.pending_events
                     .push_back(NetworkBehaviourAction::GenerateEvent(
-                        RequestResponseEvent::OutboundFailure {
+                        RequestResponseEvent::InboundFailure {
                             peer,
                             request_id,
-                            error: OutboundFailure::UnsupportedProtocols,
+                            error: InboundFailure::Codec(error),
                         },
                     ));
             }
+            RequestResponseHandlerEvent::OutboundUnsupportedProtocols(request_id) => {
+                let removed = self.remove_pending_inbound_response(&peer, connection, &request_id);
+                debug_assert!(
+                    removed,
+                    "Expect request_id to be pending before failing to connect.",
+                );
+
+                self.fail_outbound(
+                    peer,
+                    Some(connection),
+                    request_id,
+                    OutboundFailure::UnsupportedProtocols,
+                );
+                self.send_queued_requests(&peer);
+            }
             RequestResponseHandlerEvent::InboundUnsupportedProtocols(request_id) => {
                 // Note: No need to call `self.remove_pending_outbound_response`,
                 // `RequestResponseHandlerEvent::Request` was never emitted for this request and
@@ -865,14 +1274,33 @@ where
                         },
                     ));
             }
+            RequestResponseHandlerEvent::InboundLimitReached(request_id) => {
+                // Note: As above, the request was never read and is thus not
+                // in `pending_outbound_responses`.
+                self.pending_events
+                    .push_back(NetworkBehaviourAction::GenerateEvent(
+                        RequestResponseEvent::InboundFailure {
+                            peer,
+                            request_id,
+                            error: InboundFailure::TooManyRequests,
+                        },
+                    ));
+            }
         }
     }
 
     fn poll(
         &mut self,
-        _: &mut Context<'_>,
+        cx: &mut Context<'_>,
         _: &mut impl PollParameters,
     ) -> Poll<NetworkBehaviourAction<Self::OutEvent, Self::ConnectionHandler>> {
+        while let Poll::Ready(Some(request_id)) = self.retry_delays.poll_next_unpin(cx) {
+            if let Some(retry) = self.retries.get(&request_id) {
+                let (peer, request) = (retry.peer, (retry.clone_request)(&retry.request));
+                self.send_request_with_id(&peer, request_id, request);
+            }
+        }
+
         if let Some(ev) = self.pending_events.pop_front() {
             return Poll::Ready(ev);
         } else if self.pending_events.capacity() > EMPTY_QUEUE_SHRINK_THRESHOLD {
@@ -889,26 +1317,20 @@ where
 /// released.
 const EMPTY_QUEUE_SHRINK_THRESHOLD: usize = 100;
 
-/// Internal information tracked for an established connection.
-struct Connection {
-    id: ConnectionId,
-    address: Option<Multiaddr>,
-    /// Pending outbound responses where corresponding inbound requests have
-    /// been received on this connection and emitted via `poll` but have not yet
-    /// been answered.
-    pending_outbound_responses: HashSet<RequestId>,
-    /// Pending inbound responses for previously sent requests on this
-    /// connection.
-    pending_inbound_responses: HashSet<RequestId>,
-}
-
-impl Connection {
-    fn new(id: ConnectionId, address: Option<Multiaddr>) -> Self {
-        Self {
-            id,
-            address,
-            pending_outbound_responses: Default::default(),
-            pending_inbound_responses: Default::default(),
-        }
-    }
+/// Internal state of an outbound request that is retried according to the
+/// [`RetryPolicy`] or failed over to other candidate peers.
+struct Retry<TRequest> {
+    /// The peer of the current attempt.
+    peer: PeerId,
+    /// The remaining candidate peers to fail over to.
+    candidates: VecDeque<PeerId>,
+    /// The number of attempts to `peer` so far.
+    attempts: u32,
+    /// The connections to `peer` on which attempts timed out.
+    failed_connections: SmallVec<[ConnectionId; 2]>,
+    /// The request, cloned for every attempt.
+    request: TRequest,
+    /// Clones the request. The behaviour does not require `Clone` requests,
+    /// so this is captured where it does, in the retrying entry points.
+    clone_request: fn(&TRequest) -> TRequest,
 }
@@ -0,0 +1,243 @@
+// Copyright 2022 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Connection, address and dial bookkeeping shared by
+//! [`RequestResponse`](crate::RequestResponse) and
+//! [`StreamingRequestResponse`](crate::StreamingRequestResponse).
+
+use crate::RequestId;
+use libp2p_core::{connection::ConnectionId, ConnectedPoint, Multiaddr, PeerId};
+use libp2p_swarm::dial_opts::{self, DialOpts};
+use smallvec::SmallVec;
+use std::collections::{HashMap, HashSet};
+
+/// The connections and addresses of peers, as well as the requests waiting
+/// for a connection to be established.
+pub(crate) struct Peers<TRequest> {
+    /// The currently connected peers, their pending outbound and inbound responses and their known,
+    /// reachable addresses, if any.
+    connected: HashMap<PeerId, SmallVec<[Connection; 2]>>,
+    /// Externally managed addresses via `add_address` and `remove_address`.
+    addresses: HashMap<PeerId, SmallVec<[Multiaddr; 6]>>,
+    /// Requests that have not yet been sent and are waiting for a connection
+    /// to be established.
+    pending_requests: HashMap<PeerId, SmallVec<[TRequest; 10]>>,
+}
+
+impl<TRequest> Peers<TRequest> {
+    pub(crate) fn new() -> Self {
+        Peers {
+            connected: HashMap::new(),
+            addresses: HashMap::new(),
+            pending_requests: HashMap::new(),
+        }
+    }
+
+    /// Adds a known address for a peer.
+    pub(crate) fn add_address(&mut self, peer: &PeerId, address: Multiaddr) {
+        self.addresses.entry(*peer).or_default().push(address);
+    }
+
+    /// Removes an address of a peer previously added via `add_address`.
+    pub(crate) fn remove_address(&mut self, peer: &PeerId, address: &Multiaddr) {
+        let mut last = false;
+        if let Some(addresses) = self.addresses.get_mut(peer) {
+            addresses.retain(|a| a != address);
+            last = addresses.is_empty();
+        }
+        if last {
+            self.addresses.remove(peer);
+        }
+    }
+
+    /// Returns the addresses of the established connections to the peer,
+    /// followed by the addresses added via `add_address`.
+    pub(crate) fn addresses_of_peer(&self, peer: &PeerId) -> Vec<Multiaddr> {
+        let mut addresses = Vec::new();
+        if let Some(connections) = self.connected.get(peer) {
+            addresses.extend(connections.iter().filter_map(|c| c.address.clone()))
+        }
+        if let Some(more) = self.addresses.get(peer) {
+            addresses.extend(more.iter().cloned());
+        }
+        addresses
+    }
+
+    /// Checks whether a peer is currently connected.
+    pub(crate) fn is_connected(&self, peer: &PeerId) -> bool {
+        !self.connections(peer).is_empty()
+    }
+
+    /// Returns the established connections to the peer.
+    pub(crate) fn connections(&self, peer: &PeerId) -> &[Connection] {
+        self.connected
+            .get(peer)
+            .map(|connections| connections.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Returns the established connections to the peer, if any.
+    pub(crate) fn connections_mut(&mut self, peer: &PeerId) -> Option<&mut [Connection]> {
+        self.connected
+            .get_mut(peer)
+            .filter(|connections| !connections.is_empty())
+            .map(|connections| connections.as_mut_slice())
+    }
+
+    /// Returns a mutable reference to the connection corresponding to the
+    /// given [`PeerId`] and [`ConnectionId`].
+    pub(crate) fn get_connection_mut(
+        &mut self,
+        peer: &PeerId,
+        connection: ConnectionId,
+    ) -> Option<&mut Connection> {
+        self.connected
+            .get_mut(peer)
+            .and_then(|connections| connections.iter_mut().find(|c| c.id == connection))
+    }
+
+    /// Returns the requests to the peer waiting for a connection.
+    pub(crate) fn pending_requests(&self, peer: &PeerId) -> &[TRequest] {
+        self.pending_requests
+            .get(peer)
+            .map(|requests| requests.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Records a request waiting for a connection to the peer, returning the
+    /// options to dial the peer with.
+    pub(crate) fn dial(&mut self, peer: &PeerId, request: TRequest) -> DialOpts {
+        self.pending_requests
+            .entry(*peer)
+            .or_default()
+            .push(request);
+        DialOpts::peer_id(*peer)
+            .condition(dial_opts::PeerCondition::Disconnected)
+            .build()
+    }
+
+    /// Removes the requests waiting for a connection to a peer that could
+    /// not be dialed.
+    pub(crate) fn dial_failed(&mut self, peer: &PeerId) -> SmallVec<[TRequest; 10]> {
+        // If there are pending outgoing requests when a dial failure occurs,
+        // it is implied that we are not connected to the peer, since pending
+        // outgoing requests are drained when a connection is established and
+        // only created when a peer is not connected when a request is made.
+        // Thus these requests must be considered failed, even if there is
+        // another, concurrent dialing attempt ongoing.
+        self.pending_requests.remove(peer).unwrap_or_default()
+    }
+
+    /// Records an established connection, returning the requests waiting
+    /// for it if it is the first connection to the peer.
+    pub(crate) fn connection_established(
+        &mut self,
+        peer: &PeerId,
+        conn: ConnectionId,
+        endpoint: &ConnectedPoint,
+        other_established: usize,
+    ) -> SmallVec<[TRequest; 10]> {
+        self.connected
+            .entry(*peer)
+            .or_default()
+            .push(Connection::new(conn, dialed_address(endpoint)));
+
+        if other_established == 0 {
+            self.pending_requests.remove(peer).unwrap_or_default()
+        } else {
+            SmallVec::new()
+        }
+    }
+
+    /// Records a changed address of an established connection.
+    pub(crate) fn address_change(
+        &mut self,
+        peer: &PeerId,
+        conn: ConnectionId,
+        new: &ConnectedPoint,
+    ) {
+        let connection = self
+            .get_connection_mut(peer, conn)
+            .expect("Address change can only happen on an established connection.");
+        connection.address = dialed_address(new);
+    }
+
+    /// Removes a closed connection, returning it with its pending requests.
+    pub(crate) fn connection_closed(
+        &mut self,
+        peer: &PeerId,
+        conn: ConnectionId,
+        remaining_established: usize,
+    ) -> Connection {
+        let connections = self
+            .connected
+            .get_mut(peer)
+            .expect("Expected some established connection to peer before closing.");
+
+        let connection = connections
+            .iter()
+            .position(|c| c.id == conn)
+            .map(|p: usize| connections.remove(p))
+            .expect("Expected connection to be established before closing.");
+
+        debug_assert_eq!(connections.is_empty(), remaining_established == 0);
+        if connections.is_empty() {
+            self.connected.remove(peer);
+        }
+
+        connection
+    }
+}
+
+/// The address to redial a connection on, if it was dialed.
+fn dialed_address(endpoint: &ConnectedPoint) -> Option<Multiaddr> {
+    match endpoint {
+        ConnectedPoint::Dialer { address, .. } => Some(address.clone()),
+        ConnectedPoint::Listener { .. } => None,
+    }
+}
+
+/// Internal information tracked for an established connection.
+pub(crate) struct Connection {
+    pub(crate) id: ConnectionId,
+    pub(crate) address: Option<Multiaddr>,
+    /// Pending outbound responses where corresponding inbound requests have
+    /// been received on this connection and emitted via `poll` but have not yet
+    /// been answered.
+    pub(crate) pending_outbound_responses: HashSet<RequestId>,
+    /// Pending inbound responses for previously sent requests on this
+    /// connection.
+    pub(crate) pending_inbound_responses: HashSet<RequestId>,
+    /// Inbound requests on this connection that have been rejected due to
+    /// the inbound request limits and whose substream is being closed.
+    pub(crate) rejected_inbound_requests: HashSet<RequestId>,
+}
+
+impl Connection {
+    fn new(id: ConnectionId, address: Option<Multiaddr>) -> Self {
+        Self {
+            id,
+            address,
+            pending_outbound_responses: Default::default(),
+            pending_inbound_responses: Default::default(),
+            rejected_inbound_requests: Default::default(),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+// Copyright 2022 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! A generic [`RequestResponseCodec`] for requests and responses of any
+//! serde types, encoded in a serde data [`Format`].
+//!
+//! Every message is prefixed with its length as an unsigned varint and is
+//! limited in size. Messages that exceed the size limit or cannot be encoded
+//! or decoded fail the request with a [`CodecError`].
+//!
+//! The [`crate::cbor`] and [`crate::json`] modules provide the formats and
+//! codecs for CBOR and JSON.
+
+use crate::codec::{read_length_prefixed, write_length_prefixed, CodecError};
+use crate::RequestResponseCodec;
+
+use async_trait::async_trait;
+use futures::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fmt, io, marker::PhantomData};
+
+/// The default maximum size of a request in bytes.
+const DEFAULT_REQUEST_SIZE_MAXIMUM: usize = 1024 * 1024;
+/// The default maximum size of a response in bytes.
+const DEFAULT_RESPONSE_SIZE_MAXIMUM: usize = 10 * 1024 * 1024;
+
+/// A serde data format in which a [`Codec`] encodes messages.
+pub trait Format: Send + 'static {
+    /// Encodes a message, returning a description of the error on failure.
+    fn encode(message: &impl Serialize) -> Result<Vec<u8>, String>;
+
+    /// Decodes a message, returning a description of the error on failure.
+    fn decode<M: DeserializeOwned>(data: &[u8]) -> Result<M, String>;
+}
+
+/// A codec encoding requests of type `Req` and responses of type `Resp` in
+/// the format `F`.
+///
+/// The protocols are named by strings, e.g. `"/my-app/1.0.0".to_string()`.
+pub struct Codec<F, Req, Resp> {
+    request_size_maximum: usize,
+    response_size_maximum: usize,
+    phantom: PhantomData<(F, Req, Resp)>,
+}
+
+impl<F, Req, Resp> Codec<F, Req, Resp> {
+    /// Sets the maximum size of a request in bytes.
+    pub fn set_request_size_maximum(&mut self, v: usize) -> &mut Self {
+        self.request_size_maximum = v;
+        self
+    }
+
+    /// Sets the maximum size of a response in bytes.
+    pub fn set_response_size_maximum(&mut self, v: usize) -> &mut Self {
+        self.response_size_maximum = v;
+        self
+    }
+}
+
+impl<F, Req, Resp> Default for Codec<F, Req, Resp> {
+    fn default() -> Self {
+        Codec {
+            request_size_maximum: DEFAULT_REQUEST_SIZE_MAXIMUM,
+            response_size_maximum: DEFAULT_RESPONSE_SIZE_MAXIMUM,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, Req, Resp> Clone for Codec<F, Req, Resp> {
+    fn clone(&self) -> Self {
+        Codec {
+            request_size_maximum: self.request_size_maximum,
+            response_size_maximum: self.response_size_maximum,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, Req, Resp> fmt::Debug for Codec<F, Req, Resp> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Codec")
+            .field("request_size_maximum", &self.request_size_maximum)
+            .field("response_size_maximum", &self.response_size_maximum)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<F, Req, Resp> RequestResponseCodec for Codec<F, Req, Resp>
+where
+    F: Format,
+    Req: Serialize + DeserializeOwned + Send,
+    Resp: Serialize + DeserializeOwned + Send,
+{
+    type Protocol = String;
+    type Request = Req;
+    type Response = Resp;
+
+    async fn read_request<T>(&mut self, _: &String, io: &mut T) -> io::Result<Req>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let data = read_length_prefixed(io, self.request_size_maximum).await?;
+        F::decode(&data).map_err(|e| CodecError::Decode(e).into())
+    }
+
+    async fn read_response<T>(&mut self, _: &String, io: &mut T) -> io::Result<Resp>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let data = read_length_prefixed(io, self.response_size_maximum).await?;
+        F::decode(&data).map_err(|e| CodecError::Decode(e).into())
+    }
+
+    async fn write_request<T>(&mut self, _: &String, io: &mut T, req: Req) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let data = F::encode(&req).map_err(CodecError::Encode)?;
+        write_length_prefixed(io, &data, self.request_size_maximum).await
+    }
+
+    async fn write_response<T>(&mut self, _: &String, io: &mut T, res: Resp) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let data = F::encode(&res).map_err(CodecError::Encode)?;
+        write_length_prefixed(io, &data, self.response_size_maximum).await
+    }
+}
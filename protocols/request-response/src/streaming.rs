@@ -0,0 +1,671 @@
+// Copyright 2022 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Request/response protocols with streamed responses.
+//!
+//! [`StreamingRequestResponse`] is a `NetworkBehaviour` like
+//! [`RequestResponse`](crate::RequestResponse), except that a response is a
+//! stream of chunks defined in terms of a [`StreamingCodec`] instead of a
+//! single message. This allows transferring responses that are too large to
+//! be held in memory at once.
+//!
+//! Upon receiving a [`StreamingRequestResponseMessage::Request`], the response
+//! is sent by writing chunks to the [`ResponseSink`] of the request and closing
+//! it afterwards. The requester receives the chunks in order through the
+//! [`ResponseStream`] of a [`StreamingRequestResponseMessage::Response`].
+//!
+//! Both sides apply backpressure: only a few chunks are buffered between the
+//! application and the substream, so writing to a [`ResponseSink`] waits
+//! until the remote consumes the [`ResponseStream`].
+//!
+//! Either side can cancel a response. Dropping the [`ResponseSink`] without
+//! closing it aborts the response, which the requester observes as an error
+//! ending the [`ResponseStream`]. Dropping the [`ResponseStream`] stops
+//! receiving the response, which the responder observes as the
+//! [`ResponseSink`] no longer accepting chunks and an
+//! [`InboundFailure::ResponseAborted`].
+//!
+//! The request timeout configured via [`RequestResponseConfig`] applies to
+//! sending or receiving the request and to every individual response chunk
+//! thereafter, rather than to the response as a whole.
+
+pub mod handler;
+
+pub use crate::codec::StreamingCodec;
+
+use crate::{
+    peers::Peers, InboundFailure, OutboundFailure, ProtocolSupport, RequestId,
+    RequestResponseConfig, EMPTY_QUEUE_SHRINK_THRESHOLD,
+};
+use futures::{
+    channel::{mpsc, oneshot},
+    prelude::*,
+    stream::FusedStream,
+};
+use handler::{
+    StreamingRequestProtocol, StreamingRequestResponseHandler, StreamingRequestResponseHandlerEvent,
+};
+use libp2p_core::{connection::ConnectionId, ConnectedPoint, Multiaddr, PeerId};
+use libp2p_swarm::{
+    DialError, IntoConnectionHandler, NetworkBehaviour, NetworkBehaviourAction, NotifyHandler,
+    PollParameters,
+};
+use smallvec::SmallVec;
+use std::{
+    collections::VecDeque,
+    io,
+    pin::Pin,
+    sync::{atomic::AtomicU64, Arc},
+    task::{Context, Poll},
+};
+
+/// An inbound request or the start of an inbound response.
+#[derive(Debug)]
+pub enum StreamingRequestResponseMessage<TRequest, TChunk> {
+    /// A request message.
+    Request {
+        /// The ID of this request.
+        request_id: RequestId,
+        /// The request message.
+        request: TRequest,
+        /// The sink to write the response chunks to.
+        ///
+        /// If this sink is dropped without being closed, the response is
+        /// aborted and a [`StreamingRequestResponseEvent::InboundFailure`]
+        /// with [`InboundFailure::ResponseOmission`] is emitted.
+        sink: ResponseSink<TChunk>,
+    },
+    /// A response.
+    Response {
+        /// The ID of the request that produced this response.
+        ///
+        /// See [`StreamingRequestResponse::send_request`].
+        request_id: RequestId,
+        /// The stream of response chunks.
+        stream: ResponseStream<TChunk>,
+    },
+}
+
+/// The events emitted by a [`StreamingRequestResponse`] protocol.
+#[derive(Debug)]
+pub enum StreamingRequestResponseEvent<TRequest, TChunk> {
+    /// An incoming message (request or response).
+    Message {
+        /// The peer who sent the message.
+        peer: PeerId,
+        /// The incoming message.
+        message: StreamingRequestResponseMessage<TRequest, TChunk>,
+    },
+    /// An outbound request failed before its response started to be received.
+    ///
+    /// Failures while receiving the response are reported through the
+    /// [`ResponseStream`].
+    OutboundFailure {
+        /// The peer to whom the request was sent.
+        peer: PeerId,
+        /// The (local) ID of the failed request.
+        request_id: RequestId,
+        /// The error that occurred.
+        error: OutboundFailure,
+    },
+    /// An inbound request failed.
+    InboundFailure {
+        /// The peer from whom the request was received.
+        peer: PeerId,
+        /// The ID of the failed inbound request.
+        request_id: RequestId,
+        /// The error that occurred.
+        error: InboundFailure,
+    },
+    /// A response to an inbound request has been sent completely.
+    ///
+    /// When this event is received, all chunks of the response have been
+    /// flushed on the underlying transport connection.
+    ResponseSent {
+        /// The peer to whom the response was sent.
+        peer: PeerId,
+        /// The ID of the inbound request whose response was sent.
+        request_id: RequestId,
+    },
+}
+
+/// A sink for the chunks of a response to an inbound request.
+///
+/// The response is complete once the sink is closed, e.g. via
+/// [`SinkExt::close`](futures::SinkExt::close). Dropping the sink without closing it aborts the
+/// response. Sending fails once the response can no longer be sent, e.g.
+/// because the remote cancelled it or the connection closed.
+#[derive(Debug)]
+pub struct ResponseSink<TChunk> {
+    pub(crate) sender: mpsc::Sender<TChunk>,
+    pub(crate) finished: Option<oneshot::Sender<()>>,
+}
+
+impl<TChunk> ResponseSink<TChunk> {
+    /// Checks whether the response sink is still open, i.e. the
+    /// `StreamingRequestResponse` behaviour is still sending the
+    /// chunks written to it.
+    pub fn is_open(&self) -> bool {
+        !self.sender.is_closed()
+    }
+}
+
+impl<TChunk> Sink<TChunk> for ResponseSink<TChunk> {
+    type Error = mpsc::SendError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.sender.poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, chunk: TChunk) -> Result<(), Self::Error> {
+        self.sender.start_send(chunk)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.sender).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if let Some(finished) = self.finished.take() {
+            let _ = finished.send(());
+        }
+        Pin::new(&mut self.sender).poll_close(cx)
+    }
+}
+
+/// A stream of the chunks of a response to an outbound request.
+///
+/// The stream ends after the last chunk of a complete response. If the
+/// response fails, e.g. because the responder aborted it, the error is
+/// returned as the last item. If the connection closes before the response
+/// is complete, that error is of kind [`io::ErrorKind::ConnectionAborted`]
+/// and wraps an [`OutboundFailure::ConnectionClosed`]. Dropping the stream
+/// cancels the response.
+#[derive(Debug)]
+pub struct ResponseStream<TChunk> {
+    /// The chunks of the response, followed by `Ok(None)` once it is
+    /// complete or by an error.
+    pub(crate) receiver: mpsc::Receiver<io::Result<Option<TChunk>>>,
+    /// Whether the last item of the stream has been returned.
+    pub(crate) finished: bool,
+}
+
+impl<TChunk> Stream for ResponseStream<TChunk> {
+    type Item = io::Result<TChunk>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.finished {
+            return Poll::Ready(None);
+        }
+        let item = match futures::ready!(self.receiver.poll_next_unpin(cx)) {
+            Some(Ok(Some(chunk))) => return Poll::Ready(Some(Ok(chunk))),
+            Some(Ok(None)) => None,
+            Some(Err(e)) => Some(Err(e)),
+            // The connection handler receiving the response is gone.
+            None => Some(Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                OutboundFailure::ConnectionClosed,
+            ))),
+        };
+        self.finished = true;
+        Poll::Ready(item)
+    }
+}
+
+impl<TChunk> FusedStream for ResponseStream<TChunk> {
+    fn is_terminated(&self) -> bool {
+        self.finished
+    }
+}
+
+/// An action of a [`StreamingRequestResponse`] behaviour to return from `poll`.
+type StreamingRequestResponseAction<TCodec> = NetworkBehaviourAction<
+    StreamingRequestResponseEvent<
+        <TCodec as StreamingCodec>::Request,
+        <TCodec as StreamingCodec>::Chunk,
+    >,
+    StreamingRequestResponseHandler<TCodec>,
+>;
+
+/// A request/response protocol with streamed responses for some codec.
+pub struct StreamingRequestResponse<TCodec>
+where
+    TCodec: StreamingCodec + Clone + Send + 'static,
+{
+    /// The supported inbound protocols.
+    inbound_protocols: SmallVec<[TCodec::Protocol; 2]>,
+    /// The supported outbound protocols.
+    outbound_protocols: SmallVec<[TCodec::Protocol; 2]>,
+    /// The next (local) request ID.
+    next_request_id: RequestId,
+    /// The next (inbound) request ID.
+    next_inbound_id: Arc<AtomicU64>,
+    /// The protocol configuration.
+    config: RequestResponseConfig,
+    /// The protocol codec for reading and writing requests and response chunks.
+    codec: TCodec,
+    /// Pending events to return from `poll`.
+    pending_events: VecDeque<StreamingRequestResponseAction<TCodec>>,
+    /// The connections and addresses of peers and the requests waiting for
+    /// a connection to be established.
+    peers: Peers<StreamingRequestProtocol<TCodec>>,
+}
+
+impl<TCodec> StreamingRequestResponse<TCodec>
+where
+    TCodec: StreamingCodec + Clone + Send + 'static,
+{
+    /// Creates a new `StreamingRequestResponse` behaviour for the given
+    /// protocols, codec and configuration.
+    pub fn new<I>(codec: TCodec, protocols: I, cfg: RequestResponseConfig) -> Self
+    where
+        I: IntoIterator<Item = (TCodec::Protocol, ProtocolSupport)>,
+    {
+        let mut inbound_protocols = SmallVec::new();
+        let mut outbound_protocols = SmallVec::new();
+        for (p, s) in protocols {
+            if s.inbound() {
+                inbound_protocols.push(p.clone());
+            }
+            if s.outbound() {
+                outbound_protocols.push(p.clone());
+            }
+        }
+        StreamingRequestResponse {
+            inbound_protocols,
+            outbound_protocols,
+            next_request_id: RequestId(1),
+            next_inbound_id: Arc::new(AtomicU64::new(1)),
+            config: cfg,
+            codec,
+            pending_events: VecDeque::new(),
+            peers: Peers::new(),
+        }
+    }
+
+    /// Initiates sending a request.
+    ///
+    /// If the targeted peer is currently not connected, a dialing
+    /// attempt is initiated and the request is sent as soon as a
+    /// connection is established.
+    ///
+    /// See [`RequestResponse::send_request`](crate::RequestResponse::send_request)
+    /// for how peers are dialed.
+    pub fn send_request(&mut self, peer: &PeerId, request: TCodec::Request) -> RequestId {
+        let request_id = self.next_request_id;
+        self.next_request_id.0 += 1;
+        let request = StreamingRequestProtocol {
+            request_id,
+            codec: self.codec.clone(),
+            protocols: self.outbound_protocols.clone(),
+            request,
+        };
+
+        if let Some(request) = self.try_send_request(peer, request) {
+            let handler = self.new_handler();
+            let opts = self.peers.dial(peer, request);
+            self.pending_events
+                .push_back(NetworkBehaviourAction::Dial { opts, handler });
+        }
+
+        request_id
+    }
+
+    /// Adds a known address for a peer that can be used for
+    /// dialing attempts by the `Swarm`, i.e. is returned
+    /// by [`NetworkBehaviour::addresses_of_peer`].
+    ///
+    /// Addresses added in this way are only removed by `remove_address`.
+    pub fn add_address(&mut self, peer: &PeerId, address: Multiaddr) {
+        self.peers.add_address(peer, address)
+    }
+
+    /// Removes an address of a peer previously added via `add_address`.
+    pub fn remove_address(&mut self, peer: &PeerId, address: &Multiaddr) {
+        self.peers.remove_address(peer, address)
+    }
+
+    /// Checks whether a peer is currently connected.
+    pub fn is_connected(&self, peer: &PeerId) -> bool {
+        self.peers.is_connected(peer)
+    }
+
+    /// Checks whether an outbound request to the peer with the provided
+    /// [`PeerId`] is still pending, i.e. the response has not started
+    /// to be received yet.
+    pub fn is_pending_outbound(&self, peer: &PeerId, request_id: &RequestId) -> bool {
+        let est_conn = self
+            .peers
+            .connections(peer)
+            .iter()
+            .any(|c| c.pending_inbound_responses.contains(request_id));
+        let pen_conn = self
+            .peers
+            .pending_requests(peer)
+            .iter()
+            .any(|rp| rp.request_id == *request_id);
+
+        est_conn || pen_conn
+    }
+
+    /// Checks whether an inbound request from the peer with the provided
+    /// [`PeerId`] is still pending, i.e. its response has not been sent
+    /// completely.
+    pub fn is_pending_inbound(&self, peer: &PeerId, request_id: &RequestId) -> bool {
+        self.peers
+            .connections(peer)
+            .iter()
+            .any(|c| c.pending_outbound_responses.contains(request_id))
+    }
+
+    /// Tries to send a request by queueing an appropriate event to be
+    /// emitted to the `Swarm`. If the peer is not currently connected,
+    /// the given request is return unchanged.
+    fn try_send_request(
+        &mut self,
+        peer: &PeerId,
+        request: StreamingRequestProtocol<TCodec>,
+    ) -> Option<StreamingRequestProtocol<TCodec>> {
+        match self.peers.connections_mut(peer) {
+            Some(connections) => {
+                let ix = (request.request_id.0 as usize) % connections.len();
+                let conn = &mut connections[ix];
+                conn.pending_inbound_responses.insert(request.request_id);
+                self.pending_events
+                    .push_back(NetworkBehaviourAction::NotifyHandler {
+                        peer_id: *peer,
+                        handler: NotifyHandler::One(conn.id),
+                        event: request,
+                    });
+                None
+            }
+            None => Some(request),
+        }
+    }
+
+    /// Removes the pending outbound response for the given peer and connection,
+    /// emitting the given failure if the response was still pending or a
+    /// `ResponseSent` event if `error` is `None`.
+    fn finish_inbound(
+        &mut self,
+        peer: PeerId,
+        connection: ConnectionId,
+        request_id: RequestId,
+        error: Option<InboundFailure>,
+    ) {
+        // Note: Inbound requests that time out before being received are
+        // never added to `pending_outbound_responses`.
+        if let Some(c) = self.peers.get_connection_mut(&peer, connection) {
+            c.pending_outbound_responses.remove(&request_id);
+        }
+
+        let event = match error {
+            Some(error) => StreamingRequestResponseEvent::InboundFailure {
+                peer,
+                request_id,
+                error,
+            },
+            None => StreamingRequestResponseEvent::ResponseSent { peer, request_id },
+        };
+        self.pending_events
+            .push_back(NetworkBehaviourAction::GenerateEvent(event));
+    }
+
+    /// Removes the pending inbound response for the given peer and connection
+    /// and emits the given failure.
+    fn fail_outbound(
+        &mut self,
+        peer: PeerId,
+        connection: ConnectionId,
+        request_id: RequestId,
+        error: OutboundFailure,
+    ) {
+        let removed = self
+            .peers
+            .get_connection_mut(&peer, connection)
+            .map(|c| c.pending_inbound_responses.remove(&request_id))
+            .unwrap_or(false);
+        debug_assert!(removed, "Expect request_id to be pending before failing.");
+
+        self.pending_events
+            .push_back(NetworkBehaviourAction::GenerateEvent(
+                StreamingRequestResponseEvent::OutboundFailure {
+                    peer,
+                    request_id,
+                    error,
+                },
+            ));
+    }
+}
+
+impl<TCodec> NetworkBehaviour for StreamingRequestResponse<TCodec>
+where
+    TCodec: StreamingCodec + Send + Clone + 'static,
+{
+    type ConnectionHandler = StreamingRequestResponseHandler<TCodec>;
+    type OutEvent = StreamingRequestResponseEvent<TCodec::Request, TCodec::Chunk>;
+
+    fn new_handler(&mut self) -> Self::ConnectionHandler {
+        StreamingRequestResponseHandler::new(
+            self.inbound_protocols.clone(),
+            self.codec.clone(),
+            self.config.connection_keep_alive,
+            self.config.request_timeout,
+            self.next_inbound_id.clone(),
+        )
+    }
+
+    fn addresses_of_peer(&mut self, peer: &PeerId) -> Vec<Multiaddr> {
+        self.peers.addresses_of_peer(peer)
+    }
+
+    fn inject_address_change(
+        &mut self,
+        peer: &PeerId,
+        conn: &ConnectionId,
+        _old: &ConnectedPoint,
+        new: &ConnectedPoint,
+    ) {
+        self.peers.address_change(peer, *conn, new);
+    }
+
+    fn inject_connection_established(
+        &mut self,
+        peer: &PeerId,
+        conn: &ConnectionId,
+        endpoint: &ConnectedPoint,
+        _errors: Option<&Vec<Multiaddr>>,
+        other_established: usize,
+    ) {
+        let pending = self
+            .peers
+            .connection_established(peer, *conn, endpoint, other_established);
+        for request in pending {
+            let request = self.try_send_request(peer, request);
+            assert!(request.is_none());
+        }
+    }
+
+    fn inject_connection_closed(
+        &mut self,
+        peer_id: &PeerId,
+        conn: &ConnectionId,
+        _: &ConnectedPoint,
+        _: <Self::ConnectionHandler as IntoConnectionHandler>::Handler,
+        remaining_established: usize,
+    ) {
+        let connection = self
+            .peers
+            .connection_closed(peer_id, *conn, remaining_established);
+
+        for request_id in connection.pending_outbound_responses {
+            self.pending_events
+                .push_back(NetworkBehaviourAction::GenerateEvent(
+                    StreamingRequestResponseEvent::InboundFailure {
+                        peer: *peer_id,
+                        request_id,
+                        error: InboundFailure::ConnectionClosed,
+                    },
+                ));
+        }
+
+        for request_id in connection.pending_inbound_responses {
+            self.pending_events
+                .push_back(NetworkBehaviourAction::GenerateEvent(
+                    StreamingRequestResponseEvent::OutboundFailure {
+                        peer: *peer_id,
+                        request_id,
+                        error: OutboundFailure::ConnectionClosed,
+                    },
+                ));
+        }
+    }
+
+    fn inject_dial_failure(
+        &mut self,
+        peer: Option<PeerId>,
+        _: Self::ConnectionHandler,
+        _: &DialError,
+    ) {
+        if let Some(peer) = peer {
+            for request in self.peers.dial_failed(&peer) {
+                self.pending_events
+                    .push_back(NetworkBehaviourAction::GenerateEvent(
+                        StreamingRequestResponseEvent::OutboundFailure {
+                            peer,
+                            request_id: request.request_id,
+                            error: OutboundFailure::DialFailure,
+                        },
+                    ));
+            }
+        }
+    }
+
+    fn inject_event(
+        &mut self,
+        peer: PeerId,
+        connection: ConnectionId,
+        event: StreamingRequestResponseHandlerEvent<TCodec>,
+    ) {
+        match event {
+            StreamingRequestResponseHandlerEvent::Response { request_id, stream } => {
+                let removed = self
+                    .peers
+                    .get_connection_mut(&peer, connection)
+                    .map(|c| c.pending_inbound_responses.remove(&request_id))
+                    .unwrap_or(false);
+                debug_assert!(
+                    removed,
+                    "Expect request_id to be pending before receiving response.",
+                );
+
+                let message = StreamingRequestResponseMessage::Response { request_id, stream };
+                self.pending_events
+                    .push_back(NetworkBehaviourAction::GenerateEvent(
+                        StreamingRequestResponseEvent::Message { peer, message },
+                    ));
+            }
+            StreamingRequestResponseHandlerEvent::Request {
+                request_id,
+                request,
+                sink,
+            } => {
+                let message = StreamingRequestResponseMessage::Request {
+                    request_id,
+                    request,
+                    sink,
+                };
+                self.pending_events
+                    .push_back(NetworkBehaviourAction::GenerateEvent(
+                        StreamingRequestResponseEvent::Message { peer, message },
+                    ));
+
+                match self.peers.get_connection_mut(&peer, connection) {
+                    Some(connection) => {
+                        let inserted = connection.pending_outbound_responses.insert(request_id);
+                        debug_assert!(inserted, "Expect id of new request to be unknown.");
+                    }
+                    // Connection closed after `StreamingRequestResponseEvent::Request` has been emitted.
+                    None => {
+                        self.pending_events
+                            .push_back(NetworkBehaviourAction::GenerateEvent(
+                                StreamingRequestResponseEvent::InboundFailure {
+                                    peer,
+                                    request_id,
+                                    error: InboundFailure::ConnectionClosed,
+                                },
+                            ));
+                    }
+                }
+            }
+            StreamingRequestResponseHandlerEvent::ResponseSent(request_id) => {
+                self.finish_inbound(peer, connection, request_id, None)
+            }
+            StreamingRequestResponseHandlerEvent::ResponseOmission(request_id) => self
+                .finish_inbound(
+                    peer,
+                    connection,
+                    request_id,
+                    Some(InboundFailure::ResponseOmission),
+                ),
+            StreamingRequestResponseHandlerEvent::ResponseAborted(request_id) => self
+                .finish_inbound(
+                    peer,
+                    connection,
+                    request_id,
+                    Some(InboundFailure::ResponseAborted),
+                ),
+            StreamingRequestResponseHandlerEvent::InboundTimeout(request_id) => {
+                self.finish_inbound(peer, connection, request_id, Some(InboundFailure::Timeout))
+            }
+            StreamingRequestResponseHandlerEvent::InboundUnsupportedProtocols(request_id) => self
+                .finish_inbound(
+                    peer,
+                    connection,
+                    request_id,
+                    Some(InboundFailure::UnsupportedProtocols),
+                ),
+            StreamingRequestResponseHandlerEvent::OutboundTimeout(request_id) => {
+                self.fail_outbound(peer, connection, request_id, OutboundFailure::Timeout)
+            }
+            StreamingRequestResponseHandlerEvent::OutboundUnsupportedProtocols(request_id) => self
+                .fail_outbound(
+                    peer,
+                    connection,
+                    request_id,
+                    OutboundFailure::UnsupportedProtocols,
+                ),
+        }
+    }
+
+    fn poll(
+        &mut self,
+        _: &mut Context<'_>,
+        _: &mut impl PollParameters,
+    ) -> Poll<NetworkBehaviourAction<Self::OutEvent, Self::ConnectionHandler>> {
+        if let Some(ev) = self.pending_events.pop_front() {
+            return Poll::Ready(ev);
+        } else if self.pending_events.capacity() > EMPTY_QUEUE_SHRINK_THRESHOLD {
+            self.pending_events.shrink_to_fit();
+        }
+
+        Poll::Pending
+    }
+}
@@ -0,0 +1,529 @@
+// Copyright 2022 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+mod protocol;
+
+use super::{ResponseSink, ResponseStream};
+use crate::codec::StreamingCodec;
+use crate::{RequestId, EMPTY_QUEUE_SHRINK_THRESHOLD};
+
+pub use protocol::{StreamingRequestProtocol, StreamingResponseProtocol};
+
+use futures::{
+    channel::{mpsc, oneshot},
+    future::{self, BoxFuture, Either},
+    prelude::*,
+    stream::FuturesUnordered,
+};
+use futures_timer::Delay;
+use instant::Instant;
+use libp2p_core::upgrade::{NegotiationError, UpgradeError};
+use libp2p_swarm::{
+    handler::{ConnectionHandler, ConnectionHandlerEvent, ConnectionHandlerUpgrErr, KeepAlive},
+    NegotiatedSubstream, SubstreamProtocol,
+};
+use smallvec::SmallVec;
+use std::{
+    collections::VecDeque,
+    fmt, io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// The number of response chunks buffered between the application and
+/// a substream before applying backpressure.
+const CHUNK_BUFFER_SIZE: usize = 8;
+
+/// Frame tag preceding every response chunk on a substream.
+const FRAME_CHUNK: u8 = 0;
+/// Frame tag marking the end of a complete response on a substream.
+///
+/// A substream that ends without it carries an aborted response.
+const FRAME_END: u8 = 1;
+/// Frame tag sent by the requester after the request to cancel the response.
+const FRAME_CANCEL: u8 = 2;
+
+/// A connection handler of a `StreamingRequestResponse` protocol.
+#[doc(hidden)]
+pub struct StreamingRequestResponseHandler<TCodec>
+where
+    TCodec: StreamingCodec,
+{
+    /// The supported inbound protocols.
+    inbound_protocols: SmallVec<[TCodec::Protocol; 2]>,
+    /// The request/response message codec.
+    codec: TCodec,
+    /// The keep-alive timeout of idle connections. A connection is considered
+    /// idle if there are no outbound substreams and no streamed responses.
+    keep_alive_timeout: Duration,
+    /// The timeout for the request on inbound and outbound substreams and
+    /// for each response chunk thereafter.
+    substream_timeout: Duration,
+    /// The current connection keep-alive.
+    keep_alive: KeepAlive,
+    /// A pending fatal error that results in the connection being closed.
+    pending_error: Option<ConnectionHandlerUpgrErr<io::Error>>,
+    /// Queue of events to emit in `poll()`.
+    pending_events: VecDeque<StreamingRequestResponseHandlerEvent<TCodec>>,
+    /// Outbound upgrades waiting to be emitted as an `OutboundSubstreamRequest`.
+    outbound: VecDeque<StreamingRequestProtocol<TCodec>>,
+    /// Responses currently sent or received on negotiated substreams,
+    /// optionally resolving to an event once they are done.
+    streams:
+        FuturesUnordered<BoxFuture<'static, Option<StreamingRequestResponseHandlerEvent<TCodec>>>>,
+    inbound_request_id: Arc<AtomicU64>,
+}
+
+impl<TCodec> StreamingRequestResponseHandler<TCodec>
+where
+    TCodec: StreamingCodec,
+{
+    pub(super) fn new(
+        inbound_protocols: SmallVec<[TCodec::Protocol; 2]>,
+        codec: TCodec,
+        keep_alive_timeout: Duration,
+        substream_timeout: Duration,
+        inbound_request_id: Arc<AtomicU64>,
+    ) -> Self {
+        Self {
+            inbound_protocols,
+            codec,
+            keep_alive: KeepAlive::Yes,
+            keep_alive_timeout,
+            substream_timeout,
+            outbound: VecDeque::new(),
+            streams: FuturesUnordered::new(),
+            pending_events: VecDeque::new(),
+            pending_error: None,
+            inbound_request_id,
+        }
+    }
+}
+
+/// The events emitted by the [`StreamingRequestResponseHandler`].
+#[doc(hidden)]
+pub enum StreamingRequestResponseHandlerEvent<TCodec>
+where
+    TCodec: StreamingCodec,
+{
+    /// A request has been received.
+    Request {
+        request_id: RequestId,
+        request: TCodec::Request,
+        sink: ResponseSink<TCodec::Chunk>,
+    },
+    /// A response has started to be received.
+    Response {
+        request_id: RequestId,
+        stream: ResponseStream<TCodec::Chunk>,
+    },
+    /// A response to an inbound request has been sent completely.
+    ResponseSent(RequestId),
+    /// A response to an inbound request was omitted as a result
+    /// of dropping the [`ResponseSink`] of an inbound `Request`
+    /// without closing it.
+    ResponseOmission(RequestId),
+    /// A response to an inbound request failed while being sent.
+    ResponseAborted(RequestId),
+    /// An outbound request timed out while sending the request.
+    OutboundTimeout(RequestId),
+    /// An outbound request failed to negotiate a mutually supported protocol.
+    OutboundUnsupportedProtocols(RequestId),
+    /// An inbound request timed out while waiting for the request
+    /// or for the next response chunk.
+    InboundTimeout(RequestId),
+    /// An inbound request failed to negotiate a mutually supported protocol.
+    InboundUnsupportedProtocols(RequestId),
+}
+
+impl<TCodec: StreamingCodec> fmt::Debug for StreamingRequestResponseHandlerEvent<TCodec> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamingRequestResponseHandlerEvent::Request { request_id, .. } => f
+                .debug_struct("StreamingRequestResponseHandlerEvent::Request")
+                .field("request_id", request_id)
+                .finish(),
+            StreamingRequestResponseHandlerEvent::Response { request_id, .. } => f
+                .debug_struct("StreamingRequestResponseHandlerEvent::Response")
+                .field("request_id", request_id)
+                .finish(),
+            StreamingRequestResponseHandlerEvent::ResponseSent(request_id) => f
+                .debug_tuple("StreamingRequestResponseHandlerEvent::ResponseSent")
+                .field(request_id)
+                .finish(),
+            StreamingRequestResponseHandlerEvent::ResponseOmission(request_id) => f
+                .debug_tuple("StreamingRequestResponseHandlerEvent::ResponseOmission")
+                .field(request_id)
+                .finish(),
+            StreamingRequestResponseHandlerEvent::ResponseAborted(request_id) => f
+                .debug_tuple("StreamingRequestResponseHandlerEvent::ResponseAborted")
+                .field(request_id)
+                .finish(),
+            StreamingRequestResponseHandlerEvent::OutboundTimeout(request_id) => f
+                .debug_tuple("StreamingRequestResponseHandlerEvent::OutboundTimeout")
+                .field(request_id)
+                .finish(),
+            StreamingRequestResponseHandlerEvent::OutboundUnsupportedProtocols(request_id) => f
+                .debug_tuple("StreamingRequestResponseHandlerEvent::OutboundUnsupportedProtocols")
+                .field(request_id)
+                .finish(),
+            StreamingRequestResponseHandlerEvent::InboundTimeout(request_id) => f
+                .debug_tuple("StreamingRequestResponseHandlerEvent::InboundTimeout")
+                .field(request_id)
+                .finish(),
+            StreamingRequestResponseHandlerEvent::InboundUnsupportedProtocols(request_id) => f
+                .debug_tuple("StreamingRequestResponseHandlerEvent::InboundUnsupportedProtocols")
+                .field(request_id)
+                .finish(),
+        }
+    }
+}
+
+impl<TCodec> ConnectionHandler for StreamingRequestResponseHandler<TCodec>
+where
+    TCodec: StreamingCodec + Send + Clone + 'static,
+{
+    type InEvent = StreamingRequestProtocol<TCodec>;
+    type OutEvent = StreamingRequestResponseHandlerEvent<TCodec>;
+    type Error = ConnectionHandlerUpgrErr<io::Error>;
+    type InboundProtocol = StreamingResponseProtocol<TCodec>;
+    type OutboundProtocol = StreamingRequestProtocol<TCodec>;
+    type OutboundOpenInfo = RequestId;
+    type InboundOpenInfo = RequestId;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        let request_id = RequestId(self.inbound_request_id.fetch_add(1, Ordering::Relaxed));
+
+        let proto = StreamingResponseProtocol {
+            protocols: self.inbound_protocols.clone(),
+            codec: self.codec.clone(),
+        };
+
+        // The timeout only covers receiving the request. The response is
+        // subject to a timeout per chunk once the upgrade is done.
+        SubstreamProtocol::new(proto, request_id).with_timeout(self.substream_timeout)
+    }
+
+    fn inject_fully_negotiated_inbound(
+        &mut self,
+        (request, io, protocol): (TCodec::Request, NegotiatedSubstream, TCodec::Protocol),
+        request_id: RequestId,
+    ) {
+        let (sender, receiver) = mpsc::channel(CHUNK_BUFFER_SIZE);
+        let (finished_sender, finished_receiver) = oneshot::channel();
+        let send = send_response(
+            self.codec.clone(),
+            protocol,
+            io,
+            receiver,
+            finished_receiver,
+            self.substream_timeout,
+        );
+        self.streams.push(
+            send.map(move |result| {
+                Some(match result {
+                    Ok(true) => StreamingRequestResponseHandlerEvent::ResponseSent(request_id),
+                    Ok(false) => StreamingRequestResponseHandlerEvent::ResponseOmission(request_id),
+                    Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                        StreamingRequestResponseHandlerEvent::InboundTimeout(request_id)
+                    }
+                    Err(e) => {
+                        log::debug!("Failed to send response to request {}: {}", request_id, e);
+                        StreamingRequestResponseHandlerEvent::ResponseAborted(request_id)
+                    }
+                })
+            })
+            .boxed(),
+        );
+
+        self.keep_alive = KeepAlive::Yes;
+        self.pending_events
+            .push_back(StreamingRequestResponseHandlerEvent::Request {
+                request_id,
+                request,
+                sink: ResponseSink {
+                    sender,
+                    finished: Some(finished_sender),
+                },
+            });
+    }
+
+    fn inject_fully_negotiated_outbound(
+        &mut self,
+        (io, protocol): (NegotiatedSubstream, TCodec::Protocol),
+        request_id: RequestId,
+    ) {
+        let (sender, receiver) = mpsc::channel(CHUNK_BUFFER_SIZE);
+        let receive = receive_response(
+            self.codec.clone(),
+            protocol,
+            io,
+            sender,
+            self.substream_timeout,
+        );
+        self.streams.push(receive.map(|()| None).boxed());
+
+        self.pending_events
+            .push_back(StreamingRequestResponseHandlerEvent::Response {
+                request_id,
+                stream: ResponseStream {
+                    receiver,
+                    finished: false,
+                },
+            });
+    }
+
+    fn inject_event(&mut self, request: Self::InEvent) {
+        self.keep_alive = KeepAlive::Yes;
+        self.outbound.push_back(request);
+    }
+
+    fn inject_dial_upgrade_error(
+        &mut self,
+        info: RequestId,
+        error: ConnectionHandlerUpgrErr<io::Error>,
+    ) {
+        match error {
+            ConnectionHandlerUpgrErr::Timeout => {
+                self.pending_events
+                    .push_back(StreamingRequestResponseHandlerEvent::OutboundTimeout(info));
+            }
+            ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Select(NegotiationError::Failed)) => {
+                // The remote merely doesn't support the protocol(s) we requested.
+                self.pending_events.push_back(
+                    StreamingRequestResponseHandlerEvent::OutboundUnsupportedProtocols(info),
+                );
+            }
+            _ => {
+                // Anything else is considered a fatal error or misbehaviour of
+                // the remote peer and results in closing the connection.
+                self.pending_error = Some(error);
+            }
+        }
+    }
+
+    fn inject_listen_upgrade_error(
+        &mut self,
+        info: RequestId,
+        error: ConnectionHandlerUpgrErr<io::Error>,
+    ) {
+        match error {
+            ConnectionHandlerUpgrErr::Timeout => self
+                .pending_events
+                .push_back(StreamingRequestResponseHandlerEvent::InboundTimeout(info)),
+            ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Select(NegotiationError::Failed)) => {
+                // The local peer merely doesn't support the protocol(s) requested.
+                self.pending_events.push_back(
+                    StreamingRequestResponseHandlerEvent::InboundUnsupportedProtocols(info),
+                );
+            }
+            _ => {
+                // Anything else is considered a fatal error or misbehaviour of
+                // the remote peer and results in closing the connection.
+                self.pending_error = Some(error);
+            }
+        }
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        self.keep_alive
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<
+        ConnectionHandlerEvent<
+            StreamingRequestProtocol<TCodec>,
+            RequestId,
+            Self::OutEvent,
+            Self::Error,
+        >,
+    > {
+        // Check for a pending (fatal) error.
+        if let Some(err) = self.pending_error.take() {
+            // The handler will not be polled again by the `Swarm`.
+            return Poll::Ready(ConnectionHandlerEvent::Close(err));
+        }
+
+        // Drain pending events.
+        if let Some(event) = self.pending_events.pop_front() {
+            return Poll::Ready(ConnectionHandlerEvent::Custom(event));
+        } else if self.pending_events.capacity() > EMPTY_QUEUE_SHRINK_THRESHOLD {
+            self.pending_events.shrink_to_fit();
+        }
+
+        // Drive the streamed responses.
+        while let Poll::Ready(Some(event)) = self.streams.poll_next_unpin(cx) {
+            if let Some(event) = event {
+                return Poll::Ready(ConnectionHandlerEvent::Custom(event));
+            }
+        }
+
+        // Emit outbound requests.
+        if let Some(request) = self.outbound.pop_front() {
+            let info = request.request_id;
+            return Poll::Ready(ConnectionHandlerEvent::OutboundSubstreamRequest {
+                protocol: SubstreamProtocol::new(request, info)
+                    .with_timeout(self.substream_timeout),
+            });
+        }
+
+        debug_assert!(self.outbound.is_empty());
+
+        if self.outbound.capacity() > EMPTY_QUEUE_SHRINK_THRESHOLD {
+            self.outbound.shrink_to_fit();
+        }
+
+        if self.streams.is_empty() && self.keep_alive.is_yes() {
+            // No new inbound or outbound requests and no streamed responses.
+            // However, we may just have started the latest inbound or outbound
+            // upgrade(s), so make sure the keep-alive timeout is preceded by
+            // the substream timeout.
+            let until = Instant::now() + self.substream_timeout + self.keep_alive_timeout;
+            self.keep_alive = KeepAlive::Until(until);
+        }
+
+        Poll::Pending
+    }
+}
+
+/// Writes the chunks received from a [`ResponseSink`] to the substream until
+/// the response is complete or cancelled by the remote.
+///
+/// Returns `true` if the response was sent completely and `false` if the sink
+/// was dropped without being closed, in which case the substream is closed
+/// without an end frame for the remote to notice the aborted response.
+async fn send_response<TCodec>(
+    mut codec: TCodec,
+    protocol: TCodec::Protocol,
+    io: NegotiatedSubstream,
+    mut chunks: mpsc::Receiver<TCodec::Chunk>,
+    mut finished: oneshot::Receiver<()>,
+    timeout: Duration,
+) -> io::Result<bool>
+where
+    TCodec: StreamingCodec + Send,
+{
+    let (mut reader, mut writer) = io.split();
+
+    let send = async move {
+        while let Some(chunk) = with_timeout(timeout, chunks.next()).await? {
+            with_timeout(timeout, writer.write_all(&[FRAME_CHUNK])).await??;
+            with_timeout(timeout, codec.write_chunk(&protocol, &mut writer, chunk)).await??;
+            with_timeout(timeout, writer.flush()).await??;
+        }
+
+        let complete = matches!(finished.try_recv(), Ok(Some(())));
+        if complete {
+            with_timeout(timeout, writer.write_all(&[FRAME_END])).await??;
+        }
+        with_timeout(timeout, writer.close()).await??;
+
+        Ok(complete)
+    };
+
+    let cancel = async move {
+        let mut tag = [0];
+        match reader.read(&mut tag).await {
+            Ok(1) if tag[0] == FRAME_CANCEL => {}
+            // The remote closed its side of the substream, which it may do
+            // right after sending the request, or the substream failed, which
+            // surfaces when sending the response.
+            _ => future::pending().await,
+        }
+    };
+
+    futures::pin_mut!(send, cancel);
+    match future::select(send, cancel).await {
+        Either::Left((result, _)) => result,
+        Either::Right(((), _)) => Err(io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            "The remote cancelled the response",
+        )),
+    }
+}
+
+/// Reads the chunks of a response from the substream into a [`ResponseStream`].
+///
+/// Once the [`ResponseStream`] is dropped, the response is cancelled by
+/// telling the remote to stop sending it. The end of the response or an
+/// error is passed on as the last item of the stream.
+async fn receive_response<TCodec>(
+    mut codec: TCodec,
+    protocol: TCodec::Protocol,
+    mut io: NegotiatedSubstream,
+    mut chunks: mpsc::Sender<io::Result<Option<TCodec::Chunk>>>,
+    timeout: Duration,
+) where
+    TCodec: StreamingCodec + Send,
+{
+    let cancelled = loop {
+        let mut tag = [0];
+        let read = match with_timeout(timeout, io.read_exact(&mut tag)).await {
+            Ok(Ok(())) => match tag[0] {
+                FRAME_CHUNK => with_timeout(timeout, codec.read_chunk(&protocol, &mut io))
+                    .await
+                    .and_then(|read| read.map(Some)),
+                FRAME_END => Ok(None),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Unknown response frame",
+                )),
+            },
+            Ok(Err(e)) | Err(e) => Err(e),
+        };
+        match read {
+            Ok(Some(chunk)) => {
+                if chunks.send(Ok(Some(chunk))).await.is_err() {
+                    // The response stream has been dropped.
+                    break true;
+                }
+            }
+            Ok(None) => {
+                let _ = chunks.send(Ok(None)).await;
+                break false;
+            }
+            Err(e) => {
+                let _ = chunks.send(Err(e)).await;
+                break false;
+            }
+        }
+    };
+
+    if cancelled {
+        let _ = with_timeout(timeout, io.write_all(&[FRAME_CANCEL])).await;
+    }
+    let _ = with_timeout(timeout, io.close()).await;
+}
+
+/// Awaits the given future, failing with [`io::ErrorKind::TimedOut`] if it
+/// does not complete within `timeout`.
+async fn with_timeout<F: Future>(timeout: Duration, future: F) -> io::Result<F::Output> {
+    futures::pin_mut!(future);
+    match future::select(future, Delay::new(timeout)).await {
+        Either::Left((output, _)) => Ok(output),
+        Either::Right(((), _)) => Err(io::ErrorKind::TimedOut.into()),
+    }
+}
@@ -0,0 +1,140 @@
+// Copyright 2022 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The definition of a streaming request/response protocol via inbound
+//! and outbound substream upgrades. The inbound upgrade receives a request
+//! and the outbound upgrade sends a request. Both yield the substream on
+//! which the response chunks are subsequently streamed.
+
+use crate::codec::StreamingCodec;
+use crate::RequestId;
+
+use futures::{future::BoxFuture, prelude::*};
+use libp2p_core::upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
+use libp2p_swarm::NegotiatedSubstream;
+use smallvec::SmallVec;
+use std::{fmt, io};
+
+/// Response substream upgrade protocol.
+///
+/// Receives a request and returns the substream to send the response on.
+#[derive(Debug)]
+pub struct StreamingResponseProtocol<TCodec>
+where
+    TCodec: StreamingCodec,
+{
+    pub(crate) codec: TCodec,
+    pub(crate) protocols: SmallVec<[TCodec::Protocol; 2]>,
+}
+
+impl<TCodec> UpgradeInfo for StreamingResponseProtocol<TCodec>
+where
+    TCodec: StreamingCodec,
+{
+    type Info = TCodec::Protocol;
+    type InfoIter = smallvec::IntoIter<[Self::Info; 2]>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.protocols.clone().into_iter()
+    }
+}
+
+impl<TCodec> InboundUpgrade<NegotiatedSubstream> for StreamingResponseProtocol<TCodec>
+where
+    TCodec: StreamingCodec + Send + 'static,
+{
+    type Output = (TCodec::Request, NegotiatedSubstream, TCodec::Protocol);
+    type Error = io::Error;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(
+        mut self,
+        mut io: NegotiatedSubstream,
+        protocol: Self::Info,
+    ) -> Self::Future {
+        async move {
+            let read = self.codec.read_request(&protocol, &mut io);
+            let request = read.await?;
+            Ok((request, io, protocol))
+        }
+        .boxed()
+    }
+}
+
+/// Request substream upgrade protocol.
+///
+/// Sends a request and returns the substream to receive the response on.
+pub struct StreamingRequestProtocol<TCodec>
+where
+    TCodec: StreamingCodec,
+{
+    pub(crate) codec: TCodec,
+    pub(crate) protocols: SmallVec<[TCodec::Protocol; 2]>,
+    pub(crate) request_id: RequestId,
+    pub(crate) request: TCodec::Request,
+}
+
+impl<TCodec> fmt::Debug for StreamingRequestProtocol<TCodec>
+where
+    TCodec: StreamingCodec,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamingRequestProtocol")
+            .field("request_id", &self.request_id)
+            .finish()
+    }
+}
+
+impl<TCodec> UpgradeInfo for StreamingRequestProtocol<TCodec>
+where
+    TCodec: StreamingCodec,
+{
+    type Info = TCodec::Protocol;
+    type InfoIter = smallvec::IntoIter<[Self::Info; 2]>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.protocols.clone().into_iter()
+    }
+}
+
+impl<TCodec> OutboundUpgrade<NegotiatedSubstream> for StreamingRequestProtocol<TCodec>
+where
+    TCodec: StreamingCodec + Send + 'static,
+{
+    type Output = (NegotiatedSubstream, TCodec::Protocol);
+    type Error = io::Error;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(
+        mut self,
+        mut io: NegotiatedSubstream,
+        protocol: Self::Info,
+    ) -> Self::Future {
+        async move {
+            let write = self.codec.write_request(&protocol, &mut io, self.request);
+            write.await?;
+            // The substream is not closed, as it may still be used to
+            // cancel the response.
+            io.flush().await?;
+            Ok((io, protocol))
+        }
+        .boxed()
+    }
+}
@@ -31,10 +31,16 @@ use libp2p_core::{
 };
 use libp2p_noise::{Keypair, NoiseConfig, X25519Spec};
 use libp2p_request_response::*;
-use libp2p_swarm::{Swarm, SwarmEvent};
+use libp2p_swarm::{
+    dial_opts::{DialOpts, PeerCondition},
+    Swarm, SwarmEvent,
+};
 use libp2p_tcp::TcpConfig;
 use rand::{self, Rng};
-use std::{io, iter};
+use std::{
+    io, iter,
+    time::{Duration, Instant},
+};
 
 #[test]
 fn is_response_outbound() {
@@ -292,8 +298,481 @@ fn emits_inbound_connection_closed_if_channel_is_dropped() {
     });
 }
 
+/// Inbound requests exceeding the inbound request limit of the peer are
+/// rejected without a response.
+///
+/// Like for a dropped response channel, the requester closes the connection
+/// upon the missing response.
+#[test]
+fn rejects_inbound_requests_over_peer_limit() {
+    let mut cfg1 = RequestResponseConfig::default();
+    cfg1.set_max_inbound_requests_per_peer(1);
+    rejects_inbound_requests_over_limit(cfg1);
+}
+
+/// Inbound requests exceeding the inbound request limit of the connection
+/// are rejected by the connection handler without reading them.
+#[test]
+fn rejects_inbound_requests_over_connection_limit() {
+    let mut cfg1 = RequestResponseConfig::default();
+    cfg1.set_max_inbound_requests_per_connection(1);
+    rejects_inbound_requests_over_limit(cfg1);
+}
+
+fn rejects_inbound_requests_over_limit(cfg1: RequestResponseConfig) {
+    let ping = Ping("ping".to_string().into_bytes());
+
+    let protocols = iter::once((PingProtocol(), ProtocolSupport::Full));
+
+    let (peer1_id, trans) = mk_transport();
+    let ping_proto1 = RequestResponse::new(PingCodec(), protocols.clone(), cfg1);
+    let mut swarm1 = Swarm::new(trans, ping_proto1, peer1_id);
+
+    let (peer2_id, trans) = mk_transport();
+    let ping_proto2 =
+        RequestResponse::new(PingCodec(), protocols, RequestResponseConfig::default());
+    let mut swarm2 = Swarm::new(trans, ping_proto2, peer2_id);
+
+    let addr = "/ip4/127.0.0.1/tcp/0".parse().unwrap();
+    swarm1.listen_on(addr).unwrap();
+
+    futures::executor::block_on(async move {
+        while swarm1.next().now_or_never().is_some() {}
+        let addr1 = Swarm::listeners(&swarm1).next().unwrap();
+
+        swarm2.behaviour_mut().add_address(&peer1_id, addr1.clone());
+        swarm2.behaviour_mut().send_request(&peer1_id, ping.clone());
+
+        let mut channel = None;
+        let mut rejected = false;
+        let mut failures = 0;
+        let mut send_second = false;
+
+        while !rejected || failures < 2 {
+            if std::mem::take(&mut send_second) {
+                swarm2.behaviour_mut().send_request(&peer1_id, ping.clone());
+            }
+
+            futures::select!(
+                event = swarm1.select_next_some() => match event {
+                    SwarmEvent::Behaviour(RequestResponseEvent::Message {
+                        peer,
+                        message: RequestResponseMessage::Request { channel: c, .. },
+                    }) => {
+                        assert_eq!(&peer, &peer2_id);
+                        // Hold on to the channel of the first request and send
+                        // the second one on the same connection.
+                        assert!(channel.replace(c).is_none());
+                        send_second = true;
+                    }
+                    SwarmEvent::Behaviour(RequestResponseEvent::InboundFailure {
+                        peer,
+                        error: InboundFailure::TooManyRequests,
+                        ..
+                    }) => {
+                        assert_eq!(&peer, &peer2_id);
+                        assert!(channel.is_some());
+                        rejected = true;
+                    }
+                    SwarmEvent::Behaviour(RequestResponseEvent::InboundFailure {
+                        error: InboundFailure::ConnectionClosed,
+                        ..
+                    }) => assert!(rejected),
+                    SwarmEvent::Behaviour(e) => panic!("Peer1: Unexpected event: {:?}", e),
+                    _ => {}
+                },
+                event = swarm2.select_next_some() => match event {
+                    SwarmEvent::Behaviour(RequestResponseEvent::OutboundFailure {
+                        error, ..
+                    }) => {
+                        assert_eq!(error, OutboundFailure::ConnectionClosed);
+                        failures += 1;
+                    }
+                    SwarmEvent::Behaviour(e) => panic!("Peer2: Unexpected event: {:?}", e),
+                    _ => {}
+                },
+            )
+        }
+    });
+}
+
+/// Outbound requests exceeding the outbound request limit of the peer are
+/// queued, and fail once the queue is full.
+#[test]
+fn queues_outbound_requests_over_limit() {
+    let ping = Ping("ping".to_string().into_bytes());
+    let pong = Pong("pong".to_string().into_bytes());
+
+    let protocols = iter::once((PingProtocol(), ProtocolSupport::Full));
+    let mut cfg2 = RequestResponseConfig::default();
+    cfg2.set_max_outbound_requests_per_peer(1)
+        .set_max_queued_outbound_requests(1);
+
+    let (peer1_id, trans) = mk_transport();
+    let ping_proto1 = RequestResponse::new(
+        PingCodec(),
+        protocols.clone(),
+        RequestResponseConfig::default(),
+    );
+    let mut swarm1 = Swarm::new(trans, ping_proto1, peer1_id);
+
+    let (peer2_id, trans) = mk_transport();
+    let ping_proto2 = RequestResponse::new(PingCodec(), protocols, cfg2);
+    let mut swarm2 = Swarm::new(trans, ping_proto2, peer2_id);
+
+    let addr = "/ip4/127.0.0.1/tcp/0".parse().unwrap();
+    swarm1.listen_on(addr).unwrap();
+
+    futures::executor::block_on(async move {
+        while swarm1.next().now_or_never().is_some() {}
+        let addr1 = Swarm::listeners(&swarm1).next().unwrap();
+
+        swarm2.behaviour_mut().add_address(&peer1_id, addr1.clone());
+        let request_ids = (0..3)
+            .map(|_| swarm2.behaviour_mut().send_request(&peer1_id, ping.clone()))
+            .collect::<Vec<_>>();
+
+        let mut responses = Vec::new();
+        let mut failures = Vec::new();
+
+        while responses.len() < 2 || failures.is_empty() {
+            futures::select!(
+                event = swarm1.select_next_some() => match event {
+                    SwarmEvent::Behaviour(RequestResponseEvent::Message {
+                        message: RequestResponseMessage::Request { channel, .. },
+                        ..
+                    }) => {
+                        swarm1
+                            .behaviour_mut()
+                            .send_response(channel, pong.clone())
+                            .unwrap();
+                    }
+                    SwarmEvent::Behaviour(RequestResponseEvent::ResponseSent { .. }) => {}
+                    SwarmEvent::Behaviour(e) => panic!("Peer1: Unexpected event: {:?}", e),
+                    _ => {}
+                },
+                event = swarm2.select_next_some() => match event {
+                    SwarmEvent::ConnectionEstablished { .. } => {
+                        // The second request is queued until the first completes.
+                        assert!(swarm2
+                            .behaviour()
+                            .is_pending_outbound(&peer1_id, &request_ids[1]));
+                    }
+                    SwarmEvent::Behaviour(RequestResponseEvent::Message {
+                        message: RequestResponseMessage::Response { request_id, .. },
+                        ..
+                    }) => responses.push(request_id),
+                    SwarmEvent::Behaviour(RequestResponseEvent::OutboundFailure {
+                        request_id,
+                        error,
+                        ..
+                    }) => {
+                        assert_eq!(error, OutboundFailure::QueueFull);
+                        failures.push(request_id);
+                    }
+                    SwarmEvent::Behaviour(e) => panic!("Peer2: Unexpected event: {:?}", e),
+                    _ => {}
+                },
+            )
+        }
+
+        assert_eq!(responses, request_ids[..2]);
+        assert_eq!(failures, request_ids[2..]);
+    });
+}
+
+/// Requests sent with `send_request_to_any` fail over to the next candidate
+/// peer, which is reported with the response.
+#[test]
+fn send_request_to_any_fails_over() {
+    let ping = Ping("ping".to_string().into_bytes());
+    let pong = Pong("pong".to_string().into_bytes());
+    let offline_peer = PeerId::random();
+
+    let protocols = iter::once((PingProtocol(), ProtocolSupport::Full));
+    let cfg = RequestResponseConfig::default();
+
+    let (peer1_id, trans) = mk_transport();
+    let ping_proto1 = RequestResponse::new(PingCodec(), protocols.clone(), cfg.clone());
+    let mut swarm1 = Swarm::new(trans, ping_proto1, peer1_id);
+
+    let (peer2_id, trans) = mk_transport();
+    let ping_proto2 = RequestResponse::new(PingCodec(), protocols, cfg);
+    let mut swarm2 = Swarm::new(trans, ping_proto2, peer2_id);
+
+    let addr = "/ip4/127.0.0.1/tcp/0".parse().unwrap();
+    swarm1.listen_on(addr).unwrap();
+
+    futures::executor::block_on(async move {
+        while swarm1.next().now_or_never().is_some() {}
+        let addr1 = Swarm::listeners(&swarm1).next().unwrap();
+
+        swarm2.behaviour_mut().add_address(&peer1_id, addr1.clone());
+        let request_id = swarm2
+            .behaviour_mut()
+            .send_request_to_any([offline_peer, peer1_id], ping.clone());
+
+        loop {
+            futures::select!(
+                event = swarm1.select_next_some() => match event {
+                    SwarmEvent::Behaviour(RequestResponseEvent::Message {
+                        message: RequestResponseMessage::Request { request, channel, .. },
+                        ..
+                    }) => {
+                        assert_eq!(&request, &ping);
+                        swarm1
+                            .behaviour_mut()
+                            .send_response(channel, pong.clone())
+                            .unwrap();
+                    }
+                    SwarmEvent::Behaviour(RequestResponseEvent::ResponseSent { .. }) => {}
+                    SwarmEvent::Behaviour(e) => panic!("Peer1: Unexpected event: {:?}", e),
+                    _ => {}
+                },
+                event = swarm2.select_next_some() => match event {
+                    SwarmEvent::Behaviour(RequestResponseEvent::Message {
+                        peer,
+                        message: RequestResponseMessage::Response { request_id: id, response },
+                    }) => {
+                        assert_eq!(&peer, &peer1_id);
+                        assert_eq!(id, request_id);
+                        assert_eq!(&response, &pong);
+                        break;
+                    }
+                    SwarmEvent::Behaviour(e) => panic!("Peer2: Unexpected event: {:?}", e),
+                    _ => {}
+                },
+            )
+        }
+    });
+}
+
+/// Failed requests are retried with backoff before the failure is reported.
+#[test]
+fn retries_failed_requests() {
+    let ping = Ping("ping".to_string().into_bytes());
+    let offline_peer = PeerId::random();
+
+    let protocols = iter::once((PingProtocol(), ProtocolSupport::Full));
+    let mut cfg = RequestResponseConfig::default();
+    cfg.set_retry_policy(Some(RetryPolicy {
+        max_attempts: 3,
+        backoff: Duration::from_millis(50),
+        retry_on_other_connections: true,
+    }));
+
+    let (peer1_id, trans) = mk_transport();
+    let ping_proto1 = RequestResponse::new(PingCodec(), protocols, cfg);
+    let mut swarm1 = Swarm::new(trans, ping_proto1, peer1_id);
+
+    let start = Instant::now();
+    let request_id = swarm1
+        .behaviour_mut()
+        .send_request_to_any([offline_peer], ping);
+
+    assert!(swarm1
+        .behaviour()
+        .is_pending_outbound(&offline_peer, &request_id));
+
+    match futures::executor::block_on(swarm1.select_next_some()) {
+        SwarmEvent::Behaviour(RequestResponseEvent::OutboundFailure {
+            peer,
+            request_id: id,
+            error,
+        }) => {
+            assert_eq!(&peer, &offline_peer);
+            assert_eq!(id, request_id);
+            assert_eq!(error, OutboundFailure::DialFailure);
+        }
+        e => panic!("Peer: Unexpected event: {:?}", e),
+    }
+    // The failure is reported after two retries with a backoff of 50ms and 100ms.
+    assert!(start.elapsed() >= Duration::from_millis(150));
+    assert!(!swarm1
+        .behaviour()
+        .is_pending_outbound(&offline_peer, &request_id));
+}
+
+/// Requests sent with `send_request_with_retry` are retried as well,
+/// dialing the unreachable peer for every attempt.
+#[test]
+fn retries_failed_send_request_with_retry() {
+    let ping = Ping("ping".to_string().into_bytes());
+    let offline_peer = PeerId::random();
+
+    let protocols = iter::once((PingProtocol(), ProtocolSupport::Full));
+    let mut cfg = RequestResponseConfig::default();
+    cfg.set_retry_policy(Some(RetryPolicy {
+        max_attempts: 3,
+        backoff: Duration::from_millis(50),
+        retry_on_other_connections: true,
+    }));
+
+    let (peer1_id, trans) = mk_transport();
+    let ping_proto1 = RequestResponse::new(PingCodec(), protocols, cfg);
+    let mut swarm1 = Swarm::new(trans, ping_proto1, peer1_id);
+
+    swarm1
+        .behaviour_mut()
+        .add_address(&offline_peer, "/ip4/127.0.0.1/tcp/1".parse().unwrap());
+    let start = Instant::now();
+    let request_id = swarm1
+        .behaviour_mut()
+        .send_request_with_retry(&offline_peer, ping);
+
+    let mut dials = 0;
+    futures::executor::block_on(async {
+        loop {
+            match swarm1.select_next_some().await {
+                SwarmEvent::Dialing(peer) => {
+                    assert_eq!(&peer, &offline_peer);
+                    dials += 1;
+                }
+                SwarmEvent::Behaviour(RequestResponseEvent::OutboundFailure {
+                    peer,
+                    request_id: id,
+                    error,
+                }) => {
+                    assert_eq!(&peer, &offline_peer);
+                    assert_eq!(id, request_id);
+                    assert_eq!(error, OutboundFailure::DialFailure);
+                    break;
+                }
+                SwarmEvent::Behaviour(e) => panic!("Peer: Unexpected event: {:?}", e),
+                _ => {}
+            }
+        }
+    });
+    assert_eq!(dials, 3);
+    assert!(start.elapsed() >= Duration::from_millis(150));
+    assert!(!swarm1
+        .behaviour()
+        .is_pending_outbound(&offline_peer, &request_id));
+}
+
+/// Timed out requests are retried, on another connection to the peer if
+/// `retry_on_other_connections` is set.
+#[test]
+fn retries_timed_out_requests() {
+    // The attempts go to different connections, i.e. servers.
+    let servers = retry_timed_out_request(true);
+    assert_eq!(servers.len(), 2);
+    assert_ne!(servers[0], servers[1]);
+
+    // The attempts go to the same connection.
+    let servers = retry_timed_out_request(false);
+    assert_eq!(servers.len(), 2);
+    assert_eq!(servers[0], servers[1]);
+}
+
+/// Sends a request to a peer that never responds on either of two
+/// connections, returning the index of the connection of each attempt.
+fn retry_timed_out_request(retry_on_other_connections: bool) -> Vec<usize> {
+    let ping = Ping("ping".to_string().into_bytes());
+
+    let protocols = iter::once((PingProtocol(), ProtocolSupport::Full));
+    let mut cfg = RequestResponseConfig::default();
+    cfg.set_request_timeout(Duration::from_millis(200));
+    cfg.set_retry_policy(Some(RetryPolicy {
+        max_attempts: 2,
+        backoff: Duration::from_millis(10),
+        retry_on_other_connections,
+    }));
+
+    // Two servers sharing an identity, so that their connections are
+    // connections to the same peer.
+    let server_keys = identity::Keypair::generate_ed25519();
+    let (tx, rx) = mpsc::unbounded::<usize>();
+    let mut server_addrs = Vec::new();
+    let mut server_id = None;
+    for ix in 0..2 {
+        let (peer_id, trans) = mk_transport_with_keys(server_keys.clone());
+        let ping_proto = RequestResponse::new(PingCodec(), protocols.clone(), cfg.clone());
+        let mut swarm = Swarm::new(trans, ping_proto, peer_id);
+        swarm
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+        let addr = futures::executor::block_on(async {
+            loop {
+                if let SwarmEvent::NewListenAddr { address, .. } = swarm.select_next_some().await {
+                    break address;
+                }
+            }
+        });
+        server_addrs.push(addr);
+        server_id = Some(peer_id);
+
+        // Reports the requests it receives, which it never responds to.
+        let tx = tx.clone();
+        async_std::task::spawn(async move {
+            let mut channels = Vec::new();
+            loop {
+                if let SwarmEvent::Behaviour(RequestResponseEvent::Message {
+                    message: RequestResponseMessage::Request { channel, .. },
+                    ..
+                }) = swarm.select_next_some().await
+                {
+                    channels.push(channel);
+                    tx.unbounded_send(ix).unwrap();
+                }
+            }
+        });
+    }
+    let server_id = server_id.unwrap();
+
+    let (client_id, trans) = mk_transport();
+    let ping_proto = RequestResponse::new(PingCodec(), protocols, cfg);
+    let mut client = Swarm::new(trans, ping_proto, client_id);
+
+    async_std::task::block_on(async move {
+        for addr in server_addrs {
+            client
+                .dial(
+                    DialOpts::peer_id(server_id)
+                        .addresses(vec![addr])
+                        .condition(PeerCondition::Always)
+                        .build(),
+                )
+                .unwrap();
+        }
+        let mut established = 0;
+        while established < 2 {
+            if let SwarmEvent::ConnectionEstablished { .. } = client.select_next_some().await {
+                established += 1;
+            }
+        }
+
+        let request_id = client
+            .behaviour_mut()
+            .send_request_with_retry(&server_id, ping);
+        loop {
+            match client.select_next_some().await {
+                SwarmEvent::Behaviour(RequestResponseEvent::OutboundFailure {
+                    peer,
+                    request_id: id,
+                    error,
+                }) => {
+                    assert_eq!(peer, server_id);
+                    assert_eq!(id, request_id);
+                    assert_eq!(error, OutboundFailure::Timeout);
+                    break;
+                }
+                SwarmEvent::Behaviour(e) => panic!("Client: Unexpected event: {:?}", e),
+                _ => {}
+            }
+        }
+        drop(tx);
+        rx.take(2).collect().await
+    })
+}
+
 fn mk_transport() -> (PeerId, transport::Boxed<(PeerId, StreamMuxerBox)>) {
-    let id_keys = identity::Keypair::generate_ed25519();
+    mk_transport_with_keys(identity::Keypair::generate_ed25519())
+}
+
+fn mk_transport_with_keys(
+    id_keys: identity::Keypair,
+) -> (PeerId, transport::Boxed<(PeerId, StreamMuxerBox)>) {
     let peer_id = id_keys.public().to_peer_id();
     let noise_keys = Keypair::<X25519Spec>::new()
         .into_authentic(&id_keys)
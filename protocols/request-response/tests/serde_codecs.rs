@@ -0,0 +1,182 @@
+// Copyright 2022 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Integration tests for the serde codecs of the `RequestResponse` network behaviour.
+
+#![cfg(any(feature = "cbor", feature = "json"))]
+
+use futures::{channel::oneshot, prelude::*};
+use libp2p_core::{
+    identity,
+    muxing::StreamMuxerBox,
+    transport::{self, Transport},
+    upgrade, PeerId,
+};
+use libp2p_noise::{Keypair, NoiseConfig, X25519Spec};
+use libp2p_request_response::*;
+use libp2p_swarm::{Swarm, SwarmEvent};
+use libp2p_tcp::TcpConfig;
+use serde::{Deserialize, Serialize};
+use std::iter;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Request {
+    size: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Response {
+    data: Vec<u8>,
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn cbor_codec() {
+    let mut codec = cbor::Codec::default();
+    codec.set_response_size_maximum(1024);
+    exchange_requests(codec);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn json_codec() {
+    let mut codec = json::Codec::default();
+    codec.set_response_size_maximum(1024);
+    exchange_requests(codec);
+}
+
+/// Sends requests for responses of different sizes to a peer, expecting the
+/// response exceeding the size limit of the given requester codec to fail
+/// without affecting the connection.
+fn exchange_requests<C>(requester_codec: C)
+where
+    C: RequestResponseCodec<Protocol = String, Request = Request, Response = Response>
+        + Default
+        + Clone
+        + Send
+        + 'static,
+{
+    let _ = env_logger::try_init();
+    let protocols = iter::once(("/test/1".to_string(), ProtocolSupport::Full));
+    let cfg = RequestResponseConfig::default();
+
+    let (peer1_id, trans) = mk_transport();
+    let proto1 = RequestResponse::new(C::default(), protocols.clone(), cfg.clone());
+    let mut swarm1 = Swarm::new(trans, proto1, peer1_id);
+
+    let (peer2_id, trans) = mk_transport();
+    let proto2 = RequestResponse::new(requester_codec, protocols, cfg);
+    let mut swarm2 = Swarm::new(trans, proto2, peer2_id);
+
+    let (addr_tx, addr_rx) = oneshot::channel();
+    swarm1
+        .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+        .unwrap();
+    async_std::task::spawn(async move {
+        let mut addr_tx = Some(addr_tx);
+        loop {
+            match swarm1.select_next_some().await {
+                SwarmEvent::NewListenAddr { address, .. } => {
+                    if let Some(addr_tx) = addr_tx.take() {
+                        addr_tx.send(address).unwrap();
+                    }
+                }
+                SwarmEvent::Behaviour(RequestResponseEvent::Message {
+                    message:
+                        RequestResponseMessage::Request {
+                            request, channel, ..
+                        },
+                    ..
+                }) => {
+                    let response = Response {
+                        data: vec![7; request.size],
+                    };
+                    swarm1
+                        .behaviour_mut()
+                        .send_response(channel, response)
+                        .unwrap();
+                }
+                _ => {}
+            }
+        }
+    });
+
+    async_std::task::block_on(async move {
+        let addr = addr_rx.await.unwrap();
+        swarm2.behaviour_mut().add_address(&peer1_id, addr);
+
+        for size in [10, 4096, 20] {
+            let request_id = swarm2
+                .behaviour_mut()
+                .send_request(&peer1_id, Request { size });
+            let (id, result) = loop {
+                match swarm2.select_next_some().await {
+                    SwarmEvent::Behaviour(RequestResponseEvent::Message {
+                        message:
+                            RequestResponseMessage::Response {
+                                request_id,
+                                response,
+                            },
+                        ..
+                    }) => break (request_id, Ok(response)),
+                    SwarmEvent::Behaviour(RequestResponseEvent::OutboundFailure {
+                        request_id,
+                        error,
+                        ..
+                    }) => break (request_id, Err(error)),
+                    SwarmEvent::Behaviour(e) => panic!("Peer2: Unexpected event: {:?}", e),
+                    _ => {}
+                }
+            };
+            assert_eq!(id, request_id);
+
+            if size == 4096 {
+                match result {
+                    Err(OutboundFailure::Codec(CodecError::MessageTooLarge {
+                        max_size, ..
+                    })) => {
+                        assert_eq!(max_size, 1024)
+                    }
+                    r => panic!("Unexpected result: {:?}", r),
+                }
+            } else {
+                assert_eq!(result.unwrap().data, vec![7; size]);
+            }
+            assert!(swarm2.behaviour().is_connected(&peer1_id));
+        }
+    });
+}
+
+fn mk_transport() -> (PeerId, transport::Boxed<(PeerId, StreamMuxerBox)>) {
+    let id_keys = identity::Keypair::generate_ed25519();
+    let peer_id = id_keys.public().to_peer_id();
+    let noise_keys = Keypair::<X25519Spec>::new()
+        .into_authentic(&id_keys)
+        .unwrap();
+    (
+        peer_id,
+        TcpConfig::new()
+            .nodelay(true)
+            .upgrade(upgrade::Version::V1)
+            .authenticate(NoiseConfig::xx(noise_keys).into_authenticated())
+            .multiplex(libp2p_yamux::YamuxConfig::default())
+            .boxed(),
+    )
+}
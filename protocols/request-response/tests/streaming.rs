@@ -0,0 +1,331 @@
+// Copyright 2022 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Integration tests for the `StreamingRequestResponse` network behaviour.
+
+use async_trait::async_trait;
+use futures::{
+    channel::{mpsc, oneshot},
+    prelude::*,
+    stream::FusedStream,
+};
+use libp2p_core::{
+    identity,
+    muxing::StreamMuxerBox,
+    transport::{self, Transport},
+    upgrade::{self, read_length_prefixed, write_length_prefixed},
+    PeerId,
+};
+use libp2p_noise::{Keypair, NoiseConfig, X25519Spec};
+use libp2p_request_response::*;
+use libp2p_swarm::{Swarm, SwarmEvent};
+use libp2p_tcp::TcpConfig;
+use std::{io, iter};
+
+type FileEvent = StreamingRequestResponseEvent<Vec<u8>, Vec<u8>>;
+
+/// Streams a response in chunks from one peer to another.
+#[test]
+fn streams_response_in_chunks() {
+    let _ = env_logger::try_init();
+    let (mut events1, mut events2, _) = spawn_swarms_with_request();
+
+    let num_chunks = 100;
+    let chunk = |i: usize| vec![i as u8; 16 * 1024];
+
+    async_std::task::block_on(async move {
+        let mut sink = expect_request(&mut events1).await;
+        let writer = async_std::task::spawn(async move {
+            for i in 0..num_chunks {
+                sink.send(chunk(i)).await.unwrap();
+            }
+            sink.close().await.unwrap();
+        });
+
+        let stream = expect_response(&mut events2).await;
+        let received = stream.map(|chunk| chunk.unwrap()).collect::<Vec<_>>().await;
+        assert_eq!(received, (0..num_chunks).map(chunk).collect::<Vec<_>>());
+
+        writer.await;
+        match events1.next().await.unwrap() {
+            StreamingRequestResponseEvent::ResponseSent { .. } => {}
+            e => panic!("Peer1: Unexpected event: {:?}", e),
+        }
+    });
+}
+
+/// Dropping the sink without closing it aborts the response, which the
+/// requester observes as an error ending the stream.
+#[test]
+fn responder_aborts_response() {
+    let _ = env_logger::try_init();
+    let (mut events1, mut events2, _) = spawn_swarms_with_request();
+
+    async_std::task::block_on(async move {
+        let mut sink = expect_request(&mut events1).await;
+        sink.send(b"partial".to_vec()).await.unwrap();
+        drop(sink);
+
+        let stream = expect_response(&mut events2).await;
+        let items = stream.collect::<Vec<_>>().await;
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].as_ref().unwrap(), b"partial");
+        assert_eq!(
+            items[1].as_ref().unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+
+        match events1.next().await.unwrap() {
+            StreamingRequestResponseEvent::InboundFailure {
+                error: InboundFailure::ResponseOmission,
+                ..
+            } => {}
+            e => panic!("Peer1: Unexpected event: {:?}", e),
+        }
+    });
+}
+
+/// Dropping the response stream cancels the response on the responder.
+#[test]
+fn requester_cancels_response() {
+    let _ = env_logger::try_init();
+    let (mut events1, mut events2, _) = spawn_swarms_with_request();
+
+    async_std::task::block_on(async move {
+        let mut sink = expect_request(&mut events1).await;
+        // Write chunks until the requester cancels the response.
+        let writer = async_std::task::spawn(async move {
+            while sink.send(vec![0; 16 * 1024]).await.is_ok() {}
+            assert!(!sink.is_open());
+        });
+
+        let mut stream = expect_response(&mut events2).await;
+        assert!(stream.next().await.unwrap().is_ok());
+        drop(stream);
+
+        match events1.next().await.unwrap() {
+            StreamingRequestResponseEvent::InboundFailure {
+                error: InboundFailure::ResponseAborted,
+                ..
+            } => {}
+            e => panic!("Peer1: Unexpected event: {:?}", e),
+        }
+        writer.await;
+    });
+}
+
+/// Closing the connection while a response is received ends the response
+/// stream with an error.
+#[test]
+fn connection_closes_during_response() {
+    let _ = env_logger::try_init();
+    let (mut events1, mut events2, disconnect2) = spawn_swarms_with_request();
+
+    async_std::task::block_on(async move {
+        let mut sink = expect_request(&mut events1).await;
+        sink.send(b"partial".to_vec()).await.unwrap();
+
+        let mut stream = expect_response(&mut events2).await;
+        assert_eq!(stream.next().await.unwrap().unwrap(), b"partial");
+        disconnect2.unbounded_send(()).unwrap();
+
+        let error = stream.next().await.unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionAborted);
+        assert!(stream.next().await.is_none());
+        assert!(stream.is_terminated());
+        drop(sink);
+    });
+}
+
+/// Spawns two connected swarms, the second one sending a request to the
+/// first one, and returns the behaviour events of both as well as a sender
+/// to make the second one disconnect from the first one.
+fn spawn_swarms_with_request() -> (
+    mpsc::UnboundedReceiver<FileEvent>,
+    mpsc::UnboundedReceiver<FileEvent>,
+    mpsc::UnboundedSender<()>,
+) {
+    let (mut swarm1, mut swarm2) = mk_swarms();
+    let peer1_id = *swarm1.local_peer_id();
+    let (events1_tx, events1_rx) = mpsc::unbounded();
+    let (events2_tx, events2_rx) = mpsc::unbounded();
+    let (disconnect2_tx, mut disconnect2_rx) = mpsc::unbounded();
+    let (addr_tx, addr_rx) = oneshot::channel();
+
+    swarm1
+        .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+        .unwrap();
+    async_std::task::spawn(async move {
+        let mut addr_tx = Some(addr_tx);
+        loop {
+            match swarm1.select_next_some().await {
+                SwarmEvent::NewListenAddr { address, .. } => {
+                    if let Some(addr_tx) = addr_tx.take() {
+                        addr_tx.send(address).unwrap();
+                    }
+                }
+                SwarmEvent::Behaviour(e) => {
+                    let _ = events1_tx.unbounded_send(e);
+                }
+                _ => {}
+            }
+        }
+    });
+
+    async_std::task::spawn(async move {
+        let addr = addr_rx.await.unwrap();
+        swarm2.behaviour_mut().add_address(&peer1_id, addr);
+        swarm2
+            .behaviour_mut()
+            .send_request(&peer1_id, b"file".to_vec());
+        loop {
+            futures::select! {
+                event = swarm2.select_next_some() => {
+                    if let SwarmEvent::Behaviour(e) = event {
+                        let _ = events2_tx.unbounded_send(e);
+                    }
+                }
+                () = disconnect2_rx.select_next_some() => {
+                    swarm2.disconnect_peer_id(peer1_id).unwrap();
+                }
+            }
+        }
+    });
+
+    (events1_rx, events2_rx, disconnect2_tx)
+}
+
+async fn expect_request(events: &mut mpsc::UnboundedReceiver<FileEvent>) -> ResponseSink<Vec<u8>> {
+    match events.next().await.unwrap() {
+        StreamingRequestResponseEvent::Message {
+            message: StreamingRequestResponseMessage::Request { request, sink, .. },
+            ..
+        } => {
+            assert_eq!(request, b"file");
+            sink
+        }
+        e => panic!("Peer1: Unexpected event: {:?}", e),
+    }
+}
+
+async fn expect_response(
+    events: &mut mpsc::UnboundedReceiver<FileEvent>,
+) -> ResponseStream<Vec<u8>> {
+    match events.next().await.unwrap() {
+        StreamingRequestResponseEvent::Message {
+            message: StreamingRequestResponseMessage::Response { stream, .. },
+            ..
+        } => stream,
+        e => panic!("Peer2: Unexpected event: {:?}", e),
+    }
+}
+
+fn mk_swarms() -> (
+    Swarm<StreamingRequestResponse<FileCodec>>,
+    Swarm<StreamingRequestResponse<FileCodec>>,
+) {
+    let protocols = iter::once((FileProtocol(), ProtocolSupport::Full));
+    let cfg = RequestResponseConfig::default();
+
+    let (peer1_id, trans) = mk_transport();
+    let proto1 = StreamingRequestResponse::new(FileCodec(), protocols.clone(), cfg.clone());
+    let swarm1 = Swarm::new(trans, proto1, peer1_id);
+
+    let (peer2_id, trans) = mk_transport();
+    let proto2 = StreamingRequestResponse::new(FileCodec(), protocols, cfg);
+    let swarm2 = Swarm::new(trans, proto2, peer2_id);
+
+    (swarm1, swarm2)
+}
+
+fn mk_transport() -> (PeerId, transport::Boxed<(PeerId, StreamMuxerBox)>) {
+    let id_keys = identity::Keypair::generate_ed25519();
+    let peer_id = id_keys.public().to_peer_id();
+    let noise_keys = Keypair::<X25519Spec>::new()
+        .into_authentic(&id_keys)
+        .unwrap();
+    (
+        peer_id,
+        TcpConfig::new()
+            .nodelay(true)
+            .upgrade(upgrade::Version::V1)
+            .authenticate(NoiseConfig::xx(noise_keys).into_authenticated())
+            .multiplex(libp2p_yamux::YamuxConfig::default())
+            .boxed(),
+    )
+}
+
+// Simple File Transfer Protocol
+
+#[derive(Debug, Clone)]
+struct FileProtocol();
+#[derive(Clone)]
+struct FileCodec();
+
+impl ProtocolName for FileProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        "/file/1".as_bytes()
+    }
+}
+
+#[async_trait]
+impl StreamingCodec for FileCodec {
+    type Protocol = FileProtocol;
+    type Request = Vec<u8>;
+    type Chunk = Vec<u8>;
+
+    async fn read_request<T>(&mut self, _: &FileProtocol, io: &mut T) -> io::Result<Vec<u8>>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_length_prefixed(io, 1024).await
+    }
+
+    async fn read_chunk<T>(&mut self, _: &FileProtocol, io: &mut T) -> io::Result<Vec<u8>>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_length_prefixed(io, 64 * 1024).await
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &FileProtocol,
+        io: &mut T,
+        request: Vec<u8>,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_length_prefixed(io, request).await
+    }
+
+    async fn write_chunk<T>(
+        &mut self,
+        _: &FileProtocol,
+        io: &mut T,
+        chunk: Vec<u8>,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_length_prefixed(io, chunk).await
+    }
+}
@@ -327,6 +327,17 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
             })
     };
 
+    // Build the list of statements to put in the body of `inject_supported_protocols_change()`.
+    let inject_supported_protocols_change_stmts = {
+        data_struct_fields
+            .iter()
+            .enumerate()
+            .map(move |(field_n, field)| match field.ident {
+                Some(ref i) => quote! { self.#i.inject_supported_protocols_change(protocols); },
+                None => quote! { self.#field_n.inject_supported_protocols_change(protocols); },
+            })
+    };
+
     // Build the list of statements to put in the body of `inject_listener_error()`.
     let inject_listener_error_stmts = {
         data_struct_fields
@@ -519,6 +530,9 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
                     std::task::Poll::Ready(#network_behaviour_action::CloseConnection { peer_id, connection }) => {
                         return std::task::Poll::Ready(#network_behaviour_action::CloseConnection { peer_id, connection });
                     }
+                    std::task::Poll::Ready(#network_behaviour_action::RefreshSupportedProtocols) => {
+                        return std::task::Poll::Ready(#network_behaviour_action::RefreshSupportedProtocols);
+                    }
                     std::task::Poll::Pending => break,
                 }
             }
@@ -584,6 +598,10 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
                 #(#inject_expired_external_addr_stmts);*
             }
 
+            fn inject_supported_protocols_change(&mut self, protocols: &[Vec<u8>]) {
+                #(#inject_supported_protocols_change_stmts);*
+            }
+
             fn inject_listener_error(&mut self, id: #listener_id, err: &(dyn std::error::Error + 'static)) {
                 #(#inject_listener_error_stmts);*
             }
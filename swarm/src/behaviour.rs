@@ -18,6 +18,7 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
+pub mod connection_manager;
 mod either;
 pub mod toggle;
 
@@ -298,6 +299,10 @@ pub trait NetworkBehaviour: 'static {
     /// Indicates to the behaviour that an external address was removed.
     fn inject_expired_external_addr(&mut self, _addr: &Multiaddr) {}
 
+    /// Indicates to the behaviour that the list of protocols the local node supports on inbound
+    /// substreams changed upon a [`NetworkBehaviourAction::RefreshSupportedProtocols`].
+    fn inject_supported_protocols_change(&mut self, _protocols: &[Vec<u8>]) {}
+
     /// Polls for things that swarm should do.
     ///
     /// This API mimics the API of the `Stream` trait. The method may register the current task in
@@ -323,7 +328,8 @@ pub trait PollParameters {
     ///
     /// The iterator's elements are the ASCII names as reported on the wire.
     ///
-    /// Note that the list is computed once at initialization and never refreshed.
+    /// The list is computed at initialization and refreshed whenever a behaviour issues
+    /// [`NetworkBehaviourAction::RefreshSupportedProtocols`].
     fn supported_protocols(&self) -> Self::SupportedProtocolsIter;
 
     /// Returns the list of the addresses we're listening on.
@@ -607,6 +613,13 @@ pub enum NetworkBehaviourAction<
         /// Whether to close a specific or all connections to the given peer.
         connection: CloseConnection,
     },
+
+    /// Instructs the `Swarm` to recompute the protocols it supports on inbound substreams,
+    /// i.e. [`PollParameters::supported_protocols`], from a new handler of the behaviour.
+    ///
+    /// Behaviours whose handlers change the protocols they accept over time should issue
+    /// this action on every such change.
+    RefreshSupportedProtocols,
 }
 
 impl<TOutEvent, THandler: IntoConnectionHandler, TInEventOld>
@@ -641,6 +654,9 @@ impl<TOutEvent, THandler: IntoConnectionHandler, TInEventOld>
                 peer_id,
                 connection,
             },
+            NetworkBehaviourAction::RefreshSupportedProtocols => {
+                NetworkBehaviourAction::RefreshSupportedProtocols
+            }
         }
     }
 }
@@ -672,6 +688,9 @@ impl<TOutEvent, THandler: IntoConnectionHandler> NetworkBehaviourAction<TOutEven
                 peer_id,
                 connection,
             },
+            NetworkBehaviourAction::RefreshSupportedProtocols => {
+                NetworkBehaviourAction::RefreshSupportedProtocols
+            }
         }
     }
 }
@@ -715,6 +734,9 @@ where
                 peer_id,
                 connection,
             },
+            NetworkBehaviourAction::RefreshSupportedProtocols => {
+                NetworkBehaviourAction::RefreshSupportedProtocols
+            }
         }
     }
 }
@@ -759,6 +781,9 @@ where
                 peer_id,
                 connection,
             },
+            NetworkBehaviourAction::RefreshSupportedProtocols => {
+                NetworkBehaviourAction::RefreshSupportedProtocols
+            }
         }
     }
 }
@@ -0,0 +1,444 @@
+// Copyright 2022 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! A [`NetworkBehaviour`] that keeps the number of established connections
+//! between a low and a high watermark.
+//!
+//! Whenever the number of established connections exceeds the high watermark,
+//! the [`ConnectionManager`] closes the least valuable connections until the
+//! number of connections is back at the low watermark. The value of a
+//! connection is the sum of the values its peer has been tagged with via
+//! [`ConnectionManager::tag_peer`].
+//!
+//! Connections that have been established less than a grace period ago are
+//! never closed, nor are connections to peers that are protected via
+//! [`ConnectionManager::protect_peer`], e.g. the mesh peers of a gossipsub
+//! topic or a relay server that the local node is reachable through.
+//!
+//! Contrary to [`ConnectionLimits`](crate::ConnectionLimits), which deny new
+//! connections once a hard limit is reached, the `ConnectionManager` accepts
+//! all connections and prunes them afterwards.
+
+use crate::handler::DummyConnectionHandler;
+use crate::{CloseConnection, NetworkBehaviour, NetworkBehaviourAction, PollParameters};
+use futures::prelude::*;
+use futures_timer::Delay;
+use instant::Instant;
+use libp2p_core::{connection::ConnectionId, ConnectedPoint, Multiaddr, PeerId};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// The configuration of a [`ConnectionManager`].
+#[derive(Debug, Clone)]
+pub struct ConnectionManagerConfig {
+    low_watermark: usize,
+    high_watermark: usize,
+    grace_period: Duration,
+}
+
+impl ConnectionManagerConfig {
+    /// Creates a new configuration that prunes connections down to
+    /// `low_watermark` connections once there are more than
+    /// `high_watermark` established connections.
+    ///
+    /// The grace period defaults to 60 seconds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `low_watermark` is greater than `high_watermark`.
+    pub fn new(low_watermark: usize, high_watermark: usize) -> Self {
+        assert!(
+            low_watermark <= high_watermark,
+            "The low watermark must not exceed the high watermark."
+        );
+        ConnectionManagerConfig {
+            low_watermark,
+            high_watermark,
+            grace_period: Duration::from_secs(60),
+        }
+    }
+
+    /// Sets the duration after the establishment of a connection
+    /// during which the connection is not pruned.
+    pub fn with_grace_period(mut self, grace_period: Duration) -> Self {
+        self.grace_period = grace_period;
+        self
+    }
+}
+
+/// Event emitted by the [`ConnectionManager`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionManagerEvent {
+    /// A connection has been pruned because there were
+    /// more established connections than the high watermark.
+    Pruned {
+        /// The peer the pruned connection is with.
+        peer_id: PeerId,
+        /// The ID of the pruned connection.
+        connection: ConnectionId,
+    },
+}
+
+/// A [`NetworkBehaviour`] that prunes the least valuable connections when
+/// there are too many. See the [module documentation](self) for details.
+pub struct ConnectionManager {
+    config: ConnectionManagerConfig,
+    /// The established connections that are not being closed,
+    /// with their peer and when they were established.
+    connections: HashMap<ConnectionId, (PeerId, Instant)>,
+    /// The tags of peers with their values.
+    tags: HashMap<PeerId, HashMap<String, i64>>,
+    /// The tags by which peers are protected.
+    protections: HashMap<PeerId, HashSet<String>>,
+    /// Whether to check if connections need to be pruned on the next poll.
+    check_pending: bool,
+    /// The delay until the grace period of a connection that
+    /// could have been pruned otherwise has elapsed.
+    recheck: Option<Delay>,
+    /// Queued actions to return when the behaviour is being polled.
+    queued_actions:
+        VecDeque<NetworkBehaviourAction<ConnectionManagerEvent, DummyConnectionHandler>>,
+}
+
+impl ConnectionManager {
+    /// Creates a new `ConnectionManager` with the given configuration.
+    pub fn new(config: ConnectionManagerConfig) -> Self {
+        ConnectionManager {
+            config,
+            connections: HashMap::new(),
+            tags: HashMap::new(),
+            protections: HashMap::new(),
+            check_pending: false,
+            recheck: None,
+            queued_actions: VecDeque::new(),
+        }
+    }
+
+    /// Returns the number of established connections that are not being closed.
+    pub fn num_connections(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// Tags a peer with a value, replacing a previous value of the same tag.
+    ///
+    /// Connections to peers with lower total values are pruned first. Tags are
+    /// kept until removed via [`ConnectionManager::untag_peer`], regardless of
+    /// whether the peer is connected.
+    pub fn tag_peer(&mut self, peer: PeerId, tag: impl Into<String>, value: i64) {
+        self.tags.entry(peer).or_default().insert(tag.into(), value);
+    }
+
+    /// Removes a tag from a peer.
+    pub fn untag_peer(&mut self, peer: &PeerId, tag: &str) {
+        if let Some(tags) = self.tags.get_mut(peer) {
+            tags.remove(tag);
+            if tags.is_empty() {
+                self.tags.remove(peer);
+            }
+        }
+    }
+
+    /// Returns the sum of the values a peer has been tagged with.
+    pub fn peer_value(&self, peer: &PeerId) -> i64 {
+        self.tags.get(peer).map_or(0, |tags| tags.values().sum())
+    }
+
+    /// Protects the connections to a peer from being pruned.
+    ///
+    /// A peer can be protected by multiple tags, e.g. by different
+    /// behaviours, and stays protected as long as at least one of
+    /// them is in place.
+    pub fn protect_peer(&mut self, peer: PeerId, tag: impl Into<String>) {
+        self.protections.entry(peer).or_default().insert(tag.into());
+    }
+
+    /// Removes the protection of a peer by the given tag.
+    ///
+    /// Returns whether the peer is still protected by other tags.
+    pub fn unprotect_peer(&mut self, peer: &PeerId, tag: &str) -> bool {
+        if let Some(tags) = self.protections.get_mut(peer) {
+            tags.remove(tag);
+            if !tags.is_empty() {
+                return true;
+            }
+            self.protections.remove(peer);
+            // Connections to the peer may have to be pruned now.
+            self.check_pending = true;
+        }
+        false
+    }
+
+    /// Checks whether the connections to a peer are protected from being pruned.
+    pub fn is_protected(&self, peer: &PeerId) -> bool {
+        self.protections.contains_key(peer)
+    }
+
+    /// Prunes the least valuable connections if there are more than the high
+    /// watermark, until there are as many as the low watermark.
+    fn prune(&mut self, now: Instant) {
+        if self.connections.len() <= self.config.high_watermark {
+            return;
+        }
+        let excess = self.connections.len() - self.config.low_watermark;
+
+        let mut candidates = Vec::new();
+        let mut next_expiry = None;
+        for (id, (peer, established)) in &self.connections {
+            if self.is_protected(peer) {
+                continue;
+            }
+            let expiry = *established + self.config.grace_period;
+            if expiry > now {
+                next_expiry = Some(next_expiry.map_or(expiry, |e: Instant| e.min(expiry)));
+                continue;
+            }
+            candidates.push((*id, *peer, *established));
+        }
+
+        // Prune the connections with the least valuable peers first and,
+        // among those, the most recently established ones.
+        candidates.sort_by_cached_key(|(_, peer, established)| {
+            (self.peer_value(peer), Reverse(*established))
+        });
+
+        for (connection, peer_id, _) in candidates.iter().take(excess) {
+            log::debug!("Pruning connection {:?} to {}.", connection, peer_id);
+            self.connections.remove(connection);
+            self.queued_actions
+                .push_back(NetworkBehaviourAction::CloseConnection {
+                    peer_id: *peer_id,
+                    connection: CloseConnection::One(*connection),
+                });
+            self.queued_actions
+                .push_back(NetworkBehaviourAction::GenerateEvent(
+                    ConnectionManagerEvent::Pruned {
+                        peer_id: *peer_id,
+                        connection: *connection,
+                    },
+                ));
+        }
+
+        // Check again once connections that are still
+        // in their grace period can be pruned.
+        if candidates.len() < excess {
+            if let Some(expiry) = next_expiry {
+                self.recheck = Some(Delay::new(expiry - now));
+            }
+        }
+    }
+
+    fn poll_actions(
+        &mut self,
+        cx: &mut Context<'_>,
+        now: Instant,
+    ) -> Poll<NetworkBehaviourAction<ConnectionManagerEvent, DummyConnectionHandler>> {
+        // Pruning may install a new recheck, which is polled in turn
+        // to register the waker for it.
+        loop {
+            if self.check_pending {
+                self.check_pending = false;
+                self.prune(now);
+            }
+
+            match self.recheck.as_mut().map(|recheck| recheck.poll_unpin(cx)) {
+                Some(Poll::Ready(())) => {
+                    self.recheck = None;
+                    self.check_pending = true;
+                }
+                _ => break,
+            }
+        }
+
+        if let Some(action) = self.queued_actions.pop_front() {
+            return Poll::Ready(action);
+        }
+
+        Poll::Pending
+    }
+}
+
+impl NetworkBehaviour for ConnectionManager {
+    type ConnectionHandler = DummyConnectionHandler;
+    type OutEvent = ConnectionManagerEvent;
+
+    fn new_handler(&mut self) -> Self::ConnectionHandler {
+        DummyConnectionHandler::default()
+    }
+
+    fn inject_connection_established(
+        &mut self,
+        peer_id: &PeerId,
+        connection_id: &ConnectionId,
+        _: &ConnectedPoint,
+        _: Option<&Vec<Multiaddr>>,
+        _: usize,
+    ) {
+        self.connections
+            .insert(*connection_id, (*peer_id, Instant::now()));
+        self.check_pending = true;
+    }
+
+    fn inject_connection_closed(
+        &mut self,
+        _: &PeerId,
+        connection_id: &ConnectionId,
+        _: &ConnectedPoint,
+        _: DummyConnectionHandler,
+        _: usize,
+    ) {
+        self.connections.remove(connection_id);
+    }
+
+    fn inject_event(&mut self, _: PeerId, _: ConnectionId, event: void::Void) {
+        void::unreachable(event)
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+        _: &mut impl PollParameters,
+    ) -> Poll<NetworkBehaviourAction<Self::OutEvent, Self::ConnectionHandler>> {
+        self.poll_actions(cx, Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::future::poll_fn;
+    use libp2p_core::Endpoint;
+
+    fn connect(manager: &mut ConnectionManager, peer: PeerId, id: usize) -> ConnectionId {
+        let connection = ConnectionId::new(id);
+        let endpoint = ConnectedPoint::Dialer {
+            address: Multiaddr::empty(),
+            role_override: Endpoint::Dialer,
+        };
+        manager.inject_connection_established(&peer, &connection, &endpoint, None, 0);
+        connection
+    }
+
+    /// Returns the connections pruned when polling at the given instant.
+    fn pruned(manager: &mut ConnectionManager, now: Instant) -> Vec<ConnectionId> {
+        let mut pruned = Vec::new();
+        block_on(poll_fn(|cx| {
+            while let Poll::Ready(action) = manager.poll_actions(cx, now) {
+                if let NetworkBehaviourAction::GenerateEvent(ConnectionManagerEvent::Pruned {
+                    connection,
+                    ..
+                }) = action
+                {
+                    pruned.push(connection)
+                }
+            }
+            Poll::Ready(())
+        }));
+        pruned
+    }
+
+    #[test]
+    fn prunes_least_valuable_connections_down_to_low_watermark() {
+        let config = ConnectionManagerConfig::new(2, 3).with_grace_period(Duration::ZERO);
+        let mut manager = ConnectionManager::new(config);
+        let peers = (0..4).map(|_| PeerId::random()).collect::<Vec<_>>();
+        manager.tag_peer(peers[0], "a", 10);
+        manager.tag_peer(peers[1], "a", 5);
+        manager.tag_peer(peers[1], "b", 10);
+        manager.protect_peer(peers[3], "relay");
+
+        let connections = peers
+            .iter()
+            .enumerate()
+            .map(|(i, peer)| connect(&mut manager, *peer, i))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pruned(&mut manager, Instant::now()),
+            vec![connections[2], connections[0]]
+        );
+        assert_eq!(manager.num_connections(), 2);
+
+        // The high watermark is not exceeded.
+        connect(&mut manager, PeerId::random(), 4);
+        assert!(pruned(&mut manager, Instant::now()).is_empty());
+
+        // New peers without value are pruned before tagged ones.
+        let newest = connect(&mut manager, PeerId::random(), 5);
+        manager.untag_peer(&peers[1], "b");
+        assert_eq!(manager.peer_value(&peers[1]), 5);
+        let mut pruned = pruned(&mut manager, Instant::now());
+        pruned.sort();
+        assert_eq!(pruned, vec![ConnectionId::new(4), newest]);
+        assert!(manager.connections.contains_key(&connections[1]));
+        assert!(manager.connections.contains_key(&connections[3]));
+    }
+
+    #[test]
+    fn grace_period() {
+        let grace_period = Duration::from_millis(100);
+        let config = ConnectionManagerConfig::new(0, 1).with_grace_period(grace_period);
+        let mut manager = ConnectionManager::new(config);
+        let first = connect(&mut manager, PeerId::random(), 0);
+        let second = connect(&mut manager, PeerId::random(), 1);
+
+        // Fresh connections are not pruned.
+        assert!(pruned(&mut manager, Instant::now()).is_empty());
+        assert!(manager.recheck.is_some());
+
+        // The recheck wakes the task once the connections can be pruned.
+        let mut all = Vec::new();
+        block_on(poll_fn(|cx| {
+            while let Poll::Ready(action) = manager.poll_actions(cx, Instant::now()) {
+                if let NetworkBehaviourAction::GenerateEvent(ConnectionManagerEvent::Pruned {
+                    connection,
+                    ..
+                }) = action
+                {
+                    all.push(connection)
+                }
+            }
+            if all.len() < 2 {
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        }));
+        all.sort();
+        assert_eq!(all, vec![first, second]);
+    }
+
+    #[test]
+    fn unprotect_peer() {
+        let config = ConnectionManagerConfig::new(0, 0).with_grace_period(Duration::ZERO);
+        let mut manager = ConnectionManager::new(config);
+        let peer = PeerId::random();
+        manager.protect_peer(peer, "a");
+        manager.protect_peer(peer, "b");
+        let connection = connect(&mut manager, peer, 0);
+        assert!(pruned(&mut manager, Instant::now()).is_empty());
+
+        assert!(manager.unprotect_peer(&peer, "a"));
+        assert!(pruned(&mut manager, Instant::now()).is_empty());
+        assert!(!manager.unprotect_peer(&peer, "b"));
+        assert_eq!(pruned(&mut manager, Instant::now()), vec![connection]);
+    }
+}
@@ -206,6 +206,13 @@ where
         }
     }
 
+    fn inject_supported_protocols_change(&mut self, protocols: &[Vec<u8>]) {
+        match self {
+            Either::Left(a) => a.inject_supported_protocols_change(protocols),
+            Either::Right(b) => b.inject_supported_protocols_change(protocols),
+        }
+    }
+
     fn inject_listener_error(&mut self, id: ListenerId, err: &(dyn std::error::Error + 'static)) {
         match self {
             Either::Left(a) => a.inject_listener_error(id, err),
@@ -205,6 +205,12 @@ where
         }
     }
 
+    fn inject_supported_protocols_change(&mut self, protocols: &[Vec<u8>]) {
+        if let Some(inner) = self.inner.as_mut() {
+            inner.inject_supported_protocols_change(protocols)
+        }
+    }
+
     fn inject_listener_error(&mut self, id: ListenerId, err: &(dyn std::error::Error + 'static)) {
         if let Some(inner) = self.inner.as_mut() {
             inner.inject_listener_error(id, err)
@@ -384,6 +390,9 @@ where
         let err = match err {
             ConnectionHandlerUpgrErr::Timeout => ConnectionHandlerUpgrErr::Timeout,
             ConnectionHandlerUpgrErr::Timer => ConnectionHandlerUpgrErr::Timer,
+            ConnectionHandlerUpgrErr::ResourceLimitExceeded(e) => {
+                ConnectionHandlerUpgrErr::ResourceLimitExceeded(e)
+            }
             ConnectionHandlerUpgrErr::Upgrade(err) => {
                 ConnectionHandlerUpgrErr::Upgrade(err.map_err(|err| match err {
                     EitherError::A(e) => e,
@@ -19,6 +19,7 @@
 // DEALINGS IN THE SOFTWARE.
 
 mod error;
+mod gater;
 mod handler_wrapper;
 mod listeners;
 mod substream;
@@ -29,18 +30,21 @@ pub use error::{
     ConnectionError, PendingConnectionError, PendingInboundConnectionError,
     PendingOutboundConnectionError,
 };
+pub use gater::{ConnectionGater, GatingStage, SharedConnectionGater};
 pub use listeners::{ListenersEvent, ListenersStream};
 pub use pool::{ConnectionCounters, ConnectionLimits};
 pub use pool::{EstablishedConnection, PendingConnection};
 pub use substream::{Close, Substream, SubstreamEndpoint};
 
 use crate::handler::ConnectionHandler;
+use crate::resource_manager::{ConnectionScope, ResourceLimitExceeded};
 use handler_wrapper::HandlerWrapper;
 use libp2p_core::connection::ConnectedPoint;
 use libp2p_core::multiaddr::Multiaddr;
 use libp2p_core::muxing::StreamMuxerBox;
 use libp2p_core::upgrade;
 use libp2p_core::PeerId;
+use std::sync::Arc;
 use std::{error::Error, fmt, pin::Pin, task::Context, task::Poll};
 use substream::{Muxing, SubstreamEvent};
 
@@ -60,6 +64,8 @@ pub enum Event<T> {
     Handler(T),
     /// Address of the remote has changed.
     AddressChange(Multiaddr),
+    /// A substream was rejected by the resource manager.
+    ResourceLimitExceeded(ResourceLimitExceeded),
 }
 
 /// A multiplexed connection to a peer with an associated [`ConnectionHandler`].
@@ -98,11 +104,13 @@ where
         handler: THandler,
         substream_upgrade_protocol_override: Option<upgrade::Version>,
         max_negotiating_inbound_streams: usize,
+        resource_scope: Option<Arc<ConnectionScope>>,
     ) -> Self {
         let wrapped_handler = HandlerWrapper::new(
             handler,
             substream_upgrade_protocol_override,
             max_negotiating_inbound_streams,
+            resource_scope,
         );
         Connection {
             muxing: Muxing::new(muxer),
@@ -141,6 +149,9 @@ where
                 Poll::Ready(Ok(handler_wrapper::Event::Custom(event))) => {
                     return Poll::Ready(Ok(Event::Handler(event)));
                 }
+                Poll::Ready(Ok(handler_wrapper::Event::ResourceLimitExceeded(error))) => {
+                    return Poll::Ready(Ok(Event::ResourceLimitExceeded(error)));
+                }
                 Poll::Ready(Err(err)) => return Poll::Ready(Err(err.into())),
             }
 
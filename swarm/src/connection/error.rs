@@ -19,9 +19,10 @@
 // DEALINGS IN THE SOFTWARE.
 
 use super::handler_wrapper;
+use crate::resource_manager::ResourceLimitExceeded;
 use crate::transport::TransportError;
 use crate::Multiaddr;
-use crate::{connection::ConnectionLimit, ConnectedPoint, PeerId};
+use crate::{connection::ConnectionLimit, ConnectedPoint, GatingStage, PeerId};
 use std::{fmt, io};
 
 /// Errors that can occur in the context of an established `Connection`.
@@ -107,6 +108,14 @@ pub enum PendingConnectionError<TTransErr> {
         endpoint: ConnectedPoint,
     },
 
+    /// The connection was denied by the configured
+    /// [`ConnectionGater`](crate::ConnectionGater).
+    Denied(GatingStage),
+
+    /// The connection exceeded the limits of the configured
+    /// [`ResourceManager`](crate::resource_manager::ResourceManager).
+    ResourceLimitExceeded(ResourceLimitExceeded),
+
     /// An I/O error occurred on the connection.
     // TODO: Eventually this should also be a custom error?
     IO(io::Error),
@@ -123,6 +132,10 @@ impl<T> PendingConnectionError<T> {
             PendingConnectionError::WrongPeerId { obtained, endpoint } => {
                 PendingConnectionError::WrongPeerId { obtained, endpoint }
             }
+            PendingConnectionError::Denied(s) => PendingConnectionError::Denied(s),
+            PendingConnectionError::ResourceLimitExceeded(e) => {
+                PendingConnectionError::ResourceLimitExceeded(e)
+            }
             PendingConnectionError::IO(e) => PendingConnectionError::IO(e),
         }
     }
@@ -153,6 +166,12 @@ where
                     obtained, endpoint
                 )
             }
+            PendingConnectionError::Denied(stage) => {
+                write!(f, "Pending connection: Connection {}.", stage)
+            }
+            PendingConnectionError::ResourceLimitExceeded(e) => {
+                write!(f, "Pending connection: {}.", e)
+            }
         }
     }
 }
@@ -168,6 +187,8 @@ where
             PendingConnectionError::WrongPeerId { .. } => None,
             PendingConnectionError::Aborted => None,
             PendingConnectionError::ConnectionLimit(..) => None,
+            PendingConnectionError::Denied(_) => None,
+            PendingConnectionError::ResourceLimitExceeded(e) => Some(e),
         }
     }
 }
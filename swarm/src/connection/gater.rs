@@ -0,0 +1,220 @@
+// Copyright 2022 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Pluggable gating of connections at the various stages of their establishment.
+
+use libp2p_core::connection::ConnectedPoint;
+use libp2p_core::{Multiaddr, PeerId};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Decides whether a connection may proceed at each stage of its establishment.
+///
+/// A gater is installed via [`SwarmBuilder::connection_gater`](crate::SwarmBuilder::connection_gater)
+/// and consulted by the [`Swarm`](crate::Swarm):
+///
+///   1. [`ConnectionGater::allow_dial`] for every address before it is dialed.
+///   2. [`ConnectionGater::allow_accept`] for every inbound connection accepted by a listener,
+///      before any upgrade is applied to it.
+///   3. [`ConnectionGater::allow_secured`] once the remote has been authenticated.
+///   4. [`ConnectionGater::allow_upgraded`] once the stream multiplexer has been negotiated.
+///
+/// The [`Transport`](libp2p_core::Transport) of a [`Swarm`](crate::Swarm) performs the security
+/// and multiplexer upgrades in one go. To evaluate stage 3 in between the two, the transport
+/// has to be built with the check of [`SharedConnectionGater::secured_check`]. Otherwise
+/// stage 3 is evaluated together with stage 4 once the upgraded connection reaches the
+/// [`Swarm`](crate::Swarm), before it counts as established and before any substream is
+/// opened on it.
+///
+/// A rejection closes the connection and is reported as [`PendingConnectionError::Denied`]
+/// (or [`DialError::Denied`] for outbound connections), carrying the [`GatingStage`] at which
+/// it occurred.
+///
+/// All methods allow the connection by default.
+///
+/// [`PendingConnectionError::Denied`]: crate::PendingConnectionError::Denied
+/// [`DialError::Denied`]: crate::DialError::Denied
+pub trait ConnectionGater: Send + 'static {
+    /// Whether the given address may be dialed, optionally to reach the given peer.
+    fn allow_dial(&mut self, _peer: Option<&PeerId>, _address: &Multiaddr) -> bool {
+        true
+    }
+
+    /// Whether an inbound connection from `send_back_addr` on `local_addr` may be accepted.
+    fn allow_accept(&mut self, _local_addr: &Multiaddr, _send_back_addr: &Multiaddr) -> bool {
+        true
+    }
+
+    /// Whether a connection to the authenticated `peer` may proceed.
+    fn allow_secured(&mut self, _peer: &PeerId, _endpoint: &ConnectedPoint) -> bool {
+        true
+    }
+
+    /// Whether a fully upgraded connection to `peer` may be established.
+    fn allow_upgraded(&mut self, _peer: &PeerId, _endpoint: &ConnectedPoint) -> bool {
+        true
+    }
+}
+
+/// The stage at which a [`ConnectionGater`] denied a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GatingStage {
+    /// [`ConnectionGater::allow_dial`] denied all addresses of the dial.
+    Dial,
+    /// [`ConnectionGater::allow_accept`] denied the inbound connection.
+    Accept,
+    /// [`ConnectionGater::allow_secured`] denied the connection to the given peer.
+    Secured(PeerId),
+    /// [`ConnectionGater::allow_upgraded`] denied the connection to the given peer.
+    Upgraded(PeerId),
+}
+
+impl fmt::Display for GatingStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GatingStage::Dial => write!(f, "denied before dialing"),
+            GatingStage::Accept => write!(f, "denied on accept"),
+            GatingStage::Secured(peer) => {
+                write!(f, "denied after authenticating peer {}", peer)
+            }
+            GatingStage::Upgraded(peer) => {
+                write!(f, "denied after upgrading connection to peer {}", peer)
+            }
+        }
+    }
+}
+
+/// A [`ConnectionGater`] shared between a [`Swarm`](crate::Swarm) and its
+/// [`Transport`](libp2p_core::Transport).
+///
+/// Any [`ConnectionGater`] converts into a [`SharedConnectionGater`]. Create one explicitly to
+/// consult [`ConnectionGater::allow_secured`] from within the transport upgrade:
+///
+/// ```
+/// # use libp2p::core::{identity, transport::MemoryTransport, upgrade, Transport};
+/// # use libp2p::plaintext::PlainText2Config;
+/// # use libp2p::yamux::YamuxConfig;
+/// # use libp2p_swarm::{ConnectionGater, SharedConnectionGater};
+/// struct AllowAll;
+///
+/// impl ConnectionGater for AllowAll {}
+///
+/// let local_public_key = identity::Keypair::generate_ed25519().public();
+/// let gater = SharedConnectionGater::new(AllowAll);
+/// let transport = MemoryTransport::default()
+///     .upgrade(upgrade::Version::V1)
+///     .authenticate(PlainText2Config { local_public_key })
+///     .check(gater.secured_check())
+///     .multiplex(YamuxConfig::default())
+///     .boxed();
+/// // Hand both `transport` and `gater` to the `SwarmBuilder`.
+/// ```
+#[derive(Clone)]
+pub struct SharedConnectionGater {
+    gater: Arc<Mutex<Box<dyn ConnectionGater>>>,
+    /// Whether [`ConnectionGater::allow_secured`] is evaluated within the transport.
+    secured_by_transport: Arc<AtomicBool>,
+    /// Peers denied within the transport, by the endpoint of their connection.
+    denied: Arc<Mutex<HashMap<ConnectedPoint, PeerId>>>,
+}
+
+impl SharedConnectionGater {
+    /// Creates a new [`SharedConnectionGater`] around the given gater.
+    pub fn new(gater: impl ConnectionGater) -> Self {
+        SharedConnectionGater {
+            gater: Arc::new(Mutex::new(Box::new(gater))),
+            secured_by_transport: Arc::new(AtomicBool::new(false)),
+            denied: Default::default(),
+        }
+    }
+
+    /// Returns a check evaluating [`ConnectionGater::allow_secured`], to be installed between
+    /// the security and the multiplexer upgrade of the transport via
+    /// [`Authenticated::check`](libp2p_core::transport::upgrade::Authenticated::check).
+    ///
+    /// Once this is called, the [`Swarm`](crate::Swarm) no longer evaluates
+    /// [`ConnectionGater::allow_secured`] itself.
+    pub fn secured_check(&self) -> impl FnMut(&PeerId, &ConnectedPoint) -> bool + Clone {
+        self.secured_by_transport.store(true, Ordering::Relaxed);
+        let this = self.clone();
+        move |peer, endpoint| {
+            if this.lock().allow_secured(peer, endpoint) {
+                return true;
+            }
+            this.lock_denied().insert(endpoint.clone(), *peer);
+            false
+        }
+    }
+
+    pub(crate) fn allow_dial(&self, peer: Option<&PeerId>, address: &Multiaddr) -> bool {
+        self.lock().allow_dial(peer, address)
+    }
+
+    pub(crate) fn allow_accept(&self, local_addr: &Multiaddr, send_back_addr: &Multiaddr) -> bool {
+        self.lock().allow_accept(local_addr, send_back_addr)
+    }
+
+    /// Evaluates the stages of an upgraded connection not yet evaluated within the transport.
+    pub(crate) fn check_upgraded(
+        &self,
+        peer: &PeerId,
+        endpoint: &ConnectedPoint,
+    ) -> Result<(), GatingStage> {
+        let mut gater = self.lock();
+        if !self.secured_by_transport.load(Ordering::Relaxed)
+            && !gater.allow_secured(peer, endpoint)
+        {
+            return Err(GatingStage::Secured(*peer));
+        }
+        if !gater.allow_upgraded(peer, endpoint) {
+            return Err(GatingStage::Upgraded(*peer));
+        }
+        Ok(())
+    }
+
+    /// Returns the peer denied within the transport on a connection with the given endpoint,
+    /// if any.
+    pub(crate) fn take_denied(&self, endpoint: &ConnectedPoint) -> Option<PeerId> {
+        if !self.secured_by_transport.load(Ordering::Relaxed) {
+            return None;
+        }
+        self.lock_denied().remove(endpoint)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Box<dyn ConnectionGater>> {
+        self.gater
+            .lock()
+            .expect("Connection gater lock not to be poisoned.")
+    }
+
+    fn lock_denied(&self) -> MutexGuard<'_, HashMap<ConnectedPoint, PeerId>> {
+        self.denied
+            .lock()
+            .expect("Connection gater lock not to be poisoned.")
+    }
+}
+
+impl<G: ConnectionGater> From<G> for SharedConnectionGater {
+    fn from(gater: G) -> Self {
+        SharedConnectionGater::new(gater)
+    }
+}
@@ -22,6 +22,9 @@ use crate::connection::{Substream, SubstreamEndpoint};
 use crate::handler::{
     ConnectionHandler, ConnectionHandlerEvent, ConnectionHandlerUpgrErr, KeepAlive,
 };
+use crate::resource_manager::{
+    ConnectionScope, Resource, ResourceLimitExceeded, ScopedUpgrade, StreamScope,
+};
 use crate::upgrade::SendWrapper;
 
 use futures::prelude::*;
@@ -33,6 +36,8 @@ use libp2p_core::{
     upgrade::{self, InboundUpgradeApply, OutboundUpgradeApply, UpgradeError},
     Multiaddr,
 };
+use std::collections::VecDeque;
+use std::sync::Arc;
 use std::{error, fmt, pin::Pin, task::Context, task::Poll, time::Duration};
 
 /// A wrapper for an underlying [`ConnectionHandler`].
@@ -54,7 +59,7 @@ where
             TConnectionHandler::InboundOpenInfo,
             InboundUpgradeApply<
                 Substream<StreamMuxerBox>,
-                SendWrapper<TConnectionHandler::InboundProtocol>,
+                ScopedUpgrade<SendWrapper<TConnectionHandler::InboundProtocol>>,
             >,
         >,
     >,
@@ -64,7 +69,7 @@ where
             TConnectionHandler::OutboundOpenInfo,
             OutboundUpgradeApply<
                 Substream<StreamMuxerBox>,
-                SendWrapper<TConnectionHandler::OutboundProtocol>,
+                ScopedUpgrade<SendWrapper<TConnectionHandler::OutboundProtocol>>,
             >,
         >,
     >,
@@ -87,6 +92,10 @@ where
     /// the total number of streams can be enforced at the [`StreamMuxerBox`]
     /// level.
     max_negotiating_inbound_streams: usize,
+    /// The resource manager scope of the connection, if any.
+    resource_scope: Option<Arc<ConnectionScope>>,
+    /// Substreams rejected by the resource manager, to be reported to the [`crate::Swarm`].
+    denied_streams: VecDeque<ResourceLimitExceeded>,
 }
 
 impl<TConnectionHandler: ConnectionHandler> std::fmt::Debug for HandlerWrapper<TConnectionHandler> {
@@ -109,6 +118,7 @@ impl<TConnectionHandler: ConnectionHandler> HandlerWrapper<TConnectionHandler> {
         handler: TConnectionHandler,
         substream_upgrade_protocol_override: Option<upgrade::Version>,
         max_negotiating_inbound_streams: usize,
+        resource_scope: Option<Arc<ConnectionScope>>,
     ) -> Self {
         Self {
             handler,
@@ -119,6 +129,8 @@ impl<TConnectionHandler: ConnectionHandler> HandlerWrapper<TConnectionHandler> {
             shutdown: Shutdown::None,
             substream_upgrade_protocol_override,
             max_negotiating_inbound_streams,
+            resource_scope,
+            denied_streams: VecDeque::new(),
         }
     }
 
@@ -131,6 +143,8 @@ struct SubstreamUpgrade<UserData, Upgrade> {
     user_data: Option<UserData>,
     timeout: Delay,
     upgrade: Upgrade,
+    /// The resource manager scope of the substream, if any.
+    scope: Option<Arc<StreamScope>>,
 }
 
 impl<UserData, Upgrade> Unpin for SubstreamUpgrade<UserData, Upgrade> {}
@@ -159,12 +173,20 @@ where
         }
 
         match self.upgrade.poll_unpin(cx) {
-            Poll::Ready(Ok(upgrade)) => Poll::Ready((
-                self.user_data
-                    .take()
-                    .expect("Future not to be polled again once ready."),
-                Ok(upgrade),
-            )),
+            Poll::Ready(Ok(upgrade)) => {
+                // Account the substream to its negotiated protocol, dropping and thus
+                // resetting it if that exceeds the protocol's limits.
+                let result = match self.scope.as_ref().map(|s| s.admit()) {
+                    Some(Err(e)) => Err(ConnectionHandlerUpgrErr::ResourceLimitExceeded(e)),
+                    Some(Ok(())) | None => Ok(upgrade),
+                };
+                Poll::Ready((
+                    self.user_data
+                        .take()
+                        .expect("Future not to be polled again once ready."),
+                    result,
+                ))
+            }
             Poll::Ready(Err(err)) => Poll::Ready((
                 self.user_data
                     .take()
@@ -263,15 +285,32 @@ where
                     return;
                 }
 
+                let scope = match self.open_stream_scope(Resource::InboundStreams) {
+                    Ok(scope) => scope,
+                    Err(error) => {
+                        log::debug!("Incoming substream exceeding resource limits: {}", error);
+                        self.denied_streams.push_back(error);
+                        return;
+                    }
+                };
+                let mut substream = substream;
+                if let Some(scope) = &scope {
+                    substream.set_scope(scope.clone());
+                }
+
                 let protocol = self.handler.listen_protocol();
                 let timeout = *protocol.timeout();
                 let (upgrade, user_data) = protocol.into_upgrade();
-                let upgrade = upgrade::apply_inbound(substream, SendWrapper(upgrade));
+                let upgrade = upgrade::apply_inbound(
+                    substream,
+                    ScopedUpgrade::new(SendWrapper(upgrade), scope.clone()),
+                );
                 let timeout = Delay::new(timeout);
                 self.negotiating_in.push(SubstreamUpgrade {
                     user_data: Some(user_data),
                     timeout,
                     upgrade,
+                    scope,
                 });
             }
             SubstreamEndpoint::Dialer((upgrade_id, user_data, timeout)) => {
@@ -288,6 +327,24 @@ where
                 };
 
                 let (_, upgrade) = self.queued_dial_upgrades.remove(pos);
+
+                let scope = match self.open_stream_scope(Resource::OutboundStreams) {
+                    Ok(scope) => scope,
+                    Err(error) => {
+                        log::debug!("Outgoing substream exceeding resource limits: {}", error);
+                        self.handler.inject_dial_upgrade_error(
+                            user_data,
+                            ConnectionHandlerUpgrErr::ResourceLimitExceeded(error.clone()),
+                        );
+                        self.denied_streams.push_back(error);
+                        return;
+                    }
+                };
+                let mut substream = substream;
+                if let Some(scope) = &scope {
+                    substream.set_scope(scope.clone());
+                }
+
                 let mut version = upgrade::Version::default();
                 if let Some(v) = self.substream_upgrade_protocol_override {
                     if v != version {
@@ -299,17 +356,33 @@ where
                         version = v;
                     }
                 }
-                let upgrade = upgrade::apply_outbound(substream, upgrade, version);
+                let upgrade = upgrade::apply_outbound(
+                    substream,
+                    ScopedUpgrade::new(upgrade, scope.clone()),
+                    version,
+                );
                 let timeout = Delay::new(timeout);
                 self.negotiating_out.push(SubstreamUpgrade {
                     user_data: Some(user_data),
                     timeout,
                     upgrade,
+                    scope,
                 });
             }
         }
     }
 
+    /// Accounts a new substream in the resource manager scope of the connection, if any.
+    fn open_stream_scope(
+        &self,
+        resource: Resource,
+    ) -> Result<Option<Arc<StreamScope>>, ResourceLimitExceeded> {
+        self.resource_scope
+            .as_ref()
+            .map(|scope| scope.open_stream(resource))
+            .transpose()
+    }
+
     pub fn inject_event(&mut self, event: TConnectionHandler::InEvent) {
         self.handler.inject_event(event);
     }
@@ -354,6 +427,11 @@ where
         >,
     > {
         loop {
+            // Report substreams rejected by the resource manager.
+            if let Some(error) = self.denied_streams.pop_front() {
+                return Poll::Ready(Ok(Event::ResourceLimitExceeded(error)));
+            }
+
             // Poll the [`ConnectionHandler`].
             if let Poll::Ready(handler_event) = self.handler.poll(cx) {
                 let wrapper_event = self.handle_connection_handler_event(handler_event)?;
@@ -366,7 +444,12 @@ where
                     Ok(upgrade) => self
                         .handler
                         .inject_fully_negotiated_outbound(upgrade, user_data),
-                    Err(err) => self.handler.inject_dial_upgrade_error(user_data, err),
+                    Err(err) => {
+                        if let ConnectionHandlerUpgrErr::ResourceLimitExceeded(e) = &err {
+                            self.denied_streams.push_back(e.clone());
+                        }
+                        self.handler.inject_dial_upgrade_error(user_data, err)
+                    }
                 }
 
                 // After the `inject_*` calls, the [`ConnectionHandler`] might be able to make progress.
@@ -380,7 +463,12 @@ where
                     Ok(upgrade) => self
                         .handler
                         .inject_fully_negotiated_inbound(upgrade, user_data),
-                    Err(err) => self.handler.inject_listen_upgrade_error(user_data, err),
+                    Err(err) => {
+                        if let ConnectionHandlerUpgrErr::ResourceLimitExceeded(e) = &err {
+                            self.denied_streams.push_back(e.clone());
+                        }
+                        self.handler.inject_listen_upgrade_error(user_data, err)
+                    }
                 }
 
                 // After the `inject_*` calls, the [`ConnectionHandler`] might be able to make progress.
@@ -429,11 +517,14 @@ where
 }
 
 /// Event produced by a [`HandlerWrapper`].
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Event<TOutboundOpenInfo, TCustom> {
     /// Require a new outbound substream to be opened with the remote.
     OutboundSubstreamRequest(TOutboundOpenInfo),
 
+    /// A substream was rejected by the resource manager.
+    ResourceLimitExceeded(ResourceLimitExceeded),
+
     /// Other event.
     Custom(TCustom),
 }
@@ -22,9 +22,11 @@
 use crate::{
     behaviour::{THandlerInEvent, THandlerOutEvent},
     connection::{
-        Connected, ConnectionError, ConnectionLimit, IncomingInfo, PendingConnectionError,
-        PendingInboundConnectionError, PendingOutboundConnectionError,
+        Connected, ConnectionError, ConnectionLimit, GatingStage, IncomingInfo,
+        PendingConnectionError, PendingInboundConnectionError, PendingOutboundConnectionError,
+        SharedConnectionGater,
     },
+    resource_manager::{ResourceLimitExceeded, ResourceManager},
     transport::{Transport, TransportError},
     ConnectedPoint, ConnectionHandler, Executor, IntoConnectionHandler, Multiaddr, PeerId,
 };
@@ -97,6 +99,12 @@ where
     /// current thread when the [`Pool`] is polled for new events.
     executor: Option<Box<dyn Executor + Send>>,
 
+    /// The configured connection gater, if any.
+    gater: Option<SharedConnectionGater>,
+
+    /// The configured resource manager, if any.
+    resource_manager: Option<ResourceManager>,
+
     /// If no `executor` is configured, tasks are kept in this set and
     /// polled on the current thread when the [`Pool`] is polled for new events.
     local_spawns: FuturesUnordered<Pin<Box<dyn Future<Output = ()> + Send>>>,
@@ -229,6 +237,14 @@ where
         handler: THandler,
     },
 
+    /// A substream on a connection was rejected by the resource manager.
+    ResourceLimitExceeded {
+        /// The remote peer of the connection.
+        peer_id: PeerId,
+        /// The exceeded limit.
+        error: ResourceLimitExceeded,
+    },
+
     /// A node has produced an event.
     ConnectionEvent {
         id: ConnectionId,
@@ -270,6 +286,8 @@ where
             substream_upgrade_protocol_override: config.substream_upgrade_protocol_override,
             max_negotiating_inbound_streams: config.max_negotiating_inbound_streams,
             executor: config.executor,
+            gater: config.connection_gater,
+            resource_manager: config.resource_manager,
             local_spawns: FuturesUnordered::new(),
             pending_connection_events_tx,
             pending_connection_events_rx,
@@ -283,6 +301,27 @@ where
         &self.counters
     }
 
+    /// Asks the configured connection gater, if any, whether the given address may be dialed.
+    pub fn allow_dial(&mut self, peer: Option<&PeerId>, address: &Multiaddr) -> bool {
+        self.gater
+            .as_ref()
+            .map_or(true, |g| g.allow_dial(peer, address))
+    }
+
+    /// Asks the configured connection gater, if any, whether an inbound connection may be
+    /// accepted.
+    pub fn allow_accept(&mut self, local_addr: &Multiaddr, send_back_addr: &Multiaddr) -> bool {
+        self.gater
+            .as_ref()
+            .map_or(true, |g| g.allow_accept(local_addr, send_back_addr))
+    }
+
+    /// Returns the peer the configured connection gater, if any, denied within the transport
+    /// on a connection with the given endpoint.
+    fn take_denied(&self, endpoint: ConnectedPoint) -> Option<PeerId> {
+        self.gater.as_ref().and_then(|g| g.take_denied(&endpoint))
+    }
+
     /// Gets an entry representing a connection in the pool.
     ///
     /// Returns `None` if the pool has no connection with the given ID.
@@ -542,6 +581,12 @@ where
             Poll::Ready(Some(task::EstablishedConnectionEvent::Notify { id, peer_id, event })) => {
                 return Poll::Ready(PoolEvent::ConnectionEvent { peer_id, id, event });
             }
+            Poll::Ready(Some(task::EstablishedConnectionEvent::ResourceLimitExceeded {
+                peer_id,
+                error,
+            })) => {
+                return Poll::Ready(PoolEvent::ResourceLimitExceeded { peer_id, error });
+            }
             Poll::Ready(Some(task::EstablishedConnectionEvent::AddressChange {
                 id,
                 peer_id,
@@ -620,13 +665,21 @@ where
                     self.counters.dec_pending(&endpoint);
 
                     let (endpoint, concurrent_dial_errors) = match (endpoint, outgoing) {
-                        (PendingPoint::Dialer { role_override }, Some((address, errors))) => (
-                            ConnectedPoint::Dialer {
-                                address,
-                                role_override,
-                            },
-                            Some(errors),
-                        ),
+                        (PendingPoint::Dialer { role_override }, Some((address, errors))) => {
+                            for (address, _) in &errors {
+                                self.take_denied(ConnectedPoint::Dialer {
+                                    address: address.clone(),
+                                    role_override,
+                                });
+                            }
+                            (
+                                ConnectedPoint::Dialer {
+                                    address,
+                                    role_override,
+                                },
+                                Some(errors),
+                            )
+                        }
                         (
                             PendingPoint::Listener {
                                 local_addr,
@@ -648,7 +701,7 @@ where
                         ),
                     };
 
-                    let error: Result<(), PendingInboundConnectionError<_>> = self
+                    let resource_scope: Result<_, PendingInboundConnectionError<_>> = self
                         .counters
                         // Check general established connection limit.
                         .check_max_established(&endpoint)
@@ -687,48 +740,67 @@ where
                             } else {
                                 Ok(())
                             }
+                        })
+                        // Check the connection gater, if any.
+                        .and_then(|()| {
+                            self.gater
+                                .as_ref()
+                                .map_or(Ok(()), |g| g.check_upgraded(&obtained_peer_id, &endpoint))
+                                .map_err(PendingConnectionError::Denied)
+                        })
+                        // Account the connection in the resource manager, if any.
+                        .and_then(|()| {
+                            self.resource_manager
+                                .as_ref()
+                                .map(|m| m.open_connection(obtained_peer_id, id))
+                                .transpose()
+                                .map_err(PendingConnectionError::ResourceLimitExceeded)
                         });
 
-                    if let Err(error) = error {
-                        self.spawn(
-                            poll_fn(move |cx| {
-                                if let Err(e) = ready!(muxer.poll_close(cx)) {
-                                    log::debug!(
-                                        "Failed to close connection {:?} to peer {}: {:?}",
+                    let resource_scope = match resource_scope {
+                        Ok(resource_scope) => resource_scope,
+                        Err(error) => {
+                            self.spawn(
+                                poll_fn(move |cx| {
+                                    if let Err(e) = ready!(muxer.poll_close(cx)) {
+                                        log::debug!(
+                                            "Failed to close connection {:?} to peer {}: {:?}",
+                                            id,
+                                            obtained_peer_id,
+                                            e
+                                        );
+                                    }
+                                    Poll::Ready(())
+                                })
+                                .boxed(),
+                            );
+
+                            match endpoint {
+                                ConnectedPoint::Dialer { .. } => {
+                                    return Poll::Ready(PoolEvent::PendingOutboundConnectionError {
                                         id,
-                                        obtained_peer_id,
-                                        e
-                                    );
+                                        error: error.map(|t| {
+                                            vec![(endpoint.get_remote_address().clone(), t)]
+                                        }),
+                                        handler,
+                                        peer: expected_peer_id.or(Some(obtained_peer_id)),
+                                    })
                                 }
-                                Poll::Ready(())
-                            })
-                            .boxed(),
-                        );
-
-                        match endpoint {
-                            ConnectedPoint::Dialer { .. } => {
-                                return Poll::Ready(PoolEvent::PendingOutboundConnectionError {
-                                    id,
-                                    error: error
-                                        .map(|t| vec![(endpoint.get_remote_address().clone(), t)]),
-                                    handler,
-                                    peer: expected_peer_id.or(Some(obtained_peer_id)),
-                                })
-                            }
-                            ConnectedPoint::Listener {
-                                send_back_addr,
-                                local_addr,
-                            } => {
-                                return Poll::Ready(PoolEvent::PendingInboundConnectionError {
-                                    id,
-                                    error,
-                                    handler,
+                                ConnectedPoint::Listener {
                                     send_back_addr,
                                     local_addr,
-                                })
-                            }
-                        };
-                    }
+                                } => {
+                                    return Poll::Ready(PoolEvent::PendingInboundConnectionError {
+                                        id,
+                                        error,
+                                        handler,
+                                        send_back_addr,
+                                        local_addr,
+                                    })
+                                }
+                            };
+                        }
+                    };
 
                     // Add the connection to the pool.
                     let conns = self.established.entry(obtained_peer_id).or_default();
@@ -751,6 +823,7 @@ where
                         handler.into_handler(&obtained_peer_id, &endpoint),
                         self.substream_upgrade_protocol_override,
                         self.max_negotiating_inbound_streams,
+                        resource_scope,
                     );
                     self.spawn(
                         task::new_for_established_connection(
@@ -787,12 +860,30 @@ where
                         self.counters.dec_pending(&endpoint);
 
                         match (endpoint, error) {
-                            (PendingPoint::Dialer { .. }, Either::Left(error)) => {
+                            (PendingPoint::Dialer { role_override }, Either::Left(mut error)) => {
+                                let mut peer = peer_id;
+                                if let PendingConnectionError::Transport(errors) = &error {
+                                    let denied = errors
+                                        .iter()
+                                        .filter_map(|(address, _)| {
+                                            self.take_denied(ConnectedPoint::Dialer {
+                                                address: address.clone(),
+                                                role_override,
+                                            })
+                                        })
+                                        .last();
+                                    if let Some(denied) = denied {
+                                        peer = peer.or(Some(denied));
+                                        error = PendingConnectionError::Denied(
+                                            GatingStage::Secured(denied),
+                                        );
+                                    }
+                                }
                                 return Poll::Ready(PoolEvent::PendingOutboundConnectionError {
                                     id,
                                     error,
                                     handler,
-                                    peer: peer_id,
+                                    peer,
                                 });
                             }
                             (
@@ -800,8 +891,19 @@ where
                                     send_back_addr,
                                     local_addr,
                                 },
-                                Either::Right(error),
+                                Either::Right(mut error),
                             ) => {
+                                if let PendingConnectionError::Transport(_) = &error {
+                                    let denied = self.take_denied(ConnectedPoint::Listener {
+                                        local_addr: local_addr.clone(),
+                                        send_back_addr: send_back_addr.clone(),
+                                    });
+                                    if let Some(peer) = denied {
+                                        error = PendingConnectionError::Denied(
+                                            GatingStage::Secured(peer),
+                                        );
+                                    }
+                                }
                                 return Poll::Ready(PoolEvent::PendingInboundConnectionError {
                                     id,
                                     error,
@@ -1165,6 +1267,12 @@ pub struct PoolConfig {
     ///
     /// See [super::handler_wrapper::HandlerWrapper::max_negotiating_inbound_streams].
     max_negotiating_inbound_streams: usize,
+
+    /// The connection gater to consult while establishing connections, if any.
+    connection_gater: Option<SharedConnectionGater>,
+
+    /// The resource manager accounting connections and substreams, if any.
+    resource_manager: Option<ResourceManager>,
 }
 
 impl Default for PoolConfig {
@@ -1177,6 +1285,8 @@ impl Default for PoolConfig {
             dial_concurrency_factor: NonZeroU8::new(1).expect("1 > 0"),
             substream_upgrade_protocol_override: None,
             max_negotiating_inbound_streams: 128,
+            connection_gater: None,
+            resource_manager: None,
         }
     }
 }
@@ -1243,6 +1353,18 @@ impl PoolConfig {
         self.max_negotiating_inbound_streams = v;
         self
     }
+
+    /// Configures the connection gater to consult while establishing connections.
+    pub fn with_connection_gater(mut self, gater: SharedConnectionGater) -> Self {
+        self.connection_gater = Some(gater);
+        self
+    }
+
+    /// Configures the [`ResourceManager`] accounting connections and substreams.
+    pub fn with_resource_manager(mut self, manager: ResourceManager) -> Self {
+        self.resource_manager = Some(manager);
+        self
+    }
 }
 
 trait EntryExt<'a, K, V> {
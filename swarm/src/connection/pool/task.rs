@@ -26,6 +26,7 @@ use crate::{
     connection::{
         self, ConnectionError, PendingInboundConnectionError, PendingOutboundConnectionError,
     },
+    resource_manager::ResourceLimitExceeded,
     transport::{Transport, TransportError},
     ConnectionHandler, Multiaddr, PeerId,
 };
@@ -85,6 +86,11 @@ pub enum EstablishedConnectionEvent<THandler: ConnectionHandler> {
         peer_id: PeerId,
         event: THandler::OutEvent,
     },
+    /// A substream on the connection was rejected by the resource manager.
+    ResourceLimitExceeded {
+        peer_id: PeerId,
+        error: ResourceLimitExceeded,
+    },
     /// A connection closed, possibly due to an error.
     ///
     /// If `error` is `None`, the connection has completed
@@ -225,6 +231,14 @@ pub async fn new_for_established_connection<THandler>(
                             })
                             .await;
                     }
+                    Ok(connection::Event::ResourceLimitExceeded(error)) => {
+                        let _ = events
+                            .send(EstablishedConnectionEvent::ResourceLimitExceeded {
+                                peer_id,
+                                error,
+                            })
+                            .await;
+                    }
                     Ok(connection::Event::AddressChange(new_address)) => {
                         let _ = events
                             .send(EstablishedConnectionEvent::AddressChange {
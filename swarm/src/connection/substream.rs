@@ -18,12 +18,13 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
+use crate::resource_manager::StreamScope;
 use futures::prelude::*;
 use libp2p_core::multiaddr::Multiaddr;
 use libp2p_core::muxing::{substream_from_ref, StreamMuxer, StreamMuxerEvent, SubstreamRef};
 use smallvec::SmallVec;
 use std::sync::Arc;
-use std::{fmt, io::Error as IoError, pin::Pin, task::Context, task::Poll};
+use std::{fmt, io, io::Error as IoError, pin::Pin, task::Context, task::Poll};
 
 /// Endpoint for a received substream.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -56,7 +57,77 @@ pub struct Close<TMuxer> {
 }
 
 /// A successfully opened substream.
-pub type Substream<TMuxer> = SubstreamRef<Arc<TMuxer>>;
+pub struct Substream<TMuxer>
+where
+    TMuxer: StreamMuxer,
+{
+    inner: SubstreamRef<Arc<TMuxer>>,
+    /// Keeps the substream accounted in the
+    /// [`ResourceManager`](crate::resource_manager::ResourceManager), if any, for as long as
+    /// it is alive.
+    scope: Option<Arc<StreamScope>>,
+}
+
+impl<TMuxer> Substream<TMuxer>
+where
+    TMuxer: StreamMuxer,
+{
+    fn new(inner: SubstreamRef<Arc<TMuxer>>) -> Self {
+        Substream { inner, scope: None }
+    }
+
+    /// Accounts the substream in the given scope for as long as it is alive.
+    pub(crate) fn set_scope(&mut self, scope: Arc<StreamScope>) {
+        self.scope = Some(scope);
+    }
+}
+
+impl<TMuxer> fmt::Debug for Substream<TMuxer>
+where
+    TMuxer: StreamMuxer,
+    TMuxer::Substream: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Substream")
+            .field("inner", &self.inner)
+            .field("scope", &self.scope)
+            .finish()
+    }
+}
+
+impl<TMuxer> AsyncRead for Substream<TMuxer>
+where
+    TMuxer: StreamMuxer,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<TMuxer> AsyncWrite for Substream<TMuxer>
+where
+    TMuxer: StreamMuxer,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
 
 /// Event that can happen on the `Muxing`.
 pub enum SubstreamEvent<TMuxer, TUserData>
@@ -141,7 +212,7 @@ where
         // Polling inbound substream.
         match self.inner.poll_event(cx) {
             Poll::Ready(Ok(StreamMuxerEvent::InboundSubstream(substream))) => {
-                let substream = substream_from_ref(self.inner.clone(), substream);
+                let substream = Substream::new(substream_from_ref(self.inner.clone(), substream));
                 return Poll::Ready(Ok(SubstreamEvent::InboundSubstream { substream }));
             }
             Poll::Ready(Ok(StreamMuxerEvent::AddressChange(addr))) => {
@@ -157,7 +228,8 @@ where
             let (user_data, mut outbound) = self.outbound_substreams.swap_remove(n);
             match self.inner.poll_outbound(cx, &mut outbound) {
                 Poll::Ready(Ok(substream)) => {
-                    let substream = substream_from_ref(self.inner.clone(), substream);
+                    let substream =
+                        Substream::new(substream_from_ref(self.inner.clone(), substream));
                     self.inner.destroy_outbound(outbound);
                     return Poll::Ready(Ok(SubstreamEvent::OutboundSubstream {
                         user_data,
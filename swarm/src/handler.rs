@@ -48,6 +48,7 @@ mod select;
 
 pub use crate::upgrade::{InboundUpgradeSend, OutboundUpgradeSend, SendWrapper, UpgradeInfoSend};
 
+use crate::resource_manager::ResourceLimitExceeded;
 use instant::Instant;
 use libp2p_core::{upgrade::UpgradeError, ConnectedPoint, Multiaddr, PeerId};
 use std::{cmp::Ordering, error, fmt, task::Context, task::Poll, time::Duration};
@@ -407,6 +408,9 @@ pub enum ConnectionHandlerUpgrErr<TUpgrErr> {
     Timer,
     /// Error while upgrading the substream to the protocol we want.
     Upgrade(UpgradeError<TUpgrErr>),
+    /// The substream exceeded the limits of the
+    /// [`ResourceManager`](crate::resource_manager::ResourceManager) and was reset.
+    ResourceLimitExceeded(ResourceLimitExceeded),
 }
 
 impl<TUpgrErr> ConnectionHandlerUpgrErr<TUpgrErr> {
@@ -419,6 +423,9 @@ impl<TUpgrErr> ConnectionHandlerUpgrErr<TUpgrErr> {
             ConnectionHandlerUpgrErr::Timeout => ConnectionHandlerUpgrErr::Timeout,
             ConnectionHandlerUpgrErr::Timer => ConnectionHandlerUpgrErr::Timer,
             ConnectionHandlerUpgrErr::Upgrade(e) => ConnectionHandlerUpgrErr::Upgrade(f(e)),
+            ConnectionHandlerUpgrErr::ResourceLimitExceeded(e) => {
+                ConnectionHandlerUpgrErr::ResourceLimitExceeded(e)
+            }
         }
     }
 }
@@ -436,6 +443,7 @@ where
                 write!(f, "Timer error while opening a substream")
             }
             ConnectionHandlerUpgrErr::Upgrade(err) => write!(f, "{}", err),
+            ConnectionHandlerUpgrErr::ResourceLimitExceeded(err) => write!(f, "{}", err),
         }
     }
 }
@@ -449,6 +457,7 @@ where
             ConnectionHandlerUpgrErr::Timeout => None,
             ConnectionHandlerUpgrErr::Timer => None,
             ConnectionHandlerUpgrErr::Upgrade(err) => Some(err),
+            ConnectionHandlerUpgrErr::ResourceLimitExceeded(err) => Some(err),
         }
     }
 }
@@ -165,6 +165,21 @@ where
                 }
                 _ => unreachable!(),
             },
+            ConnectionHandlerUpgrErr::ResourceLimitExceeded(e) => match (self, info) {
+                (Either::Left(handler), Either::Left(info)) => {
+                    handler.inject_dial_upgrade_error(
+                        info,
+                        ConnectionHandlerUpgrErr::ResourceLimitExceeded(e),
+                    );
+                }
+                (Either::Right(handler), Either::Right(info)) => {
+                    handler.inject_dial_upgrade_error(
+                        info,
+                        ConnectionHandlerUpgrErr::ResourceLimitExceeded(e),
+                    );
+                }
+                _ => unreachable!(),
+            },
             ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Select(error)) => match (self, info) {
                 (Either::Left(handler), Either::Left(info)) => {
                     handler.inject_dial_upgrade_error(
@@ -229,6 +244,21 @@ where
                 }
                 _ => unreachable!(),
             },
+            ConnectionHandlerUpgrErr::ResourceLimitExceeded(e) => match (self, info) {
+                (Either::Left(handler), Either::Left(info)) => {
+                    handler.inject_listen_upgrade_error(
+                        info,
+                        ConnectionHandlerUpgrErr::ResourceLimitExceeded(e),
+                    );
+                }
+                (Either::Right(handler), Either::Right(info)) => {
+                    handler.inject_listen_upgrade_error(
+                        info,
+                        ConnectionHandlerUpgrErr::ResourceLimitExceeded(e),
+                    );
+                }
+                _ => unreachable!(),
+            },
             ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Select(error)) => match (self, info) {
                 (Either::Left(handler), Either::Left(info)) => {
                     handler.inject_listen_upgrade_error(
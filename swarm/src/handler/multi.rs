@@ -193,6 +193,16 @@ where
                     }
                 }
             }
+            ConnectionHandlerUpgrErr::ResourceLimitExceeded(e) => {
+                for (k, h) in &mut self.handlers {
+                    if let Some(i) = info.take(k) {
+                        h.inject_listen_upgrade_error(
+                            i,
+                            ConnectionHandlerUpgrErr::ResourceLimitExceeded(e.clone()),
+                        )
+                    }
+                }
+            }
             ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Select(NegotiationError::Failed)) => {
                 for (k, h) in &mut self.handlers {
                     if let Some(i) = info.take(k) {
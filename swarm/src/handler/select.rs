@@ -186,6 +186,12 @@ where
             (EitherOutput::First(info), ConnectionHandlerUpgrErr::Timeout) => self
                 .proto1
                 .inject_dial_upgrade_error(info, ConnectionHandlerUpgrErr::Timeout),
+            (EitherOutput::First(info), ConnectionHandlerUpgrErr::ResourceLimitExceeded(e)) => {
+                self.proto1.inject_dial_upgrade_error(
+                    info,
+                    ConnectionHandlerUpgrErr::ResourceLimitExceeded(e),
+                )
+            }
             (
                 EitherOutput::First(info),
                 ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Select(err)),
@@ -212,6 +218,12 @@ where
             (EitherOutput::Second(info), ConnectionHandlerUpgrErr::Timer) => self
                 .proto2
                 .inject_dial_upgrade_error(info, ConnectionHandlerUpgrErr::Timer),
+            (EitherOutput::Second(info), ConnectionHandlerUpgrErr::ResourceLimitExceeded(e)) => {
+                self.proto2.inject_dial_upgrade_error(
+                    info,
+                    ConnectionHandlerUpgrErr::ResourceLimitExceeded(e),
+                )
+            }
             (
                 EitherOutput::Second(info),
                 ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Select(err)),
@@ -253,6 +265,16 @@ where
                 self.proto2
                     .inject_listen_upgrade_error(i2, ConnectionHandlerUpgrErr::Timeout)
             }
+            ConnectionHandlerUpgrErr::ResourceLimitExceeded(e) => {
+                self.proto1.inject_listen_upgrade_error(
+                    i1,
+                    ConnectionHandlerUpgrErr::ResourceLimitExceeded(e.clone()),
+                );
+                self.proto2.inject_listen_upgrade_error(
+                    i2,
+                    ConnectionHandlerUpgrErr::ResourceLimitExceeded(e),
+                )
+            }
             ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Select(NegotiationError::Failed)) => {
                 self.proto1.inject_listen_upgrade_error(
                     i1,
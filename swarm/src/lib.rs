@@ -62,14 +62,16 @@ mod upgrade;
 pub mod behaviour;
 pub mod dial_opts;
 pub mod handler;
+pub mod resource_manager;
 
 pub use behaviour::{
     CloseConnection, NetworkBehaviour, NetworkBehaviourAction, NetworkBehaviourEventProcess,
     NotifyHandler, PollParameters,
 };
 pub use connection::{
-    ConnectionCounters, ConnectionError, ConnectionLimit, ConnectionLimits, PendingConnectionError,
-    PendingInboundConnectionError, PendingOutboundConnectionError,
+    ConnectionCounters, ConnectionError, ConnectionGater, ConnectionLimit, ConnectionLimits,
+    GatingStage, PendingConnectionError, PendingInboundConnectionError,
+    PendingOutboundConnectionError, SharedConnectionGater,
 };
 pub use handler::{
     ConnectionHandler, ConnectionHandlerEvent, ConnectionHandlerSelect, ConnectionHandlerUpgrErr,
@@ -94,6 +96,7 @@ use libp2p_core::{
     Endpoint, Executor, Multiaddr, Negotiated, PeerId, Transport,
 };
 use registry::{AddressIntoIter, Addresses};
+use resource_manager::{ResourceLimitExceeded, ResourceManager};
 use smallvec::SmallVec;
 use std::collections::HashSet;
 use std::iter;
@@ -247,6 +250,14 @@ pub enum SwarmEvent<TBehaviourOutEvent, THandlerErr> {
     /// [`OutgoingConnectionError`](SwarmEvent::OutgoingConnectionError) event
     /// is reported.
     Dialing(PeerId),
+    /// A substream with the given peer was rejected, as it exceeded the limits of the
+    /// [`ResourceManager`](resource_manager::ResourceManager).
+    ResourceLimitExceeded {
+        /// Identity of the peer the substream was opened with.
+        peer_id: PeerId,
+        /// The exceeded limit.
+        error: ResourceLimitExceeded,
+    },
 }
 
 /// Contains the state of the network, plus the way it should behave.
@@ -502,7 +513,17 @@ where
                 }
             };
 
+        let addresses = addresses
+            .filter(|a| self.pool.allow_dial(peer_id.as_ref(), a))
+            .collect::<Vec<_>>();
+        if addresses.is_empty() {
+            let error = DialError::Denied(GatingStage::Dial);
+            self.behaviour.inject_dial_failure(peer_id, handler, &error);
+            return Err(error);
+        }
+
         let dials = addresses
+            .into_iter()
             .map(|a| match p2p_addr(peer_id, a) {
                 Ok(address) => {
                     let dial = match role_override {
@@ -809,6 +830,9 @@ where
                     self.behaviour.inject_event(peer_id, id, event);
                 }
             }
+            PoolEvent::ResourceLimitExceeded { peer_id, error } => {
+                return Some(SwarmEvent::ResourceLimitExceeded { peer_id, error });
+            }
             PoolEvent::AddressChange {
                 peer_id,
                 id,
@@ -841,6 +865,15 @@ where
                 send_back_addr,
             } => {
                 let handler = self.behaviour.new_handler();
+                if !self.pool.allow_accept(&local_addr, &send_back_addr) {
+                    self.behaviour
+                        .inject_listen_failure(&local_addr, &send_back_addr, handler);
+                    return Some(SwarmEvent::IncomingConnectionError {
+                        local_addr,
+                        send_back_addr,
+                        error: PendingConnectionError::Denied(GatingStage::Accept),
+                    });
+                }
                 match self.pool.add_incoming(
                     upgrade,
                     handler,
@@ -1002,6 +1035,13 @@ where
                     self.pool.disconnect(peer_id);
                 }
             },
+            NetworkBehaviourAction::RefreshSupportedProtocols => {
+                let protocols = supported_protocols(&mut self.behaviour);
+                if protocols != self.supported_protocols {
+                    self.behaviour.inject_supported_protocols_change(&protocols);
+                    self.supported_protocols = protocols;
+                }
+            }
         }
 
         None
@@ -1119,6 +1159,18 @@ enum PendingNotifyHandler {
     Any(SmallVec<[ConnectionId; 10]>),
 }
 
+/// Collects the protocols the handlers of the given behaviour accept on inbound substreams.
+fn supported_protocols<TBehaviour: NetworkBehaviour>(
+    behaviour: &mut TBehaviour,
+) -> SmallVec<[Vec<u8>; 16]> {
+    behaviour
+        .new_handler()
+        .inbound_protocol()
+        .protocol_info()
+        .map(|info| info.protocol_name().to_vec())
+        .collect()
+}
+
 /// Notify a single connection of an event.
 ///
 /// Returns `Some` with the given event if the connection is not currently
@@ -1349,6 +1401,23 @@ where
         self
     }
 
+    /// Configures a [`ConnectionGater`] that is consulted at every stage of
+    /// connection establishment.
+    ///
+    /// See [`SharedConnectionGater`] for consulting the gater between the security and the
+    /// multiplexer upgrade of the transport.
+    pub fn connection_gater(mut self, gater: impl Into<SharedConnectionGater>) -> Self {
+        self.pool_config = self.pool_config.with_connection_gater(gater.into());
+        self
+    }
+
+    /// Configures a [`ResourceManager`](resource_manager::ResourceManager) that accounts and
+    /// limits connections and substreams.
+    pub fn resource_manager(mut self, manager: ResourceManager) -> Self {
+        self.pool_config = self.pool_config.with_resource_manager(manager);
+        self
+    }
+
     /// Configures an override for the substream upgrade protocol to use.
     ///
     /// The subtream upgrade protocol is the multistream-select protocol
@@ -1374,14 +1443,7 @@ where
 
     /// Builds a `Swarm` with the current configuration.
     pub fn build(mut self) -> Swarm<TBehaviour> {
-        let supported_protocols = self
-            .behaviour
-            .new_handler()
-            .inbound_protocol()
-            .protocol_info()
-            .into_iter()
-            .map(|info| info.protocol_name().to_vec())
-            .collect();
+        let supported_protocols = supported_protocols(&mut self.behaviour);
 
         // If no executor has been explicitly configured, try to set up a thread pool.
         let pool_config =
@@ -1442,6 +1504,11 @@ pub enum DialError {
     ConnectionIo(io::Error),
     /// An error occurred while negotiating the transport protocol(s) on a connection.
     Transport(Vec<(Multiaddr, TransportError<io::Error>)>),
+    /// The connection was denied by the configured [`ConnectionGater`].
+    Denied(GatingStage),
+    /// The connection exceeded the limits of the configured
+    /// [`ResourceManager`](resource_manager::ResourceManager).
+    ResourceLimitExceeded(ResourceLimitExceeded),
 }
 
 impl From<PendingOutboundConnectionError<io::Error>> for DialError {
@@ -1454,6 +1521,8 @@ impl From<PendingOutboundConnectionError<io::Error>> for DialError {
             }
             PendingConnectionError::IO(e) => DialError::ConnectionIo(e),
             PendingConnectionError::Transport(e) => DialError::Transport(e),
+            PendingConnectionError::Denied(stage) => DialError::Denied(stage),
+            PendingConnectionError::ResourceLimitExceeded(e) => DialError::ResourceLimitExceeded(e),
         }
     }
 }
@@ -1483,6 +1552,8 @@ impl fmt::Display for DialError {
                 "Dial error: An I/O error occurred on the connection: {:?}.", e
             ),
             DialError::Transport(e) => write!(f, "An error occurred while negotiating the transport protocol(s) on a connection: {:?}.", e),
+            DialError::Denied(stage) => write!(f, "Dial error: Connection {}.", stage),
+            DialError::ResourceLimitExceeded(e) => write!(f, "Dial error: {}.", e),
         }
     }
 }
@@ -1500,6 +1571,8 @@ impl error::Error for DialError {
             DialError::WrongPeerId { .. } => None,
             DialError::ConnectionIo(_) => None,
             DialError::Transport(_) => None,
+            DialError::Denied(_) => None,
+            DialError::ResourceLimitExceeded(e) => Some(e),
         }
     }
 }
@@ -1608,6 +1681,7 @@ fn p2p_addr(peer: Option<PeerId>, addr: Multiaddr) -> Result<Multiaddr, Multiadd
 mod tests {
     use super::*;
     use crate::handler::DummyConnectionHandler;
+    use crate::resource_manager::{Resource, ResourceLimits, Scope, ScopeLimits};
     use crate::test::{CallTraceBehaviour, MockBehaviour};
     use futures::executor::block_on;
     use futures::future::poll_fn;
@@ -1622,6 +1696,7 @@ mod tests {
     use quickcheck::{quickcheck, Arbitrary, Gen, QuickCheck};
     use rand::prelude::SliceRandom;
     use rand::Rng;
+    use std::collections::VecDeque;
 
     // Test execution state.
     // Connection => Disconnecting => Connecting.
@@ -2514,4 +2589,479 @@ mod tests {
             e => panic!("Unexpected swarm event {:?}.", e),
         }
     }
+
+    /// A [`ConnectionGater`] denying the configured stages.
+    #[derive(Default)]
+    struct DenyingGater {
+        dial: bool,
+        accept: bool,
+        secured: Option<PeerId>,
+        upgraded: Option<PeerId>,
+    }
+
+    impl ConnectionGater for DenyingGater {
+        fn allow_dial(&mut self, _: Option<&PeerId>, _: &Multiaddr) -> bool {
+            !self.dial
+        }
+
+        fn allow_accept(&mut self, _: &Multiaddr, _: &Multiaddr) -> bool {
+            !self.accept
+        }
+
+        fn allow_secured(&mut self, peer: &PeerId, _: &ConnectedPoint) -> bool {
+            self.secured != Some(*peer)
+        }
+
+        fn allow_upgraded(&mut self, peer: &PeerId, _: &ConnectedPoint) -> bool {
+            self.upgraded != Some(*peer)
+        }
+    }
+
+    #[test]
+    fn connection_gater_denies_dial() {
+        let mut swarm = new_test_swarm::<_, ()>(DummyConnectionHandler::default())
+            .connection_gater(DenyingGater {
+                dial: true,
+                ..Default::default()
+            })
+            .build();
+
+        let peer_id = PeerId::random();
+        match swarm
+            .dial(
+                DialOpts::peer_id(peer_id)
+                    .addresses(vec![multiaddr![Memory(1234u64)]])
+                    .build(),
+            )
+            .expect_err("Expect dial to be denied.")
+        {
+            DialError::Denied(GatingStage::Dial) => {}
+            e => panic!("Unexpected error: {:?}", e),
+        }
+        assert_eq!(swarm.behaviour().inject_dial_failure, vec![Some(peer_id)]);
+        assert_eq!(swarm.network_info().connection_counters().num_pending(), 0);
+    }
+
+    #[test]
+    fn connection_gater_denies_accept() {
+        let mut dialer = new_test_swarm::<_, ()>(DummyConnectionHandler::default()).build();
+        let mut listener = new_test_swarm::<_, ()>(DummyConnectionHandler::default())
+            .connection_gater(DenyingGater {
+                accept: true,
+                ..Default::default()
+            })
+            .build();
+
+        listener.listen_on(multiaddr![Memory(0u64)]).unwrap();
+        let listener_address = match block_on(listener.next()).unwrap() {
+            SwarmEvent::NewListenAddr { address, .. } => address,
+            e => panic!("Unexpected network event: {:?}", e),
+        };
+
+        dialer.dial(listener_address.clone()).unwrap();
+
+        block_on(future::poll_fn(|cx| {
+            let _ = dialer.poll_next_unpin(cx);
+            match listener.poll_next_unpin(cx) {
+                Poll::Ready(Some(SwarmEvent::IncomingConnectionError {
+                    local_addr,
+                    error: PendingConnectionError::Denied(GatingStage::Accept),
+                    ..
+                })) => {
+                    assert_eq!(local_addr, listener_address);
+                    Poll::Ready(())
+                }
+                Poll::Ready(e) => panic!("Unexpected network event: {:?}", e),
+                Poll::Pending => Poll::Pending,
+            }
+        }));
+        assert_eq!(listener.network_info().num_peers(), 0);
+    }
+
+    #[test]
+    fn connection_gater_denies_secured_peer() {
+        let mut listener = new_test_swarm::<_, ()>(DummyConnectionHandler::default()).build();
+        let listener_peer_id = *listener.local_peer_id();
+        let mut dialer = new_test_swarm::<_, ()>(DummyConnectionHandler::default())
+            .connection_gater(DenyingGater {
+                secured: Some(listener_peer_id),
+                ..Default::default()
+            })
+            .build();
+
+        listener.listen_on(multiaddr![Memory(0u64)]).unwrap();
+        let listener_address = match block_on(listener.next()).unwrap() {
+            SwarmEvent::NewListenAddr { address, .. } => address,
+            e => panic!("Unexpected network event: {:?}", e),
+        };
+
+        dialer.dial(listener_address).unwrap();
+
+        block_on(future::poll_fn(|cx| {
+            let _ = listener.poll_next_unpin(cx);
+            match dialer.poll_next_unpin(cx) {
+                Poll::Ready(Some(SwarmEvent::OutgoingConnectionError {
+                    peer_id,
+                    error: DialError::Denied(GatingStage::Secured(denied)),
+                })) => {
+                    assert_eq!(peer_id, Some(listener_peer_id));
+                    assert_eq!(denied, listener_peer_id);
+                    Poll::Ready(())
+                }
+                Poll::Ready(e) => panic!("Unexpected network event: {:?}", e),
+                Poll::Pending => Poll::Pending,
+            }
+        }));
+        assert!(!dialer.is_connected(&listener_peer_id));
+    }
+
+    #[test]
+    fn connection_gater_denies_secured_peer_before_multiplexing() {
+        let mut listener = new_test_swarm::<_, ()>(DummyConnectionHandler::default()).build();
+        let listener_peer_id = *listener.local_peer_id();
+
+        let gater = SharedConnectionGater::new(DenyingGater {
+            secured: Some(listener_peer_id),
+            ..Default::default()
+        });
+        let local_public_key = identity::Keypair::generate_ed25519().public();
+        let transport = transport::MemoryTransport
+            .upgrade(upgrade::Version::V1)
+            .authenticate(plaintext::PlainText2Config {
+                local_public_key: local_public_key.clone(),
+            })
+            .check(gater.secured_check())
+            .multiplex(yamux::YamuxConfig::default())
+            .boxed();
+        let behaviour = CallTraceBehaviour::new(MockBehaviour::<_, ()>::new(
+            DummyConnectionHandler::default(),
+        ));
+        let mut dialer = SwarmBuilder::new(transport, behaviour, local_public_key.into())
+            .connection_gater(gater)
+            .build();
+
+        listener.listen_on(multiaddr![Memory(0u64)]).unwrap();
+        let listener_address = match block_on(listener.next()).unwrap() {
+            SwarmEvent::NewListenAddr { address, .. } => address,
+            e => panic!("Unexpected network event: {:?}", e),
+        };
+
+        dialer.dial(listener_address).unwrap();
+
+        let mut dialer_denied = false;
+        let mut listener_failed = false;
+        block_on(future::poll_fn(|cx| {
+            loop {
+                match dialer.poll_next_unpin(cx) {
+                    Poll::Ready(Some(SwarmEvent::OutgoingConnectionError {
+                        peer_id,
+                        error: DialError::Denied(GatingStage::Secured(denied)),
+                    })) => {
+                        assert_eq!(peer_id, Some(listener_peer_id));
+                        assert_eq!(denied, listener_peer_id);
+                        dialer_denied = true;
+                    }
+                    Poll::Ready(e) => panic!("Unexpected network event: {:?}", e),
+                    Poll::Pending => break,
+                }
+            }
+            loop {
+                match listener.poll_next_unpin(cx) {
+                    // The dialer closes the connection while the listener awaits the
+                    // multiplexer negotiation.
+                    Poll::Ready(Some(SwarmEvent::IncomingConnectionError {
+                        error: PendingConnectionError::Transport(_),
+                        ..
+                    })) => listener_failed = true,
+                    Poll::Ready(Some(SwarmEvent::IncomingConnection { .. })) => {}
+                    Poll::Ready(e) => panic!("Unexpected network event: {:?}", e),
+                    Poll::Pending => break,
+                }
+            }
+            if dialer_denied && listener_failed {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }));
+        assert!(!dialer.is_connected(&listener_peer_id));
+    }
+
+    #[test]
+    fn connection_gater_denies_upgraded_peer() {
+        let mut dialer = new_test_swarm::<_, ()>(DummyConnectionHandler::default()).build();
+        let dialer_peer_id = *dialer.local_peer_id();
+        let mut listener = new_test_swarm::<_, ()>(DummyConnectionHandler::default())
+            .connection_gater(DenyingGater {
+                upgraded: Some(dialer_peer_id),
+                ..Default::default()
+            })
+            .build();
+
+        listener.listen_on(multiaddr![Memory(0u64)]).unwrap();
+        let listener_address = match block_on(listener.next()).unwrap() {
+            SwarmEvent::NewListenAddr { address, .. } => address,
+            e => panic!("Unexpected network event: {:?}", e),
+        };
+
+        dialer.dial(listener_address).unwrap();
+
+        block_on(future::poll_fn(|cx| {
+            let _ = dialer.poll_next_unpin(cx);
+            match listener.poll_next_unpin(cx) {
+                Poll::Ready(Some(SwarmEvent::IncomingConnectionError {
+                    error: PendingConnectionError::Denied(GatingStage::Upgraded(denied)),
+                    ..
+                })) => {
+                    assert_eq!(denied, dialer_peer_id);
+                    Poll::Ready(())
+                }
+                Poll::Ready(Some(SwarmEvent::IncomingConnection { .. })) => {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+                Poll::Ready(e) => panic!("Unexpected network event: {:?}", e),
+                Poll::Pending => Poll::Pending,
+            }
+        }));
+        assert!(!listener.is_connected(&dialer_peer_id));
+        assert_eq!(listener.behaviour().inject_connection_established.len(), 0);
+    }
+
+    #[test]
+    fn supported_protocols_are_refreshed() {
+        let mut swarm =
+            new_test_swarm::<_, ()>(either::Either::Left(DummyConnectionHandler::default()))
+                .build();
+        assert!(swarm.supported_protocols.is_empty());
+
+        let behaviour = swarm.behaviour_mut().inner();
+        behaviour.handler_proto = either::Either::Right(StreamHandler::default());
+        behaviour.next_action = Some(NetworkBehaviourAction::RefreshSupportedProtocols);
+        block_on(future::poll_fn(|cx| {
+            let _ = swarm.poll_next_unpin(cx);
+            Poll::Ready(())
+        }));
+
+        assert_eq!(
+            swarm.supported_protocols.to_vec(),
+            vec![b"/test/1.0.0".to_vec()]
+        );
+        assert_eq!(
+            swarm.behaviour().inject_supported_protocols_change,
+            vec![vec![b"/test/1.0.0".to_vec()]]
+        );
+
+        // Refreshing unchanged protocols is not reported.
+        swarm.behaviour_mut().inner().next_action =
+            Some(NetworkBehaviourAction::RefreshSupportedProtocols);
+        block_on(future::poll_fn(|cx| {
+            let _ = swarm.poll_next_unpin(cx);
+            Poll::Ready(())
+        }));
+        assert_eq!(swarm.behaviour().inject_supported_protocols_change.len(), 1);
+    }
+
+    /// An upgrade negotiating `/test/1.0.0` and yielding the negotiated substream.
+    #[derive(Clone, Debug)]
+    struct TestProtocol;
+
+    impl upgrade::UpgradeInfo for TestProtocol {
+        type Info = &'static [u8];
+        type InfoIter = iter::Once<Self::Info>;
+
+        fn protocol_info(&self) -> Self::InfoIter {
+            iter::once(b"/test/1.0.0")
+        }
+    }
+
+    impl upgrade::InboundUpgrade<NegotiatedSubstream> for TestProtocol {
+        type Output = NegotiatedSubstream;
+        type Error = void::Void;
+        type Future = future::Ready<Result<Self::Output, Self::Error>>;
+
+        fn upgrade_inbound(self, socket: NegotiatedSubstream, _: Self::Info) -> Self::Future {
+            future::ready(Ok(socket))
+        }
+    }
+
+    impl upgrade::OutboundUpgrade<NegotiatedSubstream> for TestProtocol {
+        type Output = NegotiatedSubstream;
+        type Error = void::Void;
+        type Future = future::Ready<Result<Self::Output, Self::Error>>;
+
+        fn upgrade_outbound(self, socket: NegotiatedSubstream, _: Self::Info) -> Self::Future {
+            future::ready(Ok(socket))
+        }
+    }
+
+    /// A handler opening `to_open` substreams and keeping all negotiated substreams alive.
+    ///
+    /// Reports outbound substreams rejected by the resource manager.
+    #[derive(Debug, Default)]
+    struct StreamHandler {
+        to_open: usize,
+        streams: Vec<NegotiatedSubstream>,
+        rejected: VecDeque<ResourceLimitExceeded>,
+    }
+
+    impl Clone for StreamHandler {
+        fn clone(&self) -> Self {
+            StreamHandler {
+                to_open: self.to_open,
+                ..Default::default()
+            }
+        }
+    }
+
+    impl ConnectionHandler for StreamHandler {
+        type InEvent = void::Void;
+        type OutEvent = ResourceLimitExceeded;
+        type Error = void::Void;
+        type InboundProtocol = TestProtocol;
+        type OutboundProtocol = TestProtocol;
+        type InboundOpenInfo = ();
+        type OutboundOpenInfo = ();
+
+        fn listen_protocol(&self) -> SubstreamProtocol<TestProtocol, ()> {
+            SubstreamProtocol::new(TestProtocol, ())
+        }
+
+        fn inject_fully_negotiated_inbound(&mut self, stream: NegotiatedSubstream, _: ()) {
+            self.streams.push(stream);
+        }
+
+        fn inject_fully_negotiated_outbound(&mut self, stream: NegotiatedSubstream, _: ()) {
+            self.streams.push(stream);
+        }
+
+        fn inject_event(&mut self, v: void::Void) {
+            void::unreachable(v)
+        }
+
+        fn inject_dial_upgrade_error(
+            &mut self,
+            _: (),
+            error: ConnectionHandlerUpgrErr<void::Void>,
+        ) {
+            if let ConnectionHandlerUpgrErr::ResourceLimitExceeded(e) = error {
+                self.rejected.push_back(e);
+            }
+        }
+
+        fn connection_keep_alive(&self) -> KeepAlive {
+            KeepAlive::Yes
+        }
+
+        fn poll(
+            &mut self,
+            _: &mut Context<'_>,
+        ) -> Poll<ConnectionHandlerEvent<TestProtocol, (), ResourceLimitExceeded, void::Void>>
+        {
+            if let Some(e) = self.rejected.pop_front() {
+                return Poll::Ready(ConnectionHandlerEvent::Custom(e));
+            }
+            if self.to_open > 0 {
+                self.to_open -= 1;
+                return Poll::Ready(ConnectionHandlerEvent::OutboundSubstreamRequest {
+                    protocol: SubstreamProtocol::new(TestProtocol, ()),
+                });
+            }
+            Poll::Pending
+        }
+    }
+
+    /// Connects a dialer opening `to_open` substreams to a listener, driving both until
+    /// `done` returns true for an event of either.
+    fn connect_stream_swarms(
+        dialer: SwarmBuilder<CallTraceBehaviour<MockBehaviour<StreamHandler, ()>>>,
+        listener: SwarmBuilder<CallTraceBehaviour<MockBehaviour<StreamHandler, ()>>>,
+        mut done: impl FnMut(bool, SwarmEvent<(), void::Void>) -> bool,
+    ) {
+        let mut dialer = dialer.build();
+        let mut listener = listener.build();
+
+        listener.listen_on(multiaddr![Memory(0u64)]).unwrap();
+        let listener_address = match block_on(listener.next()).unwrap() {
+            SwarmEvent::NewListenAddr { address, .. } => address,
+            e => panic!("Unexpected network event: {:?}", e),
+        };
+        dialer.dial(listener_address).unwrap();
+
+        block_on(future::poll_fn(|cx| loop {
+            let (is_dialer, event) = match dialer.poll_next_unpin(cx) {
+                Poll::Ready(Some(event)) => (true, event),
+                _ => match listener.poll_next_unpin(cx) {
+                    Poll::Ready(Some(event)) => (false, event),
+                    _ => return Poll::Pending,
+                },
+            };
+            if done(is_dialer, event) {
+                return Poll::Ready(());
+            }
+        }));
+    }
+
+    #[test]
+    fn resource_manager_limits_inbound_streams_per_protocol() {
+        let manager = ResourceManager::new(ResourceLimits::default().with_limits_for_protocol(
+            "/test/1.0.0",
+            ScopeLimits::default().with_max_inbound_streams(Some(1)),
+        ));
+        let dialer = new_test_swarm::<_, ()>(StreamHandler {
+            to_open: 2,
+            ..Default::default()
+        });
+        let listener =
+            new_test_swarm::<_, ()>(StreamHandler::default()).resource_manager(manager.clone());
+
+        connect_stream_swarms(dialer, listener, |is_dialer, event| match event {
+            SwarmEvent::ResourceLimitExceeded { error, .. } => {
+                assert!(!is_dialer);
+                assert_eq!(
+                    error,
+                    ResourceLimitExceeded {
+                        scope: Scope::Protocol("/test/1.0.0".into()),
+                        resource: Resource::InboundStreams,
+                        limit: 1,
+                        current: 1,
+                    }
+                );
+                // The connection is closed once the swarms are dropped, thus check the usage
+                // while it is still alive.
+                let protocol = manager.usage(&Scope::Protocol("/test/1.0.0".into()));
+                assert_eq!(protocol.inbound_streams, 1);
+                let system = manager.usage(&Scope::System);
+                assert_eq!(system.connections, 1);
+                assert_eq!(system.inbound_streams, 1);
+                true
+            }
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn resource_manager_limits_outbound_streams() {
+        let manager = ResourceManager::new(
+            ResourceLimits::default()
+                .with_system_limits(ScopeLimits::default().with_max_outbound_streams(Some(1))),
+        );
+        let dialer = new_test_swarm::<_, ()>(StreamHandler {
+            to_open: 2,
+            ..Default::default()
+        })
+        .resource_manager(manager.clone());
+        let listener = new_test_swarm::<_, ()>(StreamHandler::default());
+
+        connect_stream_swarms(dialer, listener, |is_dialer, event| match event {
+            SwarmEvent::ResourceLimitExceeded { error, .. } => {
+                assert!(is_dialer);
+                assert_eq!(error.scope, Scope::System);
+                assert_eq!(error.resource, Resource::OutboundStreams);
+                assert_eq!(manager.usage(&Scope::System).outbound_streams, 1);
+                true
+            }
+            _ => false,
+        });
+    }
 }
@@ -0,0 +1,573 @@
+// Copyright 2022 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Accounting and limiting of the resources used by a [`Swarm`](crate::Swarm).
+//!
+//! A [`ResourceManager`] tracks established connections and substreams in a hierarchy of
+//! [`Scope`]s:
+//!
+//! - [`Scope::System`] covers all resources of the local node.
+//! - [`Scope::Peer`] covers the connections and substreams of a single remote peer.
+//! - [`Scope::Protocol`] covers the substreams of a single protocol, as negotiated via
+//!   multistream-select.
+//! - [`Scope::Connection`] covers the substreams of a single connection.
+//!
+//! Each scope is bounded by its [`ScopeLimits`], configured via [`ResourceLimits`]. A connection
+//! exceeding the limits of its scopes is closed and reported as
+//! [`PendingConnectionError::ResourceLimitExceeded`](crate::PendingConnectionError::ResourceLimitExceeded).
+//! A substream exceeding the limits of its scopes is reset, reported to the
+//! [`ConnectionHandler`](crate::ConnectionHandler) as
+//! [`ConnectionHandlerUpgrErr::ResourceLimitExceeded`](crate::ConnectionHandlerUpgrErr::ResourceLimitExceeded)
+//! once negotiated and to the user as
+//! [`SwarmEvent::ResourceLimitExceeded`](crate::SwarmEvent::ResourceLimitExceeded).
+//!
+//! Substreams are accounted to their [`Scope::Protocol`] once protocol negotiation completed,
+//! and are released once the negotiated substream is dropped.
+
+use libp2p_core::connection::ConnectionId;
+use libp2p_core::upgrade::{InboundUpgrade, OutboundUpgrade, ProtocolName, UpgradeInfo};
+use libp2p_core::PeerId;
+use std::collections::{hash_map, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::{error, fmt};
+
+/// A scope in which resources are accounted.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Scope {
+    /// All resources of the local node.
+    System,
+    /// The resources used with a single remote peer.
+    Peer(PeerId),
+    /// The resources used by a single protocol.
+    Protocol(String),
+    /// The resources used on a single connection.
+    Connection(ConnectionId),
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::System => write!(f, "system"),
+            Scope::Peer(peer) => write!(f, "peer {}", peer),
+            Scope::Protocol(protocol) => write!(f, "protocol {}", protocol),
+            Scope::Connection(id) => write!(f, "connection {:?}", id),
+        }
+    }
+}
+
+/// A resource tracked by a [`ResourceManager`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Resource {
+    /// Established connections.
+    Connections,
+    /// Substreams opened by the remote.
+    InboundStreams,
+    /// Substreams opened by the local node.
+    OutboundStreams,
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resource::Connections => write!(f, "connections"),
+            Resource::InboundStreams => write!(f, "inbound streams"),
+            Resource::OutboundStreams => write!(f, "outbound streams"),
+        }
+    }
+}
+
+/// The limits of a single [`Scope`].
+///
+/// By default, all resources are unlimited.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScopeLimits {
+    max_connections: Option<usize>,
+    max_inbound_streams: Option<usize>,
+    max_outbound_streams: Option<usize>,
+}
+
+impl ScopeLimits {
+    /// Configures the maximum number of established connections.
+    ///
+    /// Connections are only accounted in the [`Scope::System`] and [`Scope::Peer`] scopes.
+    pub fn with_max_connections(mut self, limit: Option<usize>) -> Self {
+        self.max_connections = limit;
+        self
+    }
+
+    /// Configures the maximum number of substreams opened by remotes.
+    pub fn with_max_inbound_streams(mut self, limit: Option<usize>) -> Self {
+        self.max_inbound_streams = limit;
+        self
+    }
+
+    /// Configures the maximum number of substreams opened by the local node.
+    pub fn with_max_outbound_streams(mut self, limit: Option<usize>) -> Self {
+        self.max_outbound_streams = limit;
+        self
+    }
+
+    fn limit(&self, resource: Resource) -> Option<usize> {
+        match resource {
+            Resource::Connections => self.max_connections,
+            Resource::InboundStreams => self.max_inbound_streams,
+            Resource::OutboundStreams => self.max_outbound_streams,
+        }
+    }
+}
+
+/// The [`ScopeLimits`] of all scopes of a [`ResourceManager`].
+///
+/// By default, all scopes are unlimited.
+#[derive(Debug, Clone, Default)]
+pub struct ResourceLimits {
+    system: ScopeLimits,
+    peer: ScopeLimits,
+    protocol: ScopeLimits,
+    protocols: HashMap<String, ScopeLimits>,
+    connection: ScopeLimits,
+}
+
+impl ResourceLimits {
+    /// Configures the limits of the [`Scope::System`] scope.
+    pub fn with_system_limits(mut self, limits: ScopeLimits) -> Self {
+        self.system = limits;
+        self
+    }
+
+    /// Configures the limits of each [`Scope::Peer`] scope.
+    pub fn with_peer_limits(mut self, limits: ScopeLimits) -> Self {
+        self.peer = limits;
+        self
+    }
+
+    /// Configures the limits of each [`Scope::Protocol`] scope without dedicated limits.
+    pub fn with_protocol_limits(mut self, limits: ScopeLimits) -> Self {
+        self.protocol = limits;
+        self
+    }
+
+    /// Configures dedicated limits for the [`Scope::Protocol`] scope of the given protocol.
+    pub fn with_limits_for_protocol(
+        mut self,
+        protocol: impl Into<String>,
+        limits: ScopeLimits,
+    ) -> Self {
+        self.protocols.insert(protocol.into(), limits);
+        self
+    }
+
+    /// Configures the limits of each [`Scope::Connection`] scope.
+    pub fn with_connection_limits(mut self, limits: ScopeLimits) -> Self {
+        self.connection = limits;
+        self
+    }
+
+    fn scope_limits(&self, scope: &Scope) -> &ScopeLimits {
+        match scope {
+            Scope::System => &self.system,
+            Scope::Peer(_) => &self.peer,
+            Scope::Protocol(protocol) => self.protocols.get(protocol).unwrap_or(&self.protocol),
+            Scope::Connection(_) => &self.connection,
+        }
+    }
+}
+
+/// The resources currently used in a [`Scope`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScopeUsage {
+    /// The number of established connections.
+    pub connections: usize,
+    /// The number of substreams opened by remotes.
+    pub inbound_streams: usize,
+    /// The number of substreams opened by the local node.
+    pub outbound_streams: usize,
+}
+
+impl ScopeUsage {
+    fn get(&self, resource: Resource) -> usize {
+        match resource {
+            Resource::Connections => self.connections,
+            Resource::InboundStreams => self.inbound_streams,
+            Resource::OutboundStreams => self.outbound_streams,
+        }
+    }
+
+    fn get_mut(&mut self, resource: Resource) -> &mut usize {
+        match resource {
+            Resource::Connections => &mut self.connections,
+            Resource::InboundStreams => &mut self.inbound_streams,
+            Resource::OutboundStreams => &mut self.outbound_streams,
+        }
+    }
+}
+
+/// A request for a [`Resource`] exceeded the limit of a [`Scope`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceLimitExceeded {
+    /// The scope whose limit was exceeded.
+    pub scope: Scope,
+    /// The requested resource.
+    pub resource: Resource,
+    /// The configured limit.
+    pub limit: usize,
+    /// The usage at the time of the request.
+    pub current: usize,
+}
+
+impl fmt::Display for ResourceLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Limit of {} exceeded in {} scope: {}/{}",
+            self.resource, self.scope, self.current, self.limit
+        )
+    }
+}
+
+impl error::Error for ResourceLimitExceeded {}
+
+/// Tracks and limits the resources used by a [`Swarm`](crate::Swarm).
+///
+/// Clones of a [`ResourceManager`] share the same state. See the [module-level
+/// documentation](self) for details.
+#[derive(Debug, Clone)]
+pub struct ResourceManager {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    limits: ResourceLimits,
+    usage: HashMap<Scope, ScopeUsage>,
+}
+
+impl ResourceManager {
+    /// Creates a new [`ResourceManager`] enforcing the given limits.
+    pub fn new(limits: ResourceLimits) -> Self {
+        ResourceManager {
+            state: Arc::new(Mutex::new(State {
+                limits,
+                usage: Default::default(),
+            })),
+        }
+    }
+
+    /// Returns the resources currently used in the given scope.
+    pub fn usage(&self, scope: &Scope) -> ScopeUsage {
+        self.lock().usage.get(scope).copied().unwrap_or_default()
+    }
+
+    /// Accounts a newly established connection to the given peer.
+    pub(crate) fn open_connection(
+        &self,
+        peer: PeerId,
+        id: ConnectionId,
+    ) -> Result<Arc<ConnectionScope>, ResourceLimitExceeded> {
+        self.lock().reserve(
+            &[Scope::System, Scope::Peer(peer)],
+            Resource::Connections,
+            1,
+        )?;
+        Ok(Arc::new(ConnectionScope {
+            manager: self.clone(),
+            peer,
+            id,
+        }))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .expect("Resource manager lock not to be poisoned.")
+    }
+}
+
+impl State {
+    /// Accounts `amount` of `resource` in all of the given scopes, if none of their limits is
+    /// exceeded.
+    fn reserve(
+        &mut self,
+        scopes: &[Scope],
+        resource: Resource,
+        amount: usize,
+    ) -> Result<(), ResourceLimitExceeded> {
+        for scope in scopes {
+            if let Some(limit) = self.limits.scope_limits(scope).limit(resource) {
+                let current = self.usage.get(scope).map_or(0, |u| u.get(resource));
+                if current
+                    .checked_add(amount)
+                    .map_or(true, |total| total > limit)
+                {
+                    return Err(ResourceLimitExceeded {
+                        scope: scope.clone(),
+                        resource,
+                        limit,
+                        current,
+                    });
+                }
+            }
+        }
+        for scope in scopes {
+            *self
+                .usage
+                .entry(scope.clone())
+                .or_default()
+                .get_mut(resource) += amount;
+        }
+        Ok(())
+    }
+
+    fn release(&mut self, scopes: &[Scope], resource: Resource, amount: usize) {
+        for scope in scopes {
+            if let hash_map::Entry::Occupied(mut entry) = self.usage.entry(scope.clone()) {
+                let used = entry.get_mut().get_mut(resource);
+                *used = used.saturating_sub(amount);
+                if *entry.get() == ScopeUsage::default() {
+                    entry.remove();
+                }
+            }
+        }
+    }
+}
+
+/// Accounts an established connection for as long as it is alive.
+#[derive(Debug)]
+pub(crate) struct ConnectionScope {
+    manager: ResourceManager,
+    peer: PeerId,
+    id: ConnectionId,
+}
+
+impl ConnectionScope {
+    /// Accounts a new substream in the direction given by `resource` on the connection.
+    pub(crate) fn open_stream(
+        self: &Arc<Self>,
+        resource: Resource,
+    ) -> Result<Arc<StreamScope>, ResourceLimitExceeded> {
+        self.manager.lock().reserve(&self.scopes(), resource, 1)?;
+        Ok(Arc::new(StreamScope {
+            connection: self.clone(),
+            resource,
+            protocol: Mutex::new(None),
+            admitted: AtomicBool::new(false),
+        }))
+    }
+
+    fn scopes(&self) -> [Scope; 3] {
+        [
+            Scope::System,
+            Scope::Peer(self.peer),
+            Scope::Connection(self.id),
+        ]
+    }
+}
+
+impl Drop for ConnectionScope {
+    fn drop(&mut self) {
+        self.manager.lock().release(
+            &[Scope::System, Scope::Peer(self.peer)],
+            Resource::Connections,
+            1,
+        );
+    }
+}
+
+/// Accounts a substream for as long as it is alive.
+#[derive(Debug)]
+pub(crate) struct StreamScope {
+    connection: Arc<ConnectionScope>,
+    resource: Resource,
+    /// The protocol negotiated on the substream, if any.
+    protocol: Mutex<Option<String>>,
+    /// Whether the substream is accounted to the [`Scope::Protocol`] of `protocol`.
+    admitted: AtomicBool,
+}
+
+impl StreamScope {
+    fn set_protocol(&self, protocol: &[u8]) {
+        *self.protocol.lock().expect("Lock not to be poisoned.") =
+            Some(String::from_utf8_lossy(protocol).into_owned());
+    }
+
+    /// Accounts the substream to the scope of its negotiated protocol.
+    pub(crate) fn admit(&self) -> Result<(), ResourceLimitExceeded> {
+        let protocol = self.protocol.lock().expect("Lock not to be poisoned.");
+        if let Some(protocol) = protocol.as_ref() {
+            self.connection.manager.lock().reserve(
+                &[Scope::Protocol(protocol.clone())],
+                self.resource,
+                1,
+            )?;
+            self.admitted.store(true, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for StreamScope {
+    fn drop(&mut self) {
+        let mut state = self.connection.manager.lock();
+        state.release(&self.connection.scopes(), self.resource, 1);
+        if self.admitted.load(Ordering::SeqCst) {
+            if let Some(protocol) = self
+                .protocol
+                .get_mut()
+                .expect("Lock not to be poisoned.")
+                .take()
+            {
+                state.release(&[Scope::Protocol(protocol)], self.resource, 1);
+            }
+        }
+    }
+}
+
+/// Upgrade recording the protocol negotiated on a substream in its [`StreamScope`].
+pub(crate) struct ScopedUpgrade<TUpgrade> {
+    upgrade: TUpgrade,
+    scope: Option<Arc<StreamScope>>,
+}
+
+impl<TUpgrade> ScopedUpgrade<TUpgrade> {
+    pub(crate) fn new(upgrade: TUpgrade, scope: Option<Arc<StreamScope>>) -> Self {
+        ScopedUpgrade { upgrade, scope }
+    }
+
+    fn record(&self, protocol: &impl ProtocolName) {
+        if let Some(scope) = &self.scope {
+            scope.set_protocol(protocol.protocol_name());
+        }
+    }
+}
+
+impl<TUpgrade: UpgradeInfo> UpgradeInfo for ScopedUpgrade<TUpgrade> {
+    type Info = TUpgrade::Info;
+    type InfoIter = TUpgrade::InfoIter;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.upgrade.protocol_info()
+    }
+}
+
+impl<C, TUpgrade: InboundUpgrade<C>> InboundUpgrade<C> for ScopedUpgrade<TUpgrade> {
+    type Output = TUpgrade::Output;
+    type Error = TUpgrade::Error;
+    type Future = TUpgrade::Future;
+
+    fn upgrade_inbound(self, socket: C, info: Self::Info) -> Self::Future {
+        self.record(&info);
+        self.upgrade.upgrade_inbound(socket, info)
+    }
+}
+
+impl<C, TUpgrade: OutboundUpgrade<C>> OutboundUpgrade<C> for ScopedUpgrade<TUpgrade> {
+    type Output = TUpgrade::Output;
+    type Error = TUpgrade::Error;
+    type Future = TUpgrade::Future;
+
+    fn upgrade_outbound(self, socket: C, info: Self::Info) -> Self::Future {
+        self.record(&info);
+        self.upgrade.upgrade_outbound(socket, info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overflowing_reservation_exceeds_limit() {
+        let manager = ResourceManager::new(
+            ResourceLimits::default()
+                .with_system_limits(ScopeLimits::default().with_max_connections(Some(usize::MAX))),
+        );
+        let peer = PeerId::random();
+        let _connection = manager.open_connection(peer, ConnectionId::new(0)).unwrap();
+
+        let err = manager
+            .lock()
+            .reserve(&[Scope::System], Resource::Connections, usize::MAX)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ResourceLimitExceeded {
+                scope: Scope::System,
+                resource: Resource::Connections,
+                limit: usize::MAX,
+                current: 1,
+            }
+        );
+        assert_eq!(manager.usage(&Scope::System).connections, 1);
+    }
+
+    #[test]
+    fn streams_are_accounted_per_connection_and_protocol() {
+        let manager = ResourceManager::new(
+            ResourceLimits::default()
+                .with_peer_limits(ScopeLimits::default().with_max_connections(Some(1)))
+                .with_connection_limits(ScopeLimits::default().with_max_inbound_streams(Some(2)))
+                .with_limits_for_protocol(
+                    "/a",
+                    ScopeLimits::default().with_max_inbound_streams(Some(1)),
+                ),
+        );
+        let peer = PeerId::random();
+        let id = ConnectionId::new(0);
+
+        let connection = manager.open_connection(peer, id).unwrap();
+        assert_eq!(
+            manager
+                .open_connection(peer, ConnectionId::new(1))
+                .unwrap_err()
+                .scope,
+            Scope::Peer(peer)
+        );
+
+        let first = connection.open_stream(Resource::InboundStreams).unwrap();
+        let second = connection.open_stream(Resource::InboundStreams).unwrap();
+        assert_eq!(
+            connection
+                .open_stream(Resource::InboundStreams)
+                .unwrap_err()
+                .scope,
+            Scope::Connection(id)
+        );
+
+        first.set_protocol(b"/a");
+        first.admit().unwrap();
+        second.set_protocol(b"/a");
+        assert_eq!(
+            second.admit().unwrap_err().scope,
+            Scope::Protocol("/a".into())
+        );
+        assert_eq!(manager.usage(&Scope::Peer(peer)).inbound_streams, 2);
+
+        drop((first, second, connection));
+        for scope in [
+            Scope::System,
+            Scope::Peer(peer),
+            Scope::Protocol("/a".into()),
+            Scope::Connection(id),
+        ] {
+            assert_eq!(manager.usage(&scope), ScopeUsage::default());
+        }
+    }
+}
@@ -112,6 +112,7 @@ where
     pub inject_new_external_addr: Vec<Multiaddr>,
     pub inject_expired_listen_addr: Vec<(ListenerId, Multiaddr)>,
     pub inject_expired_external_addr: Vec<Multiaddr>,
+    pub inject_supported_protocols_change: Vec<Vec<Vec<u8>>>,
     pub inject_listener_error: Vec<ListenerId>,
     pub inject_listener_closed: Vec<(ListenerId, bool)>,
     pub poll: usize,
@@ -134,6 +135,7 @@ where
             inject_new_external_addr: Vec::new(),
             inject_expired_listen_addr: Vec::new(),
             inject_expired_external_addr: Vec::new(),
+            inject_supported_protocols_change: Vec::new(),
             inject_listener_error: Vec::new(),
             inject_listener_closed: Vec::new(),
             poll: 0,
@@ -390,6 +392,11 @@ where
         self.inner.inject_expired_external_addr(a);
     }
 
+    fn inject_supported_protocols_change(&mut self, p: &[Vec<u8>]) {
+        self.inject_supported_protocols_change.push(p.to_vec());
+        self.inner.inject_supported_protocols_change(p);
+    }
+
     fn inject_listener_error(&mut self, l: ListenerId, e: &(dyn std::error::Error + 'static)) {
         self.inject_listener_error.push(l.clone());
         self.inner.inject_listener_error(l, e);